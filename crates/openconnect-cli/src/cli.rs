@@ -1,5 +1,22 @@
-use clap::{CommandFactory, Parser, Subcommand};
+use crate::{client::state::StartOptions, server::probe::parse_probe_host};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell};
+use openconnect_core::{
+    config::{
+        is_valid_script_env_name, parse_cert_fingerprint, parse_http_header, AddressFamily,
+        LogLevel, ServerAddress, Subnet, TlsVersion, SUPPORTED_REPORTED_OS,
+    },
+    paths,
+    protocols::VpnProtocol,
+    storage::{Defaults, ServerProfile},
+    trusted_network::TrustedNetwork,
+    udp_policy::UdpRule,
+    verify::parse_verify_url,
+};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -8,6 +25,14 @@ use clap_complete::{generate, Shell};
     long_about = env!("CARGO_PKG_DESCRIPTION"),
 )]
 pub struct Cli {
+    /// Root directory for config, logs, socket and PID file, overrides OPENCONNECT_RS_HOME
+    #[arg(long, global = true, value_hint = clap::ValueHint::DirPath)]
+    pub config_dir: Option<String>,
+
+    /// Print plain `key: value` lines instead of tables, the default when the output is not a terminal
+    #[arg(long, global = true, default_value = "false")]
+    pub no_table: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -22,13 +47,278 @@ pub enum Commands {
         /// The path to the local config file
         #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
         config_file: Option<String>,
+
+        /// Connect to the password server defined by OPENCONNECT_SERVER, OPENCONNECT_USERNAME and OPENCONNECT_PASSWORD(_FILE) instead of a saved one, NAME only names it
+        ///
+        /// The config file is not read, its preferences are taken from OPENCONNECT_PROTOCOL, OPENCONNECT_VPNC_SCRIPT, OPENCONNECT_LOG_LEVEL and OPENCONNECT_AUTO_RECONNECT
+        #[arg(long, default_value = "false", conflicts_with_all = ["config_file", "profile"])]
+        from_env: bool,
+
+        /// Block all traffic outside the tunnel while the VPN is supposed to be up (Linux only, requires nftables)
+        ///
+        /// The rules are kept if the daemon crashes, use `kill-switch clear` to remove them
+        #[arg(long, default_value = "false")]
+        kill_switch: bool,
+
+        /// Serve tunnel metrics in Prometheus format on http://<metrics-address>:<port>/metrics
+        #[arg(long)]
+        metrics_port: Option<u16>,
+
+        /// The address to bind the metrics endpoint to
+        #[arg(long, default_value = "127.0.0.1", requires = "metrics_port")]
+        metrics_address: IpAddr,
+
+        /// Randomize each auto-reconnect delay within this percentage, to spread out reconnecting clients
+        #[arg(long, default_value = "20", value_parser = clap::value_parser!(u8).range(0..=100))]
+        reconnect_jitter: u8,
+
+        /// Give up re-establishing a dropped connection after this many failed attempts in a row, retries forever unless set
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        reconnect_retries: Option<u32>,
+
+        /// Seconds before the first attempt to re-establish a dropped connection, doubled after every failed one up to 5 minutes
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..=300))]
+        reconnect_backoff: u64,
+
+        /// Reconnect when the DNS servers of the system resolver change, e.g. after a captive portal
+        #[arg(long, default_value = "false")]
+        reconnect_on_dns_change: bool,
+
+        #[command(flatten)]
+        connection: ConnectionArgs,
+
+        /// Only authenticate and print the parameters pushed by the gateway, then disconnect
+        ///
+        /// Runs in the foreground and neither creates a tun device nor changes routes or DNS
+        #[arg(long, default_value = "false", conflicts_with_all = ["kill_switch", "metrics_port", "reconnect_retries", "reconnect_on_dns_change", "wait", "tun_device", "tun_fd", "foreground"])]
+        info_only: bool,
+
+        /// Keep the daemon in the foreground instead of detaching it, e.g. to run under systemd
+        ///
+        /// The process exits non-zero if the connection could not be started
+        #[arg(long, default_value = "false")]
+        foreground: bool,
+
+        /// With --info-only, print the session cookie after authenticating and exit, e.g. for other openconnect tools
+        ///
+        /// Anyone holding the cookie can use the session until it expires. It is printed to stdout only, never logged
+        #[arg(long, default_value = "false", requires = "info_only")]
+        print_cookie: bool,
+
+        /// Terminate an existing session if the gateway's session limit is reached, instead of failing
+        #[arg(long, default_value = "false")]
+        force: bool,
+
+        /// Log in to an OIDC server in the browser with the authorization code flow, for issuers without the device flow
+        ///
+        /// The issuer must allow the redirect URI http://localhost:17175/callback
+        #[arg(long, default_value = "false")]
+        oidc_browser: bool,
+
+        /// Trust the gateway certificate with this SHA-256 fingerprint for this connection only, even if it fails verification
+        ///
+        /// Accepts `sha256:<hex>`, a hex digest with or without `:` between the bytes, or `pin-sha256:<base64>`
+        #[arg(long, value_name = "SHA256", value_parser = parse_cert_fingerprint)]
+        accept_cert: Option<String>,
+
+        /// Stop reconnecting when the gateway presents another certificate than before, instead of awaiting `trust-cert`
+        #[arg(long, default_value = "false", conflicts_with = "accept_cert")]
+        strict_cert: bool,
+
+        /// Copy the TOS/DSCP bits of the tunneled packets to the encapsulating packets, e.g. for VoIP QoS
+        #[arg(long = "passtos", default_value = "false")]
+        pass_tos: bool,
+
+        /// Pass a variable to the vpnc-script, exported as OPENCONNECT_RS_<KEY> so that it can't replace a variable of openconnect, can be repeated
+        #[arg(long = "script-env", value_name = "KEY=VALUE", value_parser = parse_script_env)]
+        script_env: Vec<(String, String)>,
+
+        /// Append the protocol messages of libopenconnect to this file in the log directory, apart from the log of the CLI, e.g. for an upstream bug report
+        #[arg(long, value_name = "FILE", value_parser = parse_openconnect_log)]
+        openconnect_log: Option<PathBuf>,
+
+        /// The most verbose level written to the --openconnect-log file: err, info, debug or trace
+        #[arg(
+            long,
+            value_name = "LEVEL",
+            default_value = "debug",
+            requires = "openconnect_log"
+        )]
+        openconnect_log_level: LogLevel,
+
+        /// Block until the connection is established, exit non-zero on error or after the timeout in seconds
+        #[arg(long, value_name = "TIMEOUT", num_args = 0..=1, require_equals = true, default_missing_value = "30")]
+        wait: Option<u64>,
+
+        /// Connect through an existing persistent tun device instead of creating one, the daemon then runs without escalating
+        ///
+        /// The device still gets configured by the vpnc-script, which needs CAP_NET_ADMIN
+        #[arg(long, value_name = "IFNAME", conflicts_with = "tun_fd")]
+        tun_device: Option<String>,
+
+        /// Connect through an open tun device file descriptor inherited from the caller, the daemon then runs without escalating
+        ///
+        /// No vpnc-script is run, the caller configures the addresses and routes shown by `status`
+        #[arg(long, value_name = "FD", value_parser = clap::value_parser!(i32).range(3..))]
+        tun_fd: Option<i32>,
+    },
+
+    #[command(
+        about = "Print the effective configuration start would use for a server, with secrets redacted"
+    )]
+    Show {
+        /// The server name saved in local config file to show
+        name: String,
+
+        /// The path to the local config file
+        #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+        config_file: Option<String>,
+
+        #[command(flatten)]
+        connection: ConnectionArgs,
     },
 
     #[command(about = "Get the current VPN connection status", visible_aliases = ["info", "stat"])]
-    Status,
+    Status {
+        /// Show the summary of the last session saved on disconnect instead of asking the daemon
+        #[arg(long, default_value = "false")]
+        last: bool,
+
+        /// Seconds to wait for the daemon to respond
+        #[arg(long, default_value = "5", conflicts_with = "last")]
+        timeout: u64,
+
+        /// Print the status as JSON, with the parameters pushed by the gateway under `info` and unset fields as null
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+
+    #[command(
+        about = "Show the traffic counters of the running connection, or the lifetime counters of a server, kept across sessions"
+    )]
+    Stats {
+        /// The server name saved in local config file, the running connection if not given
+        name: Option<String>,
+    },
+
+    #[command(
+        about = "Apply the DNS and route configuration of the running connection again, e.g. after another tool overwrote it"
+    )]
+    Reapply,
+
+    #[command(
+        about = "Make new TLS and DTLS handshakes for the running connection, e.g. when its throughput degraded"
+    )]
+    Rekey,
+
+    #[command(
+        about = "Measure the round-trip time through the tunnel, as the time of TCP handshakes with a host behind it"
+    )]
+    Ping {
+        /// The number of handshakes
+        #[arg(short, long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..=100))]
+        count: u32,
+
+        /// The host to measure instead of the probe host of the server or the first DNS server pushed by the gateway
+        #[arg(long, value_name = "HOST:PORT", value_parser = parse_probe_host)]
+        target: Option<String>,
+    },
+
+    #[command(
+        about = "Connect to a server, run a command while the tunnel is up and disconnect when it exits, e.g. in CI",
+        long_about = "Connect to a server, run a command while the tunnel is up and disconnect when it exits, e.g. in CI\n\nExits with the code of the command, or 75 if it was killed because a limit was exceeded or the daemon stopped"
+    )]
+    Run {
+        /// The server name saved in local config file to connect to
+        name: String,
+
+        /// The path to the local config file
+        #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+        config_file: Option<String>,
+
+        /// Seconds to wait for the connection before giving up without running the command
+        #[arg(long, default_value = "30")]
+        wait: u64,
+
+        /// Kill the command when the tunnel dropped more than this many times while it runs, instead of reconnecting again
+        #[arg(long, value_name = "N")]
+        max_reconnects: Option<u32>,
+
+        /// Kill the command when it still runs after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        max_duration: Option<u64>,
+
+        /// The command to run and its arguments, after `--`
+        #[arg(required = true, last = true, value_hint = clap::ValueHint::CommandWithArguments)]
+        command: Vec<String>,
+    },
 
     #[command(about = "Close the current connection and exit the daemon process", visible_aliases = ["kill", "disconnect"])]
-    Stop,
+    Stop {
+        /// Seconds to wait for the daemon to close the connection and respond
+        #[arg(long, default_value = "10")]
+        timeout: u64,
+    },
+
+    #[command(
+        about = "Supply a password (and TOTP code) to a daemon awaiting credentials after the gateway required a new login"
+    )]
+    Reauth {
+        /// The one-time code for the second factor, if the gateway asks for one
+        #[arg(long)]
+        totp: Option<String>,
+    },
+
+    #[command(
+        about = "Open a prompt to run commands like list, start, status and stop without the openconnect prefix"
+    )]
+    Interactive,
+
+    #[command(
+        about = "Trust the new certificate of the gateway after the daemon paused reconnecting because it changed"
+    )]
+    TrustCert {
+        /// The SHA-256 fingerprint of the new certificate, as reported by `status`
+        #[arg(value_parser = parse_cert_fingerprint)]
+        fingerprint: String,
+    },
+
+    #[command(about = "Enable or disable auto-reconnect of the running daemon without stopping the tunnel", visible_aliases = ["autoreconnect"])]
+    AutoReconnect {
+        /// Whether the daemon should re-establish the connection when it drops
+        state: Toggle,
+    },
+
+    #[command(
+        about = "Check whether the DTLS (UDP) port of a VPN server is reachable before connecting"
+    )]
+    Probe {
+        /// The server name saved in local config file to probe
+        name: String,
+
+        /// The UDP port to probe, defaults to the port of the server URL
+        #[arg(short, long)]
+        port: Option<u16>,
+    },
+
+    #[command(about = "Inspect or clear the kill switch firewall rules")]
+    KillSwitch {
+        #[arg(value_enum)]
+        action: KillSwitchAction,
+    },
+
+    #[command(
+        about = "Tear down the tunnel interfaces and routes left behind by a crashed daemon"
+    )]
+    Cleanup {
+        /// Only list the orphaned tunnels
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+
+        /// Skip the confirmation of each tunnel
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
+    },
 
     #[command(
         subcommand,
@@ -49,17 +339,98 @@ pub enum Commands {
         name: String,
     },
 
+    #[command(
+        about = "Export the key encrypting the saved passwords under a passphrase, to copy the config file to another machine"
+    )]
+    KeyExport,
+
+    #[command(
+        about = "Import a key exported by key-export, so that a config file copied from another machine can decrypt its passwords"
+    )]
+    KeyImport {
+        /// The exported key
+        key: String,
+
+        /// Skip the confirmation
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
+    },
+
     #[command(about = "Delete a VPN server configuration from local config file", visible_aliases = ["rm", "remove", "del"])]
     Delete {
         /// The server name saved in local config file to delete
-        name: String,
+        #[arg(required_unless_present_any = ["all", "pattern"], conflicts_with_all = ["all", "pattern"])]
+        name: Option<String>,
+
+        /// Delete all servers except the default server, after a confirmation
+        #[arg(long, default_value = "false", conflicts_with = "pattern")]
+        all: bool,
+
+        /// Delete the servers whose name matches a glob with `*` and `?`, after a confirmation
+        #[arg(long = "match", value_name = "GLOB")]
+        pattern: Option<String>,
+
+        /// Only list the servers which would be deleted
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+
+        /// Skip the confirmation of --all and --match
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
     },
 
     #[command(about = "List all VPN server configurations in local config file", visible_aliases = ["ls", "l"])]
     List,
 
-    #[command(about = "Show logs of the daemon process", visible_aliases = ["log"])]
-    Logs,
+    #[command(about = "Print the JSON Schema of the config file")]
+    Schema,
+
+    #[command(
+        subcommand,
+        about = "Get or set the defaults applied to all connections, unless a server or a flag sets them"
+    )]
+    Config(ConfigAction),
+
+    #[command(about = "Show logs of the daemon process", visible_aliases = ["log"], args_conflicts_with_subcommands = true)]
+    Logs {
+        /// Only show the latest connection log of this server
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Print one JSON record per line with timestamp, level, target, message and fields
+        #[arg(long, default_value = "false")]
+        json: bool,
+
+        #[command(subcommand)]
+        action: Option<LogsAction>,
+    },
+
+    #[command(
+        about = "Collect logs, redacted config and connection status into a zip file for support tickets"
+    )]
+    Diagnose {
+        /// The path of the zip file to write
+        #[arg(short, long, default_value = "openconnect-diagnostics.zip", value_hint = clap::ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// The server name to include the config of, defaults to the default server or all servers
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+
+    #[command(about = "Generate a systemd unit which keeps the connection to a server up")]
+    GenerateService {
+        /// The server name saved in local config file to connect to
+        name: String,
+
+        /// The path to the local config file
+        #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+        config_file: Option<PathBuf>,
+
+        /// The path of the unit file to write, prints it to stdout if not given
+        #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
 
     #[command(about = "Generate shell completion script")]
     GenComplete {
@@ -71,6 +442,68 @@ pub enum Commands {
     },
 }
 
+/// Options of `start` which change how the connection is made, shared with `show`
+#[derive(Args, Debug)]
+pub struct ConnectionArgs {
+    /// Warn when the server certificate expires within this many days
+    #[arg(long, default_value = "14")]
+    pub cert_expiry_warning_days: u32,
+
+    /// Replace the search domains pushed by the server, can be repeated
+    #[arg(long = "search-domain", value_name = "DOMAIN")]
+    pub search_domains: Vec<String>,
+
+    /// Keep retrying DTLS (UDP) instead of falling back to TLS when it is not established within 10 seconds
+    #[arg(long, default_value = "false")]
+    pub no_dtls_fallback: bool,
+
+    /// The platform reported to the gateway, overrides the value stored for the server
+    #[arg(long = "os", value_parser = clap::builder::PossibleValuesParser::new(SUPPORTED_REPORTED_OS.iter().copied()))]
+    pub reported_os: Option<String>,
+
+    /// The proxy to connect through, a URL, `auto` for the system proxy or `none`, overrides the value stored for the server
+    #[arg(long, value_name = "PROXY")]
+    pub proxy: Option<String>,
+
+    /// Connect with the options of a profile of the server, see `add --profile-option`
+    #[arg(long, value_name = "PROFILE")]
+    pub profile: Option<String>,
+
+    /// Reject gateways which negotiate an older TLS version for the control channel
+    #[arg(long, value_name = "VERSION")]
+    pub min_tls_version: Option<TlsVersion>,
+
+    /// Only accept these ciphers for the control channel, an OpenSSL style list like `AES256-GCM:CHACHA20-POLY1305`
+    #[arg(long, value_name = "CIPHERS")]
+    pub ciphers: Option<String>,
+
+    /// Extra header of the login requests as `Name: Value`, can be repeated. Only `User-Agent` is supported by libopenconnect
+    #[arg(long = "header", value_name = "HEADER", value_parser = parse_http_header)]
+    pub http_headers: Vec<(String, String)>,
+
+    /// Connect to the gateway over `ipv4` or `ipv6` only, independent of the addresses inside the tunnel
+    #[arg(long, value_name = "FAMILY")]
+    pub transport_family: Option<AddressFamily>,
+}
+
+impl From<ConnectionArgs> for StartOptions {
+    fn from(args: ConnectionArgs) -> Self {
+        StartOptions {
+            cert_expiry_warning_days: args.cert_expiry_warning_days,
+            search_domains: args.search_domains,
+            dtls_fallback: !args.no_dtls_fallback,
+            reported_os: args.reported_os,
+            proxy: args.proxy,
+            profile: args.profile,
+            min_tls_version: args.min_tls_version,
+            ciphers: args.ciphers,
+            http_headers: args.http_headers,
+            transport_family: args.transport_family,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum SeverConfigArgs {
     #[command(long_about = "Add an OIDC authentication VPN server")]
@@ -79,8 +512,8 @@ pub enum SeverConfigArgs {
         #[arg(short, long)]
         name: String,
 
-        /// The VPN server URL
-        #[arg(short, long, value_hint = clap::ValueHint::Url)]
+        /// The VPN server URL, IPv6 addresses with a port in brackets like [2001:db8::1]:443
+        #[arg(short, long, value_hint = clap::ValueHint::Url, value_parser = parse_server)]
         server: String,
 
         /// The OIDC issuer URL
@@ -92,12 +525,84 @@ pub enum SeverConfigArgs {
         client_id: String,
 
         /// The OIDC client secret
-        #[arg(short = 'k', long)]
+        #[arg(short = 'k', long, group = "client_secret_input")]
         client_secret: Option<String>,
 
+        /// Read the OIDC client secret from this environment variable at connect time, instead of storing it
+        #[arg(long, group = "client_secret_input")]
+        client_secret_env: Option<String>,
+
+        /// Read the OIDC client secret from this file at connect time, instead of storing it
+        #[arg(long, group = "client_secret_input", value_hint = clap::ValueHint::FilePath)]
+        client_secret_file: Option<PathBuf>,
+
         /// Allow insecure peer certificate verification
         #[arg(short, long, default_value = "false")]
         allow_insecure: Option<bool>,
+
+        /// Name of another saved server to connect first, this server is then reached through its tunnel
+        #[arg(long)]
+        connect_via: Option<String>,
+
+        /// The platform reported to the gateway instead of the actual OS
+        #[arg(long = "os", value_parser = clap::builder::PossibleValuesParser::new(SUPPORTED_REPORTED_OS.iter().copied()))]
+        reported_os: Option<String>,
+
+        /// The VPN protocol of the gateway instead of the default one: anyconnect, nc, gp, pulse, fortinet or array
+        #[arg(long, value_parser = parse_protocol)]
+        protocol: Option<String>,
+
+        /// The proxy to connect through, a URL like http://proxy:8080, `auto` for the system proxy or `none`
+        #[arg(long, value_name = "PROXY")]
+        proxy: Option<String>,
+
+        /// A host:port behind the tunnel to check with a TCP connect after connecting, e.g. an intranet server
+        #[arg(long, value_name = "HOST:PORT", value_parser = parse_probe_host)]
+        probe_host: Option<String>,
+
+        /// An http(s) URL behind the tunnel which has to answer before the connection counts as up, e.g. a health endpoint
+        #[arg(long, value_name = "URL", value_parser = parse_verify_url)]
+        verify_url: Option<String>,
+
+        /// The status the verify URL has to answer with instead of any 2xx, redirects are not followed
+        #[arg(long, value_name = "STATUS", requires = "verify_url", value_parser = clap::value_parser!(u16).range(100..600))]
+        verify_expect_status: Option<u16>,
+
+        /// Don't show desktop notifications of the GUI when the server connects or disconnects
+        #[arg(long)]
+        no_notify: bool,
+
+        /// A DNS suffix or host:port of a trusted network, the daemon stays disconnected while on it, can be repeated
+        #[arg(long = "trusted-network", value_name = "SUFFIX|HOST:PORT", value_parser = parse_trusted_network)]
+        trusted_networks: Vec<String>,
+
+        /// Enable or disable UDP (DTLS) on a network, like ssid:Office=off or interface:eth0=on, the first matching rule applies, can be repeated
+        #[arg(long = "udp-rule", value_name = "ssid:SSID|interface:NAME=on|off", value_parser = parse_udp_rule)]
+        udp_policy: Vec<String>,
+
+        /// Abort the connection if the gateway assigns an address outside of this network, e.g. 10.8.0.0/16
+        #[arg(long, value_name = "CIDR", value_parser = parse_subnet)]
+        expected_subnet: Option<String>,
+
+        /// Accept only the gateway certificate with this SHA-256 fingerprint, even if it fails verification, like `--servercert` of openconnect
+        #[arg(long = "servercert", value_name = "SHA256", value_parser = parse_cert_fingerprint)]
+        server_cert: Option<String>,
+
+        /// Create the tunnel device with this MTU instead of the one the gateway negotiates, e.g. when large packets get lost
+        #[arg(long, value_parser = clap::value_parser!(u32).range(576..=65535))]
+        mtu: Option<u32>,
+
+        /// An executable printing JSON overrides of the connection parameters, run by the daemon before every connect
+        #[arg(long, value_hint = clap::ValueHint::ExecutablePath, value_parser = parse_profile_script)]
+        profile_script: Option<PathBuf>,
+
+        /// Log out of the OIDC issuer with the ID token of the login when the connection is stopped
+        #[arg(long, default_value = "false")]
+        oidc_logout: bool,
+
+        /// An option of a profile selected with `start --profile`, like split:vpnc-script=/usr/local/bin/vpn-slice-script, can be repeated
+        #[arg(long = "profile-option", value_name = "PROFILE:KEY=VALUE", value_parser = parse_profile_option)]
+        profile_options: Vec<(String, String, String)>,
     },
 
     #[command(
@@ -108,20 +613,281 @@ pub enum SeverConfigArgs {
         #[arg(short, long)]
         name: String,
 
-        /// The VPN server URL
-        #[arg(short, long, value_hint = clap::ValueHint::Url)]
+        /// The VPN server URL, IPv6 addresses with a port in brackets like [2001:db8::1]:443
+        #[arg(short, long, value_hint = clap::ValueHint::Url, value_parser = parse_server)]
         server: String,
 
         /// The username for password authentication
         #[arg(short, long)]
         username: String,
 
+        /// Read the password from this environment variable at connect time, instead of prompting and storing it
+        #[arg(long, conflicts_with = "password_file")]
+        password_env: Option<String>,
+
+        /// Read the password from this file at connect time, instead of prompting and storing it
+        #[arg(long, value_hint = clap::ValueHint::FilePath)]
+        password_file: Option<PathBuf>,
+
         /// Allow insecure peer certificate verification
         #[arg(short, long, default_value = "false")]
         allow_insecure: Option<bool>,
+
+        /// Name of another saved server to connect first, this server is then reached through its tunnel
+        #[arg(long)]
+        connect_via: Option<String>,
+
+        /// The platform reported to the gateway instead of the actual OS
+        #[arg(long = "os", value_parser = clap::builder::PossibleValuesParser::new(SUPPORTED_REPORTED_OS.iter().copied()))]
+        reported_os: Option<String>,
+
+        /// The VPN protocol of the gateway instead of the default one: anyconnect, nc, gp, pulse, fortinet or array
+        #[arg(long, value_parser = parse_protocol)]
+        protocol: Option<String>,
+
+        /// The proxy to connect through, a URL like http://proxy:8080, `auto` for the system proxy or `none`
+        #[arg(long, value_name = "PROXY")]
+        proxy: Option<String>,
+
+        /// Log in through the gateway's web login (SAML) in a browser, no password is prompted
+        #[arg(long, default_value = "false", conflicts_with_all = ["password_env", "password_file"])]
+        web_auth: bool,
+
+        /// Answer a field of a custom login form, like domain=EMEA, a dropdown by the name or label of a choice, can be repeated
+        #[arg(long = "form-default", value_name = "FIELD=VALUE", value_parser = parse_form_default)]
+        form_defaults: Vec<(String, String)>,
+
+        /// A host:port behind the tunnel to check with a TCP connect after connecting, e.g. an intranet server
+        #[arg(long, value_name = "HOST:PORT", value_parser = parse_probe_host)]
+        probe_host: Option<String>,
+
+        /// An http(s) URL behind the tunnel which has to answer before the connection counts as up, e.g. a health endpoint
+        #[arg(long, value_name = "URL", value_parser = parse_verify_url)]
+        verify_url: Option<String>,
+
+        /// The status the verify URL has to answer with instead of any 2xx, redirects are not followed
+        #[arg(long, value_name = "STATUS", requires = "verify_url", value_parser = clap::value_parser!(u16).range(100..600))]
+        verify_expect_status: Option<u16>,
+
+        /// Don't show desktop notifications of the GUI when the server connects or disconnects
+        #[arg(long)]
+        no_notify: bool,
+
+        /// A DNS suffix or host:port of a trusted network, the daemon stays disconnected while on it, can be repeated
+        #[arg(long = "trusted-network", value_name = "SUFFIX|HOST:PORT", value_parser = parse_trusted_network)]
+        trusted_networks: Vec<String>,
+
+        /// Enable or disable UDP (DTLS) on a network, like ssid:Office=off or interface:eth0=on, the first matching rule applies, can be repeated
+        #[arg(long = "udp-rule", value_name = "ssid:SSID|interface:NAME=on|off", value_parser = parse_udp_rule)]
+        udp_policy: Vec<String>,
+
+        /// Abort the connection if the gateway assigns an address outside of this network, e.g. 10.8.0.0/16
+        #[arg(long, value_name = "CIDR", value_parser = parse_subnet)]
+        expected_subnet: Option<String>,
+
+        /// Accept only the gateway certificate with this SHA-256 fingerprint, even if it fails verification, like `--servercert` of openconnect
+        #[arg(long = "servercert", value_name = "SHA256", value_parser = parse_cert_fingerprint)]
+        server_cert: Option<String>,
+
+        /// Create the tunnel device with this MTU instead of the one the gateway negotiates, e.g. when large packets get lost
+        #[arg(long, value_parser = clap::value_parser!(u32).range(576..=65535))]
+        mtu: Option<u32>,
+
+        /// Authenticate with this client certificate, a PEM or PKCS#12 file or a pkcs11: URL
+        #[arg(long, value_hint = clap::ValueHint::FilePath, value_parser = parse_client_cert)]
+        client_cert: Option<PathBuf>,
+
+        /// The private key of the client certificate, if it is not in the certificate file
+        #[arg(long, value_hint = clap::ValueHint::FilePath, value_parser = parse_client_cert, requires = "client_cert")]
+        client_key: Option<PathBuf>,
+
+        /// Prompt for the password of an encrypted client certificate key, which is stored like the password
+        #[arg(long, requires = "client_cert")]
+        encrypted_key: bool,
+
+        /// An executable printing JSON overrides of the connection parameters, run by the daemon before every connect
+        #[arg(long, value_hint = clap::ValueHint::ExecutablePath, value_parser = parse_profile_script)]
+        profile_script: Option<PathBuf>,
+
+        /// An option of a profile selected with `start --profile`, like split:vpnc-script=/usr/local/bin/vpn-slice-script, can be repeated
+        #[arg(long = "profile-option", value_name = "PROFILE:KEY=VALUE", value_parser = parse_profile_option)]
+        profile_options: Vec<(String, String, String)>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    #[command(about = "Print a default, or all of them without a key")]
+    Get {
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(Defaults::KEYS))]
+        key: Option<String>,
+    },
+
+    #[command(about = "Set a default")]
+    Set {
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(Defaults::KEYS))]
+        key: String,
+
+        value: String,
+    },
+
+    #[command(about = "Reset a default to the built-in behavior")]
+    Unset {
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(Defaults::KEYS))]
+        key: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LogsAction {
+    #[command(
+        about = "Write the log of a single connection session to a file, e.g. for a support ticket"
+    )]
+    Export {
+        /// The server name saved in local config file
+        #[arg(short, long)]
+        name: String,
+
+        /// `latest` or the session id of the log file name, like 20240101-120000
+        #[arg(short, long, default_value = "latest")]
+        session: String,
+
+        /// The file to write the log to
+        #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+        output: PathBuf,
     },
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum KillSwitchAction {
+    /// Show whether the kill switch rules are installed
+    Status,
+
+    /// Remove the kill switch rules, e.g. after the daemon crashed
+    Clear,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Toggle {
+    On,
+    Off,
+}
+
+impl From<Toggle> for bool {
+    fn from(toggle: Toggle) -> bool {
+        matches!(toggle, Toggle::On)
+    }
+}
+
+/// Validate a trusted network, but keep it as given in the config file
+fn parse_trusted_network(value: &str) -> Result<String, String> {
+    value.parse::<TrustedNetwork>()?;
+    Ok(value.to_string())
+}
+
+fn parse_udp_rule(value: &str) -> Result<String, String> {
+    value.parse::<UdpRule>()?;
+    Ok(value.to_string())
+}
+
+fn parse_server(value: &str) -> Result<String, String> {
+    value.parse::<ServerAddress>()?;
+    Ok(value.to_string())
+}
+
+/// Store the name of libopenconnect, the protocol has to be supported by the linked one
+fn parse_protocol(value: &str) -> Result<String, String> {
+    let protocol = value.parse::<VpnProtocol>()?;
+    if protocol.protocol().is_none() {
+        return Err(format!(
+            "Protocol {} is not supported by the linked libopenconnect",
+            protocol
+        ));
+    }
+    Ok(protocol.name().to_string())
+}
+
+fn parse_subnet(value: &str) -> Result<String, String> {
+    value.parse::<Subnet>()?;
+    Ok(value.to_string())
+}
+
+fn parse_form_default(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((field, value)) if !field.is_empty() && !field.contains(char::is_whitespace) => {
+            Ok((field.to_string(), value.to_string()))
+        }
+        _ => Err(format!("Expected <FIELD>=<VALUE>, got {}", value)),
+    }
+}
+
+fn parse_profile_option(value: &str) -> Result<(String, String, String), String> {
+    let invalid = || {
+        format!(
+            "Expected <PROFILE>:<KEY>=<VALUE> with a key of {}, got {}",
+            ServerProfile::KEYS.join(", "),
+            value
+        )
+    };
+    let (profile, option) = value.split_once(':').ok_or_else(invalid)?;
+    let (key, option_value) = option.split_once('=').ok_or_else(invalid)?;
+    if profile.is_empty() || profile.contains(char::is_whitespace) {
+        return Err(invalid());
+    }
+    ServerProfile::default()
+        .set(key, option_value)
+        .map_err(|e| e.to_string())?;
+    Ok((
+        profile.to_string(),
+        key.to_string(),
+        option_value.to_string(),
+    ))
+}
+
+fn parse_script_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if is_valid_script_env_name(key) => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!(
+            "Expected <KEY>=<VALUE> with a key of ASCII letters, digits and underscores, got {}",
+            value
+        )),
+    }
+}
+
+/// The daemon only writes to the log directory, so just a file name is accepted
+fn parse_openconnect_log(value: &str) -> Result<PathBuf, String> {
+    paths::openconnect_log_file(Path::new(value)).map_err(|e| e.to_string())?;
+    Ok(PathBuf::from(value))
+}
+
+/// The daemon runs in another working directory, so the script is stored with an absolute path
+fn parse_profile_script(value: &str) -> Result<PathBuf, String> {
+    let script = std::fs::canonicalize(value)
+        .map_err(|e| format!("Profile script {} not found: {}", value, e))?;
+    if !script.is_file() {
+        return Err(format!("Profile script {} is not a file", script.display()));
+    }
+    Ok(script)
+}
+
+fn parse_client_cert(value: &str) -> Result<PathBuf, String> {
+    // a PKCS#11 token is looked up by libopenconnect
+    if value.starts_with("pkcs11:") {
+        return Ok(PathBuf::from(value));
+    }
+    let file = std::fs::canonicalize(value)
+        .map_err(|e| format!("Client certificate {} not found: {}", value, e))?;
+    if !file.is_file() {
+        return Err(format!(
+            "Client certificate {} is not a file",
+            file.display()
+        ));
+    }
+    Ok(file)
+}
+
 pub fn print_completions(generator: Shell, binary_name: Option<String>) {
     let mut cmd = Cli::command();
     let cmd = &mut cmd;
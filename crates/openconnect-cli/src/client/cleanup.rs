@@ -0,0 +1,89 @@
+use crate::tunnels::{self, TunnelRecord};
+use colored::Colorize;
+use openconnect_core::paths;
+
+/// Tear down the tunnels a crashed daemon left behind, after a confirmation of each unless `yes`
+pub fn request_cleanup(dry_run: bool, yes: bool) {
+    let records = tunnels::load().unwrap_or_else(|e| {
+        eprintln!(
+            "{}",
+            format!("\nFailed to read the recorded tunnels: {}", e).red()
+        );
+        std::process::exit(1);
+    });
+    let (orphaned, live): (Vec<TunnelRecord>, Vec<TunnelRecord>) =
+        records.into_iter().partition(TunnelRecord::is_orphaned);
+
+    if orphaned.is_empty() {
+        println!("No orphaned tunnels found");
+        return;
+    }
+
+    println!("Orphaned tunnels:");
+    for record in &orphaned {
+        println!(
+            "  {} of {} (daemon {}, gateway {})",
+            record.ifname,
+            record.server_name,
+            record.pid,
+            record.info.gateway_addr.as_deref().unwrap_or("unknown")
+        );
+    }
+    if dry_run {
+        println!("Run without --dry-run to run their vpnc-script for disconnect and remove them");
+        return;
+    }
+
+    // re-executes the command, which finds the same tunnels again
+    #[cfg(target_os = "macos")]
+    sudo::with_env(&[paths::HOME_ENV]).expect("Failed to escalate permissions");
+
+    #[cfg(target_os = "linux")]
+    sudo::with_env(&["HOME", paths::HOME_ENV]).expect("Failed to escalate permissions");
+
+    // the records of tunnels which are gone anyway are dropped as well
+    let mut kept: Vec<TunnelRecord> = live
+        .into_iter()
+        .filter(|record| tunnels::is_running(record.pid))
+        .collect();
+    let mut failed = false;
+    for record in orphaned {
+        if !yes {
+            let confirmed = dialoguer::Confirm::new()
+                .with_prompt(format!(
+                    "Tear down {} of {}?",
+                    record.ifname, record.server_name
+                ))
+                .default(false)
+                .interact()
+                .expect("Failed to get confirmation");
+            if !confirmed {
+                kept.push(record);
+                continue;
+            }
+        }
+
+        match record.tear_down() {
+            Ok(()) => println!("Removed {}", record.ifname),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!("\nFailed to tear down {}: {}", record.ifname, e).red()
+                );
+                failed = true;
+                kept.push(record);
+            }
+        }
+    }
+
+    if let Err(e) = tunnels::save(&kept) {
+        eprintln!(
+            "{}",
+            format!("\nFailed to save the recorded tunnels: {}", e).red()
+        );
+        std::process::exit(1);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -1,9 +1,18 @@
-use crate::cli::SeverConfigArgs;
+use crate::{cli::SeverConfigArgs, output};
 use base64::Engine;
 use colored::Colorize;
-use comfy_table::Table;
-use openconnect_core::storage::{OidcServer, PasswordServer, StoredConfigs, StoredServer};
-use std::path::PathBuf;
+use openconnect_core::{
+    cipher_key,
+    env_server::server_from_env,
+    probe::{host_and_port, UdpProbeResult},
+    secret::SecretSource,
+    storage::{
+        config_schema, Defaults, OidcServer, PasswordServer, ServerProfile, StoredConfigs,
+        StoredServer,
+    },
+    VpnClient,
+};
+use std::{collections::HashMap, path::PathBuf};
 
 pub async fn read_server_config_from_fs(
     server_name: &str,
@@ -34,6 +43,27 @@ pub async fn read_server_config_from_fs(
     }
 }
 
+/// The server to start, with `from_env` built from the environment instead of read from the config file
+pub async fn read_start_server(
+    server_name: &str,
+    config_file: PathBuf,
+    from_env: bool,
+) -> anyhow::Result<(StoredServer, StoredConfigs)> {
+    if !from_env {
+        return read_server_config_from_fs(server_name, config_file).await;
+    }
+
+    let (server, stored_configs) = server_from_env(server_name, |key| std::env::var(key).ok())?;
+    if let StoredServer::Password(PasswordServer { server, .. }) = &server {
+        println!(
+            "Connecting to password server from the environment: {}",
+            server_name
+        );
+        println!("Server host: {}", server);
+    }
+    Ok((server, stored_configs))
+}
+
 fn add_server_internal(stored_server: StoredServer) {
     let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
 
@@ -53,6 +83,15 @@ fn add_server_internal(stored_server: StoredServer) {
     });
 }
 
+/// Group the `--profile-option` values by profile, they were validated when parsed
+fn collect_profiles(options: Vec<(String, String, String)>) -> HashMap<String, ServerProfile> {
+    let mut profiles: HashMap<String, ServerProfile> = HashMap::new();
+    for (name, key, value) in options {
+        let _ = profiles.entry(name).or_default().set(&key, &value);
+    }
+    profiles
+}
+
 pub fn request_add_server(server_config: SeverConfigArgs) {
     let new_server = match server_config {
         SeverConfigArgs::Oidc {
@@ -61,8 +100,30 @@ pub fn request_add_server(server_config: SeverConfigArgs) {
             issuer,
             client_id,
             client_secret,
+            client_secret_env,
+            client_secret_file,
             allow_insecure,
+            connect_via,
+            reported_os,
+            protocol,
+            proxy,
+            probe_host,
+            verify_url,
+            verify_expect_status,
+            no_notify,
+            trusted_networks,
+            udp_policy,
+            expected_subnet,
+            server_cert,
+            mtu,
+            profile_script,
+            oidc_logout,
+            profile_options,
         } => {
+            let client_secret_source = client_secret_env
+                .map(SecretSource::Env)
+                .or(client_secret_file.map(SecretSource::File));
+
             let oidc_server = OidcServer {
                 name,
                 server,
@@ -71,6 +132,23 @@ pub fn request_add_server(server_config: SeverConfigArgs) {
                 client_secret,
                 allow_insecure,
                 updated_at: None,
+                client_secret_source,
+                connect_via,
+                reported_os,
+                protocol,
+                proxy,
+                probe_host,
+                verify_url,
+                verify_expect_status,
+                notify: no_notify.then_some(false),
+                trusted_networks,
+                udp_policy,
+                expected_subnet,
+                server_cert,
+                mtu,
+                profile_script,
+                oidc_logout,
+                profiles: collect_profiles(profile_options),
             };
 
             StoredServer::Oidc(oidc_server)
@@ -79,20 +157,84 @@ pub fn request_add_server(server_config: SeverConfigArgs) {
             name,
             server,
             username,
+            password_env,
+            password_file,
             allow_insecure,
+            connect_via,
+            reported_os,
+            protocol,
+            proxy,
+            web_auth,
+            form_defaults,
+            probe_host,
+            verify_url,
+            verify_expect_status,
+            no_notify,
+            trusted_networks,
+            udp_policy,
+            expected_subnet,
+            server_cert,
+            mtu,
+            client_cert,
+            client_key,
+            encrypted_key,
+            profile_script,
+            profile_options,
         } => {
-            let password = dialoguer::Password::new()
-                .with_prompt("Enter password")
-                .interact()
-                .expect("Failed to get password");
+            let password_source = password_env
+                .map(SecretSource::Env)
+                .or(password_file.map(SecretSource::File));
+
+            // the web login asks for the credentials itself, a gateway may not ask for more than the client certificate
+            let password = if password_source.is_none() && !web_auth {
+                Some(
+                    dialoguer::Password::new()
+                        .with_prompt("Enter password")
+                        .allow_empty_password(client_cert.is_some())
+                        .interact()
+                        .expect("Failed to get password"),
+                )
+                .filter(|password| !password.is_empty())
+            } else {
+                None
+            };
+
+            let key_password = encrypted_key.then(|| {
+                dialoguer::Password::new()
+                    .with_prompt("Enter client certificate key password")
+                    .interact()
+                    .expect("Failed to get key password")
+            });
 
             let password_server = PasswordServer {
                 name,
                 server,
                 username,
-                password: Some(password),
+                password,
                 allow_insecure,
                 updated_at: None,
+                auth_group: None,
+                password_source,
+                connect_via,
+                reported_os,
+                protocol,
+                proxy,
+                web_auth,
+                form_defaults: form_defaults.into_iter().collect(),
+                probe_host,
+                verify_url,
+                verify_expect_status,
+                notify: no_notify.then_some(false),
+                trusted_networks,
+                udp_policy,
+                expected_subnet,
+                server_cert,
+                mtu,
+                client_cert,
+                client_key,
+                key_password,
+                profile_script,
+                profiles: collect_profiles(profile_options),
             };
 
             StoredServer::Password(password_server)
@@ -102,7 +244,48 @@ pub fn request_add_server(server_config: SeverConfigArgs) {
     add_server_internal(new_server);
 }
 
-pub fn request_delete_server(name: &str) {
+/// The servers `delete` removes
+pub enum DeleteSelection {
+    Name(String),
+    All,
+
+    /// Names matching a glob with `*` and `?`
+    Match(String),
+}
+
+/// Match a name against a glob, `*` matches any run of characters and `?` a single one
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // the position of the last `*` and the name position it currently covers up to
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, covered)) => {
+                    p = star + 1;
+                    n = covered + 1;
+                    backtrack = Some((star, covered + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn request_delete_servers(selection: DeleteSelection, dry_run: bool, yes: bool) {
     let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
 
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
@@ -114,10 +297,63 @@ pub fn request_delete_server(name: &str) {
             .await
             .expect("Failed to read config file");
 
-        stored_configs
-            .remove_server(name)
-            .await
-            .expect("Failed to delete server");
+        let bulk = !matches!(selection, DeleteSelection::Name(_));
+        let mut names: Vec<String> = match selection {
+            DeleteSelection::Name(name) => vec![name],
+            DeleteSelection::All => stored_configs.servers.keys().cloned().collect(),
+            DeleteSelection::Match(pattern) => stored_configs
+                .servers
+                .keys()
+                .filter(|name| glob_match(&pattern, name))
+                .cloned()
+                .collect(),
+        };
+        names.sort();
+
+        // a bulk delete leaves the default server alone instead of failing
+        if bulk {
+            if let Some(default) = stored_configs.default.as_ref() {
+                if names.contains(default) {
+                    println!("Keeping default server {}", default);
+                    names.retain(|name| name != default);
+                }
+            }
+        }
+
+        if names.is_empty() {
+            println!("No servers to delete");
+            return;
+        }
+
+        if dry_run || bulk {
+            println!("Servers to delete:");
+            for name in &names {
+                println!("  {}", name);
+            }
+        }
+        if dry_run {
+            return;
+        }
+
+        if bulk && !yes {
+            let confirmed = dialoguer::Confirm::new()
+                .with_prompt(format!("Delete {} servers?", names.len()))
+                .default(false)
+                .interact()
+                .expect("Failed to get confirmation");
+            if !confirmed {
+                println!("Aborted");
+                return;
+            }
+        }
+
+        if let Err(e) = stored_configs.remove_servers(&names).await {
+            eprintln!("{}", format!("\nFailed to delete servers: {}", e).red());
+            std::process::exit(1);
+        }
+        if bulk {
+            println!("Deleted {} servers", names.len());
+        }
     });
 }
 
@@ -129,50 +365,180 @@ pub fn request_list_servers() {
         let mut stored_configs = StoredConfigs::new(None, config_file);
 
         let stored_configs = stored_configs.read_from_file().await.unwrap();
-        let mut table = Table::new();
-        table.set_header(vec![
-            "Name".to_string(),
-            "Type".to_string(),
-            "Server".to_string(),
-            "Allow Insecure".to_string(),
-            "Updated At".to_string(),
-        ]);
-
-        for (name, server) in stored_configs.servers.iter() {
-            match server {
-                StoredServer::Oidc(OidcServer {
-                    server,
-                    allow_insecure,
-                    updated_at,
-                    ..
-                }) => {
-                    table.add_row(vec![
-                        name.clone(),
-                        "OIDC Server".to_string(),
-                        server.clone(),
-                        allow_insecure.unwrap_or(false).to_string(),
-                        updated_at.as_ref().unwrap_or(&"".to_string()).to_owned(),
-                    ]);
-                }
-                StoredServer::Password(PasswordServer {
-                    server,
-                    allow_insecure,
-                    updated_at,
-                    ..
-                }) => {
-                    table.add_row(vec![
-                        name.clone(),
-                        "Password Server".to_string(),
-                        server.clone(),
-                        allow_insecure.unwrap_or(false).to_string(),
-                        updated_at.as_ref().unwrap_or(&"".to_string()).to_owned(),
-                    ]);
+        print_servers(stored_configs);
+    });
+}
+
+/// Print a record per stored server
+pub fn print_servers(stored_configs: &StoredConfigs) {
+    let header = vec![
+        "Name".to_string(),
+        "Type".to_string(),
+        "Server".to_string(),
+        "Allow Insecure".to_string(),
+        "Profiles".to_string(),
+        "Updated At".to_string(),
+    ];
+
+    let mut records = vec![];
+    for (name, stored_server) in stored_configs.servers.iter() {
+        let profiles = stored_server.profile_names().join(", ");
+        match stored_server {
+            StoredServer::Oidc(OidcServer {
+                server,
+                allow_insecure,
+                updated_at,
+                ..
+            }) => {
+                records.push(vec![
+                    name.clone(),
+                    "OIDC Server".to_string(),
+                    server.clone(),
+                    allow_insecure.unwrap_or(false).to_string(),
+                    profiles,
+                    updated_at.as_ref().unwrap_or(&"".to_string()).to_owned(),
+                ]);
+            }
+            StoredServer::Password(PasswordServer {
+                server,
+                allow_insecure,
+                updated_at,
+                ..
+            }) => {
+                records.push(vec![
+                    name.clone(),
+                    "Password Server".to_string(),
+                    server.clone(),
+                    allow_insecure.unwrap_or(false).to_string(),
+                    profiles,
+                    updated_at.as_ref().unwrap_or(&"".to_string()).to_owned(),
+                ]);
+            }
+        }
+    }
+
+    output::print_records(header, records);
+}
+
+pub fn request_print_schema() {
+    let schema =
+        serde_json::to_string_pretty(&config_schema()).expect("Failed to serialize schema");
+    println!("{}", schema);
+}
+
+/// Print a default, or a table of all of them
+pub fn request_get_defaults(key: Option<&str>) {
+    let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    runtime.block_on(async {
+        let mut stored_configs = StoredConfigs::new(None, config_file);
+        let stored_configs = stored_configs
+            .read_from_file()
+            .await
+            .expect("Failed to read config file");
+        let defaults = &stored_configs.defaults;
+
+        if let Some(key) = key {
+            match defaults.get(key) {
+                Ok(value) => println!("{}", value.unwrap_or_default()),
+                Err(e) => {
+                    eprintln!("{}", format!("\n{}", e).red());
+                    std::process::exit(1);
                 }
             }
+            return;
+        }
+
+        let records = Defaults::KEYS
+            .iter()
+            .map(|key| {
+                let value = defaults.get(key).ok().flatten();
+                vec![key.to_string(), value.unwrap_or("built-in".to_string())]
+            })
+            .collect();
+        output::print_records(vec!["Key".to_string(), "Value".to_string()], records);
+    });
+}
+
+/// Set a default, or reset it with `None`
+pub fn request_set_default(key: &str, value: Option<&str>) {
+    let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    runtime.block_on(async {
+        let mut stored_configs = StoredConfigs::new(None, config_file);
+        stored_configs
+            .read_from_file()
+            .await
+            .expect("Failed to read config file");
+
+        if let Err(e) = stored_configs.set_defaults_value(key, value).await {
+            eprintln!("{}", format!("\nFailed to set {}: {}", key, e).red());
+            std::process::exit(1);
         }
 
-        println!("{table}");
+        match value {
+            Some(value) => println!("Set {} to {}", key, value),
+            None => println!("Reset {}", key),
+        }
+    });
+}
+
+pub fn request_probe_server(server_name: &str, port: Option<u16>) {
+    let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let server = runtime.block_on(async {
+        let mut stored_configs = StoredConfigs::new(None, config_file);
+
+        stored_configs
+            .read_from_file()
+            .await
+            .expect("Failed to read config file");
+
+        match stored_configs.servers.get(server_name) {
+            Some(StoredServer::Oidc(OidcServer { server, .. })) => server.clone(),
+            Some(StoredServer::Password(PasswordServer { server, .. })) => server.clone(),
+            None => {
+                eprintln!("{}", format!("\nServer {} not found", server_name).red());
+                std::process::exit(1);
+            }
+        }
     });
+
+    let Some((host, server_port)) = host_and_port(&server) else {
+        eprintln!("{}", format!("\nInvalid server URL: {}", server).red());
+        std::process::exit(1);
+    };
+    let port = port.unwrap_or(server_port);
+
+    println!("Probing UDP (DTLS) on {}:{}...", host, port);
+    let result = VpnClient::probe_udp(&host, port);
+    println!("UDP: {}", result);
+
+    match result {
+        UdpProbeResult::Reachable => {
+            println!("{}", "DTLS should work on this network".green());
+        }
+        UdpProbeResult::Closed => {
+            println!(
+                "{}",
+                "UDP looks blocked, consider disabling UDP for this server to avoid waiting for a failed DTLS negotiation"
+                    .yellow()
+            );
+        }
+        UdpProbeResult::NoResponse => {
+            println!(
+                "{}",
+                "Inconclusive, many gateways ignore unsolicited DTLS handshakes. If connections are slow, try disabling UDP"
+                    .yellow()
+            );
+        }
+        UdpProbeResult::Error(_) => {
+            std::process::exit(1);
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -244,6 +610,63 @@ pub fn request_export_server(server_name: &str) {
     });
 }
 
+/// Print the key of the saved passwords wrapped under a passphrase, see [cipher_key]
+pub fn request_key_export() {
+    eprintln!(
+        "{}",
+        "Anyone with the exported key and its passphrase can decrypt the passwords of your config file. Transfer it over a secure channel and choose a strong passphrase."
+            .yellow()
+    );
+    let passphrase = dialoguer::Password::new()
+        .with_prompt("Enter passphrase")
+        .with_confirmation("Repeat passphrase", "Passphrases don't match")
+        .interact()
+        .expect("Failed to get passphrase");
+
+    match cipher_key::wrap(&cipher_key::current_seed(), &passphrase) {
+        Ok(wrapped) => println!("{}", wrapped),
+        Err(e) => {
+            eprintln!("{}", format!("\nFailed to export key: {}", e).red());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replace the key of the saved passwords with one exported on another machine
+pub fn request_key_import(wrapped: &str, yes: bool) {
+    let passphrase = dialoguer::Password::new()
+        .with_prompt("Enter passphrase")
+        .interact()
+        .expect("Failed to get passphrase");
+
+    let seed = cipher_key::unwrap(wrapped, &passphrase).unwrap_or_else(|e| {
+        eprintln!("{}", format!("\nFailed to import key: {}", e).red());
+        std::process::exit(1);
+    });
+
+    if !yes {
+        let prompt = if cipher_key::is_imported() {
+            "Replace the key imported before? Passwords saved with it can't be decrypted anymore"
+        } else {
+            "Passwords saved on this machine before can't be decrypted with the imported key, continue?"
+        };
+        let confirmed = dialoguer::Confirm::new()
+            .with_prompt(prompt)
+            .default(false)
+            .interact()
+            .expect("Failed to get confirmation");
+        if !confirmed {
+            return;
+        }
+    }
+
+    if let Err(e) = cipher_key::install(&seed) {
+        eprintln!("{}", format!("\nFailed to import key: {}", e).red());
+        std::process::exit(1);
+    }
+    println!("Key imported, the passwords of a config file copied from the exporting machine can be decrypted now");
+}
+
 pub fn request_import_server(base64: &str) {
     let decoded = base64::prelude::BASE64_STANDARD
         .decode(base64.as_bytes())
@@ -289,6 +712,28 @@ pub fn request_import_server(base64: &str) {
                 password: Some(password),
                 allow_insecure,
                 updated_at: None,
+                auth_group: None,
+                password_source: None,
+                connect_via: None,
+                reported_os: None,
+                protocol: None,
+                proxy: None,
+                probe_host: None,
+                verify_url: None,
+                verify_expect_status: None,
+                notify: None,
+                trusted_networks: vec![],
+                udp_policy: vec![],
+                expected_subnet: None,
+                server_cert: None,
+                mtu: None,
+                client_cert: None,
+                client_key: None,
+                key_password: None,
+                profile_script: None,
+                web_auth: false,
+                form_defaults: HashMap::new(),
+                profiles: HashMap::new(),
             })
         }
         SharableServer::Oidc {
@@ -315,6 +760,23 @@ pub fn request_import_server(base64: &str) {
                 client_secret,
                 allow_insecure,
                 updated_at: None,
+                client_secret_source: None,
+                connect_via: None,
+                reported_os: None,
+                protocol: None,
+                proxy: None,
+                probe_host: None,
+                verify_url: None,
+                verify_expect_status: None,
+                notify: None,
+                trusted_networks: vec![],
+                udp_policy: vec![],
+                expected_subnet: None,
+                server_cert: None,
+                mtu: None,
+                profile_script: None,
+                oidc_logout: false,
+                profiles: HashMap::new(),
             })
         }
     };
@@ -336,3 +798,14 @@ fn test_import_server() {
     let base64 = base64::prelude::BASE64_STANDARD.encode(json.as_bytes());
     request_import_server(&base64);
 }
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("test-*", "test-eu"));
+    assert!(glob_match("*-eu", "corp-eu"));
+    assert!(glob_match("corp-??", "corp-eu"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("a*b*c", "aXXbYYc"));
+    assert!(!glob_match("test-*", "corp-test-eu"));
+    assert!(!glob_match("corp-?", "corp-eu"));
+}
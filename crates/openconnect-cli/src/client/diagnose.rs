@@ -0,0 +1,235 @@
+use crate::{client::state::is_executable, sock, JsonRequest, JsonResponse};
+use colored::Colorize;
+use futures::TryStreamExt;
+use openconnect_core::{
+    log::Logger,
+    paths,
+    storage::{StoredConfigError, StoredConfigs, StoredServer},
+};
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+const REDACTED: &str = "<redacted>";
+
+/// Config keys holding secrets, their values are replaced in the bundle
+const SECRET_KEYS: &[&str] = &["password", "clientSecret", "cookie", "sslKey", "clientKey"];
+
+/// Log lines mentioning one of these are cut off after the keyword
+const SECRET_LOG_KEYWORDS: &[&str] = &["cookie", "password", "secret", "token", "key"];
+
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiagnoseError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to write bundle: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Failed to read config: {0}")]
+    Config(#[from] StoredConfigError),
+
+    #[error("Failed to serialize: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Server {0} not found")]
+    ServerNotFound(String),
+}
+
+/// Replace the values of secret keys anywhere in the value
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    if !value.is_null() {
+                        *value = Value::String(REDACTED.to_string());
+                    }
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn scrub_log_line(line: &str) -> String {
+    let lowercase = line.to_lowercase();
+    let cut = SECRET_LOG_KEYWORDS
+        .iter()
+        .filter_map(|keyword| lowercase.find(keyword).map(|pos| pos + keyword.len()))
+        .min();
+
+    match cut {
+        // lowercasing only keeps the byte offsets of ASCII text, redact the whole line otherwise
+        Some(cut) if line.is_ascii() => format!("{} {}", &line[..cut], REDACTED),
+        Some(_) => REDACTED.to_string(),
+        None => line.to_string(),
+    }
+}
+
+/// The server to diagnose together with the server it connects via, or all servers if none is given or set as default
+fn relevant_servers<'a>(
+    stored_configs: &'a StoredConfigs,
+    name: Option<&str>,
+) -> Result<Vec<&'a StoredServer>, DiagnoseError> {
+    let server = match name {
+        Some(name) => Some(
+            stored_configs
+                .servers
+                .get(name)
+                .ok_or_else(|| DiagnoseError::ServerNotFound(name.to_string()))?,
+        ),
+        None => stored_configs.default_server(),
+    };
+
+    Ok(match server {
+        Some(server) => std::iter::once(server)
+            .chain(
+                server
+                    .connect_via()
+                    .and_then(|via| stored_configs.servers.get(via)),
+            )
+            .collect(),
+        None => stored_configs.servers.values().collect(),
+    })
+}
+
+fn about() -> String {
+    let vpnc_script = paths::vpnc_script()
+        .map(|path| {
+            format!(
+                "{} (exists: {}, executable: {})",
+                path.display(),
+                path.exists(),
+                is_executable(&path).unwrap_or(false)
+            )
+        })
+        .unwrap_or_else(|e| e.to_string());
+    let config_file = paths::config_file()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|e| e.to_string());
+
+    [
+        format!("openconnect-rs: {}", env!("CARGO_PKG_VERSION")),
+        format!(
+            "libopenconnect: {}",
+            openconnect_core::openconnect_version().unwrap_or_default()
+        ),
+        format!(
+            "platform: {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+        format!("config file: {}", config_file),
+        format!("vpnc-script: {}", vpnc_script),
+        format!("log dir: {}", Logger::get_log_path().display()),
+        format!("socket: {} (exists: {})", sock::address(), sock::exists()),
+        format!("pid file: {}", paths::pid_file().display()),
+    ]
+    .join("\n")
+        + "\n"
+}
+
+async fn status() -> String {
+    let query = async {
+        let mut client = sock::DaemonClient::connect().await.ok()?;
+        client.send(JsonRequest::Info).await.ok()?;
+        client.framed_reader.try_next().await.ok()?
+    };
+
+    match tokio::time::timeout(STATUS_TIMEOUT, query).await {
+        Ok(Some(response @ JsonResponse::InfoResult { .. })) => {
+            serde_json::to_string_pretty(&response).unwrap_or_else(|e| e.to_string())
+        }
+        _ => "The daemon is not running or did not respond".to_string(),
+    }
+}
+
+async fn write_bundle(output: &Path, name: Option<&str>) -> Result<(), DiagnoseError> {
+    let mut stored_configs = StoredConfigs::new(None, StoredConfigs::getorinit_config_file()?);
+    stored_configs.read_from_file().await?;
+
+    let mut servers = serde_json::to_value(relevant_servers(&stored_configs, name)?)?;
+    redact(&mut servers);
+
+    let mut zip = ZipWriter::new(std::fs::File::create(output)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("about.txt", options)?;
+    zip.write_all(about().as_bytes())?;
+
+    zip.start_file("config.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(&servers)?.as_bytes())?;
+
+    zip.start_file("status.json", options)?;
+    zip.write_all(status().await.as_bytes())?;
+
+    let log = Logger::list_logs(name)
+        .into_iter()
+        .next()
+        .or_else(|| Logger::list_logs(None).into_iter().next());
+    if let Some(log) = log {
+        let file_name = log.file_name().unwrap_or_default().to_string_lossy();
+        zip.start_file(format!("logs/{}", file_name), options)?;
+        for line in std::fs::read_to_string(&log)?.lines() {
+            writeln!(zip, "{}", scrub_log_line(line))?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn request_diagnose(output: PathBuf, name: Option<String>) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    match runtime.block_on(write_bundle(&output, name.as_deref())) {
+        Ok(()) => {
+            println!("Diagnostic bundle written to {}", output.display());
+            println!("Secrets are redacted, still review the bundle before sharing it");
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!("\nFailed to create diagnostic bundle: {}", e).red()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn test_redact() {
+    let mut value = serde_json::json!([{
+        "name": "corp",
+        "password": "hunter2",
+        "clientSecret": null,
+        "passwordSource": { "type": "env", "value": "VPN_PASSWORD" },
+        "nested": { "cookie": "webvpn=abc" },
+    }]);
+    redact(&mut value);
+
+    assert_eq!(value[0]["name"], "corp");
+    assert_eq!(value[0]["password"], REDACTED);
+    assert!(value[0]["clientSecret"].is_null());
+    assert_eq!(value[0]["passwordSource"]["value"], "VPN_PASSWORD");
+    assert_eq!(value[0]["nested"]["cookie"], REDACTED);
+}
+
+#[test]
+fn test_scrub_log_line() {
+    assert_eq!(
+        scrub_log_line("INFO obtained cookie webvpn=abc"),
+        "INFO obtained cookie <redacted>"
+    );
+    assert_eq!(scrub_log_line("INFO connected"), "INFO connected");
+}
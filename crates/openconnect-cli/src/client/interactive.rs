@@ -0,0 +1,204 @@
+//! A prompt to manage connections without typing the binary for every command
+//!
+//! The stored servers are loaded once, for `list` and the completion of server names. Other lines are run as CLI
+//! commands in a child process, since the command handlers exit the process on errors and `start` re-executes
+//! itself with sudo.
+
+use crate::{
+    cli::{Cli, Commands},
+    client::config::print_servers,
+};
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
+use openconnect_core::{paths, storage::StoredConfigs};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    history::DefaultHistory,
+    Context, Editor, Helper, Highlighter, Hinter, Validator,
+};
+use std::path::Path;
+
+const PROMPT: &str = "openconnect> ";
+
+/// Lines handled by the prompt itself instead of a child process
+const BUILTINS: [&str; 4] = ["help", "reload", "exit", "quit"];
+
+/// Commands changing the stored servers, which are loaded again after them
+const CONFIG_COMMANDS: [&str; 3] = ["add", "import", "delete"];
+
+/// Completes the command as the first word and a server name after it
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct LineHelper {
+    commands: Vec<String>,
+    servers: Vec<String>,
+}
+
+impl Completer for LineHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let candidates = if line[..start].trim().is_empty() {
+            &self.commands
+        } else {
+            &self.servers
+        };
+
+        let pairs = candidates
+            .iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate.clone(),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+fn load_configs(config_file: &Path) -> anyhow::Result<StoredConfigs> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut stored_configs = StoredConfigs::new(None, config_file.to_path_buf());
+    let stored_configs = runtime.block_on(stored_configs.read_from_file())?.clone();
+    Ok(stored_configs)
+}
+
+fn server_names(stored_configs: &StoredConfigs) -> Vec<String> {
+    let mut names: Vec<String> = stored_configs.servers.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Load the config file again and complete the server names of it
+fn reload(
+    config_file: &Path,
+    editor: &mut Editor<LineHelper, DefaultHistory>,
+) -> anyhow::Result<StoredConfigs> {
+    let stored_configs = load_configs(config_file)?;
+    if let Some(helper) = editor.helper_mut() {
+        helper.servers = server_names(&stored_configs);
+    }
+    Ok(stored_configs)
+}
+
+/// The server a `start` line connects to, if the line is one
+fn start_target(args: &[&str]) -> Option<String> {
+    let cli = Cli::try_parse_from(std::iter::once("openconnect").chain(args.iter().copied()));
+    match cli.ok()?.command {
+        Commands::Start { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+/// Run a CLI command in a child process with the global options of the prompt
+fn run_command(args: &[&str], no_table: bool) {
+    let Ok(exe) = std::env::current_exe() else {
+        eprintln!("{}", "Failed to find the openconnect binary".red());
+        return;
+    };
+
+    let mut command = std::process::Command::new(exe);
+    command.args(args);
+    if no_table {
+        command.arg("--no-table");
+    }
+    // the child does not see the --config-dir of the prompt
+    if let Some(config_dir) = paths::overridden_dir() {
+        command.env(paths::HOME_ENV, config_dir);
+    }
+
+    match command.status() {
+        Ok(status) if !status.success() => {
+            eprintln!("{}", format!("Command exited with {}", status).red());
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("{}", format!("Failed to run command: {}", e).red()),
+    }
+}
+
+fn print_help() {
+    println!("Commands of the CLI without the `openconnect` prefix, e.g. `list`, `start <NAME>`, `status` or `stop`");
+    println!("  help     print this help, `<COMMAND> --help` prints the help of a command");
+    println!("  reload   read the config file again, e.g. after changing it in another terminal");
+    println!("  exit     leave the prompt, the connection keeps running");
+}
+
+pub fn run_interactive(no_table: bool) {
+    let config_file = StoredConfigs::getorinit_config_file().expect("Failed to get config file");
+    let mut stored_configs = match load_configs(&config_file) {
+        Ok(stored_configs) => stored_configs,
+        Err(e) => {
+            eprintln!("{}", format!("\nFailed to read config file: {}", e).red());
+            std::process::exit(1);
+        }
+    };
+
+    let commands = Cli::command()
+        .get_subcommands()
+        .filter(|command| command.get_name() != "interactive")
+        .map(|command| command.get_name().to_string())
+        .chain(BUILTINS.iter().map(|builtin| builtin.to_string()))
+        .collect();
+    let mut editor: Editor<LineHelper, DefaultHistory> =
+        Editor::new().expect("Failed to create line editor");
+    editor.set_helper(Some(LineHelper {
+        commands,
+        servers: server_names(&stored_configs),
+    }));
+
+    println!("Using Config file: {:?}, type `help` for help", config_file);
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", format!("Failed to read line: {}", e).red());
+                break;
+            }
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = args.first() else {
+            continue;
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        match *command {
+            "exit" | "quit" => break,
+            "help" => print_help(),
+            "list" if args.len() == 1 => print_servers(&stored_configs),
+            "reload" => match reload(&config_file, &mut editor) {
+                Ok(reloaded) => {
+                    stored_configs = reloaded;
+                    println!("Loaded {} servers", stored_configs.servers.len());
+                }
+                Err(e) => eprintln!("{}", format!("Failed to read config file: {}", e).red()),
+            },
+            "interactive" => eprintln!("{}", "Already in interactive mode".red()),
+            _ => {
+                // fail early instead of spawning a login for a typo
+                if let Some(name) = start_target(&args) {
+                    if !stored_configs.servers.contains_key(&name) {
+                        eprintln!("{}", format!("Server {} not found", name).red());
+                        continue;
+                    }
+                }
+                run_command(&args, no_table);
+
+                if CONFIG_COMMANDS.contains(command) {
+                    if let Ok(reloaded) = reload(&config_file, &mut editor) {
+                        stored_configs = reloaded;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,2 +1,8 @@
+pub(crate) mod cleanup;
 pub(crate) mod config;
+pub(crate) mod diagnose;
+pub(crate) mod interactive;
+pub(crate) mod run;
+pub(crate) mod service;
+pub(crate) mod show;
 pub(crate) mod state;
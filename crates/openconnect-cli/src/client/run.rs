@@ -0,0 +1,213 @@
+//! A connection for the lifetime of a command, e.g. a CI job which needs the VPN
+//!
+//! The connection is started by `start --wait` in a child process, since it re-executes itself with sudo and detaches
+//! the daemon. The command runs while the daemon keeps the tunnel up, and the connection is stopped when it exits.
+//! `--max-reconnects` and `--max-duration` bound how long a flapping tunnel is kept alive for the command.
+
+use crate::{client::state::poll_status, sock};
+use colored::Colorize;
+use openconnect_core::{paths, Status};
+use std::{
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+
+/// The exit code when a limit was exceeded or the daemon stopped, distinct from the codes of most commands
+pub const LIMIT_EXCEEDED_EXIT_CODE: i32 = 75;
+
+/// The exit code when the command could not be run, like a shell does
+const COMMAND_NOT_RUN_EXIT_CODE: i32 = 127;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How much recovery of the tunnel a command may wait for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// Drops of the tunnel after which the command is killed
+    pub max_reconnects: Option<u32>,
+
+    /// Time after the connection was established after which the command is killed
+    pub max_duration: Option<Duration>,
+}
+
+impl RunLimits {
+    /// Why the command has to be killed, `None` while it may keep running
+    fn exceeded(&self, drops: u32, elapsed: Duration) -> Option<String> {
+        if let Some(max_reconnects) = self.max_reconnects.filter(|max| drops > *max) {
+            return Some(format!(
+                "The tunnel dropped {} times, more than --max-reconnects {}",
+                drops, max_reconnects
+            ));
+        }
+        if let Some(max_duration) = self.max_duration.filter(|max| elapsed > *max) {
+            return Some(format!(
+                "The command ran longer than --max-duration {}s",
+                max_duration.as_secs()
+            ));
+        }
+        None
+    }
+}
+
+/// Counts the drops of the tunnel, each status after `Connected` which is not `Connected` again
+#[derive(Debug)]
+struct DropCounter {
+    connected: bool,
+    drops: u32,
+}
+
+impl DropCounter {
+    /// The connection is established before the command starts
+    fn connected() -> Self {
+        DropCounter {
+            connected: true,
+            drops: 0,
+        }
+    }
+
+    /// A daemon which does not answer in time is skipped, it is busy rather than disconnected
+    fn observe(&mut self, status: Option<&Status>) {
+        let Some(status) = status else {
+            return;
+        };
+        let connected = *status == Status::Connected;
+        if self.connected && !connected {
+            self.drops += 1;
+        }
+        self.connected = connected;
+    }
+}
+
+fn openconnect_command() -> Command {
+    let exe = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!(
+            "{}",
+            format!("\nFailed to find the openconnect binary: {}", e).red()
+        );
+        std::process::exit(1);
+    });
+    let mut command = Command::new(exe);
+    // the child does not see the --config-dir of this process
+    if let Some(config_dir) = paths::overridden_dir() {
+        command.env(paths::HOME_ENV, config_dir);
+    }
+    command
+}
+
+fn stop_connection() {
+    let status = openconnect_command()
+        .arg("stop")
+        .arg(format!("--timeout={}", STOP_TIMEOUT.as_secs()))
+        .status();
+    if !matches!(status, Ok(status) if status.success()) {
+        eprintln!("{}", "\nFailed to stop the connection, run `stop`".red());
+    }
+}
+
+fn kill_command(child: &mut Child, reason: &str) -> ! {
+    eprintln!("{}", format!("\n{}, killing the command", reason).red());
+    let _ = child.kill();
+    let _ = child.wait();
+    stop_connection();
+    std::process::exit(LIMIT_EXCEEDED_EXIT_CODE);
+}
+
+pub fn request_run(
+    name: String,
+    config_file: Option<String>,
+    wait: u64,
+    limits: RunLimits,
+    command: Vec<String>,
+) {
+    let mut start = openconnect_command();
+    start
+        .arg("start")
+        .arg(&name)
+        .arg(format!("--wait={}", wait));
+    if let Some(config_file) = config_file {
+        start.arg("--config-file").arg(config_file);
+    }
+    match start.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!("\nFailed to start the connection: {}", e).red()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let started = Instant::now();
+    let mut child = match Command::new(&command[0]).args(&command[1..]).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("{}", format!("\nFailed to run {}: {}", command[0], e).red());
+            stop_connection();
+            std::process::exit(COMMAND_NOT_RUN_EXIT_CODE);
+        }
+    };
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let mut drops = DropCounter::connected();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                stop_connection();
+                std::process::exit(status.code().unwrap_or(1));
+            }
+            Ok(None) => {}
+            Err(e) => kill_command(
+                &mut child,
+                &format!("Failed to wait for the command: {}", e),
+            ),
+        }
+
+        if !sock::exists() {
+            kill_command(&mut child, "The daemon stopped");
+        }
+        drops.observe(
+            runtime
+                .block_on(poll_status())
+                .map(|status| status.status)
+                .as_ref(),
+        );
+        if let Some(reason) = limits.exceeded(drops.drops, started.elapsed()) {
+            kill_command(&mut child, &reason);
+        }
+
+        std::thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+#[test]
+fn test_run_limits() {
+    let mut drops = DropCounter::connected();
+    for status in [
+        Some(Status::Connected),
+        Some(Status::Connecting("Connecting".to_string())),
+        None,
+        Some(Status::Connected),
+        Some(Status::Error(
+            openconnect_core::result::OpenconnectError::SetCookieError(-1),
+        )),
+        Some(Status::Disconnected),
+    ] {
+        drops.observe(status.as_ref());
+    }
+    assert_eq!(drops.drops, 2);
+
+    let limits = RunLimits {
+        max_reconnects: Some(2),
+        max_duration: Some(Duration::from_secs(60)),
+    };
+    assert!(limits.exceeded(2, Duration::from_secs(59)).is_none());
+    assert!(limits.exceeded(3, Duration::from_secs(1)).is_some());
+    assert!(limits.exceeded(0, Duration::from_secs(61)).is_some());
+    assert!(RunLimits::default()
+        .exceeded(100, Duration::from_secs(3600))
+        .is_none());
+}
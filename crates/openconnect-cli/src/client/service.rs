@@ -0,0 +1,154 @@
+use colored::Colorize;
+use openconnect_core::{paths, storage::StoredConfigs};
+use std::path::{Path, PathBuf};
+
+/// What the unit needs to know about the installation it runs
+struct ServiceUnit<'a> {
+    name: &'a str,
+    exe: &'a Path,
+    config_file: Option<&'a Path>,
+
+    /// Environment variable locating the config directory, which the service does not inherit from the user
+    environment: (&'static str, &'a Path),
+    config_dir: &'a Path,
+}
+
+/// Escape the specifiers systemd expands in all settings
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Quote a word of a setting, e.g. an argument of `ExecStart=`
+fn quote(word: &str) -> String {
+    let escaped = escape_specifiers(word)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+impl ServiceUnit<'_> {
+    fn render(&self) -> String {
+        let mut exec_start = vec![
+            quote(&self.exe.to_string_lossy()),
+            "start".to_string(),
+            "--foreground".to_string(),
+        ];
+        if let Some(config_file) = self.config_file {
+            exec_start.push("--config-file".to_string());
+            exec_start.push(quote(&config_file.to_string_lossy()));
+        }
+        exec_start.push(quote(self.name));
+        // variables are expanded in ExecStart only
+        let exec_start = exec_start.join(" ").replace('$', "$$");
+        let (env_name, env_value) = self.environment;
+
+        format!(
+            r#"[Unit]
+Description=OpenConnect VPN connection to {name}
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=simple
+Environment={environment}
+ExecStart={exec_start}
+Restart=on-failure
+RestartSec=10
+
+# the vpnc-script needs CAP_NET_ADMIN for the tun device and routes, and writes
+# the DNS configuration to /etc, the session and stats files to the config dir
+CapabilityBoundingSet=CAP_NET_ADMIN CAP_DAC_OVERRIDE
+NoNewPrivileges=yes
+ProtectSystem=true
+ProtectHome=read-only
+ReadWritePaths={config_dir}
+ProtectControlGroups=yes
+ProtectKernelModules=yes
+RestrictSUIDSGID=yes
+RestrictRealtime=yes
+LockPersonality=yes
+DevicePolicy=closed
+DeviceAllow=/dev/net/tun rw
+
+[Install]
+WantedBy=multi-user.target
+"#,
+            name = escape_specifiers(self.name),
+            environment = quote(&format!("{}={}", env_name, env_value.to_string_lossy())),
+            exec_start = exec_start,
+            config_dir = escape_specifiers(&self.config_dir.to_string_lossy()),
+        )
+    }
+}
+
+/// Print or write a systemd unit which keeps the connection to the server up
+pub fn request_generate_service(name: &str, config_file: Option<PathBuf>, output: Option<PathBuf>) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let exists = runtime.block_on(async {
+        let file = match &config_file {
+            Some(config_file) => config_file.clone(),
+            None => StoredConfigs::getorinit_config_file().expect("Failed to get config file"),
+        };
+        let mut stored_configs = StoredConfigs::new(None, file);
+        stored_configs
+            .read_from_file()
+            .await
+            .expect("Failed to read config file");
+        stored_configs.servers.contains_key(name)
+    });
+    if !exists {
+        eprintln!("{}", format!("\nServer {} not found", name).red());
+        std::process::exit(1);
+    }
+
+    let exe = std::env::current_exe().expect("Failed to get the path of the CLI binary");
+    let config_dir = paths::config_dir().expect("Failed to get config dir");
+
+    // root's home differs from the user's, point the service at the directory this CLI uses
+    let home_dir;
+    let environment = match paths::overridden_dir() {
+        Some(_) => (paths::HOME_ENV, config_dir.as_path()),
+        None => {
+            home_dir = home::home_dir().expect("Home directory not found");
+            ("HOME", home_dir.as_path())
+        }
+    };
+
+    let config_file = config_file.map(|file| std::fs::canonicalize(&file).unwrap_or(file));
+    let unit = ServiceUnit {
+        name,
+        exe: &exe,
+        config_file: config_file.as_deref(),
+        environment,
+        config_dir: &config_dir,
+    }
+    .render();
+
+    match output {
+        Some(output) => {
+            std::fs::write(&output, unit).expect("Failed to write service file");
+            println!("Service file written to {:?}", output);
+        }
+        None => print!("{}", unit),
+    }
+}
+
+#[test]
+fn test_service_unit() {
+    let unit = ServiceUnit {
+        name: "corp vpn",
+        exe: Path::new("/usr/local/bin/openconnect"),
+        config_file: Some(Path::new("/etc/openconnect/100%.toml")),
+        environment: ("HOME", Path::new("/home/user")),
+        config_dir: Path::new("/home/user/.oidcvpn"),
+    }
+    .render();
+
+    assert!(unit.contains(
+        "ExecStart=\"/usr/local/bin/openconnect\" start --foreground --config-file \"/etc/openconnect/100%%.toml\" \"corp vpn\"\n"
+    ));
+    assert!(unit.contains("Environment=\"HOME=/home/user\"\n"));
+    assert!(unit.contains("ReadWritePaths=/home/user/.oidcvpn\n"));
+    assert!(unit.contains("CapabilityBoundingSet=CAP_NET_ADMIN"));
+    assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+}
@@ -0,0 +1,395 @@
+use crate::{
+    client::state::{
+        connection_config, connection_entrypoint, effective_accept_cert, effective_defaults,
+        effective_enable_udp, effective_http_headers, effective_proxy, effective_reported_os,
+        effective_search_domains, select_profile, StartOptions, StateError,
+    },
+    output, ConnectionRole,
+};
+use colored::Colorize;
+use openconnect_core::{
+    config::DEFAULT_REPORTED_OS,
+    proxy::display_proxy,
+    secret::SecretSource,
+    storage::{Defaults, OidcServer, PasswordServer, StoredConfigs, StoredServer},
+};
+use std::path::PathBuf;
+
+const REDACTED: &str = "<redacted>";
+
+/// Where a secret is taken from, without resolving it
+fn describe_secret(literal: Option<&String>, source: Option<&SecretSource>) -> String {
+    match (source, literal) {
+        (Some(SecretSource::Env(name)), _) => format!("{} (env {})", REDACTED, name),
+        (Some(SecretSource::File(path)), _) => {
+            format!("{} (file {})", REDACTED, path.display())
+        }
+        (None, Some(_)) => format!("{} (stored)", REDACTED),
+        (None, None) => "not set".to_string(),
+    }
+}
+
+fn describe_default(value: Option<&str>, default: &str) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => format!("{} (default)", default),
+    }
+}
+
+fn effective_rows(
+    stored_server: &StoredServer,
+    defaults: &Defaults,
+    options: &StartOptions,
+) -> Result<Vec<Vec<String>>, StateError> {
+    let role = match stored_server.connect_via() {
+        Some(via) => ConnectionRole::Via(via.to_string()),
+        None => ConnectionRole::Primary,
+    };
+
+    let form_defaults = match stored_server {
+        StoredServer::Password(PasswordServer { form_defaults, .. }) => {
+            let mut form_defaults: Vec<String> = form_defaults
+                .iter()
+                .map(|(field, value)| format!("{}={}", field, value))
+                .collect();
+            form_defaults.sort();
+            form_defaults
+        }
+        StoredServer::Oidc(_) => vec![],
+    };
+
+    let (name, server, allow_insecure, mut auth_rows) = match stored_server {
+        StoredServer::Password(PasswordServer {
+            name,
+            server,
+            username,
+            password,
+            password_source,
+            auth_group,
+            allow_insecure,
+            web_auth,
+            client_cert,
+            client_key,
+            key_password,
+            ..
+        }) => (
+            name,
+            server,
+            allow_insecure,
+            vec![
+                vec![
+                    format!("Auth Type"),
+                    if *web_auth {
+                        "Web login (SAML)".to_string()
+                    } else {
+                        "Password".to_string()
+                    },
+                ],
+                vec![format!("Username"), username.clone()],
+                vec![
+                    format!("Password"),
+                    describe_secret(password.as_ref(), password_source.as_ref()),
+                ],
+                vec![
+                    format!("Auth Group"),
+                    describe_default(auth_group.as_deref(), "server default"),
+                ],
+                vec![
+                    format!("Client Certificate"),
+                    match client_cert {
+                        Some(client_cert) => client_cert.display().to_string(),
+                        None => "not set".to_string(),
+                    },
+                ],
+                vec![
+                    format!("Client Key"),
+                    match (client_cert, client_key) {
+                        (_, Some(client_key)) => client_key.display().to_string(),
+                        (Some(_), None) => "in the certificate file".to_string(),
+                        (None, None) => "not set".to_string(),
+                    },
+                ],
+                vec![
+                    format!("Key Password"),
+                    describe_secret(key_password.as_ref(), None),
+                ],
+            ],
+        ),
+        StoredServer::Oidc(OidcServer {
+            name,
+            server,
+            issuer,
+            client_id,
+            client_secret,
+            client_secret_source,
+            allow_insecure,
+            oidc_logout,
+            ..
+        }) => (
+            name,
+            server,
+            allow_insecure,
+            vec![
+                vec![format!("Auth Type"), format!("OIDC")],
+                vec![format!("OIDC Issuer"), issuer.clone()],
+                vec![format!("OIDC Client ID"), client_id.clone()],
+                vec![
+                    format!("OIDC Client Secret"),
+                    describe_secret(client_secret.as_ref(), client_secret_source.as_ref()),
+                ],
+                vec![format!("OIDC Logout"), oidc_logout.to_string()],
+            ],
+        ),
+    };
+
+    let defaults = &effective_defaults(defaults, options, &role);
+    let reported_os = effective_reported_os(stored_server, options, &role);
+    let http_proxy = effective_proxy(stored_server, options, &role);
+    let config = connection_config(
+        defaults,
+        options.cert_expiry_warning_days,
+        effective_search_domains(options, &role),
+        http_proxy.as_deref(),
+        options.min_tls_version,
+        options.ciphers.as_deref(),
+        options.transport_family,
+    )?;
+    // the cookie is only obtained on start
+    let mut entrypoint = connection_entrypoint(
+        name,
+        server,
+        allow_insecure.unwrap_or(false),
+        None,
+        options.dtls_fallback,
+        reported_os.as_deref(),
+        stored_server.protocol().or_else(|| defaults.protocol()),
+    );
+    for (name, value) in effective_http_headers(options, &role) {
+        entrypoint.add_http_header(name, value);
+    }
+    if let Some(expected_subnet) = stored_server.expected_subnet() {
+        entrypoint.expected_subnet(expected_subnet);
+    }
+    if let Some(mtu) = stored_server.mtu() {
+        entrypoint.mtu(mtu);
+    }
+    if let Some(accept_cert) = effective_accept_cert(stored_server, options, &role) {
+        entrypoint.accept_cert(accept_cert);
+    }
+    if let Some(enable_udp) = effective_enable_udp(options, &role) {
+        entrypoint.enable_udp(enable_udp);
+    }
+    let entrypoint = entrypoint.build()?;
+
+    let mut rows = vec![
+        vec![format!("Server Name"), name.clone()],
+        vec![format!("Server URL"), entrypoint.server.clone()],
+        vec![
+            format!("Connect Via"),
+            stored_server.connect_via().unwrap_or_default().to_string(),
+        ],
+        vec![
+            format!("Probe Host"),
+            stored_server.probe_host().unwrap_or("none").to_string(),
+        ],
+    ];
+    if let Some((verify_url, expect_status)) = stored_server.verify_url() {
+        rows.push(vec![
+            format!("Verify URL"),
+            match expect_status {
+                Some(expect_status) => format!("{} (status {})", verify_url, expect_status),
+                None => format!("{} (status 2xx)", verify_url),
+            },
+        ]);
+    }
+    if !stored_server.notify() {
+        rows.push(vec![format!("Notifications"), format!("off")]);
+    }
+    if let Some(expected_subnet) = entrypoint.expected_subnet {
+        rows.push(vec![
+            format!("Expected Subnet"),
+            expected_subnet.to_string(),
+        ]);
+    }
+    if let Some(mtu) = entrypoint.mtu {
+        rows.push(vec![format!("MTU"), mtu.to_string()]);
+    }
+    if !stored_server.profiles().is_empty() {
+        rows.push(vec![
+            format!("Profiles"),
+            stored_server.profile_names().join(", "),
+        ]);
+    }
+    if let Some(profile) = &options.profile {
+        rows.push(vec![format!("Profile"), profile.clone()]);
+    }
+    if let Some(profile_script) = stored_server.profile_script() {
+        rows.push(vec![
+            format!("Profile Script"),
+            profile_script.display().to_string(),
+        ]);
+    }
+    rows.append(&mut auth_rows);
+    if !form_defaults.is_empty() {
+        rows.push(vec![format!("Form Defaults"), form_defaults.join("\n")]);
+    }
+
+    rows.extend(vec![
+        vec![
+            format!("Protocol"),
+            format!(
+                "{} ({})",
+                entrypoint.protocol.name, entrypoint.protocol.pretty_name
+            ),
+        ],
+        vec![
+            format!("Reported OS"),
+            describe_default(entrypoint.reported_os.as_deref(), DEFAULT_REPORTED_OS),
+        ],
+        vec![
+            format!("HTTP Headers"),
+            if entrypoint.http_headers.is_empty() {
+                "none".to_string()
+            } else {
+                entrypoint
+                    .http_headers
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        ],
+        vec![
+            format!("Accept Insecure Cert"),
+            entrypoint.accept_insecure_cert.to_string(),
+        ],
+        vec![
+            format!("Server Cert"),
+            entrypoint
+                .accepted_cert
+                .clone()
+                .unwrap_or("not pinned".to_string()),
+        ],
+        vec![format!("UDP (DTLS)"), entrypoint.enable_udp.to_string()],
+        vec![
+            format!("UDP Rules"),
+            if stored_server.udp_policy().is_empty() {
+                "none".to_string()
+            } else {
+                stored_server.udp_policy().join(", ")
+            },
+        ],
+        vec![
+            format!("DTLS Fallback"),
+            match entrypoint.dtls_fallback_timeout {
+                Some(timeout) => format!("after {}s", timeout.as_secs()),
+                None => "off".to_string(),
+            },
+        ],
+        vec![
+            format!("vpnc-script"),
+            config.vpncscript.clone().unwrap_or_default(),
+        ],
+        vec![
+            format!("HTTP Proxy"),
+            config
+                .http_proxy
+                .as_deref()
+                .map(display_proxy)
+                .unwrap_or("none".to_string()),
+        ],
+        vec![
+            format!("Transport Family"),
+            config
+                .transport_family
+                .map(|family| family.to_string())
+                .unwrap_or("any".to_string()),
+        ],
+        vec![
+            format!("Min TLS Version"),
+            config
+                .min_tls_version
+                .map(|version| version.to_string())
+                .unwrap_or("libopenconnect default".to_string()),
+        ],
+        vec![
+            format!("Ciphers"),
+            config
+                .cipher_list
+                .map(|ciphers| ciphers.join(":"))
+                .unwrap_or("any".to_string()),
+        ],
+        vec![format!("Log Level"), format!("{:?}", config.loglevel)],
+        vec![
+            format!("Auto Reconnect"),
+            defaults.auto_reconnect.unwrap_or(true).to_string(),
+        ],
+        vec![
+            format!("Cert Expiry Warning"),
+            format!(
+                "{} days",
+                config.cert_expiry_warning.as_secs() / (24 * 60 * 60)
+            ),
+        ],
+        vec![
+            format!("Search Domains"),
+            match config.search_domains {
+                Some(domains) => domains.join(", "),
+                None => "pushed by server".to_string(),
+            },
+        ],
+    ]);
+
+    Ok(rows)
+}
+
+pub fn request_show_server(name: &str, config_file: PathBuf, mut options: StartOptions) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let mut stored_configs = StoredConfigs::new(None, config_file);
+        let stored_configs = match stored_configs.read_from_file().await {
+            Ok(stored_configs) => stored_configs,
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to read config: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+
+        let Some(stored_server) = stored_configs.servers.get(name) else {
+            eprintln!("{}", format!("\nServer {} not found", name).red());
+            std::process::exit(1);
+        };
+
+        if let Err(e) = select_profile(stored_server, &mut options) {
+            eprintln!("{}", format!("\n{}", e).red());
+            std::process::exit(1);
+        }
+
+        match effective_rows(stored_server, &stored_configs.defaults, &options) {
+            Ok(rows) => {
+                output::print_rows(rows);
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nInvalid configuration: {}", e).red());
+                std::process::exit(1);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_describe_secret() {
+    assert_eq!(
+        describe_secret(
+            Some(&"hunter2".to_string()),
+            Some(&SecretSource::Env("VPN_PASSWORD".to_string()))
+        ),
+        "<redacted> (env VPN_PASSWORD)"
+    );
+    assert_eq!(
+        describe_secret(Some(&"hunter2".to_string()), None),
+        "<redacted> (stored)"
+    );
+    assert_eq!(describe_secret(None, None), "not set");
+}
@@ -1,20 +1,41 @@
-use crate::{sock, JsonRequest, JsonResponse};
+use crate::{
+    last_session::LastSession, output, server_stats, sock, ConnectionRole, ConnectionStatus,
+    JsonRequest, JsonResponse, StartParams,
+};
 use colored::Colorize;
-use comfy_table::Table;
 use futures::TryStreamExt;
 use openconnect_core::{
-    config::{ConfigBuilder, EntrypointBuilder, LogLevel},
+    captive_portal,
+    config::{
+        AddressFamily, Config, ConfigBuilder, EntrypointBuilder, LogLevel, ReconnectPolicy,
+        TlsVersion, TunDevice, DEFAULT_DTLS_FALLBACK_TIMEOUT,
+    },
     events::EventHandlers,
+    ip_info::IpInfo,
     log::Logger,
+    paths,
+    protocols::{find_protocol, Protocol},
+    proxy::resolve_proxy,
     result::OpenconnectError,
-    storage::{OidcServer, PasswordServer, StoredConfigs, StoredServer},
-    Connectable, VpnClient,
+    storage::{
+        Defaults, OidcServer, PasswordServer, ServerProfile, StoredConfigError, StoredConfigs,
+        StoredServer,
+    },
+    Connectable, Status, VpnClient,
 };
 use openconnect_oidc::{
-    obtain_cookie_by_oidc_token,
     oidc_device::{OpenIDDeviceAuth, OpenIDDeviceAuthConfig, OpenIDDeviceAuthError},
+    oidc_logout::OpenIDLogoutConfig,
+    oidc_token::{OpenIDTokenAuth, OpenIDTokenAuthConfig, OpenIDTokenAuthError, OIDC_REDIRECT_URI},
+    request_cookie_by_oidc_token, ObtainCookieError,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use std::path::PathBuf;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(thiserror::Error, Debug)]
@@ -30,14 +51,90 @@ pub enum StateError {
 
     #[error("OpenID device auth error: {0}")]
     OpenIDAuthError(#[from] OpenIDDeviceAuthError),
+
+    #[error("OpenID auth error: {0}")]
+    OpenIDTokenAuthError(#[from] OpenIDTokenAuthError),
+
+    #[error("OpenID cookie error: {0}")]
+    ObtainCookieError(#[from] ObtainCookieError),
+
+    #[error("Kill switch error: {0}")]
+    KillSwitchError(#[from] crate::killswitch::KillSwitchError),
+
+    #[error("Stored config error: {0}")]
+    StoredConfigError(#[from] StoredConfigError),
+
+    #[error("Secret error: {0}")]
+    SecretError(#[from] openconnect_core::secret::SecretError),
+
+    #[error("{0}")]
+    ProfileScriptError(#[from] openconnect_core::profile_script::ProfileScriptError),
+
+    #[error("{0}")]
+    PreflightError(#[from] openconnect_core::preflight::PreflightError),
+
+    #[error("{0}")]
+    CaptivePortal(#[from] captive_portal::CaptivePortal),
+
+    #[error("{0}")]
+    ConnectViaError(String),
+
+    #[error("vpnc-script {0} not found")]
+    VpncScriptNotFound(String),
+
+    #[error("vpnc-script {0} is not executable and setting the executable bit failed: {1}, run `chmod +x {0}`")]
+    VpncScriptNotExecutable(String, #[source] std::io::Error),
+}
+
+impl StateError {
+    /// The gateway was unavailable during the login, see [with_login_retries]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            StateError::OpenconnectError(e) => e.is_transient(),
+            StateError::ObtainCookieError(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Attempts of a login which fails on a transient error of the gateway, such as a 503 or a timeout
+const LOGIN_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry of a login, doubled for each further one
+const LOGIN_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Retry a login as long as it fails transiently, up to [LOGIN_ATTEMPTS]
+///
+/// Other failures such as rejected credentials are returned right away, so that retries can't lock the account.
+/// This is independent of the auto-reconnect of the daemon, which only starts once the tunnel was up.
+async fn with_login_retries<T, F, Fut>(mut login: F) -> Result<T, StateError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, StateError>>,
+{
+    let mut attempt = 1;
+    let mut delay = LOGIN_RETRY_DELAY;
+    loop {
+        match login().await {
+            Err(e) if e.is_transient() && attempt < LOGIN_ATTEMPTS => {
+                tracing::warn!(
+                    "Login failed ({}), retrying in {}s ({}/{})",
+                    e,
+                    delay.as_secs(),
+                    attempt,
+                    LOGIN_ATTEMPTS - 1
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
 }
 
 pub fn get_vpnc_script() -> Result<String, StateError> {
-    let homedir = home::home_dir().ok_or(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Failed to get home directory",
-    ))?;
-    let vpncscript = homedir.join(".oidcvpn/bin/vpnc-script");
+    let vpncscript = paths::vpnc_script()?;
     let vpncscript = vpncscript.to_str().ok_or(std::io::Error::new(
         std::io::ErrorKind::InvalidData,
         "Failed to get vpnc-script path as string",
@@ -46,44 +143,327 @@ pub fn get_vpnc_script() -> Result<String, StateError> {
     Ok(vpncscript.to_string())
 }
 
+/// Whether any execute bit of the file is set
+pub fn is_executable(path: &Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+/// Set the executable bit of the vpnc-script if it lacks it, like the GUI does for the bundled script
+///
+/// libopenconnect only reports a failed script once the tunnel is up, this fails before the login instead.
+pub fn ensure_vpnc_script_executable(defaults: &Defaults) -> Result<(), StateError> {
+    use std::os::unix::fs::PermissionsExt;
+    let vpncscript = match defaults.vpnc_script {
+        Some(ref vpncscript) => vpncscript.clone(),
+        None => get_vpnc_script()?,
+    };
+
+    match is_executable(Path::new(&vpncscript)) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            tracing::warn!("Setting the executable bit of vpnc-script {}", vpncscript);
+            std::fs::set_permissions(&vpncscript, std::fs::Permissions::from_mode(0o755))
+                .map_err(|e| StateError::VpncScriptNotExecutable(vpncscript, e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(StateError::VpncScriptNotFound(vpncscript))
+        }
+        Err(e) => Err(StateError::VpncScriptNotExecutable(vpncscript, e)),
+    }
+}
+
+/// The config the daemon connects with, shared with `show`
+pub fn connection_config(
+    defaults: &Defaults,
+    cert_expiry_warning_days: u32,
+    search_domains: Vec<String>,
+    http_proxy: Option<&str>,
+    min_tls_version: Option<TlsVersion>,
+    ciphers: Option<&str>,
+    transport_family: Option<AddressFamily>,
+) -> Result<Config, StateError> {
+    let vpncscript = match defaults.vpnc_script {
+        Some(ref vpncscript) => vpncscript.clone(),
+        None => get_vpnc_script()?,
+    };
+
+    let mut config = ConfigBuilder::default();
+    config
+        .vpncscript(&vpncscript)
+        .loglevel(defaults.log_level().unwrap_or(LogLevel::Info))
+        .cert_expiry_warning(Duration::from_secs(
+            cert_expiry_warning_days as u64 * 24 * 60 * 60,
+        ))
+        .search_domains(search_domains);
+
+    if let Some(http_proxy) = http_proxy {
+        config.http_proxy(http_proxy);
+    }
+
+    if let Some(min_tls_version) = min_tls_version {
+        config.min_tls_version(min_tls_version);
+    }
+
+    if let Some(ciphers) = ciphers {
+        config.cipher_list(ciphers);
+    }
+
+    if let Some(transport_family) = transport_family {
+        config.transport_family(transport_family);
+    }
+
+    Ok(config.build()?)
+}
+
+/// The entrypoint the daemon connects with once the cookie is obtained, shared with `show`
+pub fn connection_entrypoint(
+    name: &str,
+    server: &str,
+    allow_insecure: bool,
+    cookie: Option<&str>,
+    dtls_fallback: bool,
+    reported_os: Option<&str>,
+    protocol: Option<Protocol>,
+) -> EntrypointBuilder {
+    let mut entrypoint = EntrypointBuilder::new();
+    entrypoint
+        .name(name)
+        .server(server)
+        .accept_insecure_cert(allow_insecure)
+        .enable_udp(true)
+        .dtls_fallback(dtls_fallback.then_some(DEFAULT_DTLS_FALLBACK_TIMEOUT));
+
+    if let Some(cookie) = cookie {
+        entrypoint.cookie(cookie);
+    }
+
+    if let Some(reported_os) = reported_os {
+        entrypoint.reported_os(reported_os);
+    }
+
+    if let Some(protocol) = protocol {
+        entrypoint.protocol(protocol);
+    }
+
+    entrypoint
+}
+
+/// The platform to report, the override given on start is meant for the primary server only
+pub fn effective_reported_os(
+    stored_server: &StoredServer,
+    options: &StartOptions,
+    role: &ConnectionRole,
+) -> Option<String> {
+    match role {
+        ConnectionRole::Upstream => stored_server.reported_os().map(str::to_string),
+        _ => options
+            .reported_os
+            .clone()
+            .or(stored_server.reported_os().map(str::to_string)),
+    }
+}
+
+/// The proxy to connect through, the override given on start is meant for the primary server only
+pub fn effective_proxy(
+    stored_server: &StoredServer,
+    options: &StartOptions,
+    role: &ConnectionRole,
+) -> Option<String> {
+    let server = match stored_server {
+        StoredServer::Oidc(OidcServer { server, .. }) => server,
+        StoredServer::Password(PasswordServer { server, .. }) => server,
+    };
+    let explicit = match role {
+        ConnectionRole::Upstream => None,
+        _ => options.proxy.as_deref(),
+    };
+
+    resolve_proxy(explicit, stored_server.proxy(), server)
+}
+
+/// Look up the profile given on start, its options then apply to the primary server
+pub fn select_profile(
+    stored_server: &StoredServer,
+    options: &mut StartOptions,
+) -> Result<(), StoredConfigError> {
+    if let Some(profile) = &options.profile {
+        options.server_profile = stored_server.profile(profile)?.clone();
+    }
+    Ok(())
+}
+
+/// The search domain override is meant for the network of the primary server only, the ones given on start replace the profile's
+pub fn effective_search_domains(options: &StartOptions, role: &ConnectionRole) -> Vec<String> {
+    match role {
+        ConnectionRole::Upstream => vec![],
+        _ if options.search_domains.is_empty() => options.server_profile.search_domains.clone(),
+        _ => options.search_domains.clone(),
+    }
+}
+
+/// The defaults with the vpnc-script of the profile, which sets up the routes of the primary server only
+pub fn effective_defaults(
+    defaults: &Defaults,
+    options: &StartOptions,
+    role: &ConnectionRole,
+) -> Defaults {
+    match (role, &options.server_profile.vpnc_script) {
+        (ConnectionRole::Upstream, _) | (_, None) => defaults.clone(),
+        (_, Some(vpnc_script)) => Defaults {
+            vpnc_script: Some(vpnc_script.clone()),
+            ..defaults.clone()
+        },
+    }
+}
+
+/// Whether the profile enables UDP, meant for the primary server only
+pub fn effective_enable_udp(options: &StartOptions, role: &ConnectionRole) -> Option<bool> {
+    match role {
+        ConnectionRole::Upstream => None,
+        _ => options.server_profile.enable_udp,
+    }
+}
+
+/// The extra headers are meant for the login to the primary server only
+pub fn effective_http_headers<'a>(
+    options: &'a StartOptions,
+    role: &ConnectionRole,
+) -> &'a [(String, String)] {
+    match role {
+        ConnectionRole::Upstream => &[],
+        _ => &options.http_headers,
+    }
+}
+
+/// The certificate accepted on start is the one of the primary server only, it takes precedence over the pinned one
+pub fn effective_accept_cert<'a>(
+    stored_server: &'a StoredServer,
+    options: &'a StartOptions,
+    role: &ConnectionRole,
+) -> Option<&'a str> {
+    match role {
+        ConnectionRole::Upstream => None,
+        _ => options.accept_cert.as_deref(),
+    }
+    .or(stored_server.server_cert())
+}
+
+/// Print the web login URL of the gateway and try to open it, it can still be opened manually if that fails
+fn open_web_login(url: &str) -> bool {
+    println!("Please complete the login in your browser: {}", url);
+    if let Err(e) = open::that(url) {
+        eprintln!("Failed to open the browser: {}", e);
+    }
+    true
+}
+
+/// Log in to the server with its stored credentials, `config` is the one of [login_config]
 pub async fn obtain_cookie_from_password_server(
     password_server: &PasswordServer,
     stored_configs: &StoredConfigs,
-) -> Result<Option<String>, StateError> {
+    config: Config,
+    reported_os: Option<&str>,
+    http_headers: &[(String, String)],
+    accept_cert: Option<&str>,
+    force: bool,
+) -> Result<(Option<String>, Option<String>), StateError> {
     let password_server = password_server.decrypted_by(&stored_configs.cipher);
 
-    let vpncscript = get_vpnc_script()?;
-
-    let config = ConfigBuilder::default()
-        .vpncscript(&vpncscript)
-        .loglevel(LogLevel::Info)
-        .build()?;
-
-    let entrypoint = EntrypointBuilder::new()
+    let mut entrypoint = EntrypointBuilder::new();
+    entrypoint
         .name(&password_server.name)
         .server(&password_server.server)
         .username(&password_server.username)
-        .password(&password_server.password.clone().unwrap_or("".to_string()))
+        .password(&password_server.resolve_password()?.unwrap_or_default())
         .accept_insecure_cert(password_server.allow_insecure.unwrap_or(false))
         .enable_udp(true)
-        .build()?;
+        .web_auth(password_server.web_auth)
+        .terminate_existing_session(force);
+
+    for (field, value) in &password_server.form_defaults {
+        entrypoint.form_default(field, value);
+    }
+
+    if let Some(reported_os) = reported_os {
+        entrypoint.reported_os(reported_os);
+    }
+
+    if let Some(protocol) = password_server
+        .protocol
+        .as_deref()
+        .and_then(find_protocol)
+        .or_else(|| stored_configs.defaults.protocol())
+    {
+        entrypoint.protocol(protocol);
+    }
 
-    let event_handler = EventHandlers::default();
+    for (name, value) in http_headers {
+        entrypoint.add_http_header(name, value);
+    }
+
+    if let Some(accept_cert) = accept_cert {
+        entrypoint.accept_cert(accept_cert);
+    }
+
+    if let Some(client_cert) = &password_server.client_cert {
+        entrypoint.client_cert(&client_cert.to_string_lossy());
+        if let Some(client_key) = &password_server.client_key {
+            entrypoint.client_key(&client_key.to_string_lossy());
+        }
+        if let Some(key_password) = &password_server.key_password {
+            entrypoint.key_password(key_password);
+        }
+    }
+
+    let entrypoint = entrypoint.build()?;
+
+    let event_handler = EventHandlers::default().with_handle_external_browser(open_web_login);
 
     let client = VpnClient::new(config, event_handler)?;
     let client_clone = client.clone();
 
-    Ok(tokio::task::spawn_blocking(move || client_clone.connect_for_cookie(entrypoint)).await??)
+    let cookie =
+        tokio::task::spawn_blocking(move || client_clone.connect_for_cookie(entrypoint)).await??;
+
+    // the cookie may only be valid on the gateway the login was redirected to
+    let redirect = client
+        .get_redirected_from()
+        .and_then(|_| client.get_connect_url());
+    Ok((cookie, redirect))
 }
 
-pub async fn obtain_cookie_from_oidc_server(
-    oidc_server: &OidcServer,
-    _stored_configs: &StoredConfigs,
-) -> Result<Option<String>, StateError> {
+/// Log in with the authorization code flow like the GUI, for issuers without the device flow
+///
+/// The redirect to [OIDC_REDIRECT_URI] is received on a local listener, so the browser must run on the same machine.
+async fn obtain_oidc_token_by_browser(oidc_server: &OidcServer) -> Result<String, StateError> {
+    let openid_config = OpenIDTokenAuthConfig {
+        issuer_url: oidc_server.issuer.clone(),
+        redirect_uri: OIDC_REDIRECT_URI.to_string(),
+        client_id: oidc_server.client_id.clone(),
+        client_secret: oidc_server.resolve_client_secret()?,
+        use_pkce_challenge: true,
+    };
+
+    let mut openid = OpenIDTokenAuth::new(openid_config).await?;
+    let (authorize_url, req_state, _) = openid.auth_request();
+    open_web_login(authorize_url.as_str());
+
+    let (code, callback_state) = openid.wait_for_callback().await?;
+    if req_state.secret() != callback_state.secret() {
+        return Err(OpenIDTokenAuthError::StateValidationError(
+            "State validation failed".to_string(),
+        ))?;
+    }
+
+    Ok(openid.exchange_token(code).await?)
+}
+
+async fn obtain_oidc_token_by_device(oidc_server: &OidcServer) -> Result<String, StateError> {
     let openid_config = OpenIDDeviceAuthConfig {
         issuer_url: oidc_server.issuer.clone(),
         client_id: oidc_server.client_id.clone(),
-        client_secret: oidc_server.client_secret.clone(),
+        client_secret: oidc_server.resolve_client_secret()?,
     };
 
     let mut openid = OpenIDDeviceAuth::new(openid_config).await?;
@@ -96,18 +476,265 @@ pub async fn obtain_cookie_from_oidc_server(
         user_code.secret()
     );
 
-    let token = openid
+    Ok(openid
         .exchange_token(&device_auth_response, tokio::time::sleep, None)
-        .await?;
+        .await?)
+}
 
-    Ok(obtain_cookie_by_oidc_token(&oidc_server.server, &token).await)
+/// Log in with the device flow, or the browser with `by_browser`, also returns what ends the session at the issuer if
+/// [OidcServer::oidc_logout] is set
+pub async fn obtain_cookie_from_oidc_server(
+    oidc_server: &OidcServer,
+    _stored_configs: &StoredConfigs,
+    by_browser: bool,
+) -> Result<(Option<String>, Option<OpenIDLogoutConfig>), StateError> {
+    let token = if by_browser {
+        obtain_oidc_token_by_browser(oidc_server).await?
+    } else {
+        obtain_oidc_token_by_device(oidc_server).await?
+    };
+
+    let token = &token;
+    let cookie = with_login_retries(move || async move {
+        Ok(request_cookie_by_oidc_token(&oidc_server.server, token).await?)
+    })
+    .await?;
+
+    let logout = oidc_server.oidc_logout.then(|| OpenIDLogoutConfig {
+        issuer_url: oidc_server.issuer.clone(),
+        client_id: oidc_server.client_id.clone(),
+        id_token: token.clone(),
+    });
+
+    Ok((Some(cookie), logout))
 }
 
-pub fn request_get_status() {
+/// Table rows of the parameters pushed by the gateway
+fn ip_info_rows(info: IpInfo) -> Vec<Vec<String>> {
+    let addr = info.addr.unwrap_or("".to_string());
+    let netmask = info.netmask.unwrap_or("".to_string());
+    let addr6 = match info.addr6 {
+        Some(addr6) => addr6,
+        None if info.ipv6_failed => "Not assigned, IPv6 is broken".to_string(),
+        None => "".to_string(),
+    };
+    let netmask6 = info.netmask6.unwrap_or("".to_string());
+    let dns1 = info.dns[0].clone().unwrap_or("".to_string());
+    let dns2 = info.dns[1].clone().unwrap_or("".to_string());
+    let dns3 = info.dns[2].clone().unwrap_or("".to_string());
+    let nbns1 = info.nbns[0].clone().unwrap_or("".to_string());
+    let nbns2 = info.nbns[1].clone().unwrap_or("".to_string());
+    let nbns3 = info.nbns[2].clone().unwrap_or("".to_string());
+    let domain = info.domain.unwrap_or("".to_string());
+    let proxy_pac = info.proxy_pac.unwrap_or("".to_string());
+    let mtu = info.mtu.to_string();
+    let search_domains = info.search_domains.join(", ");
+    let split_dns_domains = info.split_dns_domains.join(", ");
+    let gateway_addr = info.gateway_addr.clone().unwrap_or("".to_string());
+    vec![
+        vec![format!("IPv4 Address"), addr],
+        vec![format!("IPv4 Netmask"), netmask],
+        vec![format!("IPv6 Address"), addr6],
+        vec![format!("IPv6 Netmask"), netmask6],
+        vec![format!("DNS 1"), dns1],
+        vec![format!("DNS 2"), dns2],
+        vec![format!("DNS 3"), dns3],
+        vec![format!("NBNS 1"), nbns1],
+        vec![format!("NBNS 2"), nbns2],
+        vec![format!("NBNS 3"), nbns3],
+        vec![format!("Domain"), domain],
+        vec![format!("Search Domains"), search_domains],
+        vec![format!("Split DNS Domains"), split_dns_domains],
+        vec![format!("Proxy PAC"), proxy_pac],
+        vec![format!("MTU"), mtu],
+        vec![format!("Gateway Address"), gateway_addr],
+    ]
+}
+
+/// Print the traffic counters of the running connection, and the lifetime counters of its server including them
+pub fn request_live_stats() {
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
     runtime.block_on(async {
-        let client = sock::UnixDomainClient::connect().await;
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::Stats)
+                    .await
+                    .expect("Failed to send stats command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::StatsResult {
+                            server_name,
+                            stats: Some(stats),
+                            ..
+                        } => {
+                            // the lifetime counters are only updated once a session ends
+                            let lifetime = server_stats::load()
+                                .ok()
+                                .and_then(|mut all_stats| all_stats.remove(&server_name))
+                                .unwrap_or_default();
+                            output::print_rows(vec![
+                                vec![format!("Server Name"), server_name],
+                                vec![format!("RX Bytes"), stats.rx_bytes.to_string()],
+                                vec![format!("TX Bytes"), stats.tx_bytes.to_string()],
+                                vec![format!("RX Packets"), stats.rx_pkts.to_string()],
+                                vec![format!("TX Packets"), stats.tx_pkts.to_string()],
+                                vec![
+                                    format!("Total RX Bytes"),
+                                    (lifetime.rx_bytes + stats.rx_bytes).to_string(),
+                                ],
+                                vec![
+                                    format!("Total TX Bytes"),
+                                    (lifetime.tx_bytes + stats.tx_bytes).to_string(),
+                                ],
+                            ]);
+                        }
+                        JsonResponse::StatsResult { err_message, .. } => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "\nFailed to get the traffic counters: {}",
+                                    err_message.unwrap_or_default()
+                                )
+                                .red()
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
+
+/// Print the lifetime counters of a server, see [server_stats]
+pub fn request_server_stats(name: &str) {
+    let all_stats = match server_stats::load() {
+        Ok(all_stats) => all_stats,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!("\nFailed to read the server stats: {}", e).red()
+            );
+            std::process::exit(1);
+        }
+    };
+    let Some(stats) = all_stats.get(name) else {
+        println!("No session of {} ended yet", name);
+        return;
+    };
+
+    output::print_rows(vec![
+        vec![format!("Server Name"), name.to_string()],
+        vec![format!("Total RX Bytes"), stats.rx_bytes.to_string()],
+        vec![format!("Total TX Bytes"), stats.tx_bytes.to_string()],
+        vec![
+            format!("Total Connected Time"),
+            format!("{}s", stats.connected_secs),
+        ],
+        vec![format!("Connections"), stats.connects.to_string()],
+        vec![
+            format!("Last Connected"),
+            stats
+                .last_connected
+                .map(|last_connected| last_connected.to_rfc3339())
+                .unwrap_or_default(),
+        ],
+    ]);
+}
+
+pub fn request_last_session(json: bool) {
+    let last_session = match LastSession::load() {
+        Ok(Some(last_session)) => last_session,
+        Ok(None) if json => {
+            println!("null");
+            return;
+        }
+        Ok(None) => {
+            println!("No session was saved yet");
+            return;
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!("\nFailed to read the last session: {}", e).red()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&last_session).expect("Failed to serialize last session")
+        );
+        return;
+    }
+
+    let mut rows = vec![
+        vec![format!("Server Name"), last_session.server_name],
+        vec![format!("Server URL"), last_session.server_url],
+        vec![format!("Server IP"), last_session.hostname],
+        vec![
+            format!("Connected Via"),
+            last_session.connected_via.unwrap_or_default(),
+        ],
+        vec![
+            format!("TLS Version"),
+            last_session.tls_version.unwrap_or_default(),
+        ],
+        vec![
+            format!("Duration"),
+            last_session
+                .duration_secs
+                .map(|secs| format!("{}s", secs))
+                .unwrap_or_default(),
+        ],
+        vec![
+            format!("Disconnected At"),
+            last_session.disconnected_at.to_rfc3339(),
+        ],
+    ];
+
+    if let Some(info) = last_session.info {
+        rows.extend(ip_info_rows(info));
+    }
+
+    output::print_rows(rows);
+}
+
+/// The response of the daemon, exits the process if it does not respond within the timeout
+async fn receive_or_exit(
+    client: &mut sock::DaemonClient,
+    timeout: Duration,
+) -> Option<JsonResponse> {
+    match client.receive(timeout).await {
+        Ok(response) => response,
+        Err(e @ sock::SockError::Timeout) => {
+            eprintln!("{}", format!("\n{}", e).red());
+            std::process::exit(1);
+        }
+        Err(_) => None,
+    }
+}
+
+/// Print the status of the daemon as table, or as the JSON of the [JsonResponse::InfoResult] with `json`
+pub fn request_get_status(timeout: Duration, json: bool) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
 
         match client {
             Ok(mut client) => {
@@ -116,62 +743,72 @@ pub fn request_get_status() {
                     .await
                     .expect("Failed to send info command");
 
-                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                if let Some(response) = receive_or_exit(&mut client, timeout).await {
                     match response {
+                        JsonResponse::InfoResult { .. } if json => {
+                            // the fields of the variant, without the enum tag around them
+                            let info_result = match serde_json::to_value(&response)
+                                .expect("Failed to serialize status")
+                            {
+                                serde_json::Value::Object(mut tagged) => tagged
+                                    .remove("InfoResult")
+                                    .unwrap_or(serde_json::Value::Null),
+                                other => other,
+                            };
+                            println!(
+                                "{}",
+                                serde_json::to_string_pretty(&info_result)
+                                    .expect("Failed to serialize status")
+                            );
+                        }
                         JsonResponse::InfoResult {
                             server_name,
                             server_url,
                             hostname,
                             status,
+                            auto_reconnect,
+                            server_cert_expiry,
+                            server_cert_expiring,
+                            tls_version,
+                            server_version,
+                            redirected_from,
+                            connected_via,
+                            probe,
                             info,
                         } => {
-                            let mut table = Table::new();
+                            let auto_reconnect = if auto_reconnect { "On" } else { "Off" };
+                            let mut server_cert_expiry = server_cert_expiry.unwrap_or_default();
+                            if server_cert_expiring {
+                                server_cert_expiry.push_str(" (expiring soon!)");
+                            }
                             let mut rows = vec![
                                 vec![format!("Server Name"), server_name],
                                 vec![format!("Server URL"), server_url],
                                 vec![format!("Server IP"), hostname],
-                                vec![format!("Connection Status"), status],
+                                vec![format!("Connection Status"), status.to_string()],
+                                vec![format!("Auto Reconnect"), auto_reconnect.to_string()],
+                                vec![format!("Server Cert Expiry"), server_cert_expiry],
+                                vec![format!("TLS Version"), tls_version.unwrap_or_default()],
+                                vec![
+                                    format!("Server Version"),
+                                    server_version.unwrap_or_default(),
+                                ],
+                                vec![format!("Connected Via"), connected_via.unwrap_or_default()],
                             ];
 
-                            if let Some(info) = info {
-                                let addr = info.addr.unwrap_or("".to_string());
-                                let netmask = info.netmask.unwrap_or("".to_string());
-                                let addr6 = info.addr6.unwrap_or("".to_string());
-                                let netmask6 = info.netmask6.unwrap_or("".to_string());
-                                let dns1 = info.dns[0].clone().unwrap_or("".to_string());
-                                let dns2 = info.dns[1].clone().unwrap_or("".to_string());
-                                let dns3 = info.dns[2].clone().unwrap_or("".to_string());
-                                let nbns1 = info.nbns[0].clone().unwrap_or("".to_string());
-                                let nbns2 = info.nbns[1].clone().unwrap_or("".to_string());
-                                let nbns3 = info.nbns[2].clone().unwrap_or("".to_string());
-                                let domain = info.domain.unwrap_or("".to_string());
-                                let proxy_pac = info.proxy_pac.unwrap_or("".to_string());
-                                let mtu = info.mtu.to_string();
-                                let gateway_addr =
-                                    info.gateway_addr.clone().unwrap_or("".to_string());
-                                let info_rows = vec![
-                                    vec![format!("IPv4 Address"), addr],
-                                    vec![format!("IPv4 Netmask"), netmask],
-                                    vec![format!("IPv6 Address"), addr6],
-                                    vec![format!("IPv6 Netmask"), netmask6],
-                                    vec![format!("DNS 1"), dns1],
-                                    vec![format!("DNS 2"), dns2],
-                                    vec![format!("DNS 3"), dns3],
-                                    vec![format!("NBNS 1"), nbns1],
-                                    vec![format!("NBNS 2"), nbns2],
-                                    vec![format!("NBNS 3"), nbns3],
-                                    vec![format!("Domain"), domain],
-                                    vec![format!("Proxy PAC"), proxy_pac],
-                                    vec![format!("MTU"), mtu],
-                                    vec![format!("Gateway Address"), gateway_addr],
-                                ];
-
-                                rows.extend(info_rows);
+                            if let Some(redirected_from) = redirected_from {
+                                rows.push(vec![format!("Redirected From"), redirected_from]);
                             }
 
-                            table.add_rows(rows);
+                            if let Some(probe) = probe {
+                                rows.push(vec![format!("Probe"), probe]);
+                            }
+
+                            if let Some(info) = info {
+                                rows.extend(ip_info_rows(*info));
+                            }
 
-                            println!("{table}");
+                            output::print_rows(rows);
                         }
                         _ => {
                             println!("Received unexpected response");
@@ -187,131 +824,642 @@ pub fn request_get_status() {
     });
 }
 
-pub fn request_start_server(name: String, config_file: PathBuf) {
-    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
-    runtime.block_on(async {
-        Logger::init().expect("Failed to initialize logger");
+/// Per-connection daemon options given on `start`
+#[derive(Debug, Clone, Default)]
+pub struct StartOptions {
+    pub kill_switch: bool,
+    pub metrics_addr: Option<SocketAddr>,
+    pub cert_expiry_warning_days: u32,
+    pub reconnect_jitter: u8,
+    pub reconnect_policy: ReconnectPolicy,
 
-        match crate::client::config::read_server_config_from_fs(&name, config_file).await {
-            Ok((stored_server, stored_configs)) => {
-                let (cookie, name, server, allow_insecure) = match stored_server {
-                    StoredServer::Password(password_server) => {
-                        let cookie = crate::client::state::obtain_cookie_from_password_server(
-                            &password_server,
-                            &stored_configs,
+    /// Reconnect the primary connection when the system DNS servers change
+    pub reconnect_on_dns_change: bool,
+    pub search_domains: Vec<String>,
+    pub dtls_fallback: bool,
+
+    /// Overrides the platform stored for the server
+    pub reported_os: Option<String>,
+
+    /// Overrides the proxy stored for the server
+    pub proxy: Option<String>,
+
+    /// The profile of the server to connect with, see [StoredServer::profile]
+    pub profile: Option<String>,
+
+    /// Build the server from the environment instead of reading the config file, see [openconnect_core::env_server]
+    pub from_env: bool,
+
+    /// The options of [StartOptions::profile], set by [select_profile]
+    pub server_profile: ServerProfile,
+
+    /// Reject gateways negotiating an older TLS version
+    pub min_tls_version: Option<TlsVersion>,
+
+    /// Reject gateways negotiating a cipher outside of this list
+    pub ciphers: Option<String>,
+
+    /// Extra headers of the login requests
+    pub http_headers: Vec<(String, String)>,
+
+    /// Connect to the gateway over this address family only
+    pub transport_family: Option<AddressFamily>,
+
+    /// Terminate an existing session on the gateway instead of failing the login
+    pub force: bool,
+
+    /// Log in to an OIDC server with the authorization code flow in the browser instead of the device flow
+    pub oidc_browser: bool,
+
+    /// The fingerprint of the gateway certificate to trust even if it fails verification
+    pub accept_cert: Option<String>,
+
+    /// Refuse to reconnect when the gateway certificate changed, instead of awaiting `trust-cert`
+    pub strict_cert: bool,
+
+    /// Copy the TOS bits to the encapsulating packets, for an upstream connection as well to keep them end to end
+    pub pass_tos: bool,
+
+    /// Extra variables of the vpnc-script environment
+    pub script_env: HashMap<String, String>,
+
+    /// Append the progress messages of libopenconnect to this file, for the login and the connection
+    pub openconnect_log: Option<PathBuf>,
+    pub openconnect_log_level: LogLevel,
+
+    /// Print the session cookie instead of fetching the parameters in info only mode
+    pub print_cookie: bool,
+
+    /// Block until the daemon reports the connection as established, or fail after this timeout
+    pub wait: Option<Duration>,
+
+    /// A pre-created tun device the daemon connects through
+    pub tun_device: Option<TunDevice>,
+}
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Ask the daemon for the connection status, `None` if it is not (yet) able to answer
+pub(crate) async fn poll_status() -> Option<ConnectionStatus> {
+    let mut client = sock::DaemonClient::connect().await.ok()?;
+    client.send(JsonRequest::Info).await.ok()?;
+
+    match tokio::time::timeout(WAIT_POLL_INTERVAL, client.framed_reader.try_next()).await {
+        Ok(Ok(Some(JsonResponse::InfoResult { status, .. }))) => Some(*status),
+        _ => None,
+    }
+}
+
+/// Poll the daemon until the connection is established, fails, or the timeout expires
+async fn wait_until_connected(timeout: Duration) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        match poll_status().await.map(|status| status.status) {
+            Some(Status::Connected) => return Ok(()),
+            Some(Status::Error(_)) => {
+                return Err("the daemon reported a connection error".to_string())
+            }
+            _ => {}
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!(
+                "timed out after {}s waiting for the connection",
+                timeout.as_secs()
+            ));
+        }
+        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+    }
+}
+
+/// The config the login is made with, the connection settings of [connection_config] which apply before the tunnel is up
+pub fn login_config(
+    options: &StartOptions,
+    defaults: &Defaults,
+    http_proxy: Option<&str>,
+) -> Result<Config, StateError> {
+    Ok(Config {
+        openconnect_log: options
+            .openconnect_log
+            .as_deref()
+            .map(paths::openconnect_log_file)
+            .transpose()?,
+        openconnect_log_level: options.openconnect_log_level,
+        ..connection_config(
+            defaults,
+            options.cert_expiry_warning_days,
+            vec![],
+            http_proxy,
+            options.min_tls_version,
+            options.ciphers.as_deref(),
+            options.transport_family,
+        )?
+    })
+}
+
+/// The outcome of logging in to a stored server
+struct Login {
+    cookie: Option<String>,
+    name: String,
+    server: String,
+    allow_insecure: Option<bool>,
+    /// The gateway the login was redirected to
+    redirect: Option<String>,
+    /// Ends the session at the OIDC issuer when the connection is stopped
+    oidc_logout: Option<OpenIDLogoutConfig>,
+}
+
+async fn obtain_cookie(
+    stored_server: &StoredServer,
+    stored_configs: &StoredConfigs,
+    options: &StartOptions,
+    reported_os: Option<&str>,
+    http_proxy: Option<&str>,
+    http_headers: &[(String, String)],
+    accept_cert: Option<&str>,
+) -> Login {
+    match stored_server {
+        StoredServer::Password(password_server) => {
+            let cookie = with_login_retries(move || async move {
+                obtain_cookie_from_password_server(
+                    password_server,
+                    stored_configs,
+                    login_config(options, &stored_configs.defaults, http_proxy)?,
+                    reported_os,
+                    http_headers,
+                    accept_cert,
+                    options.force,
+                )
+                .await
+            })
+            .await;
+
+            let (cookie, redirect) = match cookie {
+                Ok(login) => login,
+                Err(StateError::OpenconnectError(e @ OpenconnectError::SessionAlreadyActive)) => {
+                    eprintln!(
+                        "{}",
+                        format!("\n{}, start with --force to terminate it", e).red()
+                    );
+                    (None, None)
+                }
+                Err(StateError::OpenconnectError(
+                    e @ OpenconnectError::CertFingerprintMismatch(_),
+                )) => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "\n{}, start with --accept-cert <FINGERPRINT> if the gateway renewed its certificate",
+                            e
                         )
-                        .await;
+                        .red()
+                    );
+                    (None, None)
+                }
+                Err(e) => {
+                    tracing::error!("Failed to obtain cookie: {}", e);
+                    (None, None)
+                }
+            };
 
-                        let cookie = match cookie {
-                            Ok(cookie) => cookie,
-                            Err(e) => {
-                                tracing::error!("Failed to obtain cookie: {}", e);
-                                None
-                            }
-                        };
+            Login {
+                cookie,
+                name: password_server.name.clone(),
+                server: password_server.server.clone(),
+                allow_insecure: password_server.allow_insecure,
+                redirect,
+                oidc_logout: None,
+            }
+        }
+        StoredServer::Oidc(oidc_server) => {
+            let cookie_res =
+                obtain_cookie_from_oidc_server(oidc_server, stored_configs, options.oidc_browser)
+                    .await;
+
+            let (cookie, oidc_logout) = match cookie_res {
+                Ok(login) => login,
+                Err(e) => {
+                    tracing::error!("Failed to obtain cookie: {}", e);
+                    (None, None)
+                }
+            };
 
+            Login {
+                cookie,
+                name: oidc_server.name.clone(),
+                server: oidc_server.server.clone(),
+                allow_insecure: oidc_server.allow_insecure,
+                redirect: None,
+                oidc_logout,
+            }
+
+            // TODO: optimize error message handling
+        }
+    }
+}
+
+/// Authenticate against the server and ask the daemon to connect to it, exits the process on failure
+async fn start_connection(
+    unix_client: &mut sock::DaemonClient,
+    stored_server: &StoredServer,
+    stored_configs: &StoredConfigs,
+    options: &StartOptions,
+    role: ConnectionRole,
+) {
+    let reported_os = effective_reported_os(stored_server, options, &role);
+    let http_proxy = effective_proxy(stored_server, options, &role);
+
+    let Login {
+        cookie,
+        name,
+        server,
+        allow_insecure,
+        redirect,
+        oidc_logout,
+    } = obtain_cookie(
+        stored_server,
+        stored_configs,
+        options,
+        reported_os.as_deref(),
+        http_proxy.as_deref(),
+        effective_http_headers(options, &role),
+        effective_accept_cert(stored_server, options, &role),
+    )
+    .await;
+
+    let Some(cookie) = cookie else {
+        unix_client
+            .send(JsonRequest::Stop)
+            .await
+            .expect("Failed to send stop command");
+
+        eprintln!(
+            "{}",
+            "\nFailed to obtain cookie, check logs for more information".red()
+        ); // TODO: improve error message
+        std::process::exit(1);
+    };
+
+    println!("Obtained cookie from server: {}", name);
+
+    // lets the daemon log in again with credentials supplied by `reauth`
+    let (username, form_defaults) = match stored_server {
+        StoredServer::Password(password_server) => (
+            Some(password_server.username.clone()),
+            password_server.form_defaults.clone(),
+        ),
+        StoredServer::Oidc(_) => (None, HashMap::new()),
+    };
+
+    unix_client
+        .send(JsonRequest::Start(Box::new(StartParams {
+            name,
+            server,
+            redirect,
+            allow_insecure: allow_insecure.unwrap_or(false),
+            cookie,
+            username,
+            oidc_logout,
+            form_defaults,
+            kill_switch: options.kill_switch,
+            metrics_addr: options.metrics_addr,
+            cert_expiry_warning_days: options.cert_expiry_warning_days,
+            reconnect_jitter: options.reconnect_jitter,
+            reconnect_policy: options.reconnect_policy,
+            reconnect_on_dns_change: options.reconnect_on_dns_change,
+            search_domains: effective_search_domains(options, &role),
+            dtls_fallback: options.dtls_fallback,
+            reported_os,
+            http_proxy,
+            min_tls_version: options.min_tls_version,
+            ciphers: options.ciphers.clone(),
+            accept_cert: effective_accept_cert(stored_server, options, &role).map(str::to_string),
+            strict_cert: options.strict_cert,
+            pass_tos: options.pass_tos,
+            script_env: options.script_env.clone(),
+            openconnect_log: options.openconnect_log.clone(),
+            openconnect_log_level: options.openconnect_log_level,
+            tun_device: options.tun_device.clone(),
+            transport_family: options.transport_family,
+            probe_host: stored_server.probe_host().map(str::to_string),
+            verify_url: stored_server
+                .verify_url()
+                .map(|(verify_url, expect_status)| (verify_url.to_string(), expect_status)),
+            trusted_networks: stored_server.trusted_networks().to_vec(),
+            udp_policy: stored_server.udp_policy().to_vec(),
+            expected_subnet: stored_server.expected_subnet().map(str::to_string),
+            mtu: stored_server.mtu(),
+            protocol: stored_server.protocol().map(|protocol| protocol.name),
+            client_cert: match stored_server {
+                StoredServer::Password(password_server) => {
+                    let password_server = password_server.decrypted_by(&stored_configs.cipher);
+                    password_server.client_cert.map(|client_cert| {
                         (
-                            cookie,
-                            password_server.name,
-                            password_server.server,
-                            password_server.allow_insecure,
+                            client_cert,
+                            password_server.client_key,
+                            password_server.key_password,
                         )
-                    }
-                    StoredServer::Oidc(oidc_server) => {
-                        let cookie_res = crate::client::state::obtain_cookie_from_oidc_server(
-                            &oidc_server,
-                            &stored_configs,
+                    })
+                }
+                StoredServer::Oidc(_) => None,
+            },
+            enable_udp: effective_enable_udp(options, &role),
+            profile: options
+                .profile
+                .clone()
+                .filter(|_| role != ConnectionRole::Upstream),
+            role,
+        })))
+        .await
+        .expect("Failed to send start command");
+
+    if let Ok(Some(response)) = unix_client.framed_reader.try_next().await {
+        match response {
+            JsonResponse::StartResult {
+                name,
+                success,
+                err_message,
+            } => {
+                if success {
+                    println!("\nStarted connection to server: {}", name);
+                } else {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "\nFailed to start connection: {}",
+                            err_message.unwrap_or("Unknown error".to_string())
                         )
-                        .await;
+                        .red()
+                    );
+                    std::process::exit(1);
+                }
+            }
+            _ => {
+                eprintln!("{}", "\nReceived unexpected response".red());
+            }
+        }
+    }
+}
+
+pub fn request_start_server(name: String, config_file: PathBuf, mut options: StartOptions) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    runtime.block_on(async {
+        Logger::init_for_session(&name).expect("Failed to initialize logger");
+
+        match crate::client::config::read_start_server(&name, config_file, options.from_env).await {
+            Ok((stored_server, stored_configs)) => {
+                let mut unix_client = sock::DaemonClient::connect()
+                    .await
+                    .expect("Failed to connect to daemon");
+
+                if let Err(e) = select_profile(&stored_server, &mut options) {
+                    eprintln!("{}", format!("\n{}", e).red());
+                    let _ = unix_client.send(JsonRequest::Stop).await;
+                    std::process::exit(1);
+                }
 
-                        let cookie = match cookie_res {
-                            Ok(cookie) => cookie,
-                            Err(e) => {
-                                tracing::error!("Failed to obtain cookie: {}", e);
-                                None
+                // the login would only time out behind a portal, the first hop is checked for any connection
+                if let Err(e) = captive_portal::check().await {
+                    eprintln!("{}", format!("\n{}", e).red());
+                    let _ = unix_client.send(JsonRequest::Stop).await;
+                    std::process::exit(1);
+                }
+
+                // the vpnc-script of a profile is run for the primary connection only
+                let primary_defaults = effective_defaults(
+                    &stored_configs.defaults,
+                    &options,
+                    &ConnectionRole::Primary,
+                );
+                for defaults in [&stored_configs.defaults, &primary_defaults] {
+                    if let Err(e) = ensure_vpnc_script_executable(defaults) {
+                        eprintln!("{}", format!("\n{}", e).red());
+                        let _ = unix_client.send(JsonRequest::Stop).await;
+                        std::process::exit(1);
+                    }
+                }
+
+                let role = match stored_server.connect_via() {
+                    Some(via) => {
+                        let upstream = match stored_configs.servers.get(via) {
+                            Some(upstream) if upstream.connect_via().is_none() => upstream.clone(),
+                            Some(_) => {
+                                eprintln!(
+                                    "{}",
+                                    format!(
+                                        "\nServer {} is itself connected via another server, only one level is supported",
+                                        via
+                                    )
+                                    .red()
+                                );
+                                let _ = unix_client.send(JsonRequest::Stop).await;
+                                std::process::exit(1);
+                            }
+                            None => {
+                                eprintln!(
+                                    "{}",
+                                    format!("\nServer {} to connect via not found", via).red()
+                                );
+                                let _ = unix_client.send(JsonRequest::Stop).await;
+                                std::process::exit(1);
                             }
                         };
 
-                        (
-                            cookie,
-                            oidc_server.name,
-                            oidc_server.server,
-                            oidc_server.allow_insecure,
+                        println!("Connecting via server: {}", via);
+                        start_connection(
+                            &mut unix_client,
+                            &upstream,
+                            &stored_configs,
+                            &options,
+                            ConnectionRole::Upstream,
                         )
+                        .await;
 
-                        // TODO: optimize error message handling
+                        ConnectionRole::Via(via.to_string())
                     }
+                    None => ConnectionRole::Primary,
                 };
 
-                let mut unix_client = sock::UnixDomainClient::connect()
-                    .await
-                    .expect("Failed to connect to daemon");
+                // authenticate only now, the server may only be reachable through the upstream tunnel
+                start_connection(
+                    &mut unix_client,
+                    &stored_server,
+                    &stored_configs,
+                    &options,
+                    role,
+                )
+                .await;
 
-                if let Some(cookie) = cookie {
-                    println!("Obtained cookie from server");
-
-                    unix_client
-                        .send(JsonRequest::Start {
-                            name,
-                            server,
-                            allow_insecure: allow_insecure.unwrap_or(false),
-                            cookie,
-                        })
-                        .await
-                        .expect("Failed to send start command");
-
-                    if let Ok(Some(response)) = unix_client.framed_reader.try_next().await {
-                        match response {
-                            JsonResponse::StartResult {
-                                name,
-                                success,
-                                err_message,
-                            } => {
-                                if success {
-                                    println!("\nStarted connection to server: {}", name);
-                                } else {
-                                    eprintln!(
-                                        "{}",
-                                        format!(
-                                            "\nFailed to start connection: {}",
-                                            err_message.unwrap_or("Unknown error".to_string())
-                                        )
-                                        .red()
-                                    );
-                                    std::process::exit(1);
-                                }
-                            }
-                            _ => {
-                                eprintln!("{}", "\nReceived unexpected response".red());
-                            }
+                if let Some(timeout) = options.wait {
+                    match wait_until_connected(timeout).await {
+                        Ok(()) => println!("Connected to {}", name),
+                        Err(e) => {
+                            eprintln!("{}", format!("\nFailed to connect: {}", e).red());
+                            std::process::exit(1);
                         }
                     }
-                } else {
-                    unix_client
-                        .send(JsonRequest::Stop)
-                        .await
-                        .expect("Failed to send stop command");
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to get server: {}", e).red());
+                std::process::exit(1);
+            }
+        }
+    });
+}
 
-                    eprintln!(
-                        "{}",
-                        "\nFailed to obtain cookie, check logs for more information".red()
-                    ); // TODO: improve error message
+/// Authenticate and print the parameters pushed by the gateway, without creating a tun device or touching routes
+pub fn request_info_only(name: String, config_file: PathBuf, mut options: StartOptions) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    runtime.block_on(async {
+        Logger::init_for_session(&name).expect("Failed to initialize logger");
+
+        let (stored_server, stored_configs) =
+            match crate::client::config::read_start_server(&name, config_file, options.from_env).await {
+                Ok(server) => server,
+                Err(e) => {
+                    eprintln!("{}", format!("\nFailed to get server: {}", e).red());
                     std::process::exit(1);
                 }
+            };
+
+        if let Some(via) = stored_server.connect_via() {
+            eprintln!(
+                "{}",
+                format!(
+                    "\nServer is connected via {}, info only mode does not bring up its tunnel",
+                    via
+                )
+                .red()
+            );
+            std::process::exit(1);
+        }
+
+        if let Err(e) = select_profile(&stored_server, &mut options) {
+            eprintln!("{}", format!("\n{}", e).red());
+            std::process::exit(1);
+        }
+
+        if let Err(e) = captive_portal::check().await {
+            eprintln!("{}", format!("\n{}", e).red());
+            std::process::exit(1);
+        }
+
+        let reported_os = effective_reported_os(&stored_server, &options, &ConnectionRole::Primary);
+        let http_proxy = effective_proxy(&stored_server, &options, &ConnectionRole::Primary);
+        // the session is ended right away by the disconnect of the info only mode, the issuer session is kept
+        let Login {
+            cookie,
+            name,
+            server,
+            allow_insecure,
+            redirect,
+            ..
+        } = obtain_cookie(
+            &stored_server,
+            &stored_configs,
+            &options,
+            reported_os.as_deref(),
+            http_proxy.as_deref(),
+            &options.http_headers,
+            effective_accept_cert(&stored_server, &options, &ConnectionRole::Primary),
+        )
+        .await;
+        let Some(cookie) = cookie else {
+            eprintln!(
+                "{}",
+                "\nFailed to obtain cookie, check logs for more information".red()
+            );
+            std::process::exit(1);
+        };
+
+        if options.print_cookie {
+            // the warning goes to stderr, so that only the cookie is piped to another tool
+            eprintln!(
+                "{}",
+                "\nWARNING: the session cookie grants access to the VPN until it expires, never share or store it"
+                    .yellow()
+                    .bold()
+            );
+            println!("{}", cookie);
+            return;
+        }
+
+        let result = async {
+            let config = connection_config(
+                &stored_configs.defaults,
+                options.cert_expiry_warning_days,
+                effective_search_domains(&options, &ConnectionRole::Primary),
+                http_proxy.as_deref(),
+                options.min_tls_version,
+                options.ciphers.as_deref(),
+                options.transport_family,
+            )?;
+            let mut entrypoint = connection_entrypoint(
+                &name,
+                redirect.as_deref().unwrap_or(&server),
+                allow_insecure.unwrap_or(false),
+                Some(&cookie),
+                options.dtls_fallback,
+                reported_os.as_deref(),
+                stored_server
+                    .protocol()
+                    .or_else(|| stored_configs.defaults.protocol()),
+            );
+            if let Some(expected_subnet) = stored_server.expected_subnet() {
+                entrypoint.expected_subnet(expected_subnet);
+            }
+            if let Some(mtu) = stored_server.mtu() {
+                entrypoint.mtu(mtu);
+            }
+            if let Some(accept_cert) =
+                effective_accept_cert(&stored_server, &options, &ConnectionRole::Primary)
+            {
+                entrypoint.accept_cert(accept_cert);
+            }
+            let entrypoint = entrypoint.build()?;
+
+            let client = VpnClient::new(config, EventHandlers::default())?;
+            Ok::<_, StateError>(
+                tokio::task::spawn_blocking(move || client.fetch_info(entrypoint)).await??,
+            )
+        }
+        .await;
+
+        match result {
+            Ok(info) => {
+                let mut rows = vec![
+                    vec![format!("Server Name"), name],
+                    vec![format!("Server URL"), server],
+                ];
+                match info {
+                    Some(info) => {
+                        rows.extend(ip_info_rows(info));
+                    }
+                    None => {
+                        rows.push(vec![
+                            format!("IP Info"),
+                            format!("Not pushed by the server"),
+                        ]);
+                    }
+                }
+                output::print_rows(rows);
+                println!("Disconnected, no tun device was created");
             }
             Err(e) => {
-                eprintln!("{}", format!("\nFailed to get server: {}", e).red());
+                eprintln!("{}", format!("\nFailed to fetch info: {}", e).red());
                 std::process::exit(1);
             }
         }
     });
 }
 
-pub fn request_stop_server() {
+pub fn request_stop_server(timeout: Duration) {
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
     runtime.block_on(async {
-        let client = sock::UnixDomainClient::connect().await;
+        let client = sock::DaemonClient::connect().await;
 
         match client {
             Ok(mut client) => {
@@ -320,7 +1468,7 @@ pub fn request_stop_server() {
                     .await
                     .expect("Failed to send stop command");
 
-                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                if let Some(response) = receive_or_exit(&mut client, timeout).await {
                     match response {
                         JsonResponse::StopResult { name: server_name } => {
                             println!("\nStopped connection to server: {}", server_name)
@@ -338,3 +1486,264 @@ pub fn request_stop_server() {
         };
     });
 }
+
+pub fn request_set_auto_reconnect(enabled: bool) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::SetAutoReconnect(enabled))
+                    .await
+                    .expect("Failed to send auto-reconnect command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::SetAutoReconnectResult { enabled } => {
+                            let state = if enabled { "enabled" } else { "disabled" };
+                            println!("\nAuto-reconnect {}", state)
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
+
+pub fn request_reapply_config() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::ReapplyConfig)
+                    .await
+                    .expect("Failed to send reapply command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::ReapplyConfigResult { success: true, .. } => {
+                            println!("\nNetwork configuration applied again");
+                        }
+                        JsonResponse::ReapplyConfigResult { err_message, .. } => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "\nFailed to apply the network configuration: {}",
+                                    err_message.unwrap_or_default()
+                                )
+                                .red()
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
+
+/// Ask the daemon to measure the round-trip time through the tunnel and print min/avg/max
+pub fn request_rekey() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::Rekey)
+                    .await
+                    .expect("Failed to send rekey command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::RekeyResult {
+                            cipher: Some(cipher),
+                            ..
+                        } => {
+                            println!("\nSession rekeyed, cipher {}", cipher);
+                        }
+                        JsonResponse::RekeyResult { err_message, .. } => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "\nFailed to rekey the session: {}",
+                                    err_message.unwrap_or_default()
+                                )
+                                .red()
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        }
+    });
+}
+
+pub fn request_latency_probe(count: u32, target: Option<String>) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::LatencyProbe { count, target })
+                    .await
+                    .expect("Failed to send latency probe command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::LatencyProbeResult {
+                            latency: Some(latency),
+                            ..
+                        } => {
+                            println!(
+                                "\n{}: {} sent, {} received, rtt min/avg/max = {:.1}/{:.1}/{:.1} ms",
+                                latency.target,
+                                latency.sent,
+                                latency.received,
+                                latency.min_ms,
+                                latency.avg_ms,
+                                latency.max_ms
+                            );
+                        }
+                        JsonResponse::LatencyProbeResult { err_message, .. } => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "\nFailed to measure the latency: {}",
+                                    err_message.unwrap_or_default()
+                                )
+                                .red()
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
+
+pub fn request_supply_credentials(totp: Option<String>) {
+    let password = dialoguer::Password::new()
+        .with_prompt("Enter password")
+        .interact()
+        .expect("Failed to get password");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::SupplyCredentials { password, totp })
+                    .await
+                    .expect("Failed to send credentials");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::SupplyCredentialsResult { accepted: true } => {
+                            println!("\nCredentials supplied, the daemon logs in again");
+                        }
+                        JsonResponse::SupplyCredentialsResult { accepted: false } => {
+                            eprintln!("{}", "\nThe daemon is not awaiting credentials".red());
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
+
+pub fn request_trust_cert(fingerprint: String) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+
+    runtime.block_on(async {
+        let client = sock::DaemonClient::connect().await;
+
+        match client {
+            Ok(mut client) => {
+                client
+                    .send(JsonRequest::TrustCert { fingerprint })
+                    .await
+                    .expect("Failed to send trust cert command");
+
+                if let Ok(Some(response)) = client.framed_reader.try_next().await {
+                    match response {
+                        JsonResponse::TrustCertResult { accepted: true, .. } => {
+                            println!("\nCertificate trusted, the daemon reconnects");
+                        }
+                        JsonResponse::TrustCertResult { err_message, .. } => {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "\nThe certificate was not trusted: {}",
+                                    err_message.unwrap_or_default()
+                                )
+                                .red()
+                            );
+                            std::process::exit(1);
+                        }
+                        _ => {
+                            println!("Received unexpected response");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", format!("\nFailed to connect to server: {}", e).red());
+                std::process::exit(1);
+            }
+        };
+    });
+}
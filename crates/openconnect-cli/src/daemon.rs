@@ -1,44 +1,242 @@
-use colored::Colorize;
+use openconnect_core::paths;
+#[cfg(test)]
+use std::collections::VecDeque;
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum ForkResult {
     Parent,
     Child,
     Grandchild,
 }
 
-pub fn daemonize() -> ForkResult {
-    unsafe {
-        let pid = libc::fork();
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+    #[error("Failed to fork {0} process: {1}")]
+    Fork(&'static str, #[source] std::io::Error),
 
-        if pid < 0 {
-            eprintln!("{}", "\nFailed to fork child process".red());
-            std::process::exit(1);
-        } else if pid != 0 {
-            // Parent process
-            // std::process::exit(0);
-            return ForkResult::Parent;
+    #[error("Failed to create a new session: {0}")]
+    Setsid(#[source] std::io::Error),
+
+    #[error("Failed to wait for child process: {0}")]
+    Wait(#[source] std::io::Error),
+
+    #[error("Child process exited with status {0}, the daemon was not started")]
+    ChildFailed(i32),
+}
+
+/// The system calls used to daemonize, abstracted so that the transitions can be tested without forking
+trait DaemonSys {
+    /// Returns the pid of the child in the parent, and 0 in the child
+    fn fork(&mut self) -> std::io::Result<libc::pid_t>;
+    fn setsid(&mut self) -> std::io::Result<()>;
+
+    /// Wait for the given child to exit and return its exit status
+    fn wait(&mut self, pid: libc::pid_t) -> std::io::Result<i32>;
+    fn detach_stdio(&mut self);
+}
+
+struct LibcSys;
+
+impl DaemonSys for LibcSys {
+    fn fork(&mut self) -> std::io::Result<libc::pid_t> {
+        match unsafe { libc::fork() } {
+            -1 => Err(std::io::Error::last_os_error()),
+            pid => Ok(pid),
         }
+    }
 
-        libc::setsid();
+    fn setsid(&mut self) -> std::io::Result<()> {
+        match unsafe { libc::setsid() } {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
 
-        let pid = libc::fork();
+    fn wait(&mut self, pid: libc::pid_t) -> std::io::Result<i32> {
+        let mut status = 0;
+        loop {
+            if unsafe { libc::waitpid(pid, &mut status, 0) } != -1 {
+                break;
+            }
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        if libc::WIFEXITED(status) {
+            Ok(libc::WEXITSTATUS(status))
+        } else {
+            Ok(-1)
+        }
+    }
 
-        if pid < 0 {
-            eprintln!("{}", "\nFailed to fork grand-child process".red());
-            std::process::exit(1);
-        } else if pid != 0 {
-            // Child process
-            // std::process::exit(0);
-            return ForkResult::Child;
+    fn detach_stdio(&mut self) {
+        unsafe {
+            libc::umask(0);
+            libc::close(libc::STDIN_FILENO);
+            libc::close(libc::STDOUT_FILENO);
+            libc::close(libc::STDERR_FILENO);
         }
+    }
+}
+
+fn daemonize_with(sys: &mut impl DaemonSys) -> Result<ForkResult, DaemonError> {
+    let pid = sys.fork().map_err(|e| DaemonError::Fork("child", e))?;
+    if pid != 0 {
+        // the child exits right after forking the grandchild, reap it and surface its failures
+        return match sys.wait(pid).map_err(DaemonError::Wait)? {
+            0 => Ok(ForkResult::Parent),
+            status => Err(DaemonError::ChildFailed(status)),
+        };
+    }
+
+    sys.setsid().map_err(DaemonError::Setsid)?;
+
+    let pid = sys.fork().map_err(|e| DaemonError::Fork("grandchild", e))?;
+    if pid != 0 {
+        return Ok(ForkResult::Child);
+    }
+
+    sys.detach_stdio();
+    Ok(ForkResult::Grandchild)
+}
+
+/// Detach the daemon by forking twice
+///
+/// Errors in the child are returned in the child, which should report them and exit non-zero, so that the parent fails with [DaemonError::ChildFailed]
+pub fn daemonize() -> Result<ForkResult, DaemonError> {
+    daemonize_with(&mut LibcSys)
+}
+
+/// Fork only the client, so that the daemon stays in the foreground as the started process, e.g. under systemd
+///
+/// Returns the pid of the client in the daemon, and `None` in the client
+pub fn fork_client() -> Result<Option<libc::pid_t>, DaemonError> {
+    match LibcSys.fork().map_err(|e| DaemonError::Fork("client", e))? {
+        0 => Ok(None),
+        pid => Ok(Some(pid)),
+    }
+}
+
+/// Wait for the client forked by [fork_client] to exit and return its exit status
+pub fn wait_client(pid: libc::pid_t) -> Result<i32, DaemonError> {
+    LibcSys.wait(pid).map_err(DaemonError::Wait)
+}
+
+/// PID file of the daemon process, removed when dropped
+pub struct PidFile;
+
+impl PidFile {
+    pub fn create() -> std::io::Result<Self> {
+        std::fs::write(paths::pid_file(), std::process::id().to_string())?;
+        Ok(PidFile)
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(paths::pid_file());
+    }
+}
+
+/// Plays back the results of the system calls of one process
+#[cfg(test)]
+#[derive(Default)]
+struct MockSys {
+    forks: VecDeque<std::io::Result<libc::pid_t>>,
+    setsid: Option<std::io::Error>,
+    wait_status: i32,
+    detached: bool,
+}
+
+#[cfg(test)]
+impl DaemonSys for MockSys {
+    fn fork(&mut self) -> std::io::Result<libc::pid_t> {
+        self.forks.pop_front().expect("unexpected fork")
+    }
 
-        libc::umask(0);
-        libc::close(libc::STDIN_FILENO);
-        libc::close(libc::STDOUT_FILENO);
-        libc::close(libc::STDERR_FILENO);
+    fn setsid(&mut self) -> std::io::Result<()> {
+        self.setsid.take().map_or(Ok(()), Err)
+    }
+
+    fn wait(&mut self, _pid: libc::pid_t) -> std::io::Result<i32> {
+        Ok(self.wait_status)
+    }
 
-        ForkResult::Grandchild
+    fn detach_stdio(&mut self) {
+        self.detached = true;
     }
+}
+
+#[cfg(test)]
+fn eagain() -> std::io::Error {
+    std::io::Error::from_raw_os_error(libc::EAGAIN)
+}
+
+#[test]
+fn test_parent_waits_for_child() {
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(42)]),
+        ..Default::default()
+    };
+    assert_eq!(daemonize_with(&mut sys).unwrap(), ForkResult::Parent);
+
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(42)]),
+        wait_status: 1,
+        ..Default::default()
+    };
+    assert!(matches!(
+        daemonize_with(&mut sys),
+        Err(DaemonError::ChildFailed(1))
+    ));
+}
+
+#[test]
+fn test_child_and_grandchild() {
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(0), Ok(43)]),
+        ..Default::default()
+    };
+    assert_eq!(daemonize_with(&mut sys).unwrap(), ForkResult::Child);
+    assert!(!sys.detached);
+
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(0), Ok(0)]),
+        ..Default::default()
+    };
+    assert_eq!(daemonize_with(&mut sys).unwrap(), ForkResult::Grandchild);
+    assert!(sys.detached);
+}
+
+#[test]
+fn test_fork_and_setsid_failures() {
+    let mut sys = MockSys {
+        forks: VecDeque::from([Err(eagain())]),
+        ..Default::default()
+    };
+    assert!(matches!(
+        daemonize_with(&mut sys),
+        Err(DaemonError::Fork("child", _))
+    ));
+
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(0)]),
+        setsid: Some(std::io::Error::from_raw_os_error(libc::EPERM)),
+        ..Default::default()
+    };
+    assert!(matches!(
+        daemonize_with(&mut sys),
+        Err(DaemonError::Setsid(_))
+    ));
 
-    // Daemon process
+    let mut sys = MockSys {
+        forks: VecDeque::from([Ok(0), Err(eagain())]),
+        ..Default::default()
+    };
+    assert!(matches!(
+        daemonize_with(&mut sys),
+        Err(DaemonError::Fork("grandchild", _))
+    ));
 }
@@ -0,0 +1,118 @@
+//! Kill switch blocking all egress traffic which does not go through the tunnel
+//!
+//! The rules live in a dedicated nftables table, so they stay in the kernel if the daemon crashes (fail closed).
+//! They are only removed on a clean `stop`, or explicitly with `openconnect kill-switch clear`.
+//!
+//! While the kill switch is active, the following traffic is still allowed:
+//! - loopback
+//! - the tunnel interface
+//! - the VPN gateway address, so that the tunnel can be re-established
+//! - DNS queries from root processes, so that the daemon can resolve the gateway again when reconnecting
+//! - DHCP, so that the physical link keeps its lease
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+pub const TABLE_NAME: &str = "openconnect_rs_killswitch";
+
+#[derive(Debug, thiserror::Error)]
+pub enum KillSwitchError {
+    #[error("Kill switch is only supported on Linux")]
+    Unsupported,
+
+    #[error("Missing {0} to set up kill switch")]
+    MissingInfo(&'static str),
+
+    #[error("Failed to run nft: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("nft exited with error: {0}")]
+    Nft(String),
+}
+
+fn ruleset(ifname: &str, gateway: &str) -> String {
+    let gateway_rule = if gateway.contains(':') {
+        format!("ip6 daddr {} accept", gateway)
+    } else {
+        format!("ip daddr {} accept", gateway)
+    };
+
+    // declaring the table before deleting it makes the replacement atomic, even if it doesn't exist yet
+    format!(
+        r#"table inet {table}
+delete table inet {table}
+table inet {table} {{
+    chain output {{
+        type filter hook output priority 0; policy drop;
+        oifname "lo" accept
+        oifname "{ifname}" accept
+        {gateway_rule}
+        meta skuid 0 udp dport 53 accept
+        meta skuid 0 tcp dport 53 accept
+        udp sport 68 udp dport 67 accept
+        udp sport 546 udp dport 547 accept
+    }}
+}}
+"#,
+        table = TABLE_NAME,
+        ifname = ifname,
+        gateway_rule = gateway_rule,
+    )
+}
+
+fn run_nft(args: &[&str], stdin: Option<&str>) -> Result<String, KillSwitchError> {
+    if !cfg!(target_os = "linux") {
+        return Err(KillSwitchError::Unsupported);
+    }
+
+    let mut child = Command::new("nft")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let (Some(input), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+        child_stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(KillSwitchError::Nft(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Install (or replace) the kill switch rules for the given tunnel interface and gateway address
+pub fn install(ifname: Option<String>, gateway: Option<String>) -> Result<(), KillSwitchError> {
+    let ifname = ifname.ok_or(KillSwitchError::MissingInfo("tunnel interface name"))?;
+    let gateway = gateway.ok_or(KillSwitchError::MissingInfo("gateway address"))?;
+
+    run_nft(&["-f", "-"], Some(&ruleset(&ifname, &gateway)))?;
+    tracing::info!(
+        "Kill switch installed for interface {} and gateway {}",
+        ifname,
+        gateway
+    );
+    Ok(())
+}
+
+pub fn is_installed() -> bool {
+    run_nft(&["list", "table", "inet", TABLE_NAME], None).is_ok()
+}
+
+/// Remove the kill switch rules, it is not an error if they are not installed
+pub fn clear() -> Result<(), KillSwitchError> {
+    if !is_installed() {
+        return Ok(());
+    }
+
+    run_nft(&["delete", "table", "inet", TABLE_NAME], None)?;
+    tracing::info!("Kill switch cleared");
+    Ok(())
+}
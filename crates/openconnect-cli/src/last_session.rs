@@ -0,0 +1,39 @@
+//! Summary of the last session, written by the daemon on a clean disconnect
+//!
+//! It lets `status --last` show the parameters of the previous session once the daemon is gone.
+//! No secrets are involved, so it is stored next to the config file.
+
+use chrono::{DateTime, Utc};
+use openconnect_core::{ip_info::IpInfo, paths};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct LastSession {
+    pub server_name: String,
+    pub server_url: String,
+    pub hostname: String,
+    pub connected_via: Option<String>,
+    pub tls_version: Option<String>,
+
+    /// How long the connection was up before the disconnect
+    pub duration_secs: Option<u64>,
+    pub disconnected_at: DateTime<Utc>,
+    pub info: Option<IpInfo>,
+}
+
+impl LastSession {
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(paths::last_session_file()?, json)
+    }
+
+    /// The last saved session, `None` if no session was saved yet
+    pub fn load() -> std::io::Result<Option<Self>> {
+        let json = match std::fs::read_to_string(paths::last_session_file()?) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+}
@@ -1,24 +1,217 @@
 mod cli;
 mod client;
 mod daemon;
+mod killswitch;
+mod last_session;
+mod output;
 mod server;
+mod server_stats;
 mod sock;
+mod tunnels;
 
 use clap::Parser;
-use cli::{Cli, Commands};
-use openconnect_core::{ip_info::IpInfo, log::Logger, storage::StoredConfigs};
-use std::{io::BufRead, path::PathBuf};
+use cli::{Cli, Commands, ConfigAction, KillSwitchAction, LogsAction};
+use client::config::DeleteSelection;
+use colored::Colorize;
+use openconnect_core::{
+    config::{AddressFamily, LogLevel, ReconnectPolicy, TlsVersion, TunDevice},
+    env_server,
+    ip_info::IpInfo,
+    log::{LogRecord, Logger},
+    paths,
+    result::OpenconnectError,
+    stats::Stats,
+    storage::StoredConfigs,
+    Status,
+};
+use openconnect_oidc::oidc_logout::OpenIDLogoutConfig;
+use std::{collections::HashMap, io::BufRead, net::SocketAddr, path::PathBuf};
+
+/// How long the daemon waits for in-progress connects and main loops to stop on exit
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How a started connection relates to the other connection of the daemon
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionRole {
+    /// A standalone connection
+    Primary,
+
+    /// Established first, so that the primary connection can be made through it
+    Upstream,
+
+    /// A primary connection made through the named upstream connection
+    Via(String),
+}
+
+/// The connection [JsonRequest::Start] asks the daemon to establish
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct StartParams {
+    pub name: String,
+    pub server: String,
+
+    /// The gateway URL the login was redirected to, the cookie is only valid there
+    pub redirect: Option<String>,
+    pub allow_insecure: bool,
+    pub cookie: String,
+    pub username: Option<String>,
+
+    /// Ends the session at the OIDC issuer when the daemon stops
+    pub oidc_logout: Option<OpenIDLogoutConfig>,
+
+    /// Answers of custom login form fields, for a login with credentials supplied by `reauth`
+    pub form_defaults: HashMap<String, String>,
+    pub kill_switch: bool,
+    pub metrics_addr: Option<SocketAddr>,
+    pub cert_expiry_warning_days: u32,
+    pub reconnect_jitter: u8,
+    pub reconnect_policy: ReconnectPolicy,
+    pub reconnect_on_dns_change: bool,
+    pub search_domains: Vec<String>,
+    pub dtls_fallback: bool,
+    pub reported_os: Option<String>,
+    pub http_proxy: Option<String>,
+    pub min_tls_version: Option<TlsVersion>,
+    pub ciphers: Option<String>,
+
+    /// The fingerprint of a gateway certificate trusted for this connection only
+    pub accept_cert: Option<String>,
+
+    /// Refuse to reconnect to a changed certificate instead of awaiting [JsonRequest::TrustCert]
+    pub strict_cert: bool,
+    pub pass_tos: bool,
+
+    /// Exported to the vpnc-script with a prefix, see [openconnect_core::config::ConfigBuilder::script_env]
+    pub script_env: HashMap<String, String>,
+
+    /// The file the progress messages of libopenconnect are appended to, up to the level
+    pub openconnect_log: Option<PathBuf>,
+    pub openconnect_log_level: LogLevel,
+    pub tun_device: Option<TunDevice>,
+    pub transport_family: Option<AddressFamily>,
+
+    /// Checked to be reachable through the tunnel after connecting
+    pub probe_host: Option<String>,
+
+    /// Has to answer with the status, any 2xx if `None`, before the start succeeds
+    pub verify_url: Option<(String, Option<u16>)>,
+
+    /// The connection is left down while one of them matches
+    pub trusted_networks: Vec<String>,
+
+    /// Decide whether to use UDP on the network the connection is made on
+    pub udp_policy: Vec<String>,
+
+    /// Set by the profile of the server, decides over the rules of [StartParams::udp_policy]
+    pub enable_udp: Option<bool>,
+
+    /// The network the address assigned by the gateway must be in
+    pub expected_subnet: Option<String>,
+
+    /// The MTU of the tunnel device regardless of the gateway
+    pub mtu: Option<u32>,
+
+    /// The protocol of the server, [openconnect_core::storage::Defaults::protocol] applies unless set
+    pub protocol: Option<String>,
+
+    /// The client certificate, its key if in another file and the key password of a password server
+    pub client_cert: Option<(PathBuf, Option<PathBuf>, Option<String>)>,
+
+    /// The profile of the primary server to connect with, the daemon reads its vpnc-script from the config itself
+    pub profile: Option<String>,
+    pub role: ConnectionRole,
+}
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum JsonRequest {
-    Start {
-        name: String,
-        server: String,
-        allow_insecure: bool,
-        cookie: String,
-    },
+    /// Boxed, the parameters are many times the size of the other requests
+    Start(Box<StartParams>),
     Stop,
     Info,
+    SetAutoReconnect(bool),
+
+    /// Log in again when the daemon is awaiting credentials because the gateway rejected the session
+    SupplyCredentials {
+        password: String,
+        totp: Option<String>,
+    },
+
+    /// Run the vpnc-script of the primary connection again, without touching the tunnel
+    ReapplyConfig,
+
+    /// Make new TLS and DTLS handshakes for the session of the primary connection
+    Rekey,
+
+    /// Reconnect to the new certificate when the daemon paused because the gateway certificate changed
+    TrustCert {
+        fingerprint: String,
+    },
+
+    /// The traffic counters of the primary connection, only while it is connected
+    Stats,
+
+    /// Measure the round-trip time through the tunnel, to the probe host or the DNS server unless `target` is given
+    LatencyProbe {
+        count: u32,
+        target: Option<String>,
+    },
+
+    /// Check that the daemon answers, see [sock::exit_when_socket_exists]
+    Ping,
+}
+
+/// The status of the client, and what the daemon waits for on top of it
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ConnectionStatus {
+    pub status: Status,
+
+    /// Waiting for [JsonRequest::SupplyCredentials]
+    pub awaiting_credentials: bool,
+    pub on_trusted_network: bool,
+
+    /// The previous and the presented fingerprint while waiting for [JsonRequest::TrustCert]
+    pub cert_change: Option<(String, String)>,
+
+    /// The attempt to re-establish a dropped connection, and the attempts allowed by the [ReconnectPolicy]
+    pub reconnect_attempt: Option<(u32, Option<u32>)>,
+}
+
+/// The label of the status in the `status` table
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((previous, presented)) = &self.cert_change {
+            return write!(
+                f,
+                "Certificate changed from {} to {}, awaiting trust-cert",
+                previous, presented
+            );
+        }
+
+        // only set between a drop and the next connection
+        if let Some((attempt, max_retries)) = self.reconnect_attempt {
+            return match max_retries {
+                Some(max_retries) => {
+                    write!(f, "Reconnecting, attempt {} of {}", attempt, max_retries)
+                }
+                None => write!(f, "Reconnecting, attempt {}", attempt),
+            };
+        }
+
+        match &self.status {
+            _ if self.awaiting_credentials => write!(f, "Awaiting credentials"),
+            Status::Connected => write!(f, "Connected"),
+            _ if self.on_trusted_network => write!(f, "On trusted network"),
+            Status::Connecting(_) => write!(f, "Connecting"),
+            Status::Disconnected => write!(f, "Disconnected"),
+            Status::Disconnecting => write!(f, "Disconnecting"),
+            // tells whether the gateway ended the session on purpose
+            Status::Error(
+                e @ (OpenconnectError::DisconnectedByServer(_)
+                | OpenconnectError::CertFingerprintMismatch(_)),
+            ) => write!(f, "{}", e),
+            Status::Error(_) => write!(f, "Error"),
+            Status::Initialized => write!(f, "Initialized"),
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -35,15 +228,104 @@ pub enum JsonResponse {
         server_name: String,
         server_url: String,
         hostname: String,
-        status: String,
+
+        /// Boxed to keep the response small, the flattened fields are serialized the same
+        #[serde(flatten)]
+        status: Box<ConnectionStatus>,
+        auto_reconnect: bool,
+        server_cert_expiry: Option<String>,
+        server_cert_expiring: bool,
+        tls_version: Option<String>,
+
+        /// The software version the gateway reported, `None` if it did not report one
+        server_version: Option<String>,
+
+        /// The configured server URL, if its login was redirected to the gateway of `server_url`
+        redirected_from: Option<String>,
+        connected_via: Option<String>,
+
+        /// The outcome of the connectivity self-check, `None` if no probe host is configured or the check is pending
+        probe: Option<String>,
         info: Option<Box<IpInfo>>,
     },
+    SetAutoReconnectResult {
+        enabled: bool,
+    },
+    SupplyCredentialsResult {
+        accepted: bool,
+    },
+    ReapplyConfigResult {
+        success: bool,
+        err_message: Option<String>,
+    },
+    RekeyResult {
+        /// The TLS cipher negotiated by the new handshake
+        cipher: Option<String>,
+        err_message: Option<String>,
+    },
+    TrustCertResult {
+        accepted: bool,
+        err_message: Option<String>,
+    },
+    LatencyProbeResult {
+        latency: Option<server::probe::Latency>,
+        err_message: Option<String>,
+    },
+    StatsResult {
+        server_name: String,
+        stats: Option<Stats>,
+        err_message: Option<String>,
+    },
+    Pong,
+}
+
+/// Print the log lines as JSON records, lines which do not start a record continue the message of the previous one
+fn print_json_logs(lines: impl Iterator<Item = String>) {
+    let print = |record: &LogRecord| {
+        println!(
+            "{}",
+            serde_json::to_string(record).expect("Failed to serialize log record")
+        )
+    };
+
+    let mut current: Option<LogRecord> = None;
+    for line in lines {
+        match Logger::parse_line(&line) {
+            Some(record) => {
+                if let Some(previous) = current.replace(record) {
+                    print(&previous);
+                }
+            }
+            None => {
+                if let Some(previous) = current.as_mut().filter(|_| !line.trim().is_empty()) {
+                    previous.message.push('\n');
+                    previous.message.push_str(&line);
+                }
+            }
+        }
+    }
+    if let Some(record) = current {
+        print(&record);
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
 
+    if let Some(config_dir) = cli.config_dir {
+        paths::set_config_dir(PathBuf::from(config_dir));
+    }
+    output::set_plain(cli.no_table);
+
     match cli.command {
+        Commands::GenerateService {
+            name,
+            config_file,
+            output,
+        } => {
+            crate::client::service::request_generate_service(&name, config_file, output);
+        }
+
         Commands::GenComplete {
             generator,
             binary_name,
@@ -62,29 +344,119 @@ fn main() {
             crate::client::config::request_export_server(&name);
         }
 
-        Commands::Delete { name } => {
-            crate::client::config::request_delete_server(&name);
+        Commands::KeyExport => {
+            crate::client::config::request_key_export();
+        }
+
+        Commands::KeyImport { key, yes } => {
+            crate::client::config::request_key_import(&key, yes);
+        }
+
+        Commands::Delete {
+            name,
+            all,
+            pattern,
+            dry_run,
+            yes,
+        } => {
+            let selection = match (name, pattern) {
+                (Some(name), _) => DeleteSelection::Name(name),
+                (None, Some(pattern)) => DeleteSelection::Match(pattern),
+                (None, None) if all => DeleteSelection::All,
+                (None, None) => unreachable!("clap requires a name, --all or --match"),
+            };
+            crate::client::config::request_delete_servers(selection, dry_run, yes);
         }
 
         Commands::List => {
             crate::client::config::request_list_servers();
         }
 
-        Commands::Status => {
-            crate::client::state::request_get_status();
+        Commands::Schema => {
+            crate::client::config::request_print_schema();
+        }
+
+        Commands::Config(action) => match action {
+            ConfigAction::Get { key } => {
+                crate::client::config::request_get_defaults(key.as_deref());
+            }
+            ConfigAction::Set { key, value } => {
+                crate::client::config::request_set_default(&key, Some(&value));
+            }
+            ConfigAction::Unset { key } => {
+                crate::client::config::request_set_default(&key, None);
+            }
+        },
+
+        Commands::Show {
+            name,
+            config_file,
+            connection,
+        } => {
+            let config_file = config_file.map(PathBuf::from).unwrap_or(
+                StoredConfigs::getorinit_config_file().expect("Failed to get config file"),
+            );
+            crate::client::show::request_show_server(&name, config_file, connection.into());
+        }
+
+        Commands::Status {
+            last,
+            timeout,
+            json,
+        } => {
+            if last {
+                crate::client::state::request_last_session(json);
+            } else {
+                crate::client::state::request_get_status(
+                    std::time::Duration::from_secs(timeout),
+                    json,
+                );
+            }
+        }
+
+        Commands::Stats { name: None } => {
+            crate::client::state::request_live_stats();
+        }
+
+        Commands::Stats { name: Some(name) } => {
+            crate::client::state::request_server_stats(&name);
         }
 
-        Commands::Logs => {
-            let log_path = Logger::get_log_path();
-            let files = std::fs::read_dir(log_path)
-                .expect("Failed to read log directory")
-                .flatten()
-                .filter(|f| f.metadata().unwrap().is_file())
-                .max_by_key(|f| f.metadata().unwrap().modified().unwrap());
+        Commands::Logs {
+            action:
+                Some(LogsAction::Export {
+                    name,
+                    session,
+                    output,
+                }),
+            ..
+        } => {
+            let Some(file) = Logger::find_session(&name, &session) else {
+                let sessions: Vec<String> = Logger::list_logs(Some(&name))
+                    .iter()
+                    .filter_map(|path| Logger::session_id(path).map(str::to_string))
+                    .collect();
+                eprintln!(
+                    "{}",
+                    format!("\nNo session {} found for server {}", session, name).red()
+                );
+                if !sessions.is_empty() {
+                    eprintln!("Available sessions: {}", sessions.join(", "));
+                }
+                std::process::exit(1);
+            };
+            std::fs::copy(&file, &output).expect("Failed to write log file");
+            println!("Session log {:?} written to {:?}", file, output);
+        }
 
-            if let Some(file) = files {
-                let file = std::fs::File::open(file.path()).expect("Failed to open log file");
+        Commands::Logs { name, json, .. } => {
+            if let Some(file) = Logger::list_logs(name.as_deref()).first() {
+                let file = std::fs::File::open(file).expect("Failed to open log file");
                 let reader = std::io::BufReader::new(file);
+                if json {
+                    print_json_logs(reader.lines().map_while(Result::ok));
+                    return;
+                }
                 for line in reader.lines() {
                     println!("{}", line.unwrap());
                 }
@@ -93,51 +465,263 @@ fn main() {
             }
         }
 
-        Commands::Stop => {
-            crate::client::state::request_stop_server();
+        Commands::Diagnose { output, name } => {
+            crate::client::diagnose::request_diagnose(output, name);
         }
 
-        Commands::Start { name, config_file } => {
-            sock::exit_when_socket_exists();
+        Commands::AutoReconnect { state } => {
+            crate::client::state::request_set_auto_reconnect(state.into());
+        }
 
-            #[cfg(target_os = "macos")]
-            sudo::escalate_if_needed().expect("Failed to escalate permissions");
+        Commands::Reauth { totp } => {
+            crate::client::state::request_supply_credentials(totp);
+        }
+
+        Commands::Interactive => {
+            crate::client::interactive::run_interactive(cli.no_table);
+        }
+
+        Commands::TrustCert { fingerprint } => {
+            crate::client::state::request_trust_cert(fingerprint);
+        }
 
+        Commands::Reapply => {
+            crate::client::state::request_reapply_config();
+        }
+
+        Commands::Rekey => {
+            crate::client::state::request_rekey();
+        }
+
+        Commands::Ping { count, target } => {
+            crate::client::state::request_latency_probe(count, target);
+        }
+
+        Commands::Run {
+            name,
+            config_file,
+            wait,
+            max_reconnects,
+            max_duration,
+            command,
+        } => {
+            let limits = crate::client::run::RunLimits {
+                max_reconnects,
+                max_duration: max_duration.map(std::time::Duration::from_secs),
+            };
+            crate::client::run::request_run(name, config_file, wait, limits, command);
+        }
+
+        Commands::Stop { timeout } => {
+            crate::client::state::request_stop_server(std::time::Duration::from_secs(timeout));
+        }
+
+        Commands::Probe { name, port } => {
+            crate::client::config::request_probe_server(&name, port);
+        }
+
+        Commands::KillSwitch { action } => {
             #[cfg(target_os = "linux")]
-            sudo::with_env(&["HOME"]).expect("Failed to escalate permissions"); // keep HOME env so that we can find the config file and vpnc script
+            sudo::escalate_if_needed().expect("Failed to escalate permissions");
 
-            let config_file = config_file.map(PathBuf::from).unwrap_or(
-                StoredConfigs::getorinit_config_file().expect("Failed to get config file"),
-            );
+            match action {
+                KillSwitchAction::Status => {
+                    if killswitch::is_installed() {
+                        println!("Kill switch is active");
+                    } else {
+                        println!("Kill switch is not active");
+                    }
+                }
+                KillSwitchAction::Clear => {
+                    if let Err(e) = killswitch::clear() {
+                        eprintln!("{}", format!("\nFailed to clear kill switch: {}", e).red());
+                        std::process::exit(1);
+                    }
+                    println!("Kill switch cleared");
+                }
+            }
+        }
+
+        Commands::Cleanup { dry_run, yes } => {
+            crate::client::cleanup::request_cleanup(dry_run, yes);
+        }
+
+        Commands::Start {
+            name,
+            config_file,
+            from_env,
+            kill_switch,
+            metrics_port,
+            metrics_address,
+            reconnect_jitter,
+            reconnect_retries,
+            reconnect_backoff,
+            reconnect_on_dns_change,
+            connection,
+            info_only,
+            foreground,
+            print_cookie,
+            force,
+            oidc_browser,
+            accept_cert,
+            strict_cert,
+            pass_tos,
+            script_env,
+            openconnect_log,
+            openconnect_log_level,
+            wait,
+            tun_device,
+            tun_fd,
+        } => {
+            // the server from the environment is never saved, so no config file is created for it
+            let config_file = match config_file {
+                _ if from_env => PathBuf::new(),
+                Some(config_file) => PathBuf::from(config_file),
+                None => StoredConfigs::getorinit_config_file().expect("Failed to get config file"),
+            };
+
+            if info_only {
+                let options = crate::client::state::StartOptions {
+                    from_env,
+                    force,
+                    oidc_browser,
+                    print_cookie,
+                    accept_cert,
+                    openconnect_log,
+                    openconnect_log_level,
+                    ..connection.into()
+                };
+                crate::client::state::request_info_only(name, config_file, options);
+                return;
+            }
+
+            sock::exit_when_socket_exists();
+
+            let tun_device = tun_device
+                .map(TunDevice::Name)
+                .or(tun_fd.map(TunDevice::Fd));
 
-            match daemon::daemonize() {
-                daemon::ForkResult::Parent => {
-                    println!();
-                    println!("===============================\n");
-                    println!("OpenConnect VPN CLI Client\n");
-                    println!("===============================\n");
+            // a pre-created tun device relies on capabilities granted from outside instead of root
+            if tun_device.is_none() {
+                // the server from the environment is read after escalating
+                let env_prefix = if from_env {
+                    env_server::ENV_PREFIX
+                } else {
+                    paths::HOME_ENV
+                };
+
+                // the daemon has to listen where the commands of the user look for the socket
+                #[cfg(target_os = "macos")]
+                sudo::with_env(&[
+                    paths::HOME_ENV,
+                    env_prefix,
+                    paths::SOCK_ENV,
+                    paths::XDG_RUNTIME_DIR_ENV,
+                ])
+                .expect("Failed to escalate permissions");
+
+                #[cfg(target_os = "linux")]
+                sudo::with_env(&[
+                    "HOME",
+                    paths::HOME_ENV,
+                    env_prefix,
+                    paths::SOCK_ENV,
+                    paths::XDG_RUNTIME_DIR_ENV,
+                ])
+                .expect("Failed to escalate permissions");
+                // keep HOME env so that we can find the config file and vpnc script
+            }
+
+            let start_client = |name: String| {
+                println!();
+                println!("===============================\n");
+                println!("OpenConnect VPN CLI Client\n");
+                println!("===============================\n");
+                if from_env {
+                    println!("Using the server from the environment");
+                } else {
                     println!("Using Config file: {:?}", config_file);
-                    crate::client::state::request_start_server(name, config_file);
-                    println!("The process will be running in the background, you should use cli to interact with it.");
-                    std::process::exit(0);
                 }
-                daemon::ForkResult::Child => {
-                    std::process::exit(0);
+                let options = crate::client::state::StartOptions {
+                    from_env,
+                    kill_switch,
+                    metrics_addr: metrics_port.map(|port| SocketAddr::new(metrics_address, port)),
+                    reconnect_jitter,
+                    reconnect_policy: ReconnectPolicy {
+                        max_retries: reconnect_retries,
+                        base_backoff: std::time::Duration::from_secs(reconnect_backoff),
+                    },
+                    reconnect_on_dns_change,
+                    force,
+                    oidc_browser,
+                    accept_cert,
+                    strict_cert,
+                    pass_tos,
+                    script_env: script_env.iter().cloned().collect(),
+                    openconnect_log: openconnect_log.clone(),
+                    openconnect_log_level,
+                    wait: wait.map(std::time::Duration::from_secs),
+                    tun_device,
+                    ..connection.into()
+                };
+                crate::client::state::request_start_server(name, config_file.clone(), options);
+            };
+
+            let mut client_pid = None;
+            if foreground {
+                match daemon::fork_client() {
+                    Ok(None) => {
+                        start_client(name);
+                        std::process::exit(0);
+                    }
+                    Ok(Some(pid)) => client_pid = Some(pid),
+                    Err(e) => {
+                        eprintln!("{}", format!("\nFailed to start daemon: {}", e).red());
+                        std::process::exit(1);
+                    }
                 }
-                daemon::ForkResult::Grandchild => {
-                    // Daemon process
+            } else {
+                match daemon::daemonize() {
+                    Ok(daemon::ForkResult::Parent) => {
+                        start_client(name);
+                        println!("The process will be running in the background, you should use cli to interact with it.");
+                        std::process::exit(0);
+                    }
+                    Ok(daemon::ForkResult::Child) => {
+                        std::process::exit(0);
+                    }
+                    Ok(daemon::ForkResult::Grandchild) => {
+                        // Daemon process
+                    }
+                    Err(e) => {
+                        eprintln!("{}", format!("\nFailed to start daemon: {}", e).red());
+                        std::process::exit(1);
+                    }
                 }
             }
 
             let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
-            runtime.block_on(async {
-                Logger::init().expect("Failed to initialize logger");
-                let start_result = crate::server::start_daemon().await;
-                if let Err(e) = start_result {
+            let started = runtime.block_on(async {
+                Logger::init_for_session(&name).expect("Failed to initialize logger");
+                let _pid_file = daemon::PidFile::create().expect("Failed to write PID file");
+                let start_result = crate::server::start_daemon(config_file.clone()).await;
+                if let Err(e) = &start_result {
                     tracing::error!("Failed to start daemon: {}", e);
                 }
+                start_result.is_ok()
             });
+
+            // don't wait forever for a blocking connect which did not react to the cancel
+            runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+
+            // let the service manager restart a connection which could not be started
+            if let Some(pid) = client_pid {
+                let client_status = daemon::wait_client(pid).unwrap_or(-1);
+                if !started || client_status != 0 {
+                    std::process::exit(1);
+                }
+            }
         }
     }
 }
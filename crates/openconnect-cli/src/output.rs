@@ -0,0 +1,112 @@
+//! Tables of the CLI output, printed as plain `key: value` lines where a table does not fit
+//!
+//! Tables wrap their content to the width of the terminal. Plain lines are printed with `--no-table`,
+//! when the output is not a terminal, or when the terminal is narrower than [MIN_TABLE_WIDTH].
+
+use comfy_table::{ContentArrangement, Table};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Below this many columns even a wrapped table is hard to read
+const MIN_TABLE_WIDTH: u16 = 50;
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Always print plain lines instead of tables, e.g. from the `--no-table` CLI flag
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+fn new_table() -> Table {
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table
+}
+
+fn is_plain(table: &Table) -> bool {
+    PLAIN.load(Ordering::Relaxed)
+        || !table.is_tty()
+        || table.width().is_some_and(|width| width < MIN_TABLE_WIDTH)
+}
+
+/// A `key: value` line, the continuation lines of a multi-line value are indented
+fn plain_line(key: &str, value: &str) -> String {
+    format!("{}: {}", key, value.replace('\n', "\n  "))
+}
+
+/// Rows of a label and a value as plain lines
+fn render_plain_rows(rows: &[Vec<String>]) -> String {
+    rows.iter()
+        .map(|row| match row.as_slice() {
+            [key, value, ..] => plain_line(key, value),
+            [key] => key.clone(),
+            [] => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Records as blocks of `header: value` lines, separated by an empty line
+fn render_plain_records(header: &[String], records: &[Vec<String>]) -> String {
+    records
+        .iter()
+        .map(|record| {
+            header
+                .iter()
+                .zip(record)
+                .map(|(key, value)| plain_line(key, value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Print rows of a label and a value
+pub fn print_rows(rows: Vec<Vec<String>>) {
+    let mut table = new_table();
+    if is_plain(&table) {
+        println!("{}", render_plain_rows(&rows));
+        return;
+    }
+
+    table.add_rows(rows);
+    println!("{table}");
+}
+
+/// Print records with a header, one row per record
+pub fn print_records(header: Vec<String>, records: Vec<Vec<String>>) {
+    let mut table = new_table();
+    if is_plain(&table) {
+        println!("{}", render_plain_records(&header, &records));
+        return;
+    }
+
+    table.set_header(header);
+    table.add_rows(records);
+    println!("{table}");
+}
+
+#[test]
+fn test_render_plain() {
+    let rows = vec![
+        vec!["Server Name".to_string(), "corp".to_string()],
+        vec![
+            "Routes".to_string(),
+            "10.0.0.0/8\n172.16.0.0/12".to_string(),
+        ],
+    ];
+    assert_eq!(
+        render_plain_rows(&rows),
+        "Server Name: corp\nRoutes: 10.0.0.0/8\n  172.16.0.0/12"
+    );
+
+    let header = vec!["Name".to_string(), "Type".to_string()];
+    let records = vec![
+        vec!["corp".to_string(), "OIDC Server".to_string()],
+        vec!["lab".to_string(), "Password Server".to_string()],
+    ];
+    assert_eq!(
+        render_plain_records(&header, &records),
+        "Name: corp\nType: OIDC Server\n\nName: lab\nType: Password Server"
+    );
+}
@@ -0,0 +1,182 @@
+//! Minimal HTTP endpoint exposing the tunnel metrics in the Prometheus text exposition format
+//!
+//! Only `GET /metrics` is served, anything else is answered with 404.
+
+use super::State;
+use openconnect_core::{Connectable, Status};
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Time given to the main loop to report fresh traffic counters before answering a scrape
+const STATS_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub up: bool,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_pkts: u64,
+    pub tx_pkts: u64,
+    pub reconnects: u64,
+    pub uptime_seconds: u64,
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+impl Metrics {
+    async fn collect(state: &State) -> Self {
+        let client = state.client.read().await.clone();
+        let Some(client) = client else {
+            return Metrics {
+                reconnects: state.reconnects.load(Ordering::SeqCst),
+                ..Default::default()
+            };
+        };
+
+        client.request_stats();
+        tokio::time::sleep(STATS_WAIT).await;
+        let stats = client.get_stats().unwrap_or_default();
+
+        let uptime_seconds = state
+            .connected_at
+            .read()
+            .await
+            .map(|connected_at| connected_at.elapsed().as_secs())
+            .unwrap_or(0);
+
+        Metrics {
+            up: client.get_status() == Status::Connected,
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_pkts: stats.rx_pkts,
+            tx_pkts: stats.tx_pkts,
+            reconnects: state.reconnects.load(Ordering::SeqCst),
+            uptime_seconds,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "openconnect_up",
+            "gauge",
+            "Whether the tunnel is connected",
+            self.up as u64,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_rx_bytes_total",
+            "counter",
+            "Bytes received through the tunnel",
+            self.rx_bytes,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_tx_bytes_total",
+            "counter",
+            "Bytes sent through the tunnel",
+            self.tx_bytes,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_rx_packets_total",
+            "counter",
+            "Packets received through the tunnel",
+            self.rx_pkts,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_tx_packets_total",
+            "counter",
+            "Packets sent through the tunnel",
+            self.tx_pkts,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_reconnects_total",
+            "counter",
+            "Number of times the daemon re-established the connection",
+            self.reconnects,
+        );
+        write_metric(
+            &mut out,
+            "openconnect_uptime_seconds",
+            "gauge",
+            "Seconds since the current connection was established",
+            self.uptime_seconds,
+        );
+        out
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<State>) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..read]);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = Metrics::collect(&state).await.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Bind the metrics endpoint, binding is done eagerly so that errors can be reported to the caller
+pub async fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Serving metrics on http://{}/metrics", addr);
+    Ok(listener)
+}
+
+pub async fn serve(listener: TcpListener, state: Arc<State>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, state).await {
+                        tracing::debug!("Failed to serve metrics: {}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::error!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+#[test]
+fn test_render_metrics() {
+    let metrics = Metrics {
+        up: true,
+        rx_bytes: 1024,
+        reconnects: 2,
+        ..Default::default()
+    };
+    let rendered = metrics.render();
+    assert!(rendered.contains("# TYPE openconnect_up gauge\nopenconnect_up 1\n"));
+    assert!(rendered.contains("openconnect_rx_bytes_total 1024\n"));
+    assert!(rendered.contains("openconnect_reconnects_total 2\n"));
+}
@@ -1,32 +1,812 @@
+mod metrics;
+pub mod probe;
+
 use crate::{
-    client::state::{get_vpnc_script, StateError},
-    sock::{self, UnixDomainServer},
-    JsonRequest, JsonResponse,
+    client::state::{connection_config, connection_entrypoint, StateError},
+    killswitch,
+    last_session::LastSession,
+    server_stats,
+    sock::{DaemonServer, SockError},
+    tunnels::{self, TunnelRecord},
+    ConnectionRole, ConnectionStatus, JsonRequest, JsonResponse, StartParams,
 };
-use futures::{SinkExt, TryStreamExt};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt, TryStreamExt};
 use openconnect_core::{
-    config::{ConfigBuilder, EntrypointBuilder, LogLevel},
+    captive_portal,
+    config::{
+        validate_script_env, AddressFamily, Config, Entrypoint, LogLevel, ReconnectPolicy,
+        TlsVersion, TunDevice,
+    },
+    env_server::server_from_env,
     events::EventHandlers,
+    log, paths, preflight,
+    profile_script::{self, EntrypointOverride},
+    protocols::find_protocol,
+    resolver,
+    result::OpenconnectError,
+    storage::{Defaults, StoredConfigError, StoredConfigs, StoredServer},
+    trusted_network::{self, TrustedNetwork},
+    udp_policy::{self, CurrentNetwork, UdpRule},
+    verify::{self, VerifyError},
     Connectable, Status, VpnClient,
 };
-use std::sync::Arc;
+use openconnect_oidc::oidc_logout::{self, OpenIDLogoutConfig};
+use probe::ProbeResult;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::{
     select,
     signal::unix::{signal, SignalKind},
-    sync::RwLock,
+    sync::{oneshot, Mutex, RwLock},
 };
+use tracing::Instrument;
+
+/// How often the daemon checks whether the machine moved onto or off a trusted network
+const TRUSTED_NETWORK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often the traffic counters of the primary connection are refreshed, so that a dropped session is counted too
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the `stats` command waits for the main loop to report the traffic counters
+const STATS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the daemon compares the DNS servers of the system resolver, see [State::watch_dns_servers]
+const DNS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the daemon waits for an OIDC issuer to end a session before it disconnects anyway
+const OIDC_LOGOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Randomize the delay within `±jitter_percent`, so that clients dropped by the same gateway restart don't retry in lockstep
+fn jittered(delay: Duration, jitter_percent: u8) -> Duration {
+    let jitter = f64::from(jitter_percent.min(100)) / 100.0;
+    if jitter == 0.0 {
+        return delay;
+    }
+    delay.mul_f64(1.0 + rand::random::<f64>().mul_add(2.0 * jitter, -jitter))
+}
+
+/// Keep the tunnel for `cleanup` in case the daemon crashes, see [tunnels]
+fn record_tunnel(params: &ConnectParams, client: &VpnClient) {
+    // no vpnc-script runs for a tun file descriptor
+    if matches!(params.tun_device, Some(TunDevice::Fd(_))) {
+        return;
+    }
+    let (Some(ifname), Ok(Some(info))) = (client.get_ifname(), client.get_info()) else {
+        return;
+    };
+
+    let record = TunnelRecord {
+        server_name: params.name.clone(),
+        ifname,
+        vpnc_script: client.get_vpnc_script(),
+        script_env: client.get_script_env().clone(),
+        info,
+        pid: std::process::id(),
+    };
+    if let Err(e) = tunnels::record(record) {
+        tracing::warn!("Failed to record the tunnel of {}: {}", params.name, e);
+    }
+}
+
+/// Parameters of the current connection, retained so that the daemon can reconnect
+#[derive(Clone)]
+struct ConnectParams {
+    name: String,
+    server: String,
+
+    /// The gateway the login was redirected to, which issued the cookie
+    redirect: Option<String>,
+    allow_insecure: bool,
+    cookie: String,
+    username: Option<String>,
+
+    /// Answers of the login form for a login with [ConnectParams::credentials]
+    form_defaults: HashMap<String, String>,
+
+    /// Supplied by a client for a single login after the gateway rejected the cookie
+    credentials: Option<Credentials>,
+    kill_switch: bool,
+    cert_expiry_warning_days: u32,
+    reconnect_jitter: u8,
+    reconnect_policy: ReconnectPolicy,
+    search_domains: Vec<String>,
+    dtls_fallback: bool,
+    reported_os: Option<String>,
+    http_proxy: Option<String>,
+    min_tls_version: Option<TlsVersion>,
+    ciphers: Option<String>,
+    accept_cert: Option<String>,
+
+    /// The certificate of the last connection, which a reconnect has to present again, see [State::await_cert_trust]
+    pinned_cert: Option<String>,
+    strict_cert: bool,
+    pass_tos: bool,
+    script_env: HashMap<String, String>,
+    openconnect_log: Option<PathBuf>,
+    openconnect_log_level: LogLevel,
+    tun_device: Option<TunDevice>,
+    transport_family: Option<AddressFamily>,
+    probe_host: Option<String>,
+
+    /// Has to answer with the status after the initial connect, only a warning after a reconnect
+    verify_url: Option<(String, Option<u16>)>,
+
+    /// Consulted on every (re)connect, the machine may have moved to another network
+    udp_policy: Vec<UdpRule>,
+
+    /// Set by the profile given on start, decides over [ConnectParams::udp_policy]
+    enable_udp: Option<bool>,
+    expected_subnet: Option<String>,
+    mtu: Option<u32>,
+    protocol: Option<String>,
+    client_cert: Option<(PathBuf, Option<PathBuf>, Option<String>)>,
+
+    /// Run on every (re)connect, its overrides take precedence over the stored parameters
+    ///
+    /// Like [ConnectParams::defaults] read by the daemon itself, see [State::stored_configs]
+    profile_script: Option<PathBuf>,
+
+    /// Hold the vpnc-script and the pre-flight command, see [profile_defaults]
+    defaults: Defaults,
+    upstream: bool,
+}
+
+/// A fresh login pushed by a client through [JsonRequest::SupplyCredentials]
+#[derive(Clone)]
+struct Credentials {
+    password: String,
+    totp: Option<String>,
+}
+
+/// A reconnect paused because the gateway presented another certificate, see [JsonRequest::TrustCert]
+struct CertChange {
+    previous: String,
+    presented: String,
+    tx: oneshot::Sender<()>,
+}
 
 struct State {
     client: RwLock<Option<Arc<VpnClient>>>,
-    server: UnixDomainServer,
+
+    /// The connection the primary connection is made through, see [ConnectionRole]
+    upstream: RwLock<Option<Arc<VpnClient>>>,
+    server: DaemonServer,
+    auto_reconnect: AtomicBool,
+    stopping: AtomicBool,
+    reconnects: AtomicU64,
+
+    /// The attempt to re-establish the primary connection and the attempts allowed, see [ConnectionStatus]
+    reconnect_attempt: RwLock<Option<(u32, Option<u32>)>>,
+    connected_at: RwLock<Option<Instant>>,
+
+    /// Set while a connection waits for [JsonRequest::SupplyCredentials]
+    credentials_tx: Mutex<Option<oneshot::Sender<Credentials>>>,
+
+    /// Set while a reconnect waits for [JsonRequest::TrustCert]
+    cert_change: Mutex<Option<CertChange>>,
+
+    /// The outcome of the last connectivity self-check of the primary connection
+    probe: RwLock<Option<String>>,
+
+    /// The probe host of the primary connection, measured by [JsonRequest::LatencyProbe]
+    probe_host: RwLock<Option<String>>,
+
+    /// The server URL of the primary connection when its login was redirected to another gateway
+    redirected_from: RwLock<Option<String>>,
+
+    /// Sessions at OIDC issuers ended when the daemon stops, see [State::end_oidc_sessions]
+    oidc_logouts: Mutex<Vec<OpenIDLogoutConfig>>,
+
+    /// Whether the machine is on a trusted network of the primary connection, which then stays disconnected
+    on_trusted_network: AtomicBool,
+
+    /// Name and URL of the server to connect once the machine leaves the trusted network it started on
+    waiting_server: RwLock<Option<(String, String)>>,
+
+    /// The config file given to the command which started the daemon, empty for a server from the environment
+    config_file: PathBuf,
 }
 
 impl State {
-    pub fn new(server: UnixDomainServer) -> Arc<Self> {
+    pub fn new(server: DaemonServer, config_file: PathBuf) -> Arc<Self> {
         Arc::new(State {
             client: RwLock::new(None),
+            upstream: RwLock::new(None),
             server,
+            auto_reconnect: AtomicBool::new(true),
+            stopping: AtomicBool::new(false),
+            reconnects: AtomicU64::new(0),
+            reconnect_attempt: RwLock::new(None),
+            connected_at: RwLock::new(None),
+            credentials_tx: Mutex::new(None),
+            cert_change: Mutex::new(None),
+            probe: RwLock::new(None),
+            probe_host: RwLock::new(None),
+            redirected_from: RwLock::new(None),
+            oidc_logouts: Mutex::new(vec![]),
+            on_trusted_network: AtomicBool::new(false),
+            waiting_server: RwLock::new(None),
+            config_file,
+        })
+    }
+
+    fn slot(&self, upstream: bool) -> &RwLock<Option<Arc<VpnClient>>> {
+        if upstream {
+            &self.upstream
+        } else {
+            &self.client
+        }
+    }
+
+    async fn check_role(
+        &self,
+        role: &ConnectionRole,
+        kill_switch: bool,
+        tun_device: Option<&TunDevice>,
+        trusted_networks: &[String],
+    ) -> Result<(), StateError> {
+        if kill_switch && *role != ConnectionRole::Primary {
+            return Err(StateError::ConnectViaError(
+                "The kill switch is not supported when connecting via another server".to_string(),
+            ));
+        }
+
+        // both tunnels would share the single pre-created device
+        if tun_device.is_some() && *role != ConnectionRole::Primary {
+            return Err(StateError::ConnectViaError(
+                "A pre-created tun device is not supported when connecting via another server"
+                    .to_string(),
+            ));
+        }
+
+        if !trusted_networks.is_empty() && *role != ConnectionRole::Primary {
+            return Err(StateError::ConnectViaError(
+                "Trusted networks are not supported when connecting via another server".to_string(),
+            ));
+        }
+
+        // the kill switch would cut the machine off while it stays disconnected on a trusted network
+        if kill_switch && !trusted_networks.is_empty() {
+            return Err(StateError::ConnectViaError(
+                "The kill switch can't be combined with trusted networks".to_string(),
+            ));
+        }
+
+        if let ConnectionRole::Via(via) = role {
+            let upstream = self.upstream.read().await;
+            let is_up = upstream.as_ref().is_some_and(|client| {
+                client.get_server_name().as_deref() == Some(via.as_str())
+                    && client.get_status() == Status::Connected
+            });
+            if !is_up {
+                return Err(StateError::ConnectViaError(format!(
+                    "Upstream connection {} is not up",
+                    via
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn should_reconnect(&self) -> bool {
+        self.auto_reconnect.load(Ordering::SeqCst) && !self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Track the reconnect attempt of the primary connection, `None` once it is up again or given up
+    async fn set_reconnect_attempt(&self, params: &ConnectParams, attempt: Option<u32>) {
+        if !params.upstream {
+            *self.reconnect_attempt.write().await =
+                attempt.map(|attempt| (attempt, params.reconnect_policy.max_retries));
+        }
+    }
+
+    async fn is_awaiting_credentials(&self) -> bool {
+        self.credentials_tx.lock().await.is_some()
+    }
+
+    /// Wait until a client supplies credentials, `None` if the daemon is stopped meanwhile
+    async fn await_credentials(&self, name: &str) -> Option<Credentials> {
+        let (tx, rx) = oneshot::channel();
+        *self.credentials_tx.lock().await = Some(tx);
+        tracing::warn!(
+            "The gateway requires a new login for {}, awaiting credentials from `reauth`",
+            name
+        );
+        rx.await.ok()
+    }
+
+    /// Wait until a client trusts the presented certificate, false if the daemon is stopped meanwhile
+    async fn await_cert_trust(&self, name: &str, previous: &str, presented: &str) -> bool {
+        let (tx, rx) = oneshot::channel();
+        *self.cert_change.lock().await = Some(CertChange {
+            previous: previous.to_string(),
+            presented: presented.to_string(),
+            tx,
+        });
+        tracing::warn!(
+            "Not reconnecting to {} until `trust-cert {}` confirms the new certificate",
+            name,
+            presented
+        );
+        rx.await.is_ok()
+    }
+
+    /// Check that the probe host of a primary connection is reachable once it is up, see [probe]
+    fn spawn_probe(self: &Arc<Self>, params: &ConnectParams) {
+        let Some(probe_host) = params.probe_host.clone().filter(|_| !params.upstream) else {
+            return;
+        };
+        let state = self.clone();
+        let name = params.name.clone();
+
+        tokio::spawn(async move {
+            *state.probe.write().await = None;
+            *state.probe_host.write().await = Some(probe_host.clone());
+            tokio::time::sleep(probe::PROBE_DELAY).await;
+
+            let result = probe::probe(&probe_host).await;
+            match result {
+                ProbeResult::Reachable(_) => {
+                    tracing::info!("Probe host {} is {}", probe_host, result)
+                }
+                ProbeResult::Unreachable(_) => tracing::warn!(
+                    "Probe host {} is {} although connected to {}",
+                    probe_host,
+                    result,
+                    name
+                ),
+            }
+            *state.probe.write().await = Some(format!("{} {}", probe_host, result));
+        });
+    }
+
+    /// Request the verify URL of a primary connection once the tunnel carries traffic, see [verify]
+    async fn verify_tunnel(params: &ConnectParams) -> Result<(), VerifyError> {
+        let Some((url, expect_status)) = params.verify_url.as_ref().filter(|_| !params.upstream)
+        else {
+            return Ok(());
+        };
+
+        tokio::time::sleep(probe::PROBE_DELAY).await;
+        let status = verify::verify_url(url, *expect_status).await?;
+        tracing::info!("Verified {} with status {}", url, status);
+        Ok(())
+    }
+
+    /// Run the main loop of the client, and re-establish the connection when it drops
+    /// unexpectedly, as long as auto-reconnect is enabled.
+    ///
+    /// When the gateway rejects the session cookie, the connection waits for credentials instead of retrying.
+    /// While on a trusted network it waits until the machine leaves it, without a client if it starts on one.
+    async fn keep_alive(self: Arc<Self>, client: Option<Arc<VpnClient>>, params: ConnectParams) {
+        let mut client = client;
+        let mut params = params;
+        loop {
+            let mut auth_expired = false;
+            if let Some(client) = client.as_ref() {
+                if params.accept_cert.is_none() {
+                    params.pinned_cert = Some(client.get_peer_cert_hash());
+                }
+
+                if !params.upstream {
+                    // the stream ends with the connection
+                    tokio::spawn(client.stats_stream(STATS_INTERVAL).for_each(|_| async {}));
+                }
+
+                let client_cloned = client.clone();
+                let result = tokio::task::spawn_blocking(move || client_cloned.run_loop()).await;
+                if !params.upstream {
+                    self.record_session(client).await;
+                    *self.probe.write().await = None;
+                }
+
+                // leaving a trusted network resumes the session even without auto-reconnect
+                if !self.should_reconnect() && !self.is_on_trusted_network() {
+                    break;
+                }
+
+                // an administrator ending the session would likely end a new one as well
+                if let Ok(Err(ref e @ OpenconnectError::DisconnectedByServer(_))) = result {
+                    tracing::warn!("{}, not reconnecting to {}", e, params.name);
+                    break;
+                }
+
+                auth_expired = matches!(result, Ok(Err(ref e)) if e.is_auth_expired());
+                if self.is_on_trusted_network() {
+                    tracing::info!("Disconnected from {} on a trusted network", params.name);
+                } else if !auth_expired {
+                    tracing::warn!(
+                        "Connection to {} dropped, reconnecting in about {}s",
+                        params.name,
+                        params.reconnect_policy.backoff(1).as_secs()
+                    );
+                }
+            }
+
+            let reconnected = client.is_some();
+            let mut changed_cert: Option<String> = None;
+            let mut attempt = 0;
+            client = Some(loop {
+                let mut resumed = false;
+                if let Some(presented) = changed_cert.take() {
+                    let previous = params.pinned_cert.clone().unwrap_or_default();
+                    if !self
+                        .await_cert_trust(&params.name, &previous, &presented)
+                        .await
+                    {
+                        tracing::info!("Stopped while awaiting trust of the new certificate");
+                        return;
+                    }
+                    params.pinned_cert = Some(presented);
+                } else if auth_expired && params.username.is_some() {
+                    let Some(credentials) = self.await_credentials(&params.name).await else {
+                        tracing::info!("Stopped while awaiting credentials");
+                        return;
+                    };
+                    params.credentials = Some(credentials);
+                } else if self.is_on_trusted_network() {
+                    if !self.wait_until_untrusted().await {
+                        tracing::info!("Stopped while on a trusted network");
+                        return;
+                    }
+                    resumed = true;
+                } else {
+                    attempt += 1;
+                    if params.reconnect_policy.is_exhausted(attempt) {
+                        tracing::error!(
+                            "Giving up reconnecting to {} after {} failed attempts",
+                            params.name,
+                            attempt - 1
+                        );
+                        self.set_reconnect_attempt(&params, None).await;
+                        return;
+                    }
+
+                    let backoff = jittered(
+                        params.reconnect_policy.backoff(attempt),
+                        params.reconnect_jitter,
+                    );
+                    if attempt > 1 {
+                        tracing::info!(
+                            "Reconnect attempt {} to {} in about {}s",
+                            attempt,
+                            params.name,
+                            backoff.as_secs()
+                        );
+                    }
+                    self.set_reconnect_attempt(&params, Some(attempt)).await;
+                    tokio::time::sleep(backoff).await;
+                }
+
+                if !resumed && !self.should_reconnect() {
+                    tracing::info!("Auto-reconnect disabled, giving up reconnecting");
+                    self.set_reconnect_attempt(&params, None).await;
+                    return;
+                }
+
+                let connected = self.connect(&params).await;
+                // the credentials are good for a single login, the new cookie is kept instead
+                let relogin = params.credentials.take().is_some();
+
+                match connected {
+                    Ok(client) => {
+                        if let Some(cookie) = client.get_cookie() {
+                            params.cookie = cookie;
+                        }
+                        // the tunnel interface may change on reconnect
+                        if params.kill_switch {
+                            if let Err(e) = apply_kill_switch(&client) {
+                                tracing::error!("Failed to update kill switch: {}", e);
+                            }
+                        }
+                        break client;
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to reconnect: {}", e);
+                        if let (
+                            Some(previous),
+                            StateError::OpenconnectError(
+                                OpenconnectError::CertFingerprintMismatch(presented),
+                            ),
+                        ) = (&params.pinned_cert, &e)
+                        {
+                            tracing::warn!(
+                                "The certificate of {} changed from {} to {}",
+                                params.name,
+                                previous,
+                                presented
+                            );
+                            if params.strict_cert {
+                                tracing::error!(
+                                    "Not reconnecting to {} to a changed certificate",
+                                    params.name
+                                );
+                                self.set_reconnect_attempt(&params, None).await;
+                                return;
+                            }
+                            changed_cert = Some(presented.clone());
+                        }
+                        auth_expired = relogin
+                            || matches!(e, StateError::OpenconnectError(ref e) if e.is_auth_expired());
+                    }
+                }
+            });
+            *self.waiting_server.write().await = None;
+            self.set_reconnect_attempt(&params, None).await;
+
+            if reconnected {
+                tracing::info!("Reconnected to {}", params.name);
+            } else {
+                tracing::info!("Connected to {}", params.name);
+            }
+            if !params.upstream {
+                if reconnected {
+                    self.reconnects.fetch_add(1, Ordering::SeqCst);
+                }
+                *self.connected_at.write().await = Some(Instant::now());
+            }
+            self.spawn_probe(&params);
+
+            let verify_params = params.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::verify_tunnel(&verify_params).await {
+                    tracing::warn!(
+                        "Verification failed although connected to {}: {}",
+                        verify_params.name,
+                        e
+                    );
+                }
+            });
+        }
+    }
+
+    /// The stored configs, read from the config file of the daemon or from the environment for a server from it
+    ///
+    /// The commands the daemon runs as root are taken from here and never from a request, which any process of the
+    /// user could send.
+    async fn stored_configs(&self, name: &str) -> Result<StoredConfigs, StateError> {
+        // sudo kept the variables of a server from the environment for the daemon
+        if self.config_file.as_os_str().is_empty() {
+            let (_, stored_configs) = server_from_env(name, |key| std::env::var(key).ok())?;
+            return Ok(stored_configs);
+        }
+
+        paths::check_owner(&self.config_file)?;
+        let mut stored_configs = StoredConfigs::new(None, self.config_file.clone());
+        stored_configs.read_from_file().await?;
+        Ok(stored_configs)
+    }
+
+    fn is_on_trusted_network(&self) -> bool {
+        self.on_trusted_network.load(Ordering::SeqCst)
+    }
+
+    /// The trusted network the machine is on, see [trusted_network::detect]
+    async fn detect_trusted_network(&self, networks: &[TrustedNetwork]) -> Option<TrustedNetwork> {
+        let connected_info = match self.client.read().await.as_ref() {
+            Some(client) if client.get_status() == Status::Connected => {
+                client.get_info().ok().flatten()
+            }
+            _ => None,
+        };
+
+        let networks = networks.to_vec();
+        tokio::task::spawn_blocking(move || {
+            trusted_network::detect(&networks, connected_info.as_ref())
         })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Track whether the machine is on a trusted network, and leave the session of the primary connection once it is
+    async fn watch_trusted_networks(self: Arc<Self>, networks: Vec<TrustedNetwork>) {
+        while !self.stopping.load(Ordering::SeqCst) {
+            tokio::time::sleep(TRUSTED_NETWORK_INTERVAL).await;
+
+            let trusted = self.detect_trusted_network(&networks).await;
+            let was_trusted = self
+                .on_trusted_network
+                .swap(trusted.is_some(), Ordering::SeqCst);
+            match trusted {
+                Some(network) if !was_trusted => {
+                    tracing::info!("On a trusted network ({}), disconnecting", network);
+                    // not logging out, so that the session resumes once the machine leaves the network
+                    if let Some(client) = self.client.read().await.as_ref() {
+                        client.detach();
+                    }
+                }
+                None if was_trusted => tracing::info!("Left the trusted network, connecting"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Reconnect the primary connection when the DNS servers below the tunnel change, e.g. when a captive portal
+    /// hands over to the real network, while the routes look unchanged
+    async fn watch_dns_servers(self: Arc<Self>) {
+        let mut known_servers: Option<Vec<String>> = None;
+        while !self.stopping.load(Ordering::SeqCst) {
+            tokio::time::sleep(DNS_CHECK_INTERVAL).await;
+
+            let client = match self.client.read().await.as_ref() {
+                Some(client) if client.get_status() == Status::Connected => client.clone(),
+                // the servers are compared again from the next connection on
+                _ => {
+                    known_servers = None;
+                    continue;
+                }
+            };
+
+            let info = client.get_info().ok().flatten();
+            let Ok(servers) =
+                tokio::task::spawn_blocking(move || resolver::nameservers(info.as_ref())).await
+            else {
+                continue;
+            };
+
+            match known_servers.replace(servers.clone()) {
+                Some(known) if known != servers && self.should_reconnect() => {
+                    tracing::info!(
+                        "DNS servers changed from [{}] to [{}], reconnecting",
+                        known.join(", "),
+                        servers.join(", ")
+                    );
+                    known_servers = None;
+                    // keep_alive resumes the session with the same cookie
+                    client.detach();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait until the machine leaves the trusted network, false if the daemon is stopped meanwhile
+    async fn wait_until_untrusted(&self) -> bool {
+        while self.is_on_trusted_network() {
+            if self.stopping.load(Ordering::SeqCst) {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        !self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Connect to the server, the client is kept in its slot while connecting so that it can be cancelled
+    async fn connect(&self, params: &ConnectParams) -> Result<Arc<VpnClient>, StateError> {
+        // before reconnects too, a failure is retried like any failed reconnect
+        captive_portal::check().await?;
+        if let Some(command) = params.defaults.preflight_cmd.clone() {
+            let (name, server) = (params.name.clone(), params.server.clone());
+            tokio::task::spawn_blocking(move || preflight::run(&command, &name, &server)).await??;
+            tracing::info!("Pre-flight command passed for {}", params.name);
+        }
+
+        let (config, entrypoint) = connect_params_to_config(params)?;
+
+        let client = VpnClient::new(config, EventHandlers::default())?;
+        *self.slot(params.upstream).write().await = Some(client.clone());
+        if !params.upstream {
+            *self.redirected_from.write().await =
+                params.redirect.as_ref().map(|_| params.server.clone());
+        }
+
+        // a stop which arrived before the client was in its slot could not cancel it
+        if self.stopping.load(Ordering::SeqCst) {
+            return Err(OpenconnectError::Cancelled.into());
+        }
+
+        let client_cloned = client.clone();
+        tokio::task::spawn_blocking(move || client_cloned.init_connection(entrypoint)).await??;
+        record_tunnel(params, &client);
+
+        Ok(client)
+    }
+
+    /// Keep a summary of the primary connection for `status --last`, unless it is down anyway
+    async fn save_last_session(&self, client: &VpnClient, upstream: Option<&Arc<VpnClient>>) {
+        if client.get_status() != Status::Connected {
+            return;
+        }
+
+        let last_session = LastSession {
+            server_name: client.get_server_name().unwrap_or_default(),
+            server_url: client.get_server_url().unwrap_or_default(),
+            hostname: client.get_hostname().unwrap_or_default(),
+            connected_via: upstream.and_then(|upstream| upstream.get_server_name()),
+            tls_version: client.get_tls_version().map(|version| version.to_string()),
+            duration_secs: self
+                .connected_at
+                .read()
+                .await
+                .map(|connected_at| connected_at.elapsed().as_secs()),
+            disconnected_at: Utc::now(),
+            info: client.get_info().ok().flatten(),
+        };
+
+        if let Err(e) = last_session.save() {
+            tracing::error!("Failed to save the last session: {}", e);
+        }
+    }
+
+    /// Add the session of the primary connection which just ended to the [server_stats] of its server, only once
+    async fn record_session(&self, client: &VpnClient) {
+        let Some(connected_at) = self.connected_at.write().await.take() else {
+            return;
+        };
+
+        let server_name = client.get_server_name().unwrap_or_default();
+        let stats = client.get_stats().unwrap_or_default();
+        if let Err(e) = server_stats::record_session(&server_name, &stats, connected_at.elapsed()) {
+            tracing::error!("Failed to save the stats of {}: {}", server_name, e);
+        }
+    }
+
+    /// Cancel both connections in any phase, returns the name of the server which was connected
+    async fn shutdown(&self) -> Option<String> {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        // wakes a connection awaiting credentials or trust of a certificate, which then gives up
+        self.credentials_tx.lock().await.take();
+        self.cert_change.lock().await.take();
+
+        let client = self.client.write().await.take();
+        let upstream = self.upstream.write().await.take();
+        if let Some(client) = client.as_ref() {
+            self.save_last_session(client, upstream.as_ref()).await;
+            self.record_session(client).await;
+        }
+
+        let server_name = match client.as_ref().or(upstream.as_ref()) {
+            Some(client) => Some(client.get_server_name().unwrap_or_default()),
+            None => self
+                .waiting_server
+                .write()
+                .await
+                .take()
+                .map(|(name, _)| name),
+        };
+
+        // before the tunnels go down, an issuer may only be reachable through them
+        self.end_oidc_sessions().await;
+
+        // tear down in reverse order of establishment, the upstream tunnel goes last
+        for client in [client, upstream].into_iter().flatten() {
+            client.cancel();
+        }
+        if let Err(e) = tunnels::forget_own() {
+            tracing::warn!("Failed to forget the recorded tunnels: {}", e);
+        }
+
+        server_name
+    }
+
+    /// Log out of the OIDC issuers of the connections, a failure only leaves the issuer session open
+    async fn end_oidc_sessions(&self) {
+        let logouts = std::mem::take(&mut *self.oidc_logouts.lock().await);
+        for logout in logouts {
+            match tokio::time::timeout(OIDC_LOGOUT_TIMEOUT, oidc_logout::end_session(&logout)).await
+            {
+                Ok(Ok(())) => tracing::info!("Logged out of OIDC issuer {}", logout.issuer_url),
+                Ok(Err(e)) => tracing::warn!(
+                    "Failed to log out of OIDC issuer {}: {}",
+                    logout.issuer_url,
+                    e
+                ),
+                Err(_) => {
+                    tracing::warn!("Timed out logging out of OIDC issuer {}", logout.issuer_url)
+                }
+            }
+        }
     }
 }
 
@@ -34,67 +814,488 @@ trait Acceptable {
     async fn try_accept(self);
 }
 
-async fn connect_to_vpn_server(
+/// The defaults of the daemon with the vpnc-script of the profile given on start, which is set for the primary server only
+fn profile_defaults(
+    stored_configs: &StoredConfigs,
     name: &str,
-    server: &str,
-    allow_insecure: bool,
-    cookie: &str,
-) -> Result<Arc<VpnClient>, StateError> {
-    let vpncscript = get_vpnc_script()?;
-
-    let config = ConfigBuilder::default()
-        .vpncscript(&vpncscript)
-        .loglevel(LogLevel::Info)
-        .build()?;
-
-    let entrypoint = EntrypointBuilder::new()
-        .name(name)
-        .server(server)
-        .accept_insecure_cert(allow_insecure)
-        .cookie(cookie)
-        .enable_udp(true)
-        .build()?;
-
-    let event_handler = EventHandlers::default();
-
-    let client = VpnClient::new(config, event_handler)?;
-    client.init_connection(entrypoint)?;
-
-    let client_cloned = client.clone();
-    tokio::task::spawn_blocking(move || {
-        let _ = client_cloned.run_loop();
-    });
-
-    Ok(client)
+    profile: Option<&str>,
+) -> Result<Defaults, StoredConfigError> {
+    let mut defaults = stored_configs.defaults.clone();
+    if let Some(profile) = profile {
+        let stored_server = stored_configs
+            .servers
+            .get(name)
+            .ok_or_else(|| StoredConfigError::BadInput(format!("Server {} not found", name)))?;
+        if let Some(vpnc_script) = &stored_server.profile(profile)?.vpnc_script {
+            defaults.vpnc_script = Some(vpnc_script.clone());
+        }
+    }
+    Ok(defaults)
+}
+
+/// The config and entrypoint a connection is (re)established with
+fn connect_params_to_config(params: &ConnectParams) -> Result<(Config, Entrypoint), StateError> {
+    let ConnectParams {
+        name,
+        server,
+        redirect,
+        allow_insecure,
+        cookie,
+        username,
+        form_defaults,
+        credentials,
+        cert_expiry_warning_days,
+        search_domains,
+        dtls_fallback,
+        reported_os,
+        http_proxy,
+        min_tls_version,
+        ciphers,
+        accept_cert,
+        pinned_cert,
+        pass_tos,
+        script_env,
+        openconnect_log,
+        openconnect_log_level,
+        reconnect_policy,
+        tun_device,
+        transport_family,
+        udp_policy,
+        enable_udp,
+        expected_subnet,
+        mtu,
+        protocol,
+        client_cert,
+        profile_script,
+        defaults,
+        ..
+    } = params;
+
+    let profile = match profile_script {
+        Some(script) => {
+            let profile = profile_script::run(script, name, server)?;
+            tracing::info!(
+                "Profile script {} overrides {:?}",
+                script.display(),
+                profile
+            );
+            profile
+        }
+        None => EntrypointOverride::default(),
+    };
+
+    // the names are put unquoted in front of the vpnc-script, which runs as root
+    validate_script_env(script_env)?;
+    let config = Config {
+        tun_device: tun_device.clone(),
+        pass_tos: *pass_tos,
+        script_env: script_env.clone(),
+        openconnect_log: openconnect_log
+            .as_deref()
+            .map(paths::openconnect_log_file)
+            .transpose()?,
+        openconnect_log_level: *openconnect_log_level,
+        reconnect_policy: *reconnect_policy,
+        ..connection_config(
+            defaults,
+            *cert_expiry_warning_days,
+            search_domains.clone(),
+            http_proxy.as_deref(),
+            *min_tls_version,
+            ciphers.as_deref(),
+            *transport_family,
+        )?
+    };
+    // a login with credentials obtains a new cookie
+    let mut entrypoint = connection_entrypoint(
+        name,
+        profile
+            .server
+            .as_ref()
+            .or(redirect.as_ref())
+            .unwrap_or(server),
+        *allow_insecure,
+        credentials.is_none().then_some(cookie.as_str()),
+        *dtls_fallback,
+        profile.reported_os.as_deref().or(reported_os.as_deref()),
+        protocol
+            .as_deref()
+            .and_then(find_protocol)
+            .or_else(|| defaults.protocol()),
+    );
+
+    if let Some(expected_subnet) = profile
+        .expected_subnet
+        .as_ref()
+        .or(expected_subnet.as_ref())
+    {
+        entrypoint.expected_subnet(expected_subnet);
+    }
+
+    if let Some(mtu) = mtu {
+        entrypoint.mtu(*mtu);
+    }
+
+    // also presented on the CSTP connection, a missing file fails the build of the entrypoint
+    if let Some((client_cert, client_key, key_password)) = client_cert {
+        entrypoint.client_cert(&client_cert.to_string_lossy());
+        if let Some(client_key) = client_key {
+            entrypoint.client_key(&client_key.to_string_lossy());
+        }
+        if let Some(key_password) = key_password {
+            entrypoint.key_password(key_password);
+        }
+    }
+
+    // a certificate given on start is trusted on every reconnect, otherwise the one of the last connection
+    if let Some(accept_cert) = accept_cert
+        .as_ref()
+        .or(profile.accept_cert.as_ref())
+        .or(pinned_cert.as_ref())
+    {
+        entrypoint.accept_cert(accept_cert);
+    }
+
+    if !udp_policy.is_empty() {
+        let network = CurrentNetwork::detect();
+        if let Some(enable_udp) = udp_policy::decide(udp_policy, &network) {
+            tracing::info!(
+                "UDP {} on network {:?}",
+                if enable_udp { "enabled" } else { "disabled" },
+                network
+            );
+            entrypoint.enable_udp(enable_udp);
+        }
+    }
+    if let Some(enable_udp) = enable_udp {
+        entrypoint.enable_udp(*enable_udp);
+    }
+    if let Some(enable_udp) = profile.enable_udp {
+        entrypoint.enable_udp(enable_udp);
+    }
+
+    if let Some(Credentials { password, totp }) = credentials {
+        entrypoint.password(password);
+        if let Some(username) = username {
+            entrypoint.username(username);
+        }
+        if let Some(totp) = totp {
+            entrypoint.otp(totp);
+        }
+        for (field, value) in form_defaults {
+            entrypoint.form_default(field, value);
+        }
+    }
+
+    let entrypoint = entrypoint.build()?;
+
+    Ok((config, entrypoint))
+}
+
+fn apply_kill_switch(client: &VpnClient) -> Result<(), killswitch::KillSwitchError> {
+    let gateway = client
+        .get_info()
+        .ok()
+        .flatten()
+        .and_then(|info| info.gateway_addr);
+    killswitch::install(client.get_ifname(), gateway)
 }
 
 impl Acceptable for Arc<State> {
     async fn try_accept(self) {
-        if let Ok((stream, _)) = self.server.listener.accept().await {
-            let (read, write) = stream.into_split();
-            let mut framed_reader = sock::get_framed_reader::<JsonRequest>(read);
-            let mut framed_writer = sock::get_framed_writer::<JsonResponse>(write);
-
+        if let Ok((mut framed_reader, mut framed_writer)) = self.server.accept().await {
             tokio::spawn(async move {
-                while let Ok(Some(command)) = framed_reader.try_next().await {
+                loop {
+                    let command = match framed_reader.try_next().await {
+                        Ok(Some(command)) => command,
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::warn!("Closing client connection: {}", SockError::from(e));
+                            break;
+                        }
+                    };
+
                     match command {
-                        JsonRequest::Start {
-                            name,
-                            server,
-                            allow_insecure,
-                            cookie,
-                        } => {
+                        JsonRequest::Start(params) => {
+                            let StartParams {
+                                name,
+                                server,
+                                redirect,
+                                allow_insecure,
+                                cookie,
+                                username,
+                                oidc_logout,
+                                form_defaults,
+                                kill_switch,
+                                metrics_addr,
+                                cert_expiry_warning_days,
+                                reconnect_jitter,
+                                reconnect_policy,
+                                reconnect_on_dns_change,
+                                search_domains,
+                                dtls_fallback,
+                                reported_os,
+                                http_proxy,
+                                min_tls_version,
+                                ciphers,
+                                accept_cert,
+                                strict_cert,
+                                pass_tos,
+                                script_env,
+                                openconnect_log,
+                                openconnect_log_level,
+                                tun_device,
+                                transport_family,
+                                probe_host,
+                                verify_url,
+                                trusted_networks,
+                                udp_policy,
+                                enable_udp,
+                                expected_subnet,
+                                mtu,
+                                protocol,
+                                client_cert,
+                                profile,
+                                role,
+                            } = *params;
                             tracing::debug!("Received start command, name: {}", name);
-                            let connection_result =
-                                connect_to_vpn_server(&name, &server, allow_insecure, &cookie)
+
+                            if let Err(e) = self
+                                .check_role(
+                                    &role,
+                                    kill_switch,
+                                    tun_device.as_ref(),
+                                    &trusted_networks,
+                                )
+                                .await
+                            {
+                                let _ = framed_writer
+                                    .send(JsonResponse::StartResult {
+                                        name,
+                                        success: false,
+                                        err_message: Some(e.to_string()),
+                                    })
                                     .await;
 
+                                unsafe {
+                                    libc::raise(libc::SIGTERM);
+                                }
+                                continue;
+                            }
+
+                            let settings =
+                                self.stored_configs(&name).await.and_then(|stored_configs| {
+                                    let defaults = profile_defaults(
+                                        &stored_configs,
+                                        &name,
+                                        profile.as_deref(),
+                                    )?;
+                                    let profile_script = stored_configs
+                                        .servers
+                                        .get(&name)
+                                        .and_then(StoredServer::profile_script)
+                                        .map(Path::to_path_buf);
+                                    Ok((defaults, profile_script))
+                                });
+                            let (defaults, profile_script) = match settings {
+                                Ok(settings) => settings,
+                                Err(e) => {
+                                    let _ = framed_writer
+                                        .send(JsonResponse::StartResult {
+                                            name,
+                                            success: false,
+                                            err_message: Some(format!(
+                                                "Failed to read the config: {}",
+                                                e
+                                            )),
+                                        })
+                                        .await;
+
+                                    unsafe {
+                                        libc::raise(libc::SIGTERM);
+                                    }
+                                    continue;
+                                }
+                            };
+
+                            if let Some(oidc_logout) = oidc_logout {
+                                self.oidc_logouts.lock().await.push(oidc_logout);
+                            }
+
+                            // the metrics describe the primary connection
+                            let upstream = role == ConnectionRole::Upstream;
+                            let metrics_addr = metrics_addr.filter(|_| !upstream);
+                            let metrics_listener = match metrics_addr {
+                                Some(addr) => match metrics::bind(addr).await {
+                                    Ok(listener) => Some(listener),
+                                    Err(e) => {
+                                        let _ = framed_writer
+                                            .send(JsonResponse::StartResult {
+                                                name,
+                                                success: false,
+                                                err_message: Some(format!(
+                                                    "Failed to bind metrics endpoint on {}: {}",
+                                                    addr, e
+                                                )),
+                                            })
+                                            .await;
+
+                                        unsafe {
+                                            libc::raise(libc::SIGTERM);
+                                        }
+                                        continue;
+                                    }
+                                },
+                                None => None,
+                            };
+
+                            let trusted_networks: Vec<TrustedNetwork> = trusted_networks
+                                .iter()
+                                .filter_map(|network| match network.parse() {
+                                    Ok(network) => Some(network),
+                                    Err(e) => {
+                                        tracing::warn!("Ignoring trusted network: {}", e);
+                                        None
+                                    }
+                                })
+                                .collect();
+                            let udp_policy: Vec<UdpRule> = udp_policy
+                                .iter()
+                                .filter_map(|rule| match rule.parse() {
+                                    Ok(rule) => Some(rule),
+                                    Err(e) => {
+                                        tracing::warn!("Ignoring UDP rule: {}", e);
+                                        None
+                                    }
+                                })
+                                .collect();
+                            let trusted_network =
+                                self.detect_trusted_network(&trusted_networks).await;
+                            self.on_trusted_network
+                                .store(trusted_network.is_some(), Ordering::SeqCst);
+                            if !trusted_networks.is_empty() {
+                                tokio::spawn(self.clone().watch_trusted_networks(trusted_networks));
+                            }
+
+                            if reconnect_on_dns_change && !upstream {
+                                tokio::spawn(self.clone().watch_dns_servers());
+                            }
+
+                            if let Some(auto_reconnect) = defaults.auto_reconnect {
+                                self.auto_reconnect.store(auto_reconnect, Ordering::SeqCst);
+                            }
+
+                            let params = ConnectParams {
+                                name: name.clone(),
+                                server,
+                                redirect,
+                                allow_insecure,
+                                cookie,
+                                username,
+                                form_defaults,
+                                credentials: None,
+                                kill_switch,
+                                cert_expiry_warning_days,
+                                reconnect_jitter,
+                                reconnect_policy,
+                                search_domains,
+                                dtls_fallback,
+                                reported_os,
+                                http_proxy,
+                                min_tls_version,
+                                ciphers,
+                                accept_cert,
+                                pinned_cert: None,
+                                strict_cert,
+                                pass_tos,
+                                script_env,
+                                openconnect_log,
+                                openconnect_log_level,
+                                tun_device,
+                                transport_family,
+                                probe_host,
+                                verify_url,
+                                udp_policy,
+                                enable_udp,
+                                expected_subnet,
+                                mtu,
+                                protocol,
+                                client_cert,
+                                profile_script,
+                                defaults,
+                                upstream,
+                            };
+                            let connection_result = match trusted_network {
+                                Some(network) => {
+                                    tracing::info!(
+                                        "On a trusted network ({}), connecting to {} once the machine leaves it",
+                                        network,
+                                        name
+                                    );
+                                    Ok(None)
+                                }
+                                None => self.connect(&params).await.and_then(|client| {
+                                    if kill_switch {
+                                        // fail closed: never keep a tunnel up without the requested protection
+                                        if let Err(e) = apply_kill_switch(&client) {
+                                            client.cancel();
+                                            return Err(StateError::KillSwitchError(e));
+                                        }
+                                    }
+                                    Ok(Some(client))
+                                }),
+                            };
+
                             match connection_result {
                                 Ok(client) => {
-                                    {
-                                        let mut client_to_write = self.client.write().await;
-                                        *client_to_write = Some(client);
+                                    if client.is_none() {
+                                        *self.waiting_server.write().await =
+                                            Some((name.clone(), params.server.clone()));
+                                    } else if !upstream {
+                                        *self.connected_at.write().await = Some(Instant::now());
                                     }
+                                    if let Some(listener) = metrics_listener {
+                                        tokio::spawn(metrics::serve(listener, self.clone()));
+                                    }
+                                    if client.is_some() {
+                                        self.spawn_probe(&params);
+                                    }
+                                    let connected = client.is_some();
+                                    let verify_params = params.clone();
+                                    let span = log::connection_span(Some(&name));
+                                    tokio::spawn(
+                                        self.clone().keep_alive(client, params).instrument(span),
+                                    );
+
+                                    // the main loop has to run for the tunnel to carry the request
+                                    let verified = if connected {
+                                        State::verify_tunnel(&verify_params).await
+                                    } else {
+                                        Ok(())
+                                    };
+                                    if let Err(e) = verified {
+                                        tracing::error!("Verification of {} failed: {}", name, e);
+                                        self.shutdown().await;
+                                        if let Err(e) = killswitch::clear() {
+                                            tracing::error!("Failed to clear kill switch: {}", e);
+                                        }
+                                        let _ = framed_writer
+                                            .send(JsonResponse::StartResult {
+                                                name,
+                                                success: false,
+                                                err_message: Some(format!(
+                                                    "Verification failed: {}",
+                                                    e
+                                                )),
+                                            })
+                                            .await;
+
+                                        unsafe {
+                                            libc::raise(libc::SIGTERM);
+                                        }
+                                        continue;
+                                    }
+
                                     let _ = framed_writer
                                         .send(JsonResponse::StartResult {
                                             name,
@@ -121,21 +1322,14 @@ impl Acceptable for Arc<State> {
 
                         JsonRequest::Stop => {
                             tracing::debug!("Received stop command");
-                            {
-                                let client = self.client.read().await;
-                                if let Some(ref client) = *client {
-                                    let server_name =
-                                        client.get_server_name().unwrap_or("".to_string());
-                                    client.disconnect();
-
-                                    // ignore send error
-                                    let _ = framed_writer
-                                        .send(JsonResponse::StopResult { name: server_name })
-                                        .await;
-                                }
+                            if let Some(server_name) = self.shutdown().await {
+                                // ignore send error
+                                let _ = framed_writer
+                                    .send(JsonResponse::StopResult { name: server_name })
+                                    .await;
                             }
-                            {
-                                self.client.write().await.take();
+                            if let Err(e) = killswitch::clear() {
+                                tracing::error!("Failed to clear kill switch: {}", e);
                             }
                             unsafe {
                                 libc::raise(libc::SIGTERM);
@@ -154,15 +1348,35 @@ impl Acceptable for Arc<State> {
                                     let hostname = client.get_hostname().unwrap_or("".to_string());
                                     let status = client.get_status();
                                     let info = client.get_info().ok().flatten().map(Box::new);
-                                    let status = match status {
-                                        Status::Connected => "Connected",
-                                        Status::Connecting(_) => "Connecting",
-                                        Status::Disconnected => "Disconnected",
-                                        Status::Disconnecting => "Disconnecting",
-                                        Status::Error(_) => "Error",
-                                        Status::Initialized => "Initialized",
-                                    }
-                                    .to_string();
+                                    let awaiting_credentials = self.is_awaiting_credentials().await;
+                                    let cert_change =
+                                        self.cert_change.lock().await.as_ref().map(|change| {
+                                            (change.previous.clone(), change.presented.clone())
+                                        });
+                                    let status = Box::new(ConnectionStatus {
+                                        status,
+                                        awaiting_credentials,
+                                        on_trusted_network: self.is_on_trusted_network(),
+                                        cert_change,
+                                        reconnect_attempt: *self.reconnect_attempt.read().await,
+                                    });
+
+                                    let auto_reconnect = self.auto_reconnect.load(Ordering::SeqCst);
+                                    let server_cert_expiry = client
+                                        .get_server_cert_expiry()
+                                        .map(|expiry| expiry.to_rfc3339());
+                                    let server_cert_expiring = client.is_server_cert_expiring();
+                                    let tls_version =
+                                        client.get_tls_version().map(|version| version.to_string());
+                                    let server_version = client.get_server_version();
+                                    let connected_via = self
+                                        .upstream
+                                        .read()
+                                        .await
+                                        .as_ref()
+                                        .and_then(|upstream| upstream.get_server_name());
+                                    let probe = self.probe.read().await.clone();
+                                    let redirected_from = self.redirected_from.read().await.clone();
 
                                     // ignore send error
                                     let _ = framed_writer
@@ -171,11 +1385,211 @@ impl Acceptable for Arc<State> {
                                             server_url,
                                             hostname,
                                             status,
+                                            auto_reconnect,
+                                            server_cert_expiry,
+                                            server_cert_expiring,
+                                            tls_version,
+                                            server_version,
+                                            redirected_from,
+                                            connected_via,
+                                            probe,
                                             info,
                                         })
                                         .await;
+                                } else if let Some((server_name, server_url)) =
+                                    self.waiting_server.read().await.clone()
+                                {
+                                    // started on a trusted network, nothing connected yet
+                                    let _ = framed_writer
+                                        .send(JsonResponse::InfoResult {
+                                            server_name,
+                                            server_url,
+                                            hostname: String::new(),
+                                            status: Box::new(ConnectionStatus {
+                                                status: Status::Disconnected,
+                                                awaiting_credentials: false,
+                                                on_trusted_network: true,
+                                                cert_change: None,
+                                                reconnect_attempt: None,
+                                            }),
+                                            auto_reconnect: self
+                                                .auto_reconnect
+                                                .load(Ordering::SeqCst),
+                                            server_cert_expiry: None,
+                                            server_cert_expiring: false,
+                                            tls_version: None,
+                                            server_version: None,
+                                            redirected_from: None,
+                                            connected_via: None,
+                                            probe: None,
+                                            info: None,
+                                        })
+                                        .await;
+                                }
+                            }
+                        }
+
+                        JsonRequest::SetAutoReconnect(enabled) => {
+                            tracing::debug!("Received set auto-reconnect command: {}", enabled);
+                            self.auto_reconnect.store(enabled, Ordering::SeqCst);
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::SetAutoReconnectResult { enabled })
+                                .await;
+                        }
+
+                        JsonRequest::SupplyCredentials { password, totp } => {
+                            tracing::debug!("Received credentials");
+                            let accepted = match self.credentials_tx.lock().await.take() {
+                                Some(tx) => tx.send(Credentials { password, totp }).is_ok(),
+                                None => false,
+                            };
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::SupplyCredentialsResult { accepted })
+                                .await;
+                        }
+
+                        JsonRequest::TrustCert { fingerprint } => {
+                            tracing::debug!("Received trust cert command: {}", fingerprint);
+                            let mut cert_change = self.cert_change.lock().await;
+                            let result = match cert_change.take() {
+                                Some(change) if change.presented == fingerprint => change
+                                    .tx
+                                    .send(())
+                                    .map_err(|_| "The reconnect was given up".to_string()),
+                                Some(change) => {
+                                    let err = format!(
+                                        "The gateway presented {}, not {}",
+                                        change.presented, fingerprint
+                                    );
+                                    *cert_change = Some(change);
+                                    Err(err)
+                                }
+                                None => Err("No certificate change is awaiting trust".to_string()),
+                            };
+                            drop(cert_change);
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::TrustCertResult {
+                                    accepted: result.is_ok(),
+                                    err_message: result.err(),
+                                })
+                                .await;
+                        }
+
+                        JsonRequest::ReapplyConfig => {
+                            tracing::debug!("Received reapply config command");
+                            let client = self.client.read().await.clone();
+                            let result = match client {
+                                Some(client) => {
+                                    tokio::task::spawn_blocking(move || client.reapply_config())
+                                        .await
+                                        .map_err(|e| e.to_string())
+                                        .and_then(|result| result.map_err(|e| e.to_string()))
+                                }
+                                None => Err("No connection is running".to_string()),
+                            };
+                            match &result {
+                                Ok(()) => tracing::info!("Applied the network configuration again"),
+                                Err(e) => tracing::error!(
+                                    "Failed to apply the network configuration again: {}",
+                                    e
+                                ),
+                            }
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::ReapplyConfigResult {
+                                    success: result.is_ok(),
+                                    err_message: result.err(),
+                                })
+                                .await;
+                        }
+
+                        JsonRequest::Rekey => {
+                            tracing::debug!("Received rekey command");
+                            let client = self.client.read().await.clone();
+                            let result = match client {
+                                Some(client) => tokio::task::spawn_blocking(move || client.rekey())
+                                    .await
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|result| result.map_err(|e| e.to_string())),
+                                None => Err("No connection is running".to_string()),
+                            };
+                            match &result {
+                                Ok(cipher) => {
+                                    tracing::info!("Rekeyed the session, cipher {}", cipher)
                                 }
+                                Err(e) => tracing::error!("Failed to rekey the session: {}", e),
                             }
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::RekeyResult {
+                                    cipher: result.as_ref().ok().cloned(),
+                                    err_message: result.err(),
+                                })
+                                .await;
+                        }
+
+                        JsonRequest::Ping => {
+                            // ignore send error
+                            let _ = framed_writer.send(JsonResponse::Pong).await;
+                        }
+
+                        JsonRequest::Stats => {
+                            tracing::debug!("Received stats command");
+                            let client = self.client.read().await.clone();
+                            let (server_name, result) = match client {
+                                Some(client) if client.get_status() == Status::Connected => {
+                                    // the first snapshot is requested right away
+                                    let stats = Box::pin(client.stats_stream(STATS_TIMEOUT))
+                                        .next()
+                                        .await
+                                        .ok_or("The connection was closed".to_string());
+                                    (client.get_server_name().unwrap_or_default(), stats)
+                                }
+                                _ => (String::new(), Err("Not connected".to_string())),
+                            };
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::StatsResult {
+                                    server_name,
+                                    stats: result.as_ref().ok().cloned(),
+                                    err_message: result.err(),
+                                })
+                                .await;
+                        }
+
+                        JsonRequest::LatencyProbe { count, target } => {
+                            tracing::debug!("Received latency probe command");
+                            let client = self.client.read().await.clone();
+                            let result = match client {
+                                Some(client) if client.get_status() == Status::Connected => {
+                                    let probe_host = self.probe_host.read().await.clone();
+                                    let info = client.get_info().ok().flatten();
+                                    match target.or_else(|| {
+                                        probe::latency_target(probe_host.as_deref(), info.as_ref())
+                                    }) {
+                                        Some(target) => probe::measure_latency(&target, count).await,
+                                        None => Err("No probe host is configured and the gateway pushed no DNS server, pass --target".to_string()),
+                                    }
+                                }
+                                _ => Err("Not connected".to_string()),
+                            };
+
+                            // ignore send error
+                            let _ = framed_writer
+                                .send(JsonResponse::LatencyProbeResult {
+                                    latency: result.as_ref().ok().cloned(),
+                                    err_message: result.err(),
+                                })
+                                .await;
                         }
                     }
                 }
@@ -184,12 +1598,12 @@ impl Acceptable for Arc<State> {
     }
 }
 
-pub async fn start_daemon() -> anyhow::Result<()> {
-    let server = UnixDomainServer::bind()?;
+pub async fn start_daemon(config_file: PathBuf) -> anyhow::Result<()> {
+    let server = DaemonServer::bind()?;
     let mut sigterm = signal(SignalKind::terminate())?;
     let mut sigint = signal(SignalKind::interrupt())?;
     let mut sigquit = signal(SignalKind::quit())?;
-    let state = State::new(server);
+    let state = State::new(server, config_file);
 
     loop {
         let state = state.clone();
@@ -209,5 +1623,18 @@ pub async fn start_daemon() -> anyhow::Result<()> {
         };
     }
 
+    // a connect may still be in progress, cancel it so that its blocking task returns
+    state.shutdown().await;
+
     Ok(())
 }
+
+#[test]
+fn test_jittered_delay() {
+    let delay = Duration::from_secs(10);
+    assert_eq!(jittered(delay, 0), delay);
+    for _ in 0..100 {
+        let jittered = jittered(delay, 20);
+        assert!(jittered >= Duration::from_secs(8) && jittered <= Duration::from_secs(12));
+    }
+}
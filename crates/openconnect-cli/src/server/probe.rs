@@ -0,0 +1,182 @@
+//! Connectivity self-check after connecting, a TCP connect to a host behind the tunnel
+//!
+//! A connection can be up while the resources behind it are not reachable, e.g. because of asymmetric routing
+//! or a firewall. ICMP would need a raw socket, so the probe only checks that a TCP port accepts connections.
+//! The latency of the tunnel is measured the same way, as the time a TCP handshake through it takes.
+
+use openconnect_core::ip_info::IpInfo;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+
+/// The vpnc-script may still be setting up routes when the connection is reported up
+pub const PROBE_DELAY: Duration = Duration::from_secs(2);
+
+/// Time given to the probe host to accept the connection
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause between the handshakes of a latency measurement
+const LATENCY_INTERVAL: Duration = Duration::from_millis(500);
+
+pub enum ProbeResult {
+    Reachable(Duration),
+    Unreachable(String),
+}
+
+impl std::fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeResult::Reachable(elapsed) => write!(f, "reachable ({} ms)", elapsed.as_millis()),
+            ProbeResult::Unreachable(reason) => write!(f, "unreachable ({})", reason),
+        }
+    }
+}
+
+/// Validate a `host:port` probe target, the host may be a name or an IP address (IPv6 in brackets)
+pub fn parse_probe_host(value: &str) -> Result<String, String> {
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or(format!("Expected <HOST>:<PORT>, got {}", value))?;
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return Err(format!("Invalid probe host: {}", host));
+    }
+    port.parse::<u16>()
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or(format!("Invalid probe port: {}", port))?;
+
+    Ok(value.to_string())
+}
+
+/// Connect to the probe host through the tunnel
+pub async fn probe(probe_host: &str) -> ProbeResult {
+    let started = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(probe_host)).await {
+        Ok(Ok(_)) => ProbeResult::Reachable(started.elapsed()),
+        Ok(Err(e)) => ProbeResult::Unreachable(e.to_string()),
+        Err(_) => {
+            ProbeResult::Unreachable(format!("no answer within {}s", PROBE_TIMEOUT.as_secs()))
+        }
+    }
+}
+
+/// Round-trip times of the handshakes of [measure_latency], in milliseconds
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Latency {
+    pub target: String,
+    pub sent: u32,
+    pub received: u32,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Latency {
+    /// `None` if no handshake succeeded
+    fn from_samples(target: &str, sent: u32, samples: &[Duration]) -> Option<Self> {
+        let millis: Vec<f64> = samples
+            .iter()
+            .map(|sample| sample.as_secs_f64() * 1000.0)
+            .collect();
+        let min_ms = millis.iter().copied().reduce(f64::min)?;
+        let max_ms = millis.iter().copied().reduce(f64::max)?;
+
+        Some(Self {
+            target: target.to_string(),
+            sent,
+            received: samples.len() as u32,
+            min_ms,
+            avg_ms: millis.iter().sum::<f64>() / millis.len() as f64,
+            max_ms,
+        })
+    }
+}
+
+/// The probe host if one is configured, otherwise the DNS port of the first DNS server pushed by the gateway
+pub fn latency_target(probe_host: Option<&str>, info: Option<&IpInfo>) -> Option<String> {
+    if let Some(probe_host) = probe_host {
+        return Some(probe_host.to_string());
+    }
+
+    info?
+        .dns
+        .iter()
+        .flatten()
+        .find_map(|dns| dns.parse::<IpAddr>().ok())
+        .map(|dns| SocketAddr::new(dns, 53).to_string())
+}
+
+/// Time `count` TCP handshakes with the target through the tunnel, the name is resolved only once
+pub async fn measure_latency(target: &str, count: u32) -> Result<Latency, String> {
+    let addr = tokio::net::lookup_host(target)
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", target, e))?
+        .next()
+        .ok_or(format!("Failed to resolve {}", target))?;
+
+    let mut samples = vec![];
+    let mut last_error = None;
+    for i in 0..count {
+        if i > 0 {
+            tokio::time::sleep(LATENCY_INTERVAL).await;
+        }
+        let started = Instant::now();
+        match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => samples.push(started.elapsed()),
+            Ok(Err(e)) => last_error = Some(e.to_string()),
+            Err(_) => last_error = Some(format!("no answer within {}s", PROBE_TIMEOUT.as_secs())),
+        }
+    }
+
+    Latency::from_samples(target, count, &samples).ok_or(format!(
+        "{} is unreachable ({})",
+        target,
+        last_error.unwrap_or_default()
+    ))
+}
+
+#[test]
+fn test_latency() {
+    let latency = Latency::from_samples(
+        "intranet.corp:443",
+        4,
+        &[
+            Duration::from_millis(20),
+            Duration::from_millis(40),
+            Duration::from_millis(30),
+        ],
+    )
+    .unwrap();
+    assert_eq!(latency.received, 3);
+    assert_eq!(latency.min_ms, 20.0);
+    assert_eq!(latency.avg_ms, 30.0);
+    assert_eq!(latency.max_ms, 40.0);
+    assert_eq!(Latency::from_samples("intranet.corp:443", 4, &[]), None);
+
+    let info: IpInfo = serde_json::from_value(serde_json::json!({
+        "addr": null, "netmask": null, "addr6": null, "netmask6": null,
+        "dns": [null, "fd00::53", null], "nbns": [null, null, null], "domain": null,
+        "search_domains": [], "proxy_pac": null, "mtu": 1400, "split_dns_domains": [], "gateway_addr": null
+    }))
+    .unwrap();
+    assert_eq!(
+        latency_target(None, Some(&info)).as_deref(),
+        Some("[fd00::53]:53")
+    );
+    assert_eq!(
+        latency_target(Some("intranet.corp:443"), Some(&info)).as_deref(),
+        Some("intranet.corp:443")
+    );
+    assert_eq!(latency_target(None, None), None);
+}
+
+#[test]
+fn test_parse_probe_host() {
+    assert!(parse_probe_host("intranet.corp:443").is_ok());
+    assert!(parse_probe_host("[fd00::1]:22").is_ok());
+    assert!(parse_probe_host("intranet.corp").is_err());
+    assert!(parse_probe_host(":443").is_err());
+    assert!(parse_probe_host("intranet.corp:0").is_err());
+}
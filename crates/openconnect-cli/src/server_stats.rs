@@ -0,0 +1,80 @@
+//! Lifetime counters of each server, added up by the daemon whenever a session of the primary connection ends
+//!
+//! `stats <name>` shows them once the daemon is gone. Like the last session, they are stored next to the config file.
+
+use chrono::{DateTime, Utc};
+use openconnect_core::{paths, stats::Stats};
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ServerStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+
+    /// How long the sessions were up in total
+    pub connected_secs: u64,
+    pub connects: u64,
+    pub last_connected: Option<DateTime<Utc>>,
+}
+
+impl ServerStats {
+    /// Add a session which was up for `duration` and transferred `stats`
+    fn add_session(&mut self, stats: &Stats, duration: Duration, disconnected_at: DateTime<Utc>) {
+        self.rx_bytes += stats.rx_bytes;
+        self.tx_bytes += stats.tx_bytes;
+        self.connected_secs += duration.as_secs();
+        self.connects += 1;
+        self.last_connected = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| disconnected_at.checked_sub_signed(duration));
+    }
+}
+
+/// The counters of all servers, empty if no session ended yet
+pub fn load() -> std::io::Result<HashMap<String, ServerStats>> {
+    let json = match std::fs::read_to_string(paths::server_stats_file()?) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Add a session of the server which just ended
+pub fn record_session(server_name: &str, stats: &Stats, duration: Duration) -> std::io::Result<()> {
+    let mut all_stats = load()?;
+    all_stats
+        .entry(server_name.to_string())
+        .or_default()
+        .add_session(stats, duration, Utc::now());
+
+    // replaced in one step, so that a crash never leaves truncated counters behind
+    let stats_file = paths::server_stats_file()?;
+    let tmp_file = stats_file.with_extension("json.tmp");
+    std::fs::write(&tmp_file, serde_json::to_string_pretty(&all_stats)?)?;
+    std::fs::rename(tmp_file, stats_file)
+}
+
+#[test]
+fn test_add_session() {
+    let mut server_stats = ServerStats::default();
+    let disconnected_at = Utc::now();
+    let stats = Stats {
+        rx_bytes: 1000,
+        tx_bytes: 200,
+        ..Default::default()
+    };
+
+    server_stats.add_session(&stats, Duration::from_secs(60), disconnected_at);
+    server_stats.add_session(&stats, Duration::from_secs(30), disconnected_at);
+
+    assert_eq!(server_stats.rx_bytes, 2000);
+    assert_eq!(server_stats.tx_bytes, 400);
+    assert_eq!(server_stats.connected_secs, 90);
+    assert_eq!(server_stats.connects, 2);
+    assert_eq!(
+        server_stats.last_connected,
+        Some(disconnected_at - chrono::Duration::seconds(30))
+    );
+}
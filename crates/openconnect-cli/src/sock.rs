@@ -1,99 +1,364 @@
+//! The local transport between the commands and the daemon, a Unix domain socket or a named pipe on Windows
+//!
+//! Both carry the same length delimited JSON messages, see [get_framed_reader] and [get_framed_writer].
+
 use crate::{JsonRequest, JsonResponse};
 use colored::Colorize;
-use futures::SinkExt;
-use std::path::{Path, PathBuf};
+use futures::{SinkExt, TryStreamExt};
+use std::{future::Future, time::Duration};
 use thiserror::Error;
-use tokio::net::{
-    unix::{OwnedReadHalf, OwnedWriteHalf},
-    UnixListener, UnixStream,
-};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
 use tokio_serde::{formats::SymmetricalJson, Framed};
-use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use tokio_util::codec::{
+    length_delimited::LengthDelimitedCodecError, FramedRead, FramedWrite, LengthDelimitedCodec,
+};
+
+/// Upper bound of a single JSON message, a larger length prefix is rejected before allocating the frame
+///
+/// The largest message is the status with all split routes, which stays far below this
+pub const MAX_FRAME_LENGTH: usize = 64 * 1024;
 
 #[derive(Debug, Error)]
 pub enum SockError {
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(std::io::Error),
+
+    #[error("Message exceeds the maximum frame length of {MAX_FRAME_LENGTH} bytes")]
+    FrameTooLarge,
 
     #[error("No valid connection")]
     NoValidConnection,
+
+    #[error("Daemon did not respond in time")]
+    Timeout,
 }
 
-pub fn get_sock() -> PathBuf {
-    let tmp = Path::new("/tmp").to_path_buf();
-    tmp.join("openconnect-rs.sock")
+impl From<std::io::Error> for SockError {
+    fn from(err: std::io::Error) -> Self {
+        let too_large = err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<LengthDelimitedCodecError>());
+
+        if too_large {
+            SockError::FrameTooLarge
+        } else {
+            SockError::Io(err)
+        }
+    }
 }
 
+/// A transport the daemon listens on and the commands connect to
+pub trait Transport: Sized {
+    /// A connection of a command, accepted by the daemon
+    type Accepted: AsyncRead + AsyncWrite + Send + 'static;
+
+    /// A connection to the daemon
+    type Connected: AsyncRead + AsyncWrite + Send + 'static;
+
+    /// Listen for commands, fails if another daemon listens
+    fn bind() -> std::io::Result<Self>;
+
+    /// Wait for the next command to connect
+    fn accept(&self) -> impl Future<Output = std::io::Result<Self::Accepted>> + Send;
+
+    /// Connect to the daemon
+    fn connect() -> impl Future<Output = std::io::Result<Self::Connected>> + Send;
+
+    /// Whether a daemon listens, or on Unix a socket file was left behind
+    fn exists() -> bool;
+
+    /// The socket file or the pipe name
+    fn address() -> String;
+
+    /// Clean up after a daemon which is gone without closing the transport
+    fn remove_stale() -> std::io::Result<()>;
+}
+
+#[cfg(unix)]
+pub type LocalTransport = unix::UnixSocket;
+
+#[cfg(windows)]
+pub type LocalTransport = windows::NamedPipe;
+
+/// Whether a daemon listens, see [Transport::exists]
+pub fn exists() -> bool {
+    LocalTransport::exists()
+}
+
+/// The socket file or the pipe name of the daemon
+pub fn address() -> String {
+    LocalTransport::address()
+}
+
+/// How long a daemon has to answer [JsonRequest::Ping]
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ping the daemon, a refused connection means it is gone and left its socket behind
+async fn ping() -> Result<(), SockError> {
+    let mut client = DaemonClient::connect().await?;
+    client.send(JsonRequest::Ping).await?;
+    match client.receive(PING_TIMEOUT).await? {
+        Some(JsonResponse::Pong) => Ok(()),
+        _ => Err(SockError::NoValidConnection),
+    }
+}
+
+/// Exit if a daemon is running, a socket left behind by a daemon which is gone is removed instead
 pub fn exit_when_socket_exists() {
-    if get_sock().exists() {
-        eprintln!("{}","\nSocket already exists. You may have a connected VPN session or a stale socket file. You may solve by:".red());
-        eprintln!(
-            "{}",
-            "1. Stopping the connection by sending stop command.".red()
-        );
-        eprintln!(
-            "2. Manually deleting the socket file which located at: {}",
-            get_sock().display().to_string().red()
-        );
-        std::process::exit(1);
+    if !exists() {
+        return;
+    }
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    match runtime.block_on(ping()) {
+        Ok(()) => {
+            eprintln!(
+                "{}",
+                "\nA VPN session is already connected, stop it by sending stop command first."
+                    .red()
+            );
+        }
+        Err(SockError::Io(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            println!("Removing the stale socket {}", address());
+            match LocalTransport::remove_stale() {
+                Ok(()) => return,
+                Err(e) => eprintln!(
+                    "{}",
+                    format!("\nFailed to remove the stale socket {}: {}", address(), e).red()
+                ),
+            }
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "\nSocket {} already exists, but the daemon did not answer: {}",
+                    address(),
+                    e
+                )
+                .red()
+            );
+            eprintln!(
+                "{}",
+                "Stop the connection by sending stop command, or delete the socket file if no daemon is running."
+                    .red()
+            );
+        }
+    }
+
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+pub mod unix {
+    use super::Transport;
+    use openconnect_core::paths;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    /// The user or group id of the user who ran `sudo`
+    fn sudo_id(key: &str) -> Option<u32> {
+        std::env::var(key).ok()?.parse().ok()
+    }
+
+    pub struct UnixSocket {
+        listener: UnixListener,
+    }
+
+    impl Transport for UnixSocket {
+        type Accepted = UnixStream;
+        type Connected = UnixStream;
+
+        fn bind() -> std::io::Result<Self> {
+            let sock = paths::sock_file();
+            // the daemon clears its umask, the socket must not be accessible to others until it is restricted
+            let umask = unsafe { libc::umask(0o177) };
+            let listener = UnixListener::bind(&sock);
+            unsafe { libc::umask(umask) };
+            let listener = listener?.into_std()?;
+            listener.set_nonblocking(true)?;
+            let listener = UnixListener::from_std(listener)?;
+
+            // only the owner may stop or query the daemon, which is the user who escalated to start it
+            std::fs::set_permissions(&sock, std::fs::Permissions::from_mode(0o600))?;
+            if let (Some(uid), Some(gid)) = (sudo_id("SUDO_UID"), sudo_id("SUDO_GID")) {
+                std::os::unix::fs::chown(&sock, Some(uid), Some(gid))?;
+            }
+
+            Ok(UnixSocket { listener })
+        }
+
+        async fn accept(&self) -> std::io::Result<UnixStream> {
+            let (stream, _) = self.listener.accept().await?;
+            Ok(stream)
+        }
+
+        async fn connect() -> std::io::Result<UnixStream> {
+            UnixStream::connect(paths::sock_file()).await
+        }
+
+        fn exists() -> bool {
+            paths::sock_file().exists()
+        }
+
+        fn address() -> String {
+            paths::sock_file().display().to_string()
+        }
+
+        fn remove_stale() -> std::io::Result<()> {
+            std::fs::remove_file(paths::sock_file())
+        }
+    }
+
+    impl Drop for UnixSocket {
+        fn drop(&mut self) {
+            // There's no way to return a useful error here
+            std::fs::remove_file(paths::sock_file()).expect("Failed to remove socket file");
+        }
     }
 }
 
-pub type FramedWriter<T> =
-    Framed<FramedWrite<OwnedWriteHalf, LengthDelimitedCodec>, T, T, SymmetricalJson<T>>;
-pub type FramedReader<T> =
-    Framed<FramedRead<OwnedReadHalf, LengthDelimitedCodec>, T, T, SymmetricalJson<T>>;
+#[cfg(windows)]
+pub mod windows {
+    use super::Transport;
+    use std::time::Duration;
+    use tokio::{
+        net::windows::named_pipe::{
+            ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+        },
+        sync::Mutex,
+    };
+
+    pub const PIPE_NAME: &str = r"\\.\pipe\openconnect-rs";
+
+    /// All instances of the pipe are connected to other commands, see `WaitNamedPipe`
+    const ERROR_PIPE_BUSY: i32 = 231;
+    const PIPE_BUSY_RETRY: Duration = Duration::from_millis(50);
+
+    /// The daemon creates the next instance of the pipe for the next command, whenever one connects
+    pub struct NamedPipe {
+        next: Mutex<NamedPipeServer>,
+    }
+
+    impl Transport for NamedPipe {
+        type Accepted = NamedPipeServer;
+        type Connected = NamedPipeClient;
+
+        fn bind() -> std::io::Result<Self> {
+            let server = ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(PIPE_NAME)?;
+            Ok(NamedPipe {
+                next: Mutex::new(server),
+            })
+        }
+
+        async fn accept(&self) -> std::io::Result<NamedPipeServer> {
+            let mut next = self.next.lock().await;
+            next.connect().await?;
+            let following = ServerOptions::new().create(PIPE_NAME)?;
+            Ok(std::mem::replace(&mut *next, following))
+        }
 
-pub fn get_framed_writer<T: Sized>(write_half: OwnedWriteHalf) -> FramedWriter<T> {
-    let length_delimited = FramedWrite::new(write_half, LengthDelimitedCodec::new());
+        async fn connect() -> std::io::Result<NamedPipeClient> {
+            loop {
+                match ClientOptions::new().open(PIPE_NAME) {
+                    Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {}
+                    result => return result,
+                }
+                tokio::time::sleep(PIPE_BUSY_RETRY).await;
+            }
+        }
+
+        fn exists() -> bool {
+            // the daemon sees a command which disconnects right away
+            match std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(PIPE_NAME)
+            {
+                Ok(_) => true,
+                Err(e) => e.raw_os_error() == Some(ERROR_PIPE_BUSY),
+            }
+        }
+
+        fn address() -> String {
+            PIPE_NAME.to_string()
+        }
+
+        // a named pipe is gone with the daemon, it can't be stale
+        fn remove_stale() -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+pub type FramedWriter<T, W> =
+    Framed<FramedWrite<W, LengthDelimitedCodec>, T, T, SymmetricalJson<T>>;
+pub type FramedReader<T, R> = Framed<FramedRead<R, LengthDelimitedCodec>, T, T, SymmetricalJson<T>>;
+
+fn length_delimited_codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME_LENGTH)
+        .new_codec()
+}
+
+pub fn get_framed_writer<T: Sized, W: AsyncWrite>(write_half: W) -> FramedWriter<T, W> {
+    let length_delimited = FramedWrite::new(write_half, length_delimited_codec());
     let codec = SymmetricalJson::<T>::default();
     tokio_serde::SymmetricallyFramed::new(length_delimited, codec)
 }
 
-pub fn get_framed_reader<T: Sized>(read_half: OwnedReadHalf) -> FramedReader<T> {
-    let length_delimited = FramedRead::new(read_half, LengthDelimitedCodec::new());
+pub fn get_framed_reader<T: Sized, R: AsyncRead>(read_half: R) -> FramedReader<T, R> {
+    let length_delimited = FramedRead::new(read_half, length_delimited_codec());
     let codec = SymmetricalJson::<T>::default();
     tokio_serde::SymmetricallyFramed::new(length_delimited, codec)
 }
 
-pub struct UnixDomainServer {
-    pub listener: UnixListener,
+type Accepted = <LocalTransport as Transport>::Accepted;
+type Connected = <LocalTransport as Transport>::Connected;
+
+pub struct DaemonServer {
+    transport: LocalTransport,
 }
 
-impl UnixDomainServer {
+impl DaemonServer {
     pub fn bind() -> Result<Self, SockError> {
-        let listener = UnixListener::bind(get_sock())?;
-        let listener = listener.into_std()?;
-        listener.set_nonblocking(true)?;
-        let listener = UnixListener::from_std(listener)?;
-        Ok(UnixDomainServer { listener })
+        Ok(DaemonServer {
+            transport: LocalTransport::bind()?,
+        })
     }
-}
 
-impl Drop for UnixDomainServer {
-    fn drop(&mut self) {
-        // There's no way to return a useful error here
-        std::fs::remove_file(get_sock()).expect("Failed to remove socket file");
+    /// Wait for the next command, with the reader of its requests and the writer of the responses
+    pub async fn accept(
+        &self,
+    ) -> Result<
+        (
+            FramedReader<JsonRequest, ReadHalf<Accepted>>,
+            FramedWriter<JsonResponse, WriteHalf<Accepted>>,
+        ),
+        SockError,
+    > {
+        let (read, write) = tokio::io::split(self.transport.accept().await?);
+        Ok((get_framed_reader(read), get_framed_writer(write)))
     }
 }
 
-pub struct UnixDomainClient {
-    framed_writer: FramedWriter<JsonRequest>,
-    pub framed_reader: FramedReader<JsonResponse>,
+pub struct DaemonClient {
+    framed_writer: FramedWriter<JsonRequest, WriteHalf<Connected>>,
+    pub framed_reader: FramedReader<JsonResponse, ReadHalf<Connected>>,
 }
 
-impl UnixDomainClient {
+impl DaemonClient {
     pub async fn connect() -> Result<Self, SockError> {
-        let sock = get_sock();
-        if !sock.exists() {
+        if !LocalTransport::exists() {
             return Err(SockError::NoValidConnection);
         }
-        let stream = UnixStream::connect(sock).await?;
-        let (read, write) = stream.into_split();
+        let (read, write) = tokio::io::split(LocalTransport::connect().await?);
         let framed_writer = get_framed_writer(write);
         let framed_reader = get_framed_reader(read);
 
-        Ok(UnixDomainClient {
+        Ok(DaemonClient {
             framed_writer,
             framed_reader,
         })
@@ -103,4 +368,21 @@ impl UnixDomainClient {
         self.framed_writer.send(command).await?;
         Ok(())
     }
+
+    /// Wait for the next response, failing if the daemon does not answer within the timeout
+    pub async fn receive(&mut self, timeout: Duration) -> Result<Option<JsonResponse>, SockError> {
+        match tokio::time::timeout(timeout, self.framed_reader.try_next()).await {
+            Ok(response) => Ok(response?),
+            Err(_) => Err(SockError::Timeout),
+        }
+    }
+}
+
+#[test]
+fn test_frame_too_large() {
+    use tokio_util::{bytes::BytesMut, codec::Decoder};
+
+    let mut buf = BytesMut::from(&u32::MAX.to_be_bytes()[..]);
+    let err = length_delimited_codec().decode(&mut buf).unwrap_err();
+    assert!(matches!(SockError::from(err), SockError::FrameTooLarge));
 }
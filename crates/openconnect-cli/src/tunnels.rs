@@ -0,0 +1,149 @@
+//! The tunnels set up by the daemon, recorded so that `cleanup` can tear down the ones of a crashed daemon
+//!
+//! The daemon records the interface, the vpnc-script and the parameters pushed by the gateway of every tunnel, and
+//! forgets them on a clean `stop`. A tunnel whose daemon is gone while its interface still exists is orphaned. Running
+//! the vpnc-script for the `disconnect` reason with the recorded parameters removes its routes and restores the DNS
+//! configuration, like libopenconnect does on a clean disconnect. Like the last session, they are stored next to the
+//! config file.
+
+use openconnect_core::{ip_info::IpInfo, paths, run_vpnc_script};
+use std::{
+    collections::HashMap,
+    process::{Command, ExitStatus},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TunnelError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("vpnc-script exited with {0}")]
+    ScriptFailed(ExitStatus),
+
+    #[error("Failed to delete interface {0}: {1}")]
+    DeleteInterface(String, String),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TunnelRecord {
+    pub server_name: String,
+    pub ifname: String,
+    pub vpnc_script: String,
+
+    /// The extra variables of the vpnc-script environment, see [openconnect_core::config::ConfigBuilder::script_env]
+    #[serde(default)]
+    pub script_env: HashMap<String, String>,
+    pub info: IpInfo,
+
+    /// The daemon which set up the tunnel
+    pub pid: u32,
+}
+
+impl TunnelRecord {
+    /// The daemon is gone, but the interface is still there
+    pub fn is_orphaned(&self) -> bool {
+        !is_running(self.pid) && interface_exists(&self.ifname)
+    }
+
+    /// Run the vpnc-script for the `disconnect` reason, and delete the interface if the script left it behind
+    pub fn tear_down(&self) -> Result<(), TunnelError> {
+        let status = run_vpnc_script(
+            &self.vpnc_script,
+            "disconnect",
+            &self.ifname,
+            &self.info,
+            &self.script_env,
+        )?;
+        if !status.success() {
+            return Err(TunnelError::ScriptFailed(status));
+        }
+
+        // a utun device of macOS is gone with its process, a tun device of Linux may be persistent
+        #[cfg(target_os = "linux")]
+        if interface_exists(&self.ifname) {
+            let output = Command::new("ip")
+                .args(["link", "delete", &self.ifname])
+                .output()?;
+            if !output.status.success() {
+                return Err(TunnelError::DeleteInterface(
+                    self.ifname.clone(),
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the daemon of a tunnel is still running
+pub fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // signal 0 only checks for the process, EPERM means it exists but belongs to another user
+    (unsafe { libc::kill(pid, 0) } == 0)
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn interface_exists(ifname: &str) -> bool {
+    #[cfg(target_os = "linux")]
+    return std::path::Path::new("/sys/class/net").join(ifname).exists();
+
+    #[cfg(not(target_os = "linux"))]
+    return Command::new("ifconfig")
+        .arg(ifname)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+}
+
+/// The recorded tunnels, empty if none was recorded
+pub fn load() -> std::io::Result<Vec<TunnelRecord>> {
+    let tunnels_file = paths::tunnels_file()?;
+    // `cleanup` runs the vpnc-scripts of the records as root
+    match paths::check_owner(&tunnels_file) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        result => result?,
+    }
+
+    let json = std::fs::read_to_string(tunnels_file)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Replace the records in one step, the file is removed once none is left
+pub fn save(records: &[TunnelRecord]) -> std::io::Result<()> {
+    let tunnels_file = paths::tunnels_file()?;
+    if records.is_empty() {
+        return match std::fs::remove_file(tunnels_file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let tmp_file = tunnels_file.with_extension("json.tmp");
+    std::fs::write(&tmp_file, serde_json::to_string_pretty(records)?)?;
+    std::fs::rename(tmp_file, tunnels_file)
+}
+
+/// Record a tunnel which was just set up, replacing a previous one on the same interface
+pub fn record(record: TunnelRecord) -> std::io::Result<()> {
+    let mut records = load()?;
+    records.retain(|previous| previous.ifname != record.ifname);
+    records.push(record);
+    save(&records)
+}
+
+/// Forget the tunnels of this daemon, libopenconnect tears them down on a clean disconnect
+pub fn forget_own() -> std::io::Result<()> {
+    let mut records = load()?;
+    records.retain(|record| record.pid != std::process::id());
+    save(&records)
+}
+
+#[test]
+fn test_is_running() {
+    assert!(is_running(std::process::id()));
+    assert!(!is_running(u32::MAX));
+}
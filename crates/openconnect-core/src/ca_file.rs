@@ -0,0 +1,122 @@
+//! CA certificates trusted in addition to the system store, see [crate::config::ConfigBuilder::ca_path]
+//!
+//! libopenconnect only takes a single CA file, so the certificates of a CA directory are concatenated into a bundle
+//! in the runtime directory, which is kept as long as the client.
+
+use crate::paths;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const PEM_CERT_MARKER: &str = "-----BEGIN CERTIFICATE-----";
+
+/// Tells apart the bundles of several clients in one process, e.g. an upstream and a primary connection
+static BUNDLE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the file can be read and holds a PEM certificate
+pub(crate) fn is_cert_file(path: &Path) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|pem| pem.contains(PEM_CERT_MARKER))
+}
+
+/// The readable files of the directory holding a PEM certificate, sorted by name, other files are skipped
+pub(crate) fn dir_certs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut certs: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_cert_file(path))
+        .collect();
+    certs.sort();
+    Ok(certs)
+}
+
+/// The CA file passed to libopenconnect, removed when dropped if it was bundled from a directory
+pub(crate) struct CaFile {
+    path: PathBuf,
+    bundled: bool,
+}
+
+impl CaFile {
+    /// The CA file itself, or a bundle of the CA file followed by the certificates of the directory
+    pub(crate) fn new(
+        ca_cert: Option<&Path>,
+        ca_path: Option<&Path>,
+    ) -> std::io::Result<Option<Self>> {
+        let Some(ca_path) = ca_path else {
+            return Ok(ca_cert.map(|ca_cert| CaFile {
+                path: ca_cert.to_path_buf(),
+                bundled: false,
+            }));
+        };
+
+        let mut bundle = String::new();
+        for cert in ca_cert
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(dir_certs(ca_path)?)
+        {
+            bundle.push_str(std::fs::read_to_string(cert)?.trim_end());
+            bundle.push('\n');
+        }
+
+        let path = paths::runtime_dir().join(format!(
+            "openconnect-rs-ca-{}-{}.pem",
+            std::process::id(),
+            BUNDLE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, bundle)?;
+
+        Ok(Some(CaFile {
+            path,
+            bundled: true,
+        }))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CaFile {
+    fn drop(&mut self) {
+        if self.bundled {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[test]
+fn test_ca_file() {
+    let dir = std::env::temp_dir().join("openconnect-rs-test-ca");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("certs")).unwrap();
+    let cert = |name: &str| format!("{}\n{}\n-----END CERTIFICATE-----\n", PEM_CERT_MARKER, name);
+    std::fs::write(dir.join("root.pem"), cert("root")).unwrap();
+    std::fs::write(dir.join("certs/b.crt"), cert("b")).unwrap();
+    std::fs::write(dir.join("certs/a.pem"), cert("a")).unwrap();
+    std::fs::write(dir.join("certs/README"), "not a certificate").unwrap();
+
+    let certs = dir_certs(&dir.join("certs")).unwrap();
+    assert_eq!(
+        certs,
+        vec![dir.join("certs/a.pem"), dir.join("certs/b.crt")]
+    );
+
+    let single = CaFile::new(Some(&dir.join("root.pem")), None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(single.path(), dir.join("root.pem"));
+    drop(single);
+    assert!(dir.join("root.pem").exists());
+
+    let bundle = CaFile::new(Some(&dir.join("root.pem")), Some(&dir.join("certs")))
+        .unwrap()
+        .unwrap();
+    let pem = std::fs::read_to_string(bundle.path()).unwrap();
+    assert_eq!(pem, cert("root") + &cert("a") + &cert("b"));
+    let bundle_path = bundle.path().to_path_buf();
+    drop(bundle);
+    assert!(!bundle_path.exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,77 @@
+//! Captive portal detection, run before connecting
+//!
+//! On hotel or airport Wi-Fi the gateway is unreachable until the portal was cleared, which otherwise surfaces as a
+//! connection timeout. A plain HTTP URL known to answer with an empty 204 is requested without following redirects,
+//! a redirect or a page in its place is the portal intercepting the request. Any other answer, or none at all, is not
+//! taken as a portal, the connect then succeeds or fails on its own.
+
+use std::time::Duration;
+
+/// Answers with an empty 204 unless the request is intercepted
+pub const PROBE_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+
+/// Kept short, the probe runs before every connect
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "Captive portal detected — authenticate to the network first{}",
+    .login_url.as_deref().map(|url| format!(" at {}", url)).unwrap_or_default()
+)]
+pub struct CaptivePortal {
+    /// Where the portal redirected the probe to, if it did
+    pub login_url: Option<String>,
+}
+
+fn classify(status: u16, location: Option<&str>) -> Option<CaptivePortal> {
+    match status {
+        204 => None,
+        300..=399 => Some(CaptivePortal {
+            login_url: location.map(str::to_string),
+        }),
+        // the probe URL never answers with content
+        200 => Some(CaptivePortal { login_url: None }),
+        _ => None,
+    }
+}
+
+/// Fails if a captive portal intercepts plain HTTP, see [PROBE_URL]
+pub async fn check() -> Result<(), CaptivePortal> {
+    let Ok(client) = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(PROBE_TIMEOUT)
+        .build()
+    else {
+        return Ok(());
+    };
+
+    let response = match client.get(PROBE_URL).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::debug!("Captive portal probe failed: {}", e);
+            return Ok(());
+        }
+    };
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok());
+
+    match classify(response.status().as_u16(), location) {
+        Some(portal) => Err(portal),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_classify() {
+    assert_eq!(classify(204, None), None);
+    assert_eq!(classify(404, None), None);
+    assert_eq!(classify(200, None), Some(CaptivePortal { login_url: None }));
+
+    let portal = classify(302, Some("http://portal.hotel.example/login")).unwrap();
+    assert_eq!(
+        portal.to_string(),
+        "Captive portal detected — authenticate to the network first at http://portal.hotel.example/login"
+    );
+}
@@ -1,4 +1,5 @@
-use crate::VpnClient;
+use crate::{config::LogLevel, result::OpenconnectError, VpnClient};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use openconnect_sys::*;
 use std::{ffi::CString, sync::Mutex};
 
@@ -12,9 +13,25 @@ pub(crate) struct AcceptedCert {
 #[derive(Debug, Default)]
 pub(crate) struct PeerCerts {
     pub accepted_certs: Mutex<Vec<AcceptedCert>>,
+
+    /// The certificate the validation callback refused for not matching [crate::config::Entrypoint::accepted_cert]
+    pub rejected_cert: Mutex<Option<String>>,
 }
 
 impl PeerCerts {
+    /// Why libopenconnect failed, the callback can only fail the connection without a reason
+    pub(crate) fn explain(&self, err: OpenconnectError) -> OpenconnectError {
+        match self
+            .rejected_cert
+            .lock()
+            .ok()
+            .and_then(|mut cert| cert.take())
+        {
+            Some(fingerprint) => OpenconnectError::CertFingerprintMismatch(fingerprint),
+            None => err,
+        }
+    }
+
     pub(crate) extern "C" fn validate_peer_cert(
         privdata: *mut ::std::os::raw::c_void,
         _reason: *const ::std::os::raw::c_char,
@@ -24,6 +41,23 @@ impl PeerCerts {
         let host = client.get_hostname();
         let port = client.get_port();
 
+        // a fingerprint accepted for the connection decides alone, allow_insecure and the handler can't override it
+        if let Some(accepted_cert) = client.accepted_cert() {
+            if client.matches_peer_cert(&accepted_cert) == Some(true) {
+                tracing::debug!("Accepted the certificate with the given fingerprint");
+                return 0;
+            }
+            let fingerprint = client.get_peer_cert_hash();
+            tracing::error!(
+                "{}",
+                OpenconnectError::CertFingerprintMismatch(fingerprint.clone())
+            );
+            if let Ok(mut rejected_cert) = client.peer_certs.rejected_cert.lock() {
+                *rejected_cert = Some(fingerprint);
+            }
+            return 1;
+        }
+
         let openssl_cert_guard = client.peer_certs.accepted_certs.lock();
         if let Ok(openssl_cert) = openssl_cert_guard {
             for cert in openssl_cert.iter().rev() {
@@ -64,3 +98,139 @@ impl PeerCerts {
         }
     }
 }
+
+/// Read a DER TLV from the start of `data`, returns the tag, the content and the remaining bytes
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let len_bytes = (first & 0x7f) as usize;
+        if len_bytes == 0 || len_bytes > 4 || data.len() < len_bytes {
+            return None;
+        }
+        let len = data[..len_bytes]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        data = &data[len_bytes..];
+        len
+    };
+
+    if data.len() < len {
+        return None;
+    }
+    Some((tag, &data[..len], &data[len..]))
+}
+
+fn parse_asn1_time(tag: u8, value: &[u8]) -> Option<DateTime<Utc>> {
+    let value = std::str::from_utf8(value).ok()?;
+    let time = match tag {
+        0x17 => NaiveDateTime::parse_from_str(value, "%y%m%d%H%M%SZ").ok()?, // UTCTime
+        0x18 => NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%SZ").ok()?, // GeneralizedTime
+        _ => return None,
+    };
+    Some(time.and_utc())
+}
+
+/// Extract the `notAfter` field from a DER encoded X.509 certificate
+pub(crate) fn parse_cert_not_after(der: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, cert, _) = read_tlv(der)?;
+    let (_, tbs_cert, _) = read_tlv(cert)?;
+
+    // the version is optional and tagged [0], the serial number follows it
+    let (tag, _, mut rest) = read_tlv(tbs_cert)?;
+    if tag == 0xa0 {
+        (_, _, rest) = read_tlv(rest)?;
+    }
+    let (_, _, rest) = read_tlv(rest)?; // signature algorithm
+    let (_, _, rest) = read_tlv(rest)?; // issuer
+    let (_, validity, _) = read_tlv(rest)?;
+
+    let (_, _, validity) = read_tlv(validity)?; // notBefore
+    let (tag, not_after, _) = read_tlv(validity)?;
+    parse_asn1_time(tag, not_after)
+}
+
+impl VpnClient {
+    /// The fingerprint given with [crate::config::EntrypointBuilder::accept_cert]
+    pub(crate) fn accepted_cert(&self) -> Option<String> {
+        let entrypoint = self.entrypoint.read().ok()?;
+        entrypoint.as_ref()?.accepted_cert.clone()
+    }
+
+    /// Whether the certificate of the gateway has the fingerprint, `None` if there is no certificate to check yet
+    pub(crate) fn matches_peer_cert(&self, fingerprint: &str) -> Option<bool> {
+        let fingerprint = CString::new(fingerprint).ok()?;
+        match unsafe { openconnect_check_peer_cert_hash(self.vpninfo, fingerprint.as_ptr()) } {
+            0 => Some(true),
+            ret if ret > 0 => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Expiry of the certificate presented by the gateway, available once the connection is made
+    pub fn get_server_cert_expiry(&self) -> Option<DateTime<Utc>> {
+        unsafe {
+            let mut buf = std::ptr::null_mut::<u8>();
+            let len = openconnect_get_peer_cert_DER(self.vpninfo, &mut buf);
+            if len <= 0 || buf.is_null() {
+                return None;
+            }
+            let der = std::slice::from_raw_parts(buf, len as usize).to_vec();
+            openconnect_free_cert_info(self.vpninfo, buf.cast());
+            parse_cert_not_after(&der)
+        }
+    }
+
+    /// Whether the gateway certificate expires within the threshold set by [crate::config::ConfigBuilder::cert_expiry_warning]
+    pub fn is_server_cert_expiring(&self) -> bool {
+        self.get_server_cert_expiry().is_some_and(|expiry| {
+            // an already expired certificate fails the conversion
+            let remaining = (expiry - Utc::now()).to_std().unwrap_or_default();
+            remaining <= self.config.cert_expiry_warning
+        })
+    }
+
+    pub(crate) fn check_server_cert_expiry(&self) {
+        if !self.is_server_cert_expiring() {
+            return;
+        }
+
+        if let Some(expiry) = self.get_server_cert_expiry() {
+            let message = format!(
+                "The server certificate expires soon, on {}",
+                expiry.to_rfc3339()
+            );
+            tracing::warn!("{}", message);
+            self.handle_progress(LogLevel::Err, &message);
+
+            if let Some(ref handler) = self.callbacks.handle_server_cert_expiring {
+                handler(expiry);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_cert_not_after() {
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, content.len() as u8];
+        out.extend(content);
+        out
+    }
+
+    let validity = [tlv(0x17, b"240101000000Z"), tlv(0x18, b"20300615120000Z")].concat();
+    let tbs_cert = [
+        tlv(0xa0, &tlv(0x02, &[2])),
+        tlv(0x02, &[1]),
+        tlv(0x30, &[]),
+        tlv(0x30, &[]),
+        tlv(0x30, &validity),
+    ]
+    .concat();
+    let cert = tlv(0x30, &tlv(0x30, &tbs_cert));
+
+    let not_after = parse_cert_not_after(&cert).unwrap();
+    assert_eq!(not_after.to_rfc3339(), "2030-06-15T12:00:00+00:00");
+}
@@ -0,0 +1,137 @@
+//! Moving the key which encrypts the stored passwords to another machine
+//!
+//! The key of [crate::storage::PassEncryptor] is derived from the machine uid, so a config file copied to another
+//! machine can't decrypt its passwords. The seed of the key is exported wrapped under a passphrase, and importing it
+//! on the other machine stores it as `cipher-key` in the config dir, where it takes precedence over the machine uid.
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use std::io::Write;
+
+/// Used if neither a seed was imported nor the machine uid is available
+const FALLBACK_SEED: &str = "openconnect-rs-2024";
+
+/// PBKDF2-HMAC-SHA256 rounds deriving the wrapping key from the passphrase
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CipherKeyError {
+    #[error("Passphrase must have at least {MIN_PASSPHRASE_LEN} characters")]
+    PassphraseTooShort,
+
+    #[error("Invalid exported key: {0}")]
+    InvalidKey(String),
+
+    #[error("Wrong passphrase or corrupted key")]
+    WrongPassphrase,
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// The seed of the current key, the imported one if any
+pub fn current_seed() -> String {
+    imported_seed()
+        .or_else(|| machine_uid::get().ok())
+        .unwrap_or(FALLBACK_SEED.to_string())
+}
+
+fn imported_seed() -> Option<String> {
+    let seed = std::fs::read_to_string(crate::paths::cipher_key_file().ok()?).ok()?;
+    Some(seed.trim().to_string()).filter(|seed| !seed.is_empty())
+}
+
+/// Whether a seed was imported, which then replaced the machine uid
+pub fn is_imported() -> bool {
+    imported_seed().is_some()
+}
+
+fn wrapping_cipher(passphrase: &str, salt: &[u8]) -> XChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(
+        passphrase.as_bytes(),
+        salt,
+        PBKDF2_ROUNDS,
+        &mut key,
+    );
+    XChaCha20Poly1305::new(&key.into())
+}
+
+/// Encrypt the seed under the passphrase, hex encoded salt, nonce and ciphertext
+pub fn wrap(seed: &str, passphrase: &str) -> Result<String, CipherKeyError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(CipherKeyError::PassphraseTooShort);
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let encrypted = wrapping_cipher(passphrase, &salt)
+        .encrypt(&nonce, seed.as_bytes())
+        .map_err(|e| CipherKeyError::InvalidKey(e.to_string()))?;
+
+    Ok(hex::encode(
+        [salt.to_vec(), nonce.to_vec(), encrypted].concat(),
+    ))
+}
+
+/// Decrypt a seed exported by [wrap]
+pub fn unwrap(wrapped: &str, passphrase: &str) -> Result<String, CipherKeyError> {
+    let wrapped =
+        hex::decode(wrapped.trim()).map_err(|e| CipherKeyError::InvalidKey(e.to_string()))?;
+    if wrapped.len() <= SALT_LEN + NONCE_LEN {
+        return Err(CipherKeyError::InvalidKey("too short".to_string()));
+    }
+
+    let (salt, rest) = wrapped.split_at(SALT_LEN);
+    let (nonce, encrypted) = rest.split_at(NONCE_LEN);
+    let seed = wrapping_cipher(passphrase, salt)
+        .decrypt(XNonce::from_slice(nonce), encrypted)
+        .map_err(|_| CipherKeyError::WrongPassphrase)?;
+
+    String::from_utf8(seed).map_err(|e| CipherKeyError::InvalidKey(e.to_string()))
+}
+
+/// Store an imported seed, readable by the owner only
+pub fn install(seed: &str) -> Result<(), CipherKeyError> {
+    let key_file = crate::paths::cipher_key_file()?;
+    let tmp_file = key_file.with_extension("tmp");
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&tmp_file)?.write_all(seed.as_bytes())?;
+
+    std::fs::rename(tmp_file, key_file)?;
+    Ok(())
+}
+
+#[test]
+fn test_wrap_unwrap() {
+    let wrapped = wrap("machine-a", "correct horse").unwrap();
+    assert_eq!(unwrap(&wrapped, "correct horse").unwrap(), "machine-a");
+    assert!(matches!(
+        unwrap(&wrapped, "battery staple"),
+        Err(CipherKeyError::WrongPassphrase)
+    ));
+    assert!(matches!(
+        wrap("machine-a", "short"),
+        Err(CipherKeyError::PassphraseTooShort)
+    ));
+    assert!(matches!(
+        unwrap("00ff", "correct horse"),
+        Err(CipherKeyError::InvalidKey(_))
+    ));
+}
@@ -1,36 +1,490 @@
 use crate::{
+    ca_file,
     protocols::{get_anyconnect_protocol, Protocol},
     result::{OpenconnectError, OpenconnectResult},
 };
 use openconnect_sys::{PRG_DEBUG, PRG_ERR, PRG_INFO, PRG_TRACE};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Default threshold to warn about an expiring gateway certificate
+pub const DEFAULT_CERT_EXPIRY_WARNING: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// The delay before the first attempt to re-establish a dropped connection, see [ReconnectPolicy]
+pub const DEFAULT_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// The longest delay between two reconnect attempts, the backoff stops doubling there
+pub const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Platforms libopenconnect can report to the gateway, see [EntrypointBuilder::reported_os]
+pub const SUPPORTED_REPORTED_OS: &[&str] = &[
+    "linux",
+    "linux-64",
+    "win",
+    "mac-intel",
+    "android",
+    "apple-ios",
+];
+
+/// The platform reported to the gateway unless overridden
+#[cfg(target_os = "windows")]
+pub const DEFAULT_REPORTED_OS: &str = "win";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The platform reported to the gateway unless overridden
+#[cfg(target_os = "macos")]
+pub const DEFAULT_REPORTED_OS: &str = "mac-intel";
+
+/// The platform reported to the gateway unless overridden
+#[cfg(target_os = "linux")]
+pub const DEFAULT_REPORTED_OS: &str = "linux-64";
+
+/// Default time to wait for the DTLS (UDP) channel before falling back to TLS only
+pub const DEFAULT_DTLS_FALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 pub enum LogLevel {
     Err = PRG_ERR as isize,
+    #[default]
     Info = PRG_INFO as isize,
     Debug = PRG_DEBUG as isize,
     Trace = PRG_TRACE as isize,
 }
 
+impl From<tracing::Level> for LogLevel {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR | tracing::Level::WARN => LogLevel::Err,
+            tracing::Level::INFO => LogLevel::Info,
+            tracing::Level::DEBUG => LogLevel::Debug,
+            tracing::Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, String> {
+        match level.to_lowercase().as_str() {
+            "err" | "error" => Ok(LogLevel::Err),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "Unknown log level: {}, expected err, info, debug or trace",
+                level
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogLevel::Err => write!(f, "err"),
+            LogLevel::Info => write!(f, "info"),
+            LogLevel::Debug => write!(f, "debug"),
+            LogLevel::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+/// Address family of the connection to the gateway, independent of the addresses inside the tunnel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl std::str::FromStr for AddressFamily {
+    type Err = String;
+
+    fn from_str(family: &str) -> Result<Self, Self::Err> {
+        match family.to_lowercase().as_str() {
+            "4" | "ipv4" => Ok(AddressFamily::Ipv4),
+            "6" | "ipv6" => Ok(AddressFamily::Ipv6),
+            _ => Err(format!(
+                "Unknown address family: {}, expected ipv4 or ipv6",
+                family
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddressFamily::Ipv4 => write!(f, "IPv4"),
+            AddressFamily::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// An IP network in CIDR notation like `10.8.0.0/16` or `fd00::/64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn is_ipv4(&self) -> bool {
+        self.addr.is_ipv4()
+    }
+
+    /// Whether the address is part of the network, an address of the other family never is
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(*addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(subnet: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid subnet: {}, expected CIDR like 10.8.0.0/16", subnet);
+        let (addr, prefix_len) = subnet.trim().split_once('/').ok_or_else(invalid)?;
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len
+            .parse::<u8>()
+            .ok()
+            .filter(|prefix_len| *prefix_len <= max_prefix_len)
+            .ok_or_else(invalid)?;
+
+        Ok(Subnet { addr, prefix_len })
+    }
+}
+
+impl std::fmt::Display for Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// The server of an entrypoint, a host with optional scheme, port and path like `vpn.example.com/group`
+///
+/// IPv6 literals may be given bare without a port, or in brackets like `[2001:db8::1]:443`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub scheme: Option<String>,
+
+    /// The host name or IP address, without brackets
+    pub host: String,
+    pub port: Option<u16>,
+
+    /// The path after the host including the leading `/`, e.g. to select the usergroup
+    pub path: Option<String>,
+}
+
+impl ServerAddress {
+    pub fn is_ipv6(&self) -> bool {
+        self.host.parse::<Ipv6Addr>().is_ok()
+    }
+}
+
+impl std::str::FromStr for ServerAddress {
+    type Err = String;
+
+    fn from_str(server: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("Invalid server {}: {}", server, reason);
+        let (scheme, rest) = match server.trim().split_once("://") {
+            Some((scheme, rest)) => (Some(scheme.to_lowercase()), rest),
+            None => (None, server.trim()),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], Some(rest[index..].to_string())),
+            None => (rest, None),
+        };
+
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| invalid("missing closing bracket"))?;
+            if host.parse::<Ipv6Addr>().is_err() {
+                return Err(invalid("only IPv6 addresses may be in brackets"));
+            }
+            match after {
+                "" => (host, None),
+                after => (
+                    host,
+                    Some(
+                        after
+                            .strip_prefix(':')
+                            .ok_or_else(|| invalid("expected a port after the address"))?,
+                    ),
+                ),
+            }
+        } else if authority.matches(':').count() > 1 {
+            if authority.parse::<Ipv6Addr>().is_err() {
+                return Err(invalid("IPv6 addresses with a port must be in brackets"));
+            }
+            (authority, None)
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        let port = port
+            .map(|port| {
+                port.parse::<u16>()
+                    .ok()
+                    .filter(|port| *port != 0)
+                    .ok_or_else(|| invalid("invalid port"))
+            })
+            .transpose()?;
+
+        Ok(ServerAddress {
+            scheme,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+/// The form libopenconnect parses, with IPv6 addresses in brackets
+impl std::fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}://", scheme)?;
+        }
+        if self.is_ipv6() {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            write!(f, "{}", self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "{}", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// TLS protocol versions, ordered from oldest to newest
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum TlsVersion {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl TlsVersion {
+    /// Parse the version of a libopenconnect cipher description, `(TLS1.3)-(...)` with GnuTLS or `TLSv1.3-...` with OpenSSL
+    pub fn from_cipher(cipher: &str) -> Option<Self> {
+        let version = cipher.trim_start_matches('(').strip_prefix("TLS")?;
+        let version = version.strip_prefix('v').unwrap_or(version);
+        version.get(..3)?.parse().ok()
+    }
+}
+
+impl std::str::FromStr for TlsVersion {
+    type Err = String;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        match version {
+            "1.0" => Ok(TlsVersion::Tls1_0),
+            "1.1" => Ok(TlsVersion::Tls1_1),
+            "1.2" => Ok(TlsVersion::Tls1_2),
+            "1.3" => Ok(TlsVersion::Tls1_3),
+            _ => Err(format!(
+                "Unknown TLS version: {}, expected 1.0, 1.1, 1.2 or 1.3",
+                version
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls1_0 => write!(f, "TLS 1.0"),
+            TlsVersion::Tls1_1 => write!(f, "TLS 1.1"),
+            TlsVersion::Tls1_2 => write!(f, "TLS 1.2"),
+            TlsVersion::Tls1_3 => write!(f, "TLS 1.3"),
+        }
+    }
+}
+
+/// A tun device provisioned outside of the client, which avoids the privileges needed to create one
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TunDevice {
+    /// An existing persistent device, which is still configured by the vpnc-script
+    Name(String),
+
+    /// An open file descriptor of a tun device, its addresses and routes are left to the caller
+    Fd(std::os::raw::c_int),
+}
+
+/// How a dropped connection is re-established, see [ConfigBuilder::reconnect_policy]
+///
+/// libopenconnect only retries within its main loop, the caller which connects again applies the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReconnectPolicy {
+    /// Give up after this many failed attempts in a row, `None` retries forever
+    pub max_retries: Option<u32>,
+
+    /// The delay before the first attempt, doubled after every failed one
+    pub base_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            base_backoff: DEFAULT_RECONNECT_BACKOFF,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before the attempt `attempt`, counted from 1, at most [MAX_RECONNECT_BACKOFF] unless the base is longer
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_backoff
+            .saturating_mul(factor)
+            .min(MAX_RECONNECT_BACKOFF.max(self.base_backoff))
+    }
+
+    /// Whether the attempt `attempt` exceeds [ReconnectPolicy::max_retries]
+    pub fn is_exhausted(&self, attempt: u32) -> bool {
+        matches!(self.max_retries, Some(max_retries) if attempt > max_retries)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub vpncscript: Option<String>,
     pub http_proxy: Option<String>,
     pub loglevel: LogLevel,
+    pub cert_expiry_warning: Duration,
+
+    /// Replaces the search domains pushed by the server, `None` keeps them
+    pub search_domains: Option<Vec<String>>,
+
+    /// Reject gateways negotiating an older TLS version, `None` keeps the default of libopenconnect
+    pub min_tls_version: Option<TlsVersion>,
+
+    /// Reject gateways negotiating a cipher outside of this list, `None` accepts any cipher
+    pub cipher_list: Option<Vec<String>>,
+
+    /// Use this tun device instead of creating one
+    pub tun_device: Option<TunDevice>,
+
+    /// Resolve the gateway to addresses of this family only, `None` lets the resolver decide
+    pub transport_family: Option<AddressFamily>,
+
+    /// A PEM file of CA certificates trusted in addition to the system store
+    pub ca_cert: Option<PathBuf>,
+
+    /// A directory of PEM files of CA certificates trusted in addition to the system store
+    pub ca_path: Option<PathBuf>,
+
+    /// Reject gateways setting a lower MTU for the tunnel
+    pub dtls_mtu_min: Option<u16>,
+
+    /// The MTU requested from the gateway, which also caps the DTLS MTU probing of libopenconnect
+    pub dtls_mtu_max: Option<u16>,
+
+    /// Copy the TOS/DSCP bits of the tunneled packets to the DTLS packets
+    pub pass_tos: bool,
+
+    /// Extra variables of the vpnc-script environment, exported with [SCRIPT_ENV_PREFIX] before the name
+    pub script_env: HashMap<String, String>,
+
+    /// A file the progress messages of libopenconnect are appended to, apart from the log of the application
+    pub openconnect_log: Option<PathBuf>,
+
+    /// The most verbose level written to [Config::openconnect_log]
+    pub openconnect_log_level: LogLevel,
+    pub reconnect_policy: ReconnectPolicy,
 }
 
 pub struct ConfigBuilder {
     vpncscript: Option<String>,
     http_proxy: Option<String>,
     loglevel: Option<LogLevel>,
+    cert_expiry_warning: Option<Duration>,
+    search_domains: Option<Vec<String>>,
+    min_tls_version: Option<TlsVersion>,
+    cipher_list: Option<Vec<String>>,
+    tun_device: Option<TunDevice>,
+    transport_family: Option<AddressFamily>,
+    ca_cert: Option<PathBuf>,
+    ca_path: Option<PathBuf>,
+    dtls_mtu_min: Option<u16>,
+    dtls_mtu_max: Option<u16>,
+    pass_tos: bool,
+    script_env: HashMap<String, String>,
+    openconnect_log: Option<PathBuf>,
+    openconnect_log_level: LogLevel,
+    reconnect_policy: ReconnectPolicy,
 }
 
+/// The smallest MTU libopenconnect accepts for a tunnel
+pub const MIN_MTU: u16 = 576;
+
 impl ConfigBuilder {
     pub fn new() -> Self {
         Self {
             vpncscript: None,
             http_proxy: None,
             loglevel: None,
+            cert_expiry_warning: None,
+            search_domains: None,
+            min_tls_version: None,
+            cipher_list: None,
+            tun_device: None,
+            transport_family: None,
+            ca_cert: None,
+            ca_path: None,
+            dtls_mtu_min: None,
+            dtls_mtu_max: None,
+            pass_tos: false,
+            script_env: HashMap::new(),
+            openconnect_log: None,
+            openconnect_log_level: LogLevel::Debug,
+            reconnect_policy: ReconnectPolicy::default(),
         }
     }
 
@@ -49,19 +503,283 @@ impl ConfigBuilder {
         self
     }
 
+    /// Warn when the gateway certificate expires within this duration, defaults to 14 days
+    pub fn cert_expiry_warning(&mut self, cert_expiry_warning: Duration) -> &mut Self {
+        self.cert_expiry_warning = Some(cert_expiry_warning);
+        self
+    }
+
+    /// Override the search domains pushed by the server, which are passed to the vpnc-script as `CISCO_DEF_DOMAIN`
+    ///
+    /// An empty list keeps the pushed domains
+    pub fn search_domains(&mut self, search_domains: Vec<String>) -> &mut Self {
+        self.search_domains = Some(search_domains).filter(|domains| !domains.is_empty());
+        self
+    }
+
+    /// Reject gateways which negotiate an older TLS version for the control channel
+    ///
+    /// libopenconnect offers no way to restrict the handshake, so the negotiated version is checked before
+    /// credentials are submitted and once the CSTP connection is made
+    pub fn min_tls_version(&mut self, min_tls_version: TlsVersion) -> &mut Self {
+        self.min_tls_version = Some(min_tls_version);
+        self
+    }
+
+    /// Only accept the ciphers of an OpenSSL style list like `AES256-GCM:CHACHA20-POLY1305`
+    ///
+    /// Like [ConfigBuilder::min_tls_version] the negotiated cipher is checked rather than restricting the handshake.
+    /// An entry matches if it is part of the cipher description of libopenconnect, case insensitive.
+    /// Cipher names differ between the GnuTLS (`AES-256-GCM`) and OpenSSL (`AES256-GCM`) builds of libopenconnect.
+    pub fn cipher_list(&mut self, cipher_list: &str) -> &mut Self {
+        let ciphers: Vec<String> = cipher_list
+            .split([':', ',', ' '])
+            .filter(|cipher| !cipher.is_empty())
+            .map(|cipher| cipher.to_uppercase())
+            .collect();
+        self.cipher_list = Some(ciphers).filter(|ciphers| !ciphers.is_empty());
+        self
+    }
+
+    /// Connect through a pre-created tun device, so that only `CAP_NET_ADMIN` is needed instead of root
+    pub fn tun_device(&mut self, tun_device: TunDevice) -> &mut Self {
+        self.tun_device = Some(tun_device);
+        self
+    }
+
+    /// Connect to the gateway over IPv4 or IPv6 only, for the TLS and the DTLS channel
+    ///
+    /// Applies when the gateway host name is resolved, a gateway given as an IP address is used as is.
+    pub fn transport_family(&mut self, transport_family: AddressFamily) -> &mut Self {
+        self.transport_family = Some(transport_family);
+        self
+    }
+
+    /// Trust the CA certificates of a PEM file in addition to the system store
+    pub fn ca_cert(&mut self, ca_cert: &str) -> &mut Self {
+        self.ca_cert = Some(PathBuf::from(ca_cert));
+        self
+    }
+
+    /// Trust the CA certificates of all PEM files in a directory in addition to the system store
+    ///
+    /// Files which hold no certificate are skipped. Together with [ConfigBuilder::ca_cert] both are trusted,
+    /// neither takes precedence: they are bundled into one file, the certificates of `ca_cert` first.
+    pub fn ca_path(&mut self, ca_path: &str) -> &mut Self {
+        self.ca_path = Some(PathBuf::from(ca_path));
+        self
+    }
+
+    /// Reject the connection if the gateway sets an MTU below this floor, e.g. one too small for the applications in the tunnel
+    ///
+    /// libopenconnect cannot be made to raise an MTU, so it is checked once the CSTP connection is made
+    pub fn dtls_mtu_min(&mut self, dtls_mtu_min: u16) -> &mut Self {
+        self.dtls_mtu_min = Some(dtls_mtu_min);
+        self
+    }
+
+    /// Request at most this MTU from the gateway, e.g. one known not to fragment on the path
+    ///
+    /// libopenconnect derives the DTLS MTU from it and never probes above it. A gateway exceeding it is rejected.
+    pub fn dtls_mtu_max(&mut self, dtls_mtu_max: u16) -> &mut Self {
+        self.dtls_mtu_max = Some(dtls_mtu_max);
+        self
+    }
+
+    /// Copy the TOS/DSCP bits of the tunneled packets to the encapsulating DTLS packets, like `--passtos` of openconnect
+    ///
+    /// Lets the network prioritize real-time traffic such as VoIP, but exposes the traffic class to observers. Off by default.
+    pub fn pass_tos(&mut self, pass_tos: bool) -> &mut Self {
+        self.pass_tos = pass_tos;
+        self
+    }
+
+    /// Pass extra variables to the vpnc-script, e.g. the name of an environment for site specific hooks
+    ///
+    /// A name `ENV` is exported as `OPENCONNECT_RS_ENV`, so that it can't replace a variable set by libopenconnect
+    /// such as `reason` or `INTERNAL_IP4_ADDRESS`. Names may only hold ASCII letters, digits and underscores.
+    /// Not supported on Windows, where the script is not run through a shell.
+    pub fn script_env(&mut self, script_env: HashMap<String, String>) -> &mut Self {
+        self.script_env = script_env;
+        self
+    }
+
+    /// Append the progress messages of libopenconnect up to `level` to a file, e.g. for a bug report upstream
+    ///
+    /// The messages are not limited by [ConfigBuilder::loglevel], which only applies to the log of the application.
+    pub fn openconnect_log(&mut self, path: &str, level: LogLevel) -> &mut Self {
+        self.openconnect_log = Some(PathBuf::from(path));
+        self.openconnect_log_level = level;
+        self
+    }
+
+    /// Retry a dropped connection at most `max_retries` times in a row, with exponential backoff from `base_backoff`
+    ///
+    /// Retries forever every 10 seconds, doubled up to 5 minutes, unless set
+    pub fn reconnect_policy(
+        &mut self,
+        max_retries: Option<u32>,
+        base_backoff: Duration,
+    ) -> &mut Self {
+        self.reconnect_policy = ReconnectPolicy {
+            max_retries,
+            base_backoff,
+        };
+        self
+    }
+
     pub fn build(&self) -> OpenconnectResult<Config> {
+        if let Some(mtu) = [self.dtls_mtu_min, self.dtls_mtu_max]
+            .into_iter()
+            .flatten()
+            .find(|mtu| *mtu < MIN_MTU)
+        {
+            return Err(OpenconnectError::InvalidMtuBounds(format!(
+                "{} is below the minimum of {}",
+                mtu, MIN_MTU
+            )));
+        }
+
+        if let (Some(min), Some(max)) = (self.dtls_mtu_min, self.dtls_mtu_max) {
+            if min > max {
+                return Err(OpenconnectError::InvalidMtuBounds(format!(
+                    "the minimum {} is above the maximum {}",
+                    min, max
+                )));
+            }
+        }
+
+        if let Some(ca_cert) = self.ca_cert.as_deref() {
+            if !ca_file::is_cert_file(ca_cert) {
+                return Err(OpenconnectError::InvalidCaCert(format!(
+                    "{} is not a readable PEM certificate",
+                    ca_cert.display()
+                )));
+            }
+        }
+
+        if let Some(ca_path) = self.ca_path.as_deref() {
+            let certs = ca_file::dir_certs(ca_path).map_err(|e| {
+                OpenconnectError::InvalidCaCert(format!("{}: {}", ca_path.display(), e))
+            })?;
+            if certs.is_empty() {
+                return Err(OpenconnectError::InvalidCaCert(format!(
+                    "{} holds no readable PEM certificate",
+                    ca_path.display()
+                )));
+            }
+        }
+
+        if let Some(domain) = self
+            .search_domains
+            .iter()
+            .flatten()
+            .find(|domain| !is_valid_domain(domain))
+        {
+            return Err(OpenconnectError::InvalidSearchDomain(domain.clone()));
+        }
+
+        validate_script_env(&self.script_env)?;
+
         Ok(Config {
             http_proxy: self.http_proxy.clone(),
             vpncscript: self.vpncscript.clone(),
             loglevel: self.loglevel.unwrap_or(LogLevel::Info),
+            cert_expiry_warning: self
+                .cert_expiry_warning
+                .unwrap_or(DEFAULT_CERT_EXPIRY_WARNING),
+            search_domains: self.search_domains.clone(),
+            min_tls_version: self.min_tls_version,
+            cipher_list: self.cipher_list.clone(),
+            tun_device: self.tun_device.clone(),
+            transport_family: self.transport_family,
+            ca_cert: self.ca_cert.clone(),
+            ca_path: self.ca_path.clone(),
+            dtls_mtu_min: self.dtls_mtu_min,
+            dtls_mtu_max: self.dtls_mtu_max,
+            pass_tos: self.pass_tos,
+            script_env: self.script_env.clone(),
+            openconnect_log: self.openconnect_log.clone(),
+            openconnect_log_level: self.openconnect_log_level,
+            reconnect_policy: self.reconnect_policy,
         })
     }
 }
 
+/// The domains end up in a shell command, so only plain host names are allowed
+fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Prepended to the names of [Config::script_env] in the environment of the vpnc-script
+pub const SCRIPT_ENV_PREFIX: &str = "OPENCONNECT_RS_";
+
+/// The names end up unquoted in a shell command
+pub fn is_valid_script_env_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check the variables of [Config::script_env], done by [ConfigBuilder::build] and required for a [Config] built directly
+pub fn validate_script_env(script_env: &HashMap<String, String>) -> OpenconnectResult<()> {
+    if let Some(name) = script_env
+        .keys()
+        .find(|name| !is_valid_script_env_name(name))
+    {
+        return Err(OpenconnectError::InvalidScriptEnv(format!(
+            "{}, only ASCII letters, digits and underscores are allowed",
+            name
+        )));
+    }
+
+    if let Some(name) = script_env
+        .iter()
+        .find_map(|(name, value)| value.contains('\0').then_some(name))
+    {
+        return Err(OpenconnectError::InvalidScriptEnv(format!(
+            "the value of {} contains a NUL byte",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
 impl Config {
     pub fn builder() -> ConfigBuilder {
         ConfigBuilder::new()
     }
+
+    /// Whether the MTU of the tunnel is within [Config::dtls_mtu_min] and [Config::dtls_mtu_max]
+    pub fn allows_mtu(&self, mtu: i32) -> bool {
+        !matches!(self.dtls_mtu_min, Some(min) if mtu < min.into())
+            && !matches!(self.dtls_mtu_max, Some(max) if mtu > max.into())
+    }
+
+    /// The MTU bounds for messages, like `576..=1400`
+    pub fn mtu_bounds(&self) -> String {
+        format!(
+            "{}..={}",
+            self.dtls_mtu_min
+                .map(|min| min.to_string())
+                .unwrap_or_default(),
+            self.dtls_mtu_max
+                .map(|max| max.to_string())
+                .unwrap_or_default()
+        )
+    }
+
+    /// Whether a cipher description of libopenconnect is allowed by [Config::cipher_list]
+    pub fn allows_cipher(&self, cipher: &str) -> bool {
+        let Some(ref cipher_list) = self.cipher_list else {
+            return true;
+        };
+        let cipher = cipher.to_uppercase();
+
+        cipher_list.iter().any(|allowed| cipher.contains(allowed))
+    }
 }
 
 impl Default for ConfigBuilder {
@@ -80,6 +798,118 @@ pub struct Entrypoint {
     pub cookie: Option<String>,
     pub enable_udp: bool,
     pub accept_insecure_cert: bool,
+    pub auth_group: Option<String>,
+
+    /// Fall back to TLS only if DTLS is not established within this duration, `None` keeps retrying DTLS
+    pub dtls_fallback_timeout: Option<Duration>,
+
+    /// The platform reported to the gateway, defaults to the actual OS
+    pub reported_os: Option<String>,
+
+    /// Authenticate with the gateway's own web login (SAML) in an external browser
+    pub web_auth: bool,
+
+    /// One-time code for the second factor field of the login form
+    pub otp: Option<String>,
+
+    /// Terminate an existing session when the gateway's session limit is reached, instead of failing
+    pub terminate_existing_session: bool,
+
+    /// Extra headers of the auth requests, see [EntrypointBuilder::add_http_header]
+    pub http_headers: Vec<(String, String)>,
+
+    /// Abort the connection if the gateway assigns an address outside of this network
+    pub expected_subnet: Option<Subnet>,
+
+    /// Accept only a gateway certificate with this fingerprint, see [EntrypointBuilder::accept_cert]
+    pub accepted_cert: Option<String>,
+
+    /// Values of login form fields by field name, see [EntrypointBuilder::form_default]
+    pub form_defaults: HashMap<String, String>,
+
+    /// The MTU of the tunnel device regardless of the gateway, see [EntrypointBuilder::mtu]
+    pub mtu: Option<u32>,
+
+    /// The certificate to authenticate with, see [EntrypointBuilder::client_cert]
+    pub client_cert: Option<PathBuf>,
+
+    /// The private key of the client certificate, `None` if the certificate file holds it
+    pub client_key: Option<PathBuf>,
+
+    /// Decrypts the private key of the client certificate
+    pub key_password: Option<String>,
+}
+
+impl Entrypoint {
+    /// The `User-Agent` given with [EntrypointBuilder::add_http_header]
+    pub fn user_agent(&self) -> Option<&str> {
+        self.http_headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(USER_AGENT_HEADER))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Normalize a certificate fingerprint to the form libopenconnect checks, `sha256:<hex>` or `pin-sha256:<base64>`
+///
+/// The hex digest may be given with or without the `sha256:` prefix and with `:` between the bytes.
+pub fn parse_cert_fingerprint(fingerprint: &str) -> Result<String, String> {
+    let fingerprint = fingerprint.trim();
+    if let Some(pin) = fingerprint.strip_prefix("pin-sha256:") {
+        let is_base64 = pin.len() == 44
+            && pin
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c));
+        return match is_base64 {
+            true => Ok(fingerprint.to_string()),
+            false => Err(format!("Invalid pin-sha256 fingerprint: {}", fingerprint)),
+        };
+    }
+
+    let hex: String = fingerprint
+        .strip_prefix("sha256:")
+        .unwrap_or(fingerprint)
+        .chars()
+        .filter(|c| *c != ':')
+        .collect();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "Invalid SHA-256 fingerprint: {}, expected 64 hex digits",
+            fingerprint
+        ));
+    }
+
+    Ok(format!("sha256:{}", hex.to_lowercase()))
+}
+
+/// libopenconnect builds its requests itself and only lets the user agent be replaced
+const USER_AGENT_HEADER: &str = "User-Agent";
+
+/// Split a `Name: Value` header as given on the command line
+pub fn parse_http_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or(format!("Expected <NAME>: <VALUE>, got {}", header))?;
+    let (name, value) = (name.trim(), value.trim());
+    check_http_header(name, value)?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// A header name is an RFC 7230 token, the value must not contain control characters
+fn check_http_header(name: &str, value: &str) -> Result<(), String> {
+    let is_token = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if !is_token {
+        return Err(format!("Invalid HTTP header name: {:?}", name));
+    }
+
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(format!("Invalid value of HTTP header {}", name));
+    }
+
+    Ok(())
 }
 
 pub struct EntrypointBuilder {
@@ -91,6 +921,20 @@ pub struct EntrypointBuilder {
     cookie: Option<String>,
     enable_udp: bool,
     accept_insecure_cert: Option<bool>,
+    auth_group: Option<String>,
+    dtls_fallback_timeout: Option<Duration>,
+    reported_os: Option<String>,
+    web_auth: bool,
+    otp: Option<String>,
+    terminate_existing_session: bool,
+    http_headers: Vec<(String, String)>,
+    expected_subnet: Option<String>,
+    accepted_cert: Option<String>,
+    form_defaults: HashMap<String, String>,
+    mtu: Option<u32>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    key_password: Option<String>,
 }
 
 impl EntrypointBuilder {
@@ -104,6 +948,20 @@ impl EntrypointBuilder {
             cookie: None,
             enable_udp: true,
             accept_insecure_cert: None,
+            auth_group: None,
+            dtls_fallback_timeout: Some(DEFAULT_DTLS_FALLBACK_TIMEOUT),
+            reported_os: None,
+            web_auth: false,
+            otp: None,
+            terminate_existing_session: false,
+            http_headers: vec![],
+            expected_subnet: None,
+            accepted_cert: None,
+            form_defaults: HashMap::new(),
+            mtu: None,
+            client_cert: None,
+            client_key: None,
+            key_password: None,
         }
     }
 
@@ -147,13 +1005,182 @@ impl EntrypointBuilder {
         self
     }
 
+    /// Select the auth group (realm) by name or label, see [crate::VpnClient::probe_auth_groups]
+    pub fn auth_group(&mut self, auth_group: &str) -> &mut Self {
+        self.auth_group = Some(auth_group.to_string());
+        self
+    }
+
+    /// Disable UDP when the DTLS channel is not up within the timeout, `None` opts out. Defaults to 10 seconds
+    pub fn dtls_fallback(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.dtls_fallback_timeout = timeout;
+        self
+    }
+
+    /// Report another platform to the gateway, one of [SUPPORTED_REPORTED_OS]
+    pub fn reported_os(&mut self, reported_os: &str) -> &mut Self {
+        self.reported_os = Some(reported_os.to_string());
+        self
+    }
+
+    /// Offer the gateway to authenticate in an external browser, see [crate::events::EventHandlers::with_handle_external_browser]
+    pub fn web_auth(&mut self, web_auth: bool) -> &mut Self {
+        self.web_auth = web_auth;
+        self
+    }
+
+    /// Answer a second factor (TOTP) field of the login form, other password fields get [EntrypointBuilder::password]
+    pub fn otp(&mut self, otp: &str) -> &mut Self {
+        self.otp = Some(otp.to_string());
+        self
+    }
+
+    /// Let the gateway close an existing session if the session limit is reached, see [OpenconnectError::SessionAlreadyActive]
+    pub fn terminate_existing_session(&mut self, terminate: bool) -> &mut Self {
+        self.terminate_existing_session = terminate;
+        self
+    }
+
+    /// Send an extra header with the auth requests, can be repeated
+    ///
+    /// Only `User-Agent` is supported, libopenconnect offers no way to add other headers to its requests.
+    /// The headers are checked by [EntrypointBuilder::build].
+    pub fn add_http_header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.http_headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Disconnect if the address assigned by the gateway is outside of this CIDR network, e.g. a spoofed gateway
+    ///
+    /// The address of the subnet's family must be assigned and inside of it, before the tun device is set up.
+    pub fn expected_subnet(&mut self, expected_subnet: &str) -> &mut Self {
+        self.expected_subnet = Some(expected_subnet.to_string());
+        self
+    }
+
+    /// Accept the gateway certificate with this SHA-256 fingerprint for this connection only, like `--servercert` of openconnect
+    ///
+    /// A certificate the system does not trust is accepted without asking, any other certificate is rejected
+    /// even with [EntrypointBuilder::accept_insecure_cert]. A certificate the system trusts is checked before
+    /// credentials are submitted and once the CSTP connection is made.
+    pub fn accept_cert(&mut self, fingerprint: &str) -> &mut Self {
+        self.accepted_cert = Some(fingerprint.to_string());
+        self
+    }
+
+    /// Answer the login form field with this name, e.g. a dropdown or a text field of a custom form, can be repeated
+    ///
+    /// A select field is matched by the name or the label of a choice. Password fields are not answered.
+    pub fn form_default(&mut self, field: &str, value: &str) -> &mut Self {
+        self.form_defaults
+            .insert(field.to_string(), value.to_string());
+        self
+    }
+
+    /// Create the tunnel device with this MTU, e.g. when the gateway negotiates one which blackholes large packets
+    ///
+    /// The MTU is requested from the gateway like `--mtu` of openconnect and passed to the vpnc-script instead of the
+    /// one the gateway answers with, which is also reported by [crate::VpnClient::get_info].
+    pub fn mtu(&mut self, mtu: u32) -> &mut Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Authenticate with a client certificate, a PEM or PKCS#12 file or a `pkcs11:` URL, like `--certificate` of openconnect
+    ///
+    /// The private key is read from the same file unless [EntrypointBuilder::client_key] is set. Many gateways ask for
+    /// the username and password of the login form in addition.
+    pub fn client_cert(&mut self, client_cert: &str) -> &mut Self {
+        self.client_cert = Some(PathBuf::from(client_cert));
+        self
+    }
+
+    /// The private key of the client certificate in a separate file, like `--sslkey` of openconnect
+    pub fn client_key(&mut self, client_key: &str) -> &mut Self {
+        self.client_key = Some(PathBuf::from(client_key));
+        self
+    }
+
+    /// The password of an encrypted private key or PKCS#12 file, like `--key-password` of openconnect
+    ///
+    /// Without it, an encrypted key fails with [OpenconnectError::KeyPasswordRequired].
+    pub fn key_password(&mut self, key_password: &str) -> &mut Self {
+        self.key_password = Some(key_password.to_string());
+        self
+    }
+
     pub fn build(&self) -> OpenconnectResult<Entrypoint> {
+        if self.client_cert.is_none() && (self.client_key.is_some() || self.key_password.is_some())
+        {
+            return Err(OpenconnectError::EntrypointConfigError(
+                "A client key or key password requires a client certificate".to_string(),
+            ));
+        }
+        for file in [&self.client_cert, &self.client_key].into_iter().flatten() {
+            // a PKCS#11 token is looked up by libopenconnect
+            if !file.to_string_lossy().starts_with("pkcs11:") && !file.is_file() {
+                return Err(OpenconnectError::InvalidClientCert(format!(
+                    "{} is not a readable file",
+                    file.display()
+                )));
+            }
+        }
+
+        if let Some(mtu) = self.mtu {
+            if mtu < MIN_MTU.into() || mtu > u16::MAX.into() {
+                return Err(OpenconnectError::EntrypointConfigError(format!(
+                    "MTU {} is outside of {}..={}",
+                    mtu,
+                    MIN_MTU,
+                    u16::MAX
+                )));
+            }
+        }
+
+        for (name, value) in &self.http_headers {
+            check_http_header(name, value).map_err(OpenconnectError::EntrypointConfigError)?;
+            if !name.eq_ignore_ascii_case(USER_AGENT_HEADER) {
+                return Err(OpenconnectError::EntrypointConfigError(format!(
+                    "HTTP header {} is not supported, libopenconnect only allows replacing the {}",
+                    name, USER_AGENT_HEADER
+                )));
+            }
+        }
+
+        if let Some(reported_os) = &self.reported_os {
+            if !SUPPORTED_REPORTED_OS.contains(&reported_os.as_str()) {
+                return Err(OpenconnectError::EntrypointConfigError(format!(
+                    "Unsupported reported OS: {}, expected one of {}",
+                    reported_os,
+                    SUPPORTED_REPORTED_OS.join(", ")
+                )));
+            }
+        }
+
+        let expected_subnet = self
+            .expected_subnet
+            .as_deref()
+            .map(str::parse::<Subnet>)
+            .transpose()
+            .map_err(OpenconnectError::EntrypointConfigError)?;
+
+        let accepted_cert = self
+            .accepted_cert
+            .as_deref()
+            .map(parse_cert_fingerprint)
+            .transpose()
+            .map_err(OpenconnectError::EntrypointConfigError)?;
+
         let server = self
             .server
-            .clone()
+            .as_deref()
             .ok_or(OpenconnectError::EntrypointConfigError(
                 "Server is required".to_string(),
-            ))?;
+            ))?
+            .parse::<ServerAddress>()
+            .map_err(OpenconnectError::EntrypointConfigError)?
+            .to_string();
 
         let protocol = self
             .protocol
@@ -169,6 +1196,20 @@ impl EntrypointBuilder {
             cookie: self.cookie.clone(),
             enable_udp: self.enable_udp,
             accept_insecure_cert: self.accept_insecure_cert.unwrap_or(false),
+            auth_group: self.auth_group.clone(),
+            dtls_fallback_timeout: self.dtls_fallback_timeout,
+            reported_os: self.reported_os.clone(),
+            web_auth: self.web_auth,
+            otp: self.otp.clone(),
+            terminate_existing_session: self.terminate_existing_session,
+            http_headers: self.http_headers.clone(),
+            expected_subnet,
+            accepted_cert,
+            form_defaults: self.form_defaults.clone(),
+            mtu: self.mtu,
+            client_cert: self.client_cert.clone(),
+            client_key: self.client_key.clone(),
+            key_password: self.key_password.clone(),
         })
     }
 }
@@ -178,3 +1219,333 @@ impl Default for EntrypointBuilder {
         Self::new()
     }
 }
+
+#[test]
+fn test_reconnect_policy() {
+    let config = ConfigBuilder::new()
+        .reconnect_policy(Some(3), Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let policy = config.reconnect_policy;
+    assert_eq!(policy.backoff(1), Duration::from_secs(5));
+    assert_eq!(policy.backoff(3), Duration::from_secs(20));
+    assert_eq!(policy.backoff(100), MAX_RECONNECT_BACKOFF);
+    assert!(!policy.is_exhausted(3));
+    assert!(policy.is_exhausted(4));
+
+    assert!(!ReconnectPolicy::default().is_exhausted(u32::MAX));
+}
+
+#[test]
+fn test_script_env() {
+    let script_env = HashMap::from([("ENV_NAME".to_string(), "staging; true".to_string())]);
+    let config = ConfigBuilder::new()
+        .script_env(script_env.clone())
+        .build()
+        .unwrap();
+    assert_eq!(config.script_env, script_env);
+
+    assert!(ConfigBuilder::new()
+        .script_env(HashMap::from([("reason; id".to_string(), String::new())]))
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_search_domains() {
+    let config = ConfigBuilder::new()
+        .search_domains(vec![
+            "corp.example.com".to_string(),
+            "example.org".to_string(),
+        ])
+        .build()
+        .unwrap();
+    assert_eq!(
+        config.search_domains,
+        Some(vec![
+            "corp.example.com".to_string(),
+            "example.org".to_string()
+        ])
+    );
+
+    let config = ConfigBuilder::new().search_domains(vec![]).build().unwrap();
+    assert_eq!(config.search_domains, None);
+
+    assert!(ConfigBuilder::new()
+        .search_domains(vec!["example.com; rm -rf /".to_string()])
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_tls_version_from_cipher() {
+    assert_eq!(
+        TlsVersion::from_cipher("(TLS1.3)-(ECDHE-SECP256R1)-(RSA-PSS-RSAE-SHA256)-(AES-256-GCM)"),
+        Some(TlsVersion::Tls1_3)
+    );
+    assert_eq!(
+        TlsVersion::from_cipher("TLSv1.2-ECDHE-RSA-AES256-GCM-SHA384"),
+        Some(TlsVersion::Tls1_2)
+    );
+    assert_eq!(TlsVersion::from_cipher("PSK-NEGOTIATE"), None);
+    assert!(TlsVersion::Tls1_1 < TlsVersion::Tls1_2);
+}
+
+#[test]
+fn test_reported_os() {
+    let entrypoint = EntrypointBuilder::new()
+        .server("https://vpn.example.com")
+        .reported_os("win")
+        .build()
+        .unwrap();
+    assert_eq!(entrypoint.reported_os.as_deref(), Some("win"));
+
+    assert!(EntrypointBuilder::new()
+        .server("https://vpn.example.com")
+        .reported_os("beos")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_allows_cipher() {
+    let config = Config::builder()
+        .cipher_list("aes-256-gcm:AES256-GCM")
+        .build()
+        .unwrap();
+    assert!(config.allows_cipher("(TLS1.3)-(ECDHE-SECP256R1)-(RSA-PSS-RSAE-SHA256)-(AES-256-GCM)"));
+    assert!(config.allows_cipher("TLSv1.2-ECDHE-RSA-AES256-GCM-SHA384"));
+    assert!(!config.allows_cipher("TLSv1.2-ECDHE-RSA-AES128-SHA256"));
+
+    let config = Config::builder().cipher_list("").build().unwrap();
+    assert!(config.allows_cipher("TLSv1.2-ECDHE-RSA-AES128-SHA256"));
+}
+
+#[test]
+fn test_http_headers() {
+    assert_eq!(
+        parse_http_header("User-Agent: AnyConnect Darwin_i386 4.10"),
+        Ok((
+            "User-Agent".to_string(),
+            "AnyConnect Darwin_i386 4.10".to_string()
+        ))
+    );
+    assert!(parse_http_header("User-Agent").is_err());
+    assert!(parse_http_header("User Agent: x").is_err());
+    assert!(parse_http_header("User-Agent: x\r\nX-Injected: y").is_err());
+
+    let entrypoint = EntrypointBuilder::new()
+        .server("vpn.example.com")
+        .add_http_header("user-agent", "Custom")
+        .build()
+        .unwrap();
+    assert_eq!(entrypoint.user_agent(), Some("Custom"));
+
+    assert!(EntrypointBuilder::new()
+        .server("vpn.example.com")
+        .add_http_header("X-Waf-Token", "secret")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_address_family() {
+    assert_eq!("ipv6".parse(), Ok(AddressFamily::Ipv6));
+    assert_eq!("4".parse(), Ok(AddressFamily::Ipv4));
+    assert!("ipv5".parse::<AddressFamily>().is_err());
+}
+
+#[test]
+fn test_cert_fingerprint() {
+    let hex = "AB".repeat(32);
+    let expected = format!("sha256:{}", "ab".repeat(32));
+    assert_eq!(parse_cert_fingerprint(&hex), Ok(expected.clone()));
+    assert_eq!(
+        parse_cert_fingerprint(&format!("sha256:{}", hex)),
+        Ok(expected.clone())
+    );
+    assert_eq!(
+        parse_cert_fingerprint(&vec!["AB"; 32].join(":")),
+        Ok(expected)
+    );
+
+    let pin = format!("pin-sha256:{}=", "A".repeat(43));
+    assert_eq!(parse_cert_fingerprint(&pin), Ok(pin.clone()));
+
+    assert!(parse_cert_fingerprint(&"ab".repeat(20)).is_err());
+    assert!(parse_cert_fingerprint(&"zz".repeat(32)).is_err());
+    assert!(parse_cert_fingerprint("pin-sha256:short").is_err());
+}
+
+#[test]
+fn test_subnet() {
+    let subnet: Subnet = "10.8.0.0/16".parse().unwrap();
+    assert!(subnet.is_ipv4());
+    assert!(subnet.contains(&"10.8.12.34".parse().unwrap()));
+    assert!(!subnet.contains(&"10.9.0.1".parse().unwrap()));
+    assert!(!subnet.contains(&"fd00::1".parse().unwrap()));
+    assert_eq!(subnet.to_string(), "10.8.0.0/16");
+
+    let subnet: Subnet = "fd00:1::/32".parse().unwrap();
+    assert!(subnet.contains(&"fd00:1:2::3".parse().unwrap()));
+    assert!(!subnet.contains(&"fd00:2::1".parse().unwrap()));
+
+    let any: Subnet = "0.0.0.0/0".parse().unwrap();
+    assert!(any.contains(&"192.0.2.1".parse().unwrap()));
+
+    assert!("10.8.0.0".parse::<Subnet>().is_err());
+    assert!("10.8.0.0/33".parse::<Subnet>().is_err());
+    assert!("intranet/16".parse::<Subnet>().is_err());
+}
+
+#[test]
+fn test_server_address() {
+    let cases = [
+        ("192.0.2.1", "192.0.2.1", None, None),
+        ("192.0.2.1:8443", "192.0.2.1", Some(8443), None),
+        ("192.0.2.1/group", "192.0.2.1", None, Some("/group")),
+        (
+            "192.0.2.1:8443/group",
+            "192.0.2.1",
+            Some(8443),
+            Some("/group"),
+        ),
+        ("2001:db8::1", "2001:db8::1", None, None),
+        ("[2001:db8::1]", "2001:db8::1", None, None),
+        ("[2001:db8::1]:443", "2001:db8::1", Some(443), None),
+        ("2001:db8::1/group", "2001:db8::1", None, Some("/group")),
+        ("[2001:db8::1]/group", "2001:db8::1", None, Some("/group")),
+        (
+            "[2001:db8::1]:443/group",
+            "2001:db8::1",
+            Some(443),
+            Some("/group"),
+        ),
+        ("vpn.example.com", "vpn.example.com", None, None),
+        ("vpn.example.com:8443", "vpn.example.com", Some(8443), None),
+        (
+            "vpn.example.com/group",
+            "vpn.example.com",
+            None,
+            Some("/group"),
+        ),
+        (
+            "vpn.example.com:8443/group",
+            "vpn.example.com",
+            Some(8443),
+            Some("/group"),
+        ),
+    ];
+    for (server, host, port, path) in cases {
+        let address: ServerAddress = server.parse().unwrap();
+        assert_eq!(address.host, host, "{}", server);
+        assert_eq!(address.port, port, "{}", server);
+        assert_eq!(address.path.as_deref(), path, "{}", server);
+    }
+
+    let address: ServerAddress = "https://[2001:db8::1]:443/group".parse().unwrap();
+    assert_eq!(address.scheme.as_deref(), Some("https"));
+    assert_eq!(address.to_string(), "https://[2001:db8::1]:443/group");
+    assert_eq!(
+        "2001:db8::1/group"
+            .parse::<ServerAddress>()
+            .unwrap()
+            .to_string(),
+        "[2001:db8::1]/group"
+    );
+
+    assert!("2001:db8::1:443x".parse::<ServerAddress>().is_err());
+    assert!("[2001:db8::1".parse::<ServerAddress>().is_err());
+    assert!("[vpn.example.com]:443".parse::<ServerAddress>().is_err());
+    assert!("[2001:db8::1]443".parse::<ServerAddress>().is_err());
+    assert!("vpn.example.com:0".parse::<ServerAddress>().is_err());
+    assert!(":443".parse::<ServerAddress>().is_err());
+}
+
+#[test]
+fn test_ca_path() {
+    let missing = std::env::temp_dir().join("openconnect-rs-test-missing-ca");
+    assert!(ConfigBuilder::new()
+        .ca_path(&missing.to_string_lossy())
+        .build()
+        .is_err());
+    assert!(ConfigBuilder::new()
+        .ca_cert(&missing.to_string_lossy())
+        .build()
+        .is_err());
+
+    let empty = std::env::temp_dir().join("openconnect-rs-test-empty-ca");
+    std::fs::create_dir_all(&empty).unwrap();
+    assert!(matches!(
+        ConfigBuilder::new()
+            .ca_path(&empty.to_string_lossy())
+            .build(),
+        Err(OpenconnectError::InvalidCaCert(_))
+    ));
+    let _ = std::fs::remove_dir_all(&empty);
+}
+
+#[test]
+fn test_mtu_bounds() {
+    let config = ConfigBuilder::new()
+        .dtls_mtu_min(1200)
+        .dtls_mtu_max(1400)
+        .build()
+        .unwrap();
+    assert!(config.allows_mtu(1300));
+    assert!(!config.allows_mtu(1100));
+    assert!(!config.allows_mtu(1500));
+    assert_eq!(config.mtu_bounds(), "1200..=1400");
+
+    let config = ConfigBuilder::new().dtls_mtu_max(1400).build().unwrap();
+    assert!(config.allows_mtu(600));
+    assert_eq!(config.mtu_bounds(), "..=1400");
+
+    assert!(ConfigBuilder::new().dtls_mtu_min(500).build().is_err());
+    assert!(ConfigBuilder::new()
+        .dtls_mtu_min(1400)
+        .dtls_mtu_max(1200)
+        .build()
+        .is_err());
+
+    let mut entrypoint = EntrypointBuilder::new();
+    entrypoint.server("vpn.example.com");
+    assert_eq!(entrypoint.mtu(1300).build().unwrap().mtu, Some(1300));
+    assert!(entrypoint.mtu(500).build().is_err());
+}
+
+#[test]
+fn test_client_cert() {
+    let cert = std::env::temp_dir().join("openconnect-rs-test-client-cert.pem");
+    std::fs::write(&cert, "").unwrap();
+    let cert = cert.to_string_lossy();
+
+    let entrypoint = EntrypointBuilder::new()
+        .server("vpn.example.com")
+        .client_cert(&cert)
+        .key_password("secret")
+        .build()
+        .unwrap();
+    assert_eq!(entrypoint.client_key, None);
+    assert_eq!(entrypoint.key_password.as_deref(), Some("secret"));
+
+    assert!(matches!(
+        EntrypointBuilder::new()
+            .server("vpn.example.com")
+            .client_cert(&cert)
+            .client_key("/nonexistent/key.pem")
+            .build(),
+        Err(OpenconnectError::InvalidClientCert(_))
+    ));
+    assert!(EntrypointBuilder::new()
+        .server("vpn.example.com")
+        .client_cert("pkcs11:token=corp")
+        .build()
+        .is_ok());
+    assert!(EntrypointBuilder::new()
+        .server("vpn.example.com")
+        .key_password("secret")
+        .build()
+        .is_err());
+    let _ = std::fs::remove_file(cert.as_ref());
+}
@@ -0,0 +1,164 @@
+//! A password server defined by environment variables instead of the config file, e.g. in a container
+//!
+//! The password is not copied out of the environment, the server refers to it as a [SecretSource] which is resolved
+//! at connect time. The preferences of the config file are read from `OPENCONNECT_PROTOCOL`, `OPENCONNECT_VPNC_SCRIPT`,
+//! `OPENCONNECT_LOG_LEVEL` and `OPENCONNECT_AUTO_RECONNECT`.
+
+use crate::{
+    secret::SecretSource,
+    storage::{Defaults, PasswordServer, StoredConfigError, StoredConfigs, StoredServer},
+};
+use std::{collections::HashMap, path::PathBuf};
+
+/// The prefix of the variables, which `sudo` has to keep
+pub const ENV_PREFIX: &str = "OPENCONNECT_";
+
+pub const SERVER_ENV: &str = "OPENCONNECT_SERVER";
+pub const USERNAME_ENV: &str = "OPENCONNECT_USERNAME";
+pub const PASSWORD_ENV: &str = "OPENCONNECT_PASSWORD";
+pub const PASSWORD_FILE_ENV: &str = "OPENCONNECT_PASSWORD_FILE";
+pub const AUTH_GROUP_ENV: &str = "OPENCONNECT_AUTH_GROUP";
+pub const ALLOW_INSECURE_ENV: &str = "OPENCONNECT_ALLOW_INSECURE";
+
+/// The variables of the [Defaults] by their key
+const DEFAULTS_ENV: [(&str, &str); 4] = [
+    ("protocol", "OPENCONNECT_PROTOCOL"),
+    ("vpnc-script", "OPENCONNECT_VPNC_SCRIPT"),
+    ("log-level", "OPENCONNECT_LOG_LEVEL"),
+    ("auto-reconnect", "OPENCONNECT_AUTO_RECONNECT"),
+];
+
+/// Build the server named `name` from the variables looked up by `var`, with configs holding only it
+pub fn server_from_env(
+    name: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<(StoredServer, StoredConfigs), StoredConfigError> {
+    let required = |key: &str| {
+        var(key)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| StoredConfigError::BadInput(format!("{} is not set", key)))
+    };
+
+    let password_source = match (var(PASSWORD_ENV), var(PASSWORD_FILE_ENV)) {
+        (Some(_), Some(_)) => {
+            return Err(StoredConfigError::BadInput(format!(
+                "Only one of {} and {} can be set",
+                PASSWORD_ENV, PASSWORD_FILE_ENV
+            )))
+        }
+        (Some(_), None) => SecretSource::Env(PASSWORD_ENV.to_string()),
+        (None, Some(path)) => SecretSource::File(PathBuf::from(path)),
+        (None, None) => {
+            return Err(StoredConfigError::BadInput(format!(
+                "{} or {} is not set",
+                PASSWORD_ENV, PASSWORD_FILE_ENV
+            )))
+        }
+    };
+
+    let allow_insecure = var(ALLOW_INSECURE_ENV)
+        .map(|value| {
+            value.parse::<bool>().map_err(|_| {
+                StoredConfigError::BadInput(format!(
+                    "{} expects true or false, got {}",
+                    ALLOW_INSECURE_ENV, value
+                ))
+            })
+        })
+        .transpose()?;
+
+    let server = StoredServer::Password(PasswordServer {
+        name: name.to_string(),
+        server: required(SERVER_ENV)?,
+        username: required(USERNAME_ENV)?,
+        password: None,
+        allow_insecure,
+        updated_at: None,
+        auth_group: var(AUTH_GROUP_ENV),
+        password_source: Some(password_source),
+        connect_via: None,
+        reported_os: None,
+        protocol: None,
+        proxy: None,
+        probe_host: None,
+        verify_url: None,
+        verify_expect_status: None,
+        notify: None,
+        trusted_networks: vec![],
+        udp_policy: vec![],
+        expected_subnet: None,
+        server_cert: None,
+        mtu: None,
+        client_cert: None,
+        client_key: None,
+        key_password: None,
+        profile_script: None,
+        web_auth: false,
+        form_defaults: HashMap::new(),
+        profiles: HashMap::new(),
+    });
+
+    let mut defaults = Defaults::default();
+    for (key, env) in DEFAULTS_ENV {
+        if let Some(value) = var(env) {
+            defaults
+                .set(key, Some(&value))
+                .map_err(|e| StoredConfigError::BadInput(format!("Invalid {}: {}", env, e)))?;
+        }
+    }
+
+    // never saved, the config file of the machine is not read either
+    let mut stored_configs = StoredConfigs::new(None, PathBuf::new());
+    stored_configs
+        .servers
+        .insert(name.to_string(), server.clone());
+    stored_configs.defaults = defaults;
+
+    Ok((server, stored_configs))
+}
+
+#[test]
+fn test_server_from_env() {
+    let env = |vars: &[(&str, &str)]| {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key: &str| vars.get(key).cloned()
+    };
+
+    let (server, stored_configs) = server_from_env(
+        "container",
+        env(&[
+            (SERVER_ENV, "vpn.example.com"),
+            (USERNAME_ENV, "alice"),
+            (PASSWORD_FILE_ENV, "/run/secrets/vpn"),
+            (ALLOW_INSECURE_ENV, "true"),
+            ("OPENCONNECT_LOG_LEVEL", "debug"),
+        ]),
+    )
+    .unwrap();
+    let StoredServer::Password(server) = server else {
+        panic!("expected a password server");
+    };
+    assert_eq!(server.server, "vpn.example.com");
+    assert_eq!(server.allow_insecure, Some(true));
+    assert_eq!(
+        server.password_source,
+        Some(SecretSource::File(PathBuf::from("/run/secrets/vpn")))
+    );
+    assert!(stored_configs.servers.contains_key("container"));
+    assert_eq!(stored_configs.defaults.log_level.as_deref(), Some("debug"));
+
+    assert!(server_from_env("container", env(&[(USERNAME_ENV, "alice")])).is_err());
+    assert!(server_from_env(
+        "container",
+        env(&[
+            (SERVER_ENV, "vpn.example.com"),
+            (USERNAME_ENV, "alice"),
+            (PASSWORD_ENV, "hunter2"),
+            ("OPENCONNECT_LOG_LEVEL", "verbose"),
+        ])
+    )
+    .is_err());
+}
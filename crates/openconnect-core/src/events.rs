@@ -1,4 +1,5 @@
-use crate::{result::OpenconnectError, Status};
+use crate::{config::LogLevel, result::OpenconnectError, Status};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 
 /// Event handlers for the VPN client.
@@ -7,6 +8,10 @@ use std::sync::Arc;
 pub struct EventHandlers {
     pub(crate) handle_connection_state_change: Option<Arc<dyn Fn(Status)>>,
     pub(crate) handle_peer_cert_invalid: Option<Arc<dyn Fn(&str) -> bool>>,
+    pub(crate) handle_progress: Option<Arc<dyn Fn(LogLevel, &str)>>,
+    pub(crate) handle_server_cert_expiring: Option<Arc<dyn Fn(DateTime<Utc>)>>,
+    pub(crate) handle_external_browser: Option<Arc<dyn Fn(&str) -> bool>>,
+    pub(crate) handle_disconnected: Option<Arc<dyn Fn(Option<&str>)>>,
 }
 
 impl EventHandlers {
@@ -14,6 +19,10 @@ impl EventHandlers {
         Self {
             handle_connection_state_change: None,
             handle_peer_cert_invalid: None,
+            handle_progress: None,
+            handle_server_cert_expiring: None,
+            handle_external_browser: None,
+            handle_disconnected: None,
         }
     }
 
@@ -34,6 +43,55 @@ impl EventHandlers {
         self.handle_peer_cert_invalid = Some(Arc::new(handler));
         self
     }
+
+    /// Receive the progress messages reported by libopenconnect during the connection.
+    ///
+    /// Only messages at or below the configured [LogLevel] are delivered. Errors are
+    /// reported with [LogLevel::Err] in addition to the [Status::Error] state change.
+    pub fn with_handle_progress<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(LogLevel, &str),
+        F: Send + 'static,
+    {
+        self.handle_progress = Some(Arc::new(handler));
+        self
+    }
+
+    /// Receive the expiry of the gateway certificate when it is within the configured warning threshold
+    pub fn with_handle_server_cert_expiring<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(DateTime<Utc>),
+        F: Send + 'static,
+    {
+        self.handle_server_cert_expiring = Some(Arc::new(handler));
+        self
+    }
+
+    /// Open the web login URL of the gateway for [crate::config::EntrypointBuilder::web_auth]
+    ///
+    /// libopenconnect receives the resulting token on a loopback listener once the login in the browser is complete.
+    /// Return false if the URL could not be opened.
+    pub fn with_handle_external_browser<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str) -> bool,
+        F: Send + 'static,
+    {
+        self.handle_external_browser = Some(Arc::new(handler));
+        self
+    }
+
+    /// Receive the end of the main loop, with the reason if the gateway ended the session
+    ///
+    /// A session ended by the gateway also leaves the client in [Status::Error] with
+    /// [OpenconnectError::DisconnectedByServer].
+    pub fn with_handle_disconnected<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Option<&str>),
+        F: Send + 'static,
+    {
+        self.handle_disconnected = Some(Arc::new(handler));
+        self
+    }
 }
 
 impl Default for EventHandlers {
@@ -1,14 +1,20 @@
-use crate::VpnClient;
+use crate::{AuthGroup, VpnClient};
 use openconnect_sys::{
     oc_form_opt_select, openconnect_set_option_value, OC_FORM_OPT_HIDDEN, OC_FORM_OPT_IGNORE,
     OC_FORM_OPT_PASSWORD, OC_FORM_OPT_SELECT, OC_FORM_OPT_TEXT, OC_FORM_OPT_TOKEN,
-    OC_FORM_RESULT_CANCELLED, OC_FORM_RESULT_OK,
+    OC_FORM_RESULT_CANCELLED, OC_FORM_RESULT_NEWGROUP, OC_FORM_RESULT_OK,
 };
 use std::{
     ffi::{CStr, CString},
     ptr,
 };
 
+/// Forms asking which existing session to close when the session limit is reached (Juniper and Pulse)
+const SESSION_CONFLICT_FORMS: &[&str] = &["frmConfirmation", "pulse_session_kill"];
+
+/// Forms asking for the password of the client certificate key, after the one set with the certificate failed or if none was set
+const KEY_PASSWORD_FORMS: &[&str] = &["openconnect_pem", "openconnect_pkcs12", "openconnect_tpm2"];
+
 pub struct FormField {
     pub form_id: String,
     pub opt_id: String,
@@ -18,6 +24,11 @@ pub struct FormField {
 pub struct FormManager {
     last_form_empty: i32,
     saved_form_fields: Vec<FormField>, // TODO: currently not in use
+    auth_group_set: bool,
+    probing: bool,
+    probed_auth_groups: Option<Vec<AuthGroup>>,
+    session_conflict: bool,
+    key_password_rejected: bool,
 }
 
 // TODO: optimize this
@@ -26,12 +37,57 @@ impl FormManager {
         Self {
             last_form_empty: -1,
             saved_form_fields: Vec::new(),
+            auth_group_set: false,
+            probing: false,
+            probed_auth_groups: None,
+            session_conflict: false,
+            key_password_rejected: false,
         }
     }
 
+    /// Reset the per-connection state, the probe mode is kept
     pub fn reset(&mut self) {
         self.last_form_empty = -1;
         self.saved_form_fields.clear();
+        self.auth_group_set = false;
+        self.session_conflict = false;
+        self.key_password_rejected = false;
+    }
+
+    /// Whether the login was cancelled because the gateway reported an existing session
+    pub(crate) fn has_session_conflict(&self) -> bool {
+        self.session_conflict
+    }
+
+    /// Whether the login was cancelled because the private key of the client certificate could not be decrypted
+    pub(crate) fn has_key_password_rejected(&self) -> bool {
+        self.key_password_rejected
+    }
+
+    /// Collect the auth groups of the next form and cancel the authentication instead of filling it
+    pub(crate) fn start_probe(&mut self) {
+        self.probing = true;
+        self.probed_auth_groups = None;
+    }
+
+    /// Leave probe mode and return the collected auth groups, `None` if no form was received
+    pub(crate) fn take_probed_auth_groups(&mut self) -> Option<Vec<AuthGroup>> {
+        self.probing = false;
+        self.probed_auth_groups.take()
+    }
+
+    unsafe fn collect_choices(select_opt: *mut oc_form_opt_select) -> Vec<AuthGroup> {
+        (0..(*select_opt).nr_choices)
+            .map(|i| {
+                let choice = *(*select_opt).choices.offset(i as isize);
+                AuthGroup {
+                    name: CStr::from_ptr((*choice).name).to_string_lossy().to_string(),
+                    label: CStr::from_ptr((*choice).label)
+                        .to_string_lossy()
+                        .to_string(),
+                }
+            })
+            .collect()
     }
 
     unsafe fn saved_form_field(
@@ -60,13 +116,21 @@ impl FormManager {
 
         for i in 0..(*select_opt).nr_choices {
             let choice = *(*select_opt).choices.offset(i as isize);
-            let choice_label = std::ffi::CStr::from_ptr((*choice).label).to_str().unwrap();
-            if label[..input_len].eq_ignore_ascii_case(&choice_label[..input_len]) {
+            let choice_label = std::ffi::CStr::from_ptr((*choice).label).to_string_lossy();
+            let choice_name = std::ffi::CStr::from_ptr((*choice).name).to_string_lossy();
+            if choice_name == label {
+                (*select_opt).form._value = (*choice).name;
+                return 0;
+            }
+            if choice_label
+                .get(..input_len)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(label))
+            {
                 if choice_label.len() == input_len {
                     (*select_opt).form._value = (*choice).name;
                     return 0;
                 }
-                match_ = (*choice).label;
+                match_ = (*choice).name;
                 partial_matches += 1;
             }
         }
@@ -93,6 +157,13 @@ impl FormManager {
         tracing::debug!("Calling process_auth_form_cb");
 
         let client = unsafe { VpnClient::ref_from_raw(privdata) };
+
+        // never submit credentials over a connection the TLS policy rejects
+        if let Err(err) = client.check_tls_policy(false) {
+            tracing::error!("{}", err);
+            return OC_FORM_RESULT_CANCELLED as i32;
+        }
+
         unsafe {
             // TODO: review this
             let mut this = client
@@ -107,6 +178,22 @@ impl FormManager {
                 return -libc::EINVAL;
             }
 
+            let auth_id = CStr::from_ptr((*form).auth_id).to_string_lossy();
+            // the key password is not asked for, only the one given with the certificate is tried
+            if KEY_PASSWORD_FORMS.contains(&auth_id.as_ref()) {
+                this.key_password_rejected = true;
+                return OC_FORM_RESULT_CANCELLED as i32;
+            }
+
+            let session_conflict = SESSION_CONFLICT_FORMS.contains(&auth_id.as_ref());
+            if session_conflict {
+                if !client.should_terminate_existing_session() {
+                    this.session_conflict = true;
+                    return OC_FORM_RESULT_CANCELLED as i32;
+                }
+                tracing::warn!("Session limit reached, terminating an existing session");
+            }
+
             if !(*form).error.is_null() {
                 let error: String = std::ffi::CStr::from_ptr((*form).error)
                     .to_string_lossy()
@@ -114,12 +201,38 @@ impl FormManager {
                 println!("Authentication failed: {}", error);
             }
 
+            // different group selections can make other fields disappear or reappear,
+            // so the group is submitted on its own first
             if !(*form).authgroup_opt.is_null() {
-                // TODO: implement authgroup
-                println!("authgroup_opt");
+                let authgroup_opt = (*form).authgroup_opt;
+
+                if this.probing {
+                    this.probed_auth_groups = Some(Self::collect_choices(authgroup_opt));
+                    return OC_FORM_RESULT_CANCELLED as i32;
+                }
+
+                if let Some(auth_group) = client.get_auth_group() {
+                    if this.match_choice_label(authgroup_opt, &auth_group) != 0 {
+                        return OC_FORM_RESULT_CANCELLED as i32;
+                    }
+
+                    if !this.auth_group_set {
+                        this.auth_group_set = true;
+                        return OC_FORM_RESULT_NEWGROUP as i32;
+                    }
+                }
+            } else if this.probing {
+                // the server does not offer auth groups
+                this.probed_auth_groups = Some(Vec::new());
+                return OC_FORM_RESULT_CANCELLED as i32;
             }
 
             'loop_opt: while !opt.is_null() {
+                // advance before processing, so that `continue` moves on to the next option
+                let current = opt;
+                opt = (*opt).next;
+                let opt = current;
+
                 if ((*opt).flags & OC_FORM_OPT_IGNORE) != 0 {
                     continue 'loop_opt;
                 }
@@ -133,9 +246,17 @@ impl FormManager {
                             continue 'loop_opt;
                         }
 
+                        // close the first listed session
+                        if session_conflict && (*select_opt).nr_choices > 0 {
+                            (*select_opt).form._value = (**(*select_opt).choices).name;
+                            continue 'loop_opt;
+                        }
+
                         let auth_id = CStr::from_ptr((*form).auth_id).to_str().ok();
                         let opt_id = CStr::from_ptr((*select_opt).form.name).to_str().ok();
-                        let opt_response = this.saved_form_field(auth_id, opt_id);
+                        let opt_response = this
+                            .saved_form_field(auth_id, opt_id)
+                            .or_else(|| opt_id.and_then(|opt_id| client.form_default(opt_id)));
 
                         if opt_response.is_some()
                             && this.match_choice_label(select_opt, &opt_response.unwrap()) == 0
@@ -148,7 +269,9 @@ impl FormManager {
                     }
                     OC_FORM_OPT_TEXT => {
                         let opt_name = std::ffi::CStr::from_ptr((*opt).name).to_str().unwrap();
-                        let value = client.handle_text_input(opt_name);
+                        let value = client
+                            .form_default(opt_name)
+                            .or_else(|| client.handle_text_input(opt_name));
                         if let Some(value) = value {
                             let value = CString::new(value).unwrap();
                             openconnect_set_option_value(opt, value.as_ptr());
@@ -178,7 +301,8 @@ impl FormManager {
                         }
                     }
                     OC_FORM_OPT_PASSWORD => {
-                        let value = client.handle_password_input();
+                        let opt_name = CStr::from_ptr((*opt).name).to_string_lossy();
+                        let value = client.handle_password_input(&opt_name);
                         if let Some(value) = value {
                             let value = CString::new(value).unwrap();
                             openconnect_set_option_value(opt, value.as_ptr());
@@ -194,7 +318,9 @@ impl FormManager {
                         println!("OC_FORM_OPT_HIDDEN");
                         let auth_id = CStr::from_ptr((*form).auth_id).to_str().ok();
                         let opt_id = CStr::from_ptr((*opt).name).to_str().ok();
-                        let value = this.saved_form_field(auth_id, opt_id);
+                        let value = this
+                            .saved_form_field(auth_id, opt_id)
+                            .or_else(|| opt_id.and_then(|opt_id| client.form_default(opt_id)));
                         if value.is_some() {
                             let value = CString::new(value.unwrap()).unwrap();
                             openconnect_set_option_value(opt, value.as_ptr());
@@ -206,8 +332,6 @@ impl FormManager {
                         continue 'loop_opt;
                     }
                 }
-
-                opt = (*opt).next;
             }
 
             // TODO: optimize this stupid empty check
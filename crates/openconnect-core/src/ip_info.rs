@@ -1,11 +1,7 @@
-use openconnect_sys::oc_ip_info;
+use openconnect_sys::{oc_ip_info, oc_split_include};
 use std::ffi::CStr;
-
-// TODO: Implement SplitInclude
-// pub struct SplitInclude {
-//     pub route: String,
-//     pub next: Arc<SplitInclude>,
-// }
+#[cfg(not(target_os = "windows"))]
+use std::net::Ipv4Addr;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct IpInfo {
@@ -16,12 +12,28 @@ pub struct IpInfo {
     pub dns: [Option<String>; 3],
     pub nbns: [Option<String>; 3],
     pub domain: Option<String>,
+
+    /// The effective search domains, either parsed from `domain` or overridden by [crate::config::ConfigBuilder::search_domains]
+    pub search_domains: Vec<String>,
     pub proxy_pac: Option<String>,
     pub mtu: i32,
-    // pub split_dns: SplitInclude,
-    // pub split_includes: SplitInclude,
-    // pub split_excludes: SplitInclude,
+    /// Domains which should be resolved by the VPN DNS servers only (split DNS)
+    ///
+    /// libopenconnect passes these to the vpnc-script as `CISCO_SPLIT_DNS`, so resolvers can be configured per-domain
+    pub split_dns_domains: Vec<String>,
+
+    /// Routes sent through the tunnel, everything if empty
+    #[serde(default)]
+    pub split_includes: Vec<String>,
+
+    /// Routes kept out of the tunnel
+    #[serde(default)]
+    pub split_excludes: Vec<String>,
     pub gateway_addr: Option<String>,
+
+    /// The gateway pushed IPv6 settings but no IPv6 address, so IPv6 is broken although the tunnel is up
+    #[serde(default)]
+    pub ipv6_failed: bool,
 }
 
 unsafe fn raw_to_string(raw: *const i8) -> Option<String> {
@@ -32,10 +44,42 @@ unsafe fn raw_to_string(raw: *const i8) -> Option<String> {
     }
 }
 
+/// Collect the routes of a libopenconnect split include linked list
+unsafe fn split_include_to_vec(mut raw: *const oc_split_include) -> Vec<String> {
+    let mut routes = vec![];
+    while !raw.is_null() {
+        if let Some(route) = raw_to_string((*raw).route) {
+            routes.push(route);
+        }
+        raw = (*raw).next;
+    }
+    routes
+}
+
+/// The server may push several search domains separated by spaces or commas
+fn parse_search_domains(domain: Option<&str>) -> Vec<String> {
+    domain
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|domain| !domain.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether the gateway pushed an IPv6 netmask, IPv6 routes or an IPv6 DNS server, which need an IPv6 address
+fn offers_ipv6(info: &IpInfo) -> bool {
+    let is_ipv6 = |addr: &String| addr.contains(':');
+    info.netmask6.is_some()
+        || info.dns.iter().flatten().any(is_ipv6)
+        || info.split_includes.iter().any(is_ipv6)
+        || info.split_excludes.iter().any(is_ipv6)
+}
+
 impl From<&oc_ip_info> for IpInfo {
     fn from(value: &oc_ip_info) -> Self {
-        unsafe {
+        let mut info = unsafe {
             // let value = value.as_ref();
+            let domain = raw_to_string(value.domain);
             Self {
                 addr: raw_to_string(value.addr),
                 netmask: raw_to_string(value.netmask),
@@ -51,11 +95,182 @@ impl From<&oc_ip_info> for IpInfo {
                     raw_to_string(value.nbns[1]),
                     raw_to_string(value.nbns[2]),
                 ],
-                domain: raw_to_string(value.domain),
+                search_domains: parse_search_domains(domain.as_deref()),
+                domain,
                 proxy_pac: raw_to_string(value.proxy_pac),
                 mtu: value.mtu,
+                split_dns_domains: split_include_to_vec(value.split_dns),
+                split_includes: split_include_to_vec(value.split_includes),
+                split_excludes: split_include_to_vec(value.split_excludes),
                 gateway_addr: raw_to_string(value.gateway_addr),
+                ipv6_failed: false,
             }
+        };
+        info.ipv6_failed = info.addr6.is_none() && offers_ipv6(&info);
+        info
+    }
+}
+
+/// The length of an IPv4 netmask given either as a dotted mask or as a prefix length
+#[cfg(not(target_os = "windows"))]
+fn ipv4_mask(mask: &str) -> (Ipv4Addr, u32) {
+    match mask.parse::<u32>() {
+        Ok(len) => {
+            let len = len.min(32);
+            let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+            (Ipv4Addr::from(mask), len)
+        }
+        Err(_) => {
+            let mask = mask.parse().unwrap_or(Ipv4Addr::BROADCAST);
+            (mask, u32::from(mask).count_ones())
         }
     }
 }
+
+/// The `CISCO_SPLIT_INC`/`CISCO_SPLIT_EXC` variables of the routes, IPv6 routes as `CISCO_IPV6_SPLIT_*`
+#[cfg(not(target_os = "windows"))]
+fn split_route_env(env: &mut Vec<(String, String)>, kind: &str, routes: &[String]) {
+    let (ipv6, ipv4): (Vec<_>, Vec<_>) = routes
+        .iter()
+        .map(|route| route.split_once('/').unwrap_or((route.as_str(), "")))
+        .partition(|(addr, _)| addr.contains(':'));
+
+    if !ipv4.is_empty() {
+        env.push((format!("CISCO_SPLIT_{}", kind), ipv4.len().to_string()));
+    }
+    for (i, (addr, mask)) in ipv4.into_iter().enumerate() {
+        let (mask, len) = ipv4_mask(mask);
+        let prefix = format!("CISCO_SPLIT_{}_{}", kind, i);
+        env.push((format!("{}_ADDR", prefix), addr.to_string()));
+        env.push((format!("{}_MASK", prefix), mask.to_string()));
+        env.push((format!("{}_MASKLEN", prefix), len.to_string()));
+        for field in ["PROTOCOL", "SPORT", "DPORT"] {
+            env.push((format!("{}_{}", prefix, field), "0".to_string()));
+        }
+    }
+
+    if !ipv6.is_empty() {
+        env.push((format!("CISCO_IPV6_SPLIT_{}", kind), ipv6.len().to_string()));
+    }
+    for (i, (addr, len)) in ipv6.into_iter().enumerate() {
+        let prefix = format!("CISCO_IPV6_SPLIT_{}_{}", kind, i);
+        env.push((format!("{}_ADDR", prefix), addr.to_string()));
+        env.push((
+            format!("{}_MASKLEN", prefix),
+            if len.is_empty() { "128" } else { len }.to_string(),
+        ));
+    }
+}
+
+impl IpInfo {
+    /// The environment libopenconnect runs the vpnc-script with, so that it can be run again with the same parameters
+    #[cfg(not(target_os = "windows"))]
+    pub(crate) fn script_env(&self, reason: &str, tundev: &str) -> Vec<(String, String)> {
+        let mut env = vec![
+            ("reason".to_string(), reason.to_string()),
+            ("TUNDEV".to_string(), tundev.to_string()),
+            ("VPNPID".to_string(), std::process::id().to_string()),
+        ];
+        let mut set = |name: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                env.push((name.to_string(), value));
+            }
+        };
+
+        set("VPNGATEWAY", self.gateway_addr.clone());
+        set(
+            "INTERNAL_IP4_MTU",
+            (self.mtu > 0).then(|| self.mtu.to_string()),
+        );
+        if let Some(addr) = &self.addr {
+            let (mask, len) = ipv4_mask(self.netmask.as_deref().unwrap_or("32"));
+            let netaddr = addr
+                .parse::<Ipv4Addr>()
+                .map(|addr| Ipv4Addr::from(u32::from(addr) & u32::from(mask)));
+            set("INTERNAL_IP4_ADDRESS", Some(addr.clone()));
+            set("INTERNAL_IP4_NETMASK", Some(mask.to_string()));
+            set("INTERNAL_IP4_NETMASKLEN", Some(len.to_string()));
+            set(
+                "INTERNAL_IP4_NETADDR",
+                netaddr.ok().map(|addr| addr.to_string()),
+            );
+        }
+        set("INTERNAL_IP6_ADDRESS", self.addr6.clone());
+        set("INTERNAL_IP6_NETMASK", self.netmask6.clone());
+
+        let (dns6, dns4): (Vec<_>, Vec<_>) = self
+            .dns
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .partition(|dns| dns.contains(':'));
+        set("INTERNAL_IP4_DNS", Some(dns4.join(" ")));
+        set("INTERNAL_IP6_DNS", Some(dns6.join(" ")));
+        let nbns: Vec<_> = self.nbns.iter().flatten().map(String::as_str).collect();
+        set("INTERNAL_IP4_NBNS", Some(nbns.join(" ")));
+        set("CISCO_DEF_DOMAIN", Some(self.search_domains.join(" ")));
+        set("CISCO_SPLIT_DNS", Some(self.split_dns_domains.join(",")));
+        set("CISCO_PROXY_PAC", self.proxy_pac.clone());
+
+        split_route_env(&mut env, "INC", &self.split_includes);
+        split_route_env(&mut env, "EXC", &self.split_excludes);
+        env
+    }
+}
+
+#[test]
+fn test_parse_search_domains() {
+    assert_eq!(
+        parse_search_domains(Some("corp.example.com, example.org lab.example.com")),
+        vec!["corp.example.com", "example.org", "lab.example.com"]
+    );
+    assert!(parse_search_domains(None).is_empty());
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn test_script_env() {
+    let info = IpInfo {
+        addr: Some("10.1.2.3".to_string()),
+        netmask: Some("255.255.0.0".to_string()),
+        addr6: None,
+        netmask6: None,
+        dns: [
+            Some("10.0.0.53".to_string()),
+            Some("fd00::53".to_string()),
+            None,
+        ],
+        nbns: [None, None, None],
+        domain: Some("corp.example.com".to_string()),
+        search_domains: vec!["corp.example.com".to_string()],
+        proxy_pac: None,
+        mtu: 1400,
+        split_dns_domains: vec![],
+        split_includes: vec!["10.0.0.0/255.0.0.0".to_string(), "fd00::/8".to_string()],
+        split_excludes: vec!["10.9.0.0/16".to_string()],
+        gateway_addr: Some("192.0.2.1".to_string()),
+        ipv6_failed: true,
+    };
+    assert!(offers_ipv6(&info));
+    let env = info.script_env("connect", "tun0");
+    let get = |name: &str| {
+        env.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    assert_eq!(get("reason"), Some("connect"));
+    assert_eq!(get("TUNDEV"), Some("tun0"));
+    assert_eq!(get("VPNGATEWAY"), Some("192.0.2.1"));
+    assert_eq!(get("INTERNAL_IP4_NETMASKLEN"), Some("16"));
+    assert_eq!(get("INTERNAL_IP4_NETADDR"), Some("10.1.0.0"));
+    assert_eq!(get("INTERNAL_IP4_DNS"), Some("10.0.0.53"));
+    assert_eq!(get("INTERNAL_IP6_DNS"), Some("fd00::53"));
+    assert_eq!(get("INTERNAL_IP4_NBNS"), None);
+    assert_eq!(get("CISCO_DEF_DOMAIN"), Some("corp.example.com"));
+    assert_eq!(get("CISCO_SPLIT_INC"), Some("1"));
+    assert_eq!(get("CISCO_SPLIT_INC_0_MASKLEN"), Some("8"));
+    assert_eq!(get("CISCO_IPV6_SPLIT_INC_0_ADDR"), Some("fd00::"));
+    assert_eq!(get("CISCO_IPV6_SPLIT_INC_0_MASKLEN"), Some("8"));
+    assert_eq!(get("CISCO_SPLIT_EXC_0_MASK"), Some("255.255.0.0"));
+}
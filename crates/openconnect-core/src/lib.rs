@@ -8,22 +8,37 @@
 #![doc = include_str!("../examples/password_server.rs")]
 //! ```
 
+mod ca_file;
+pub mod captive_portal;
 mod cert;
+pub mod cipher_key;
 pub mod command;
 pub mod config;
 pub mod elevator;
+pub mod env_server;
 pub mod events;
 mod form;
 pub mod ip_info;
 pub mod log;
+pub mod paths;
+pub mod preflight;
+pub mod probe;
+pub mod profile_script;
 pub mod protocols;
+pub mod proxy;
+pub mod resolver;
 pub mod result;
+pub mod secret;
 pub mod stats;
 pub mod storage;
+pub mod trusted_network;
+pub mod udp_policy;
+pub mod verify;
 
+use crate::ca_file::CaFile;
 use crate::cert::PeerCerts;
 use crate::command::{CmdPipe, SIGNAL_HANDLE};
-use crate::config::{Config, Entrypoint, LogLevel};
+use crate::config::{Config, Entrypoint, LogLevel, TlsVersion, DEFAULT_REPORTED_OS};
 use crate::events::{EventHandlers, Events};
 use crate::form::FormManager;
 use crate::ip_info::IpInfo;
@@ -31,17 +46,22 @@ use crate::log::Logger;
 use crate::result::{EmitError, OpenconnectError, OpenconnectResult};
 use crate::stats::Stats;
 
+use futures::Stream;
 use openconnect_sys::*;
 use std::{
+    collections::HashMap,
     ffi::CString,
+    net::IpAddr,
     sync::{
-        atomic::{AtomicI32, Ordering},
-        Arc, RwLock, Weak,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, Mutex, RwLock, Weak,
     },
+    time::Duration,
 };
+use tokio::sync::Notify;
 
 /// Describe the connection status of the client
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Status {
     /// The client is initialized
     Initialized,
@@ -62,6 +82,164 @@ pub enum Status {
     Error(OpenconnectError),
 }
 
+/// An auth group (also called realm) offered by the server on its login form
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuthGroup {
+    /// The value submitted to the server
+    pub name: String,
+
+    /// The human readable label
+    pub label: String,
+}
+
+/// Version of the linked libopenconnect
+pub fn openconnect_version() -> Option<String> {
+    unsafe {
+        let version = openconnect_get_version();
+        if version.is_null() {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(version)
+            .to_str()
+            .ok()
+            .map(str::to_string)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+const DEFAULT_VPNC_SCRIPT: &str = "./vpnc-script";
+
+#[cfg(target_os = "windows")]
+const DEFAULT_VPNC_SCRIPT: &str = "./vpnc-script-win.js";
+
+/// Override the search domains libopenconnect exports to the vpnc-script
+///
+/// libopenconnect runs the script through `/bin/sh -c`, so an assignment prefix takes precedence over the pushed `CISCO_DEF_DOMAIN`.
+/// The domains are validated by [config::ConfigBuilder::build] and need no quoting.
+#[cfg(not(target_os = "windows"))]
+fn with_search_domains(vpnc_script: &str, search_domains: &[String]) -> String {
+    format!(
+        "CISCO_DEF_DOMAIN='{}' {}",
+        search_domains.join(" "),
+        vpnc_script
+    )
+}
+
+/// The script is not run through a shell on Windows, the override is only reflected in [IpInfo]
+#[cfg(target_os = "windows")]
+fn with_search_domains(vpnc_script: &str, _search_domains: &[String]) -> String {
+    tracing::warn!("Overriding search domains is not supported on Windows");
+    vpnc_script.to_string()
+}
+
+/// Override the MTU libopenconnect exports to the vpnc-script with [Entrypoint::mtu], like [with_search_domains]
+#[cfg(not(target_os = "windows"))]
+fn with_mtu(vpnc_script: &str, mtu: u32) -> String {
+    format!("INTERNAL_IP4_MTU={} {}", mtu, vpnc_script)
+}
+
+#[cfg(target_os = "windows")]
+fn with_mtu(vpnc_script: &str, _mtu: u32) -> String {
+    tracing::warn!("Overriding the MTU of the tunnel device is not supported on Windows");
+    vpnc_script.to_string()
+}
+
+/// Export the extra variables of [Config::script_env] to the vpnc-script, with the same assignment prefix as
+/// [with_search_domains]
+///
+/// The names have to be checked with [config::validate_script_env], which only [config::ConfigBuilder::build] does
+/// on its own. The values are single quoted.
+#[cfg(not(target_os = "windows"))]
+fn with_script_env(vpnc_script: &str, script_env: &HashMap<String, String>) -> String {
+    let mut script_env: Vec<_> = script_env.iter().collect();
+    script_env.sort();
+
+    let assignments: String = script_env
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                "{}{}='{}' ",
+                config::SCRIPT_ENV_PREFIX,
+                name,
+                value.replace('\'', "'\\''")
+            )
+        })
+        .collect();
+    format!("{}{}", assignments, vpnc_script)
+}
+
+#[cfg(target_os = "windows")]
+fn with_script_env(vpnc_script: &str, _script_env: &HashMap<String, String>) -> String {
+    tracing::warn!("Passing environment variables to the vpnc-script is not supported on Windows");
+    vpnc_script.to_string()
+}
+
+/// Run a vpnc-script through `/bin/sh -c` like libopenconnect, with the environment it exports for `reason`
+///
+/// Used to run the script again outside of libopenconnect, e.g. for the `disconnect` reason of a tunnel whose process
+/// is gone.
+#[cfg(not(target_os = "windows"))]
+pub fn run_vpnc_script(
+    vpnc_script: &str,
+    reason: &str,
+    ifname: &str,
+    info: &IpInfo,
+    script_env: &HashMap<String, String>,
+) -> std::io::Result<std::process::ExitStatus> {
+    std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(vpnc_script)
+        .envs(info.script_env(reason, ifname))
+        .envs(
+            script_env
+                .iter()
+                .map(|(name, value)| (format!("{}{}", config::SCRIPT_ENV_PREFIX, name), value)),
+        )
+        .status()
+}
+
+/// The gateway version in a progress message, from the `<version who="sg">` element of an AnyConnect reply, dumped
+/// in trace messages, or from a `Server` HTTP header in debug messages
+fn parse_server_version(message: &str) -> Option<String> {
+    const VERSION_START: &str = "<version who=\"sg\">";
+
+    let message = message.trim();
+    let version = match message.find(VERSION_START) {
+        Some(start) => {
+            let version = &message[start + VERSION_START.len()..];
+            &version[..version.find("</version>")?]
+        }
+        None => message.strip_prefix("Server: ")?,
+    };
+
+    let version = version.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// What paused the main loop, which then returns without an error like a closed connection
+#[derive(Debug, PartialEq, Eq)]
+enum Pause {
+    /// See [VpnClient::watch_dtls]
+    DtlsFallback,
+
+    /// See [VpnClient::rekey]
+    Rekey,
+}
+
+/// Take the reason of a pause from the flag set before pausing, `None` if the main loop was not paused
+fn take_pause(dtls_fallback: &AtomicBool, rekey_requested: &AtomicBool) -> Option<Pause> {
+    if dtls_fallback.swap(false, Ordering::SeqCst) {
+        Some(Pause::DtlsFallback)
+    } else if rekey_requested.swap(false, Ordering::SeqCst) {
+        Some(Pause::Rekey)
+    } else {
+        None
+    }
+}
+
+/// Password fields of the login form which ask for the second factor instead of the password
+const OTP_FIELDS: &[&str] = &["secondary_password", "answer", "otp", "passcode"];
+
 /// VpnClient struct
 ///
 /// This struct is the main entrypoint for interacting with the Openconnect C library (on top of [openconnect-sys](https://crates.io/crates/openconnect-sys))
@@ -75,6 +253,29 @@ pub struct VpnClient {
     entrypoint: RwLock<Option<Entrypoint>>,
     form_manager: RwLock<FormManager>,
     peer_certs: PeerCerts,
+    stats: RwLock<Option<Stats>>,
+    stats_reported: Notify,
+    transient_failure: RwLock<Option<String>>,
+
+    /// The reason the gateway gave for ending the session, see [OpenconnectError::DisconnectedByServer]
+    server_disconnect_reason: RwLock<Option<String>>,
+
+    /// The software version the gateway reported while connecting, see [VpnClient::get_server_version]
+    server_version: RwLock<Option<String>>,
+
+    /// The host of the entrypoint when the gateway redirected the login to another host, see [VpnClient::get_redirected_from]
+    redirected_from: RwLock<Option<String>>,
+    dtls_fallback: AtomicBool,
+
+    /// Set by [VpnClient::rekey] until the main loop resumes the paused session
+    rekey_requested: AtomicBool,
+    cancelled: AtomicBool,
+
+    /// Kept for the lifetime of the client, libopenconnect reads it on every (re)connect
+    ca_file: Option<CaFile>,
+
+    /// Opened from [Config::openconnect_log]
+    openconnect_log: Option<Mutex<std::fs::File>>,
 }
 
 unsafe impl Send for VpnClient {}
@@ -102,10 +303,42 @@ impl VpnClient {
         #[cfg(not(target_os = "windows"))]
         {
             // TODO: handle result
-            let _result = client.setup_tun_device(None, None);
+            let _result = match client.config.tun_device {
+                Some(config::TunDevice::Name(ref ifname)) => {
+                    client.setup_tun_device(None, Some(ifname.clone()))
+                }
+                Some(config::TunDevice::Fd(fd)) => client.setup_tun_fd(fd),
+                None => client.setup_tun_device(None, None),
+            };
         }
     }
 
+    /// Resolve the gateway like libopenconnect would, restricted to [Config::transport_family]
+    #[cfg(not(target_os = "windows"))]
+    pub(crate) extern "C" fn transport_getaddrinfo_vfn(
+        privdata: *mut ::std::os::raw::c_void,
+        node: *const ::std::os::raw::c_char,
+        service: *const ::std::os::raw::c_char,
+        hints: *const addrinfo,
+        res: *mut *mut addrinfo,
+    ) -> ::std::os::raw::c_int {
+        let client = unsafe { VpnClient::ref_from_raw(privdata) };
+
+        // the bindings only declare addrinfo opaquely, it is the one of the system
+        let mut hints = if hints.is_null() {
+            unsafe { std::mem::zeroed::<libc::addrinfo>() }
+        } else {
+            unsafe { *hints.cast::<libc::addrinfo>() }
+        };
+        hints.ai_family = match client.config.transport_family {
+            Some(config::AddressFamily::Ipv4) => libc::AF_INET,
+            Some(config::AddressFamily::Ipv6) => libc::AF_INET6,
+            None => hints.ai_family,
+        };
+
+        unsafe { libc::getaddrinfo(node, service, &hints, res.cast::<*mut libc::addrinfo>()) }
+    }
+
     /// Reclaim a reference from c_void
     ///
     /// SAFETY: You must ensure that the pointer is valid and points to a valid instance of `Self`
@@ -123,13 +356,120 @@ impl VpnClient {
         }
     }
 
-    pub(crate) fn handle_password_input(&self) -> Option<String> {
+    pub(crate) fn should_terminate_existing_session(&self) -> bool {
+        self.entrypoint
+            .read()
+            .ok()
+            .and_then(|entrypoint| {
+                (*entrypoint)
+                    .as_ref()
+                    .map(|entrypoint| entrypoint.terminate_existing_session)
+            })
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn get_auth_group(&self) -> Option<String> {
+        let entrypoint = self.entrypoint.read().ok()?;
+        (*entrypoint).as_ref()?.auth_group.clone()
+    }
+
+    /// The value of [Entrypoint::form_defaults] for a login form field
+    pub(crate) fn form_default(&self, field_name: &str) -> Option<String> {
         let entrypoint = self.entrypoint.read().ok()?;
-        (*entrypoint).as_ref()?.password.clone()
+        (*entrypoint)
+            .as_ref()?
+            .form_defaults
+            .get(field_name)
+            .cloned()
+    }
+
+    pub(crate) fn handle_password_input(&self, field_name: &str) -> Option<String> {
+        let entrypoint = self.entrypoint.read().ok()?;
+        let entrypoint = (*entrypoint).as_ref()?;
+        match (OTP_FIELDS.contains(&field_name), &entrypoint.otp) {
+            (true, Some(otp)) => Some(otp.clone()),
+            _ => entrypoint.password.clone(),
+        }
+    }
+
+    pub(crate) fn handle_progress(&self, level: LogLevel, message: &str) {
+        self.write_openconnect_log(level, message);
+
+        if let Some(version) = parse_server_version(message) {
+            if let Ok(mut server_version) = self.server_version.write() {
+                *server_version = Some(version);
+            }
+        }
+
+        // more verbose messages are only requested for the libopenconnect log and to find the server version,
+        // see [VpnClient::with_verbose_progress]
+        if !self.logs_level(level) {
+            return;
+        }
+
+        if level == LogLevel::Err && result::is_transient_failure(message) {
+            if let Ok(mut transient_failure) = self.transient_failure.write() {
+                *transient_failure = Some(message.trim().to_string());
+            }
+        }
+
+        if level == LogLevel::Err {
+            if let Some(reason) = result::server_disconnect_reason(message) {
+                if let Ok(mut server_disconnect_reason) = self.server_disconnect_reason.write() {
+                    *server_disconnect_reason = Some(reason);
+                }
+            }
+        }
+
+        if let Some(ref handler) = self.callbacks.handle_progress {
+            handler(level, message);
+        }
     }
 
     pub(crate) fn handle_stats(&self, (dlts, stats): (Option<String>, Option<Stats>)) {
-        println!("stats: {:?}, {:?}", dlts, stats);
+        tracing::debug!("stats: {:?}, {:?}", dlts, stats);
+        if let (Some(stats), Ok(mut stats_write_guard)) = (stats, self.stats.write()) {
+            *stats_write_guard = Some(stats);
+        }
+        self.stats_reported.notify_waiters();
+    }
+
+    /// Ask the main loop to report the traffic counters, the result is available from [VpnClient::get_stats] shortly after
+    pub fn request_stats(&self) {
+        self.send_command(command::Command::Stats);
+    }
+
+    /// The traffic counters last reported by the main loop
+    pub fn get_stats(&self) -> Option<Stats> {
+        self.stats.read().ok().and_then(|stats| stats.clone())
+    }
+
+    /// Snapshots of the traffic counters every `interval`, requested from the main loop like [VpnClient::request_stats]
+    ///
+    /// The stream waits while the client is still connecting and ends once the connection is closed.
+    pub fn stats_stream(self: &Arc<Self>, interval: Duration) -> impl Stream<Item = Stats> {
+        futures::stream::unfold((self.clone(), true), move |(client, first)| async move {
+            let mut first = first;
+            loop {
+                if !first {
+                    tokio::time::sleep(interval).await;
+                }
+                first = false;
+                match client.get_status() {
+                    Status::Connected => break,
+                    Status::Disconnecting | Status::Disconnected | Status::Error(_) => return None,
+                    Status::Initialized | Status::Connecting(_) => {}
+                }
+            }
+
+            let reported = client.stats_reported.notified();
+            client.request_stats();
+            // the main loop answers right away unless it is busy, the last report is used then
+            let _ = tokio::time::timeout(interval, reported).await;
+
+            let stats = client.get_stats().unwrap_or_default();
+            Some((stats, (client, false)))
+        })
     }
 
     pub(crate) fn handle_accept_insecure_cert(&self, fingerprint: &str) -> bool {
@@ -162,6 +502,63 @@ impl VpnClient {
         }
     }
 
+    /// libopenconnect follows a redirect of the login to another gateway on its own, record it for [VpnClient::get_redirected_from]
+    fn check_redirect(&self, initial_hostname: Option<&str>) {
+        let hostname = self.get_hostname();
+        let redirected_from = match (initial_hostname, hostname.as_deref()) {
+            (Some(initial), Some(hostname)) if !initial.eq_ignore_ascii_case(hostname) => {
+                tracing::info!("Gateway {} redirected the login to {}", initial, hostname);
+                self.emit_state_change(Status::Connecting(format!(
+                    "Redirected to gateway: {}",
+                    hostname
+                )));
+                Some(initial.to_string())
+            }
+            _ => None,
+        };
+        if let Ok(mut redirected) = self.redirected_from.write() {
+            *redirected = redirected_from;
+        }
+    }
+
+    /// Whether messages of the level are logged with [Config::loglevel]
+    pub(crate) fn logs_level(&self, level: LogLevel) -> bool {
+        level <= self.config.loglevel
+    }
+
+    /// The level libopenconnect reports progress at, more verbose than [Config::loglevel] for the libopenconnect log
+    fn progress_level(&self) -> LogLevel {
+        match self.openconnect_log {
+            Some(_) => self.config.loglevel.max(self.config.openconnect_log_level),
+            None => self.config.loglevel,
+        }
+    }
+
+    fn write_openconnect_log(&self, level: LogLevel, message: &str) {
+        use std::io::Write;
+        if level > self.config.openconnect_log_level {
+            return;
+        }
+        if let Some(Ok(mut file)) = self.openconnect_log.as_ref().map(Mutex::lock) {
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}",
+                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                level.to_string().to_uppercase(),
+                message.trim_end()
+            );
+        }
+    }
+
+    /// Run an exchange with the gateway with the most verbose progress of libopenconnect, to find its version in the
+    /// HTTP headers and bodies, the messages above [Config::loglevel] are dropped
+    fn with_verbose_progress<T>(&self, exchange: impl FnOnce() -> T) -> T {
+        self.set_loglevel(LogLevel::Trace);
+        let result = exchange();
+        self.set_loglevel(self.progress_level());
+        result
+    }
+
     pub fn set_protocol(&self, protocol: &str) -> OpenconnectResult<()> {
         let protocol =
             CString::new(protocol).map_err(|_| OpenconnectError::SetProtocolError(libc::EIO))?;
@@ -172,6 +569,49 @@ impl VpnClient {
         }
     }
 
+    pub(crate) extern "C" fn external_browser_cb(
+        _vpninfo: *mut openconnect_info,
+        uri: *const ::std::os::raw::c_char,
+        privdata: *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int {
+        let client = unsafe { VpnClient::ref_from_raw(privdata) };
+        if uri.is_null() {
+            return -libc::EINVAL;
+        }
+        let uri = unsafe { std::ffi::CStr::from_ptr(uri) }.to_string_lossy();
+
+        match client.callbacks.handle_external_browser {
+            Some(ref handler) if handler(&uri) => 0,
+            _ => {
+                tracing::error!("Failed to open the web login: {}", uri);
+                -libc::EINVAL
+            }
+        }
+    }
+
+    /// Let the gateway offer its web login (SAML), opened by [EventHandlers::with_handle_external_browser]
+    pub fn set_external_browser_handler(&self) {
+        unsafe {
+            openconnect_set_external_browser_callback(
+                self.vpninfo,
+                Some(Self::external_browser_cb),
+            );
+        }
+    }
+
+    pub fn set_transport_family_handler(&self) {
+        #[cfg(not(target_os = "windows"))]
+        unsafe {
+            openconnect_override_getaddrinfo(
+                self.vpninfo,
+                Some(VpnClient::transport_getaddrinfo_vfn),
+            );
+        }
+
+        #[cfg(target_os = "windows")]
+        tracing::warn!("Restricting the transport address family is not supported on Windows");
+    }
+
     pub fn set_stats_handler(&self) {
         unsafe {
             openconnect_set_stats_handler(self.vpninfo, Some(stats::stats_fn));
@@ -186,19 +626,23 @@ impl VpnClient {
         let vpnc_script_from_config = vpnc_script.or_else(|| self.config.vpncscript.clone());
 
         let vpnc_script = {
-            if let Some(vpnc_script) = vpnc_script_from_config {
-                CString::new(vpnc_script)
-                    .map_err(|_| OpenconnectError::SetupTunDeviceEror(libc::EIO))?
+            let vpnc_script = vpnc_script_from_config.unwrap_or(DEFAULT_VPNC_SCRIPT.to_string());
+            let vpnc_script = match &self.config.search_domains {
+                Some(search_domains) => with_search_domains(&vpnc_script, search_domains),
+                None => vpnc_script,
+            };
+            let vpnc_script = match self.get_mtu_override() {
+                Some(mtu) => with_mtu(&vpnc_script, mtu),
+                None => vpnc_script,
+            };
+            let vpnc_script = if self.config.script_env.is_empty() {
+                vpnc_script
             } else {
-                #[cfg(not(target_os = "windows"))]
-                const DEFAULT_SCRIPT: &str = "./vpnc-script";
+                with_script_env(&vpnc_script, &self.config.script_env)
+            };
 
-                #[cfg(target_os = "windows")]
-                const DEFAULT_SCRIPT: &str = "./vpnc-script-win.js";
-
-                CString::new(DEFAULT_SCRIPT)
-                    .map_err(|_| OpenconnectError::SetupTunDeviceEror(libc::EIO))?
-            }
+            CString::new(vpnc_script)
+                .map_err(|_| OpenconnectError::SetupTunDeviceEror(libc::EIO))?
         };
 
         let ifname = ifname.and_then(|s| CString::new(s).ok());
@@ -219,6 +663,64 @@ impl VpnClient {
         }
     }
 
+    /// Run the vpnc-script again with the current parameters, e.g. after another tool overwrote the DNS configuration
+    ///
+    /// The tunnel is left untouched. The script gets the environment libopenconnect exports for the `connect` reason,
+    /// through `/bin/sh -c` like libopenconnect runs it.
+    #[cfg(not(target_os = "windows"))]
+    pub fn reapply_config(&self) -> OpenconnectResult<()> {
+        if let Some(config::TunDevice::Fd(_)) = self.config.tun_device {
+            return Err(OpenconnectError::ReapplyConfigError(
+                "no vpnc-script runs for a tun file descriptor".to_string(),
+            ));
+        }
+        let (Some(ifname), Some(info)) = (self.get_ifname(), self.get_info()?) else {
+            return Err(OpenconnectError::ReapplyConfigError(
+                "the tunnel is not up".to_string(),
+            ));
+        };
+
+        let status = run_vpnc_script(
+            &self.get_vpnc_script(),
+            "connect",
+            &ifname,
+            &info,
+            &self.config.script_env,
+        )
+        .map_err(|e| OpenconnectError::ReapplyConfigError(e.to_string()))?;
+
+        if !status.success() {
+            return Err(OpenconnectError::ReapplyConfigError(format!(
+                "vpnc-script exited with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+
+    /// Use an open tun device instead of creating one, no vpnc-script is run for it
+    ///
+    /// libopenconnect closes the descriptor it is given, so a duplicate is passed to keep `fd` usable for reconnects
+    #[cfg(not(target_os = "windows"))]
+    pub fn setup_tun_fd(&self, fd: std::os::raw::c_int) -> OpenconnectResult<()> {
+        let tun_fd = unsafe { libc::dup(fd) };
+        if tun_fd < 0 {
+            let errno = std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EBADF);
+            return Err(OpenconnectError::SetupTunDeviceEror(-errno));
+        }
+
+        let ret = unsafe { openconnect_setup_tun_fd(self.vpninfo, tun_fd) };
+        match ret {
+            0 => Ok(()),
+            _ => {
+                unsafe { libc::close(tun_fd) };
+                Err(OpenconnectError::SetupTunDeviceEror(ret))
+            }
+        }
+    }
+
     pub fn set_setup_tun_handler(&self) {
         unsafe {
             openconnect_set_setup_tun_handler(self.vpninfo, Some(VpnClient::default_setup_tun_vfn));
@@ -234,6 +736,16 @@ impl VpnClient {
         }
     }
 
+    pub fn set_user_agent(&self, user_agent: &str) -> OpenconnectResult<()> {
+        let user_agent =
+            CString::new(user_agent).map_err(|_| OpenconnectError::SetUserAgentError(libc::EIO))?;
+        let ret = unsafe { openconnect_set_useragent(self.vpninfo, user_agent.as_ptr()) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(OpenconnectError::SetUserAgentError(ret)),
+        }
+    }
+
     pub fn obtain_cookie(&self) -> OpenconnectResult<()> {
         let ret = unsafe { openconnect_obtain_cookie(self.vpninfo) };
         match ret {
@@ -319,6 +831,274 @@ impl VpnClient {
             .to_string()
     }
 
+    pub fn get_cstp_cipher(&self) -> Option<String> {
+        unsafe {
+            let cipher = openconnect_get_cstp_cipher(self.vpninfo);
+            if cipher.is_null() {
+                return None;
+            }
+            std::ffi::CStr::from_ptr(cipher)
+                .to_str()
+                .map(|s| s.to_string())
+                .ok()
+        }
+    }
+
+    /// The TLS version negotiated with the gateway
+    pub fn get_tls_version(&self) -> Option<TlsVersion> {
+        self.get_cstp_cipher()
+            .as_deref()
+            .and_then(TlsVersion::from_cipher)
+    }
+
+    /// Fail if the negotiated TLS version is below [Config::min_tls_version] or the cipher is not in [Config::cipher_list],
+    /// or the gateway certificate is not the one of [Entrypoint::accepted_cert]
+    ///
+    /// An unknown version or cipher only fails if `require_known` is set, it may not be available yet during authentication
+    pub(crate) fn check_tls_policy(&self, require_known: bool) -> OpenconnectResult<()> {
+        // a certificate the system trusts is never passed to the validation callback
+        if let Some(accepted_cert) = self.accepted_cert() {
+            match self.matches_peer_cert(&accepted_cert) {
+                Some(true) => {}
+                None if !require_known => {}
+                Some(false) => {
+                    return Err(OpenconnectError::CertFingerprintMismatch(
+                        self.get_peer_cert_hash(),
+                    ))
+                }
+                None => {
+                    return Err(OpenconnectError::CertFingerprintMismatch(
+                        "an unknown certificate".to_string(),
+                    ))
+                }
+            }
+        }
+
+        if let Some(min_tls_version) = self.config.min_tls_version {
+            match self.get_tls_version() {
+                Some(version) if version >= min_tls_version => {}
+                None if !require_known => {}
+                version => {
+                    return Err(OpenconnectError::TlsVersionTooOld(
+                        version.map_or("an unknown TLS version".to_string(), |v| v.to_string()),
+                        min_tls_version.to_string(),
+                    ))
+                }
+            }
+        }
+
+        if self.config.cipher_list.is_some() {
+            match self.get_cstp_cipher() {
+                Some(cipher) if self.config.allows_cipher(&cipher) => {}
+                None if !require_known => {}
+                cipher => {
+                    return Err(OpenconnectError::CipherNotAllowed(
+                        cipher.unwrap_or("an unknown cipher".to_string()),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fail if the address assigned by the gateway is outside of [Entrypoint::expected_subnet]
+    fn check_assigned_address(&self) -> OpenconnectResult<()> {
+        let expected_subnet = self
+            .entrypoint
+            .read()
+            .ok()
+            .and_then(|entrypoint| entrypoint.as_ref().and_then(|e| e.expected_subnet));
+        let Some(expected_subnet) = expected_subnet else {
+            return Ok(());
+        };
+
+        let assigned = self.get_info()?.and_then(|info| {
+            if expected_subnet.is_ipv4() {
+                info.addr
+            } else {
+                info.addr6
+            }
+        });
+        let addr = assigned
+            .as_deref()
+            .and_then(|addr| addr.split('/').next())
+            .and_then(|addr| addr.parse::<IpAddr>().ok());
+
+        match addr {
+            Some(addr) if expected_subnet.contains(&addr) => Ok(()),
+            _ => Err(OpenconnectError::UnexpectedAddress(
+                assigned.unwrap_or("no address".to_string()),
+                expected_subnet.to_string(),
+            )),
+        }
+    }
+
+    /// Fail if the gateway set an MTU outside of [Config::dtls_mtu_min] and [Config::dtls_mtu_max]
+    fn check_mtu(&self) -> OpenconnectResult<()> {
+        if self.config.dtls_mtu_min.is_none() && self.config.dtls_mtu_max.is_none() {
+            return Ok(());
+        }
+
+        match self.get_info()? {
+            Some(info) if !self.config.allows_mtu(info.mtu) => Err(
+                OpenconnectError::MtuOutOfRange(info.mtu, self.config.mtu_bounds()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Warn if the gateway offered IPv6 but did not assign an address, see [IpInfo::ipv6_failed]
+    fn check_ipv6(&self) {
+        if !self
+            .get_info()
+            .ok()
+            .flatten()
+            .is_some_and(|info| info.ipv6_failed)
+        {
+            return;
+        }
+
+        let message =
+            "The gateway offered IPv6 but assigned no IPv6 address, IPv6 traffic will fail";
+        tracing::warn!("{}", message);
+        self.handle_progress(LogLevel::Err, message);
+    }
+
+    /// The reason the gateway gave for ending the last session, if it ended it
+    pub fn get_server_disconnect_reason(&self) -> Option<String> {
+        self.server_disconnect_reason
+            .read()
+            .ok()
+            .and_then(|reason| reason.clone())
+    }
+
+    /// The software version the gateway reported, e.g. in the auth reply of an AnyConnect gateway or its `Server` header
+    ///
+    /// libopenconnect has no API for it, so it is taken from the progress messages while connecting, `None` if the
+    /// gateway does not report one
+    pub fn get_server_version(&self) -> Option<String> {
+        self.server_version
+            .read()
+            .ok()
+            .and_then(|version| version.clone())
+    }
+
+    /// The host the login started at, if the gateway redirected it to the current [VpnClient::get_hostname], e.g. for
+    /// load balancing
+    pub fn get_redirected_from(&self) -> Option<String> {
+        self.redirected_from
+            .read()
+            .ok()
+            .and_then(|redirected_from| redirected_from.clone())
+    }
+
+    /// The URL of the gateway libopenconnect connects to, which follows a redirect of the login
+    ///
+    /// Connect to it with the cookie of the login, the cookie may only be valid on the gateway which issued it
+    pub fn get_connect_url(&self) -> Option<String> {
+        unsafe {
+            let connect_url = openconnect_get_connect_url(self.vpninfo);
+            if connect_url.is_null() {
+                return None;
+            }
+            std::ffi::CStr::from_ptr(connect_url)
+                .to_str()
+                .map(|s| s.to_string())
+                .ok()
+        }
+    }
+
+    /// Whether the DTLS (UDP) channel is established, otherwise traffic goes over TLS
+    pub fn is_dtls_connected(&self) -> bool {
+        self.get_dlts_cipher().is_some()
+    }
+
+    /// Wait for the DTLS channel while the main loop runs, and pause the main loop to fall back to TLS if it is not up in time
+    fn watch_dtls(&self, timeout: std::time::Duration) {
+        const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+        let deadline = std::time::Instant::now() + timeout;
+
+        while std::time::Instant::now() < deadline {
+            if self.get_status() != Status::Connected || self.is_dtls_connected() {
+                return;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        if self.get_status() == Status::Connected && !self.is_dtls_connected() {
+            self.dtls_fallback.store(true, Ordering::SeqCst);
+            self.send_command(command::Command::Pause);
+        }
+    }
+
+    /// Leave the session without logging out, so that it can be resumed with the same cookie
+    ///
+    /// The gateway still ends the session after its idle timeout.
+    pub fn detach(&self) {
+        if self.get_status() != Status::Connected {
+            return;
+        }
+
+        self.emit_state_change(Status::Disconnecting);
+        self.send_command(command::Command::Detach);
+        self.cmd_fd.store(-1, Ordering::SeqCst);
+    }
+
+    /// Make new TLS and DTLS handshakes for the running session, blocking until it is up again. Returns the TLS cipher
+    ///
+    /// libopenconnect has no command to rekey a session, so the main loop is paused and resumes the session with the
+    /// same cookie, like on the fallback of [Connectable::run_loop] to TLS.
+    pub fn rekey(&self) -> OpenconnectResult<String> {
+        const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+        const REKEY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+        if self.get_status() != Status::Connected {
+            return Err(OpenconnectError::NotConnected);
+        }
+        self.rekey_requested.store(true, Ordering::SeqCst);
+        self.send_command(command::Command::Pause);
+
+        let deadline = std::time::Instant::now() + REKEY_TIMEOUT;
+        while std::time::Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            if self.get_status() != Status::Connected {
+                return Err(OpenconnectError::NotConnected);
+            }
+            // cleared once the paused session is closed, the cipher is known again after the new handshake
+            if !self.rekey_requested.load(Ordering::SeqCst) {
+                if let Some(cipher) = self.get_cstp_cipher() {
+                    return Ok(cipher);
+                }
+            }
+        }
+
+        Err(OpenconnectError::RekeyTimeout(REKEY_TIMEOUT.as_secs()))
+    }
+
+    /// Abort the connection in any phase
+    ///
+    /// Unlike [Connectable::disconnect], this also interrupts an authentication or CSTP connection in progress,
+    /// which then fails with [OpenconnectError::Cancelled]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        match self.get_status() {
+            Status::Connected => self.disconnect(),
+            // the command pipe may not be set up yet, the flag is checked once it is
+            Status::Connecting(_) => self.send_command(command::Command::Cancel),
+            _ => {}
+        }
+    }
+
+    /// Fail if [VpnClient::cancel] was called, checked between the blocking steps of a connect
+    fn check_cancelled(&self) -> OpenconnectResult<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            self.emit_state_change(Status::Disconnected);
+            return Err(OpenconnectError::Cancelled);
+        }
+        Ok(())
+    }
+
     pub fn disable_dtls(&self) -> OpenconnectResult<()> {
         let ret = unsafe { openconnect_disable_dtls(self.vpninfo) };
         match ret {
@@ -366,6 +1146,9 @@ impl VpnClient {
     pub fn get_hostname(&self) -> Option<String> {
         unsafe {
             let hostname = openconnect_get_hostname(self.vpninfo);
+            if hostname.is_null() {
+                return None;
+            }
             std::ffi::CStr::from_ptr(hostname)
                 .to_str()
                 .map(|s| s.to_string())
@@ -373,6 +1156,37 @@ impl VpnClient {
         }
     }
 
+    /// The vpnc-script which sets up the tunnel, the bundled one unless configured
+    pub fn get_vpnc_script(&self) -> String {
+        self.config
+            .vpncscript
+            .clone()
+            .unwrap_or(DEFAULT_VPNC_SCRIPT.to_string())
+    }
+
+    /// The extra variables of the vpnc-script environment, see [config::ConfigBuilder::script_env]
+    pub fn get_script_env(&self) -> &HashMap<String, String> {
+        &self.config.script_env
+    }
+
+    /// How a dropped connection is re-established by the caller, see [config::ConfigBuilder::reconnect_policy]
+    pub fn get_reconnect_policy(&self) -> config::ReconnectPolicy {
+        self.config.reconnect_policy
+    }
+
+    pub fn get_ifname(&self) -> Option<String> {
+        unsafe {
+            let ifname = openconnect_get_ifname(self.vpninfo);
+            if ifname.is_null() {
+                return None;
+            }
+            std::ffi::CStr::from_ptr(ifname)
+                .to_str()
+                .map(|s| s.to_string())
+                .ok()
+        }
+    }
+
     pub fn set_client_cert(&self, cert: &str, sslkey: &str) -> OpenconnectResult<()> {
         let cert =
             CString::new(cert).map_err(|_| OpenconnectError::SetClientCertError(libc::EIO))?;
@@ -386,6 +1200,43 @@ impl VpnClient {
         }
     }
 
+    pub fn set_key_password(&self, key_password: &str) -> OpenconnectResult<()> {
+        let key_password = CString::new(key_password)
+            .map_err(|_| OpenconnectError::SetClientCertError(libc::EIO))?;
+        let ret = unsafe { openconnect_set_key_password(self.vpninfo, key_password.as_ptr()) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(OpenconnectError::SetClientCertError(ret)),
+        }
+    }
+
+    /// The MTU of the tunnel device set by [config::EntrypointBuilder::mtu]
+    fn get_mtu_override(&self) -> Option<u32> {
+        self.entrypoint
+            .read()
+            .ok()
+            .and_then(|entrypoint| entrypoint.as_ref().and_then(|entrypoint| entrypoint.mtu))
+    }
+
+    /// The MTU to request from the gateway
+    pub fn set_reqmtu(&self, mtu: i32) {
+        unsafe { openconnect_set_reqmtu(self.vpninfo, mtu) };
+    }
+
+    pub fn set_pass_tos(&self, enable: bool) {
+        unsafe { openconnect_set_pass_tos(self.vpninfo, enable as i32) };
+    }
+
+    pub fn set_cafile(&self, cafile: &str) -> OpenconnectResult<()> {
+        let cafile =
+            CString::new(cafile).map_err(|_| OpenconnectError::SetCaFileError(libc::EIO))?;
+        let ret = unsafe { openconnect_set_cafile(self.vpninfo, cafile.as_ptr()) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(OpenconnectError::SetCaFileError(ret)),
+        }
+    }
+
     pub fn set_mca_cert(&self, cert: &str, key: &str) -> OpenconnectResult<()> {
         let cert = CString::new(cert).map_err(|_| OpenconnectError::SetMCACertError(libc::EIO))?;
         let key = CString::new(key).map_err(|_| OpenconnectError::SetMCACertError(libc::EIO))?;
@@ -398,24 +1249,65 @@ impl VpnClient {
 
     pub fn get_info(&self) -> OpenconnectResult<Option<IpInfo>> {
         unsafe {
-            let info = std::ptr::null_mut();
+            let mut info: *const oc_ip_info = std::ptr::null();
             let ret = openconnect_get_ip_info(
                 self.vpninfo,
-                info,
+                &mut info,
                 std::ptr::null_mut(),
                 std::ptr::null_mut(),
             );
 
             match ret {
-                0 => Ok(info
-                    .as_ref()
-                    .and_then(|info| info.as_ref())
-                    .map(IpInfo::from)),
+                0 => Ok(info.as_ref().map(|info| {
+                    let mut info = IpInfo::from(info);
+                    if let Some(search_domains) = &self.config.search_domains {
+                        info.search_domains = search_domains.clone();
+                    }
+                    if let Some(mtu) = self.get_mtu_override() {
+                        info.mtu = mtu as i32;
+                    }
+                    info
+                })),
                 _ => Err(OpenconnectError::GetIpInfoError(ret)),
             }
         }
     }
 
+    /// Authenticate and fetch the parameters pushed by the gateway, then disconnect
+    ///
+    /// The tun device is only set up once the main loop runs, so no interface, route or DNS setting is touched
+    pub fn fetch_info(&self, entrypoint: Entrypoint) -> OpenconnectResult<Option<IpInfo>> {
+        self.init_connection(entrypoint)?;
+        let info = self.get_info();
+        self.reset_ssl();
+        self.emit_state_change(Status::Disconnected);
+        info
+    }
+
+    /// Fetch the auth groups offered by the server without authenticating
+    ///
+    /// Returns an empty list if the server does not offer a group selection
+    pub fn probe_auth_groups(&self, entrypoint: Entrypoint) -> OpenconnectResult<Vec<AuthGroup>> {
+        if let Ok(mut form_manager) = self.form_manager.write() {
+            form_manager.start_probe();
+        }
+
+        // the authentication is cancelled on purpose as soon as the groups are known
+        let result = self.connect_for_cookie(entrypoint);
+
+        let groups = self
+            .form_manager
+            .write()
+            .ok()
+            .and_then(|mut form_manager| form_manager.take_probed_auth_groups());
+
+        match (groups, result) {
+            (Some(groups), _) => Ok(groups),
+            (None, Err(e)) => Err(e),
+            (None, Ok(_)) => Ok(Vec::new()),
+        }
+    }
+
     pub(crate) fn main_loop(
         &self,
         reconnect_timeout: i32,
@@ -468,6 +1360,24 @@ impl Connectable for VpnClient {
         let useragent = std::ffi::CString::new("AnyConnect-compatible OpenConnect VPN Agent")
             .map_err(|_| OpenconnectError::OtherError("useragent is not valid".to_string()))?;
 
+        let ca_file = CaFile::new(config.ca_cert.as_deref(), config.ca_path.as_deref())
+            .map_err(|e| OpenconnectError::InvalidCaCert(e.to_string()))?;
+
+        let openconnect_log = config
+            .openconnect_log
+            .as_ref()
+            .map(|path| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map(Mutex::new)
+                    .map_err(|e| {
+                        OpenconnectError::OpenconnectLogError(format!("{}: {}", path.display(), e))
+                    })
+            })
+            .transpose()?;
+
         let instance = Arc::new(Self {
             vpninfo: std::ptr::null_mut(),
             config,
@@ -477,6 +1387,17 @@ impl Connectable for VpnClient {
             entrypoint: RwLock::new(None),
             form_manager: RwLock::new(FormManager::default()),
             peer_certs: PeerCerts::default(),
+            stats: RwLock::new(None),
+            stats_reported: Notify::new(),
+            transient_failure: RwLock::new(None),
+            server_disconnect_reason: RwLock::new(None),
+            server_version: RwLock::new(None),
+            redirected_from: RwLock::new(None),
+            dtls_fallback: AtomicBool::new(false),
+            rekey_requested: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            ca_file,
+            openconnect_log,
         });
 
         unsafe {
@@ -507,15 +1428,33 @@ impl Connectable for VpnClient {
         };
 
         SIGNAL_HANDLE.update_client_singleton(Arc::downgrade(&instance));
-        instance.set_loglevel(instance.config.loglevel);
+        instance.set_loglevel(instance.progress_level());
         instance.set_setup_tun_handler();
+        if instance.config.transport_family.is_some() {
+            instance.set_transport_family_handler();
+        }
 
         if let Some(proxy) = &instance.config.http_proxy {
+            tracing::debug!("Using HTTP proxy {}", proxy::display_proxy(proxy));
             instance
                 .set_http_proxy(proxy.as_str())
                 .emit_error(&instance)?;
         }
 
+        if let Some(ca_file) = &instance.ca_file {
+            instance
+                .set_cafile(&ca_file.path().to_string_lossy())
+                .emit_error(&instance)?;
+        }
+
+        if let Some(dtls_mtu_max) = instance.config.dtls_mtu_max {
+            instance.set_reqmtu(dtls_mtu_max.into());
+        }
+
+        if instance.config.pass_tos {
+            instance.set_pass_tos(true);
+        }
+
         instance.emit_state_change(Status::Initialized);
 
         Ok(instance)
@@ -529,28 +1468,47 @@ impl Connectable for VpnClient {
     ///
     /// entrypoint can be created using [config::EntrypointBuilder]
     fn connect_for_cookie(&self, entrypoint: Entrypoint) -> OpenconnectResult<Option<String>> {
+        let _span = log::connection_span(entrypoint.name.as_deref()).entered();
         self.emit_state_change(Status::Connecting("Initializing connection".to_string()));
         {
             if let Ok(mut form_context) = self.form_manager.try_write() {
                 form_context.reset();
             }
+            if let Ok(mut transient_failure) = self.transient_failure.write() {
+                *transient_failure = None;
+            }
         }
         self.set_protocol(&entrypoint.protocol.name)
             .emit_error(self)?;
         self.emit_state_change(Status::Connecting("Setting up system pipe".to_string()));
         self.setup_cmd_pipe().emit_error(self)?;
+        self.check_cancelled()?;
         self.set_stats_handler();
 
-        #[cfg(target_os = "windows")]
-        const OS_NAME: &str = "win";
-
-        #[cfg(target_os = "macos")]
-        const OS_NAME: &str = "mac-intel";
+        self.set_report_os(
+            entrypoint
+                .reported_os
+                .as_deref()
+                .unwrap_or(DEFAULT_REPORTED_OS),
+        )
+        .emit_error(self)?;
 
-        #[cfg(target_os = "linux")]
-        const OS_NAME: &str = "linux-64";
+        if let Some(user_agent) = entrypoint.user_agent() {
+            self.set_user_agent(user_agent).emit_error(self)?;
+        }
 
-        self.set_report_os(OS_NAME).emit_error(self)?;
+        // presented on the auth requests and the CSTP connection alike
+        if let Some(client_cert) = &entrypoint.client_cert {
+            let client_key = entrypoint.client_key.as_ref().unwrap_or(client_cert);
+            self.set_client_cert(
+                &client_cert.to_string_lossy(),
+                &client_key.to_string_lossy(),
+            )
+            .emit_error(self)?;
+            if let Some(key_password) = &entrypoint.key_password {
+                self.set_key_password(key_password).emit_error(self)?;
+            }
+        }
 
         {
             let mut entrypoint_write_guard = self
@@ -571,18 +1529,63 @@ impl Connectable for VpnClient {
             self.disable_dtls().emit_error(self)?;
         }
 
+        // takes precedence over the MTU requested by [Config::dtls_mtu_max]
+        if let Some(mtu) = entrypoint.mtu {
+            self.set_reqmtu(mtu as i32);
+        }
+
+        if entrypoint.web_auth {
+            self.set_external_browser_handler();
+        }
+
         self.emit_state_change(Status::Connecting("Parsing URL".to_string()));
         self.parse_url(&entrypoint.server).emit_error(self)?;
         let hostname = self.get_hostname();
 
         self.emit_state_change(Status::Connecting(format!(
             "Obtaining cookie from: {}",
-            hostname.unwrap_or("".to_string())
+            hostname.as_deref().unwrap_or_default()
         )));
         if let Some(cookie) = entrypoint.cookie.clone() {
             self.set_cookie(&cookie).emit_error(self)?;
         } else {
-            self.obtain_cookie().emit_error(self)?;
+            let obtained = self
+                .with_verbose_progress(|| self.obtain_cookie())
+                .map_err(|err| self.peer_certs.explain(err));
+            self.check_cancelled()?;
+            let session_conflict = self
+                .form_manager
+                .read()
+                .is_ok_and(|form_manager| form_manager.has_session_conflict());
+            if obtained.is_err() && session_conflict {
+                let err = OpenconnectError::SessionAlreadyActive;
+                self.emit_error(&err);
+                return Err(err);
+            }
+            let key_password_rejected = self
+                .form_manager
+                .read()
+                .is_ok_and(|form_manager| form_manager.has_key_password_rejected());
+            if obtained.is_err() && key_password_rejected {
+                let err = match entrypoint.key_password {
+                    Some(_) => OpenconnectError::WrongKeyPassword,
+                    None => OpenconnectError::KeyPasswordRequired,
+                };
+                self.emit_error(&err);
+                return Err(err);
+            }
+            let transient_failure = self
+                .transient_failure
+                .read()
+                .ok()
+                .and_then(|transient_failure| transient_failure.clone());
+            if let (Err(_), Some(failure)) = (&obtained, transient_failure) {
+                let err = OpenconnectError::TransientLoginError(failure);
+                self.emit_error(&err);
+                return Err(err);
+            }
+            obtained.emit_error(self)?;
+            self.check_redirect(hostname.as_deref());
         }
 
         Ok(self.get_cookie())
@@ -594,27 +1597,107 @@ impl Connectable for VpnClient {
     fn init_connection(&self, entrypoint: Entrypoint) -> OpenconnectResult<()> {
         self.emit_state_change(Status::Connecting("Make CSTP connection".to_string()));
         self.connect_for_cookie(entrypoint)?;
-        self.make_cstp_connection().emit_error(self)?;
+        let _span = log::connection_span(self.get_server_name().as_deref()).entered();
+        self.check_cancelled()?;
+        let cstp = self
+            .with_verbose_progress(|| self.make_cstp_connection())
+            .map_err(|err| self.peer_certs.explain(err));
+        // a cancel command makes libopenconnect fail, report it as cancelled
+        if let Err(err) = self.check_cancelled() {
+            if cstp.is_ok() {
+                self.reset_ssl();
+            }
+            return Err(err);
+        }
+        cstp.emit_error(self)?;
+        if let Err(err) = self
+            .check_tls_policy(true)
+            .and_then(|_| self.check_assigned_address())
+            .and_then(|_| self.check_mtu())
+        {
+            self.reset_ssl();
+            self.emit_error(&err);
+            return Err(err);
+        }
+        if let Some(version) = self.get_tls_version() {
+            tracing::info!("Negotiated {} with the gateway", version);
+        }
+        self.check_server_cert_expiry();
+        self.check_ipv6();
         self.emit_state_change(Status::Connected);
 
         Ok(())
     }
 
     /// Run main loop and block until the connection is closed
+    ///
+    /// Returns the error the main loop stopped with, unless it was stopped by [Connectable::disconnect].
+    /// See [OpenconnectError::is_auth_expired] for a session which needs a new login.
+    ///
+    /// If the DTLS channel is not established within [config::Entrypoint::dtls_fallback_timeout], the main loop is paused,
+    /// UDP is disabled and the session resumes over TLS only
     fn run_loop(&self) -> OpenconnectResult<()> {
-        loop {
-            if let Err(err) = self.main_loop(300, RECONNECT_INTERVAL_MIN) {
-                tracing::event!(tracing::Level::ERROR, "Main loop failed: {}", err);
-                break;
-            }
+        let _span = log::connection_span(self.get_server_name().as_deref()).entered();
+        let dtls_fallback_timeout = self.entrypoint.read().ok().and_then(|entrypoint| {
+            entrypoint
+                .as_ref()
+                .filter(|entrypoint| entrypoint.enable_udp)
+                .and_then(|entrypoint| entrypoint.dtls_fallback_timeout)
+        });
+
+        if let Ok(mut server_disconnect_reason) = self.server_disconnect_reason.write() {
+            *server_disconnect_reason = None;
         }
 
-        // TODO: check if the following should be invoke?
-        // self.reset_ssl();
-        // self.clear_cookie();
-        self.emit_state_change(Status::Disconnected);
+        std::thread::scope(|scope| {
+            if let Some(timeout) = dtls_fallback_timeout {
+                scope.spawn(move || self.watch_dtls(timeout));
+            }
 
-        Ok(())
+            let mut result = Ok(());
+            loop {
+                match self.main_loop(300, RECONNECT_INTERVAL_MIN) {
+                    // the session of a paused main loop is resumed with the same cookie
+                    Ok(()) => match take_pause(&self.dtls_fallback, &self.rekey_requested) {
+                        Some(Pause::DtlsFallback) => {
+                            tracing::warn!(
+                                "DTLS was not established within {}s, falling back to TLS",
+                                dtls_fallback_timeout.unwrap_or_default().as_secs()
+                            );
+                            if let Err(err) = self.disable_dtls() {
+                                tracing::warn!("Failed to disable DTLS: {}", err);
+                            }
+                        }
+                        Some(Pause::Rekey) => tracing::info!("Rekeying the session"),
+                        None => break,
+                    },
+                    Err(err) => {
+                        // a requested disconnect stops the main loop with an error as well
+                        if self.get_status() != Status::Disconnecting {
+                            let err = match self.get_server_disconnect_reason() {
+                                Some(reason) => OpenconnectError::DisconnectedByServer(reason),
+                                None => err,
+                            };
+                            tracing::event!(tracing::Level::ERROR, "Main loop failed: {}", err);
+                            result = Err(err);
+                        }
+                        break;
+                    }
+                }
+            }
+
+            // TODO: check if the following should be invoke?
+            // self.reset_ssl();
+            // self.clear_cookie();
+            match result {
+                Err(ref err @ OpenconnectError::DisconnectedByServer(_)) => self.emit_error(err),
+                _ => self.emit_state_change(Status::Disconnected),
+            }
+            if let Some(ref handler) = self.callbacks.handle_disconnected {
+                handler(self.get_server_disconnect_reason().as_deref());
+            }
+            result
+        })
     }
 
     /// Gracefully stop the main loop
@@ -668,3 +1751,63 @@ impl Events for VpnClient {
         self.emit_state_change(Status::Error(error.clone()));
     }
 }
+
+#[test]
+fn test_parse_server_version() {
+    assert_eq!(
+        parse_server_version(
+            "< <config-auth client=\"vpn\" type=\"auth-request\">\n< <version who=\"sg\">9.12(4)</version>"
+        ),
+        Some("9.12(4)".to_string())
+    );
+    assert_eq!(
+        parse_server_version("Server: Pulse Secure \n"),
+        Some("Pulse Secure".to_string())
+    );
+    assert_eq!(parse_server_version("Server: \n"), None);
+    assert_eq!(parse_server_version("Connected to 10.0.0.1:443"), None);
+}
+
+#[test]
+fn test_take_pause() {
+    let (dtls_fallback, rekey_requested) = (AtomicBool::new(false), AtomicBool::new(false));
+    assert_eq!(take_pause(&dtls_fallback, &rekey_requested), None);
+
+    // set by watch_dtls right before it pauses the main loop
+    dtls_fallback.store(true, Ordering::SeqCst);
+    assert_eq!(
+        take_pause(&dtls_fallback, &rekey_requested),
+        Some(Pause::DtlsFallback)
+    );
+    assert!(!dtls_fallback.load(Ordering::SeqCst));
+
+    // the next return of the main loop closes the connection
+    assert_eq!(take_pause(&dtls_fallback, &rekey_requested), None);
+}
+
+#[test]
+fn test_take_pause_for_rekey() {
+    let (dtls_fallback, rekey_requested) = (AtomicBool::new(false), AtomicBool::new(false));
+
+    // VpnClient::rekey only succeeds once the flag it set before pausing is cleared
+    rekey_requested.store(true, Ordering::SeqCst);
+    assert_eq!(
+        take_pause(&dtls_fallback, &rekey_requested),
+        Some(Pause::Rekey)
+    );
+    assert!(!rekey_requested.load(Ordering::SeqCst));
+    assert_eq!(take_pause(&dtls_fallback, &rekey_requested), None);
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn test_with_script_env() {
+    let script_env = HashMap::from([
+        ("SITE".to_string(), "it's lab".to_string()),
+        ("ENV".to_string(), "staging".to_string()),
+    ]);
+    assert_eq!(
+        with_script_env("/etc/vpnc-script", &script_env),
+        "OPENCONNECT_RS_ENV='staging' OPENCONNECT_RS_SITE='it'\\''s lab' /etc/vpnc-script"
+    );
+}
@@ -1,4 +1,10 @@
+use crate::{config::LogLevel, paths, VpnClient};
+use chrono::{DateTime, Utc};
 use openconnect_sys::{PRG_DEBUG, PRG_ERR, PRG_INFO, PRG_TRACE};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use tracing::{
     event,
     subscriber::{set_global_default, SetGlobalDefaultError},
@@ -6,20 +12,173 @@ use tracing::{
 };
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
+/// Session log files beyond this count are removed, oldest first
+const MAX_SESSION_LOGS: usize = 20;
+
 pub struct Logger;
 
-impl Logger {
-    pub fn get_log_path() -> &'static str {
-        #[cfg(target_os = "linux")]
-        const LOG_PATH: &str = "/var/log/openconnect-rs";
+/// A log line in a uniform shape, independent of the text layout of the log files
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LogRecord {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: Option<String>,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
 
-        #[cfg(target_os = "macos")]
-        const LOG_PATH: &str = "/Library/Logs/openconnect-rs";
+const LEVELS: [&str; 5] = ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
 
-        #[cfg(target_os = "windows")]
-        const LOG_PATH: &str = "C:\\ProgramData\\openconnect-rs";
+/// The markup of a field name in the compact format, `\x1b[3m<name>\x1b[0m\x1b[2m=\x1b[0m<value>`
+const ANSI_FIELD_START: &str = "\x1b[3m";
 
-        LOG_PATH
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip the CSI sequence up to its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// A line of the `json` format of tracing-subscriber
+fn parse_json_line(line: &str) -> Option<LogRecord> {
+    let serde_json::Value::Object(mut record) = serde_json::from_str(line).ok()? else {
+        return None;
+    };
+    let as_string = |value: serde_json::Value| match value {
+        serde_json::Value::String(value) => value,
+        value => value.to_string(),
+    };
+
+    let timestamp = parse_timestamp(record.get("timestamp")?.as_str()?)?;
+    let level = record.get("level")?.as_str()?.to_uppercase();
+    let target = record.remove("target").map(as_string);
+    let mut fields: BTreeMap<String, String> = match record.remove("fields") {
+        Some(serde_json::Value::Object(fields)) => fields
+            .into_iter()
+            .map(|(name, value)| (name, as_string(value)))
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    let message = fields.remove("message").unwrap_or_default();
+
+    Some(LogRecord {
+        timestamp,
+        level,
+        target,
+        message,
+        fields,
+    })
+}
+
+/// Name of the span the logs of a connection are recorded in, with the stored server name as `server` field
+pub const CONNECTION_SPAN: &str = "connection";
+
+/// The span attributing the logs of a connection to the server name the user gave it
+pub fn connection_span(server_name: Option<&str>) -> tracing::Span {
+    tracing::info_span!(CONNECTION_SPAN, server = %server_name.unwrap_or_default())
+}
+
+/// A line of the `compact` text format the log files are written with, `<timestamp> <level> <target>: <message>`
+fn parse_compact_line(line: &str) -> Option<LogRecord> {
+    // without the markup the fields can not be told apart from the message, so plain lines have none
+    let (head, fields) = match line.split_once(ANSI_FIELD_START) {
+        Some((head, fields)) => (head, Some(fields)),
+        None => (line, None),
+    };
+    let fields = fields
+        .into_iter()
+        .flat_map(|fields| fields.split(ANSI_FIELD_START))
+        .filter_map(|field| {
+            let field = strip_ansi(field);
+            let (name, value) = field.trim().split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+
+    let head = strip_ansi(head);
+    let (timestamp, rest) = head.trim_start().split_once(' ')?;
+    let timestamp = parse_timestamp(timestamp)?;
+    let (level, rest) = rest
+        .trim_start()
+        .split_once(' ')
+        .unwrap_or((rest.trim(), ""));
+    if !LEVELS.contains(&level) {
+        return None;
+    }
+
+    // the span name precedes the target, its fields are listed with the fields of the event
+    let rest = rest.trim();
+    let rest = rest
+        .strip_prefix(CONNECTION_SPAN)
+        .and_then(|rest| rest.strip_prefix(": "))
+        .unwrap_or(rest);
+    let (target, message) = match rest.split_once(": ") {
+        Some((target, message)) if !target.contains(char::is_whitespace) => {
+            (Some(target.to_string()), message)
+        }
+        _ => (None, rest),
+    };
+
+    Some(LogRecord {
+        timestamp,
+        level: level.to_string(),
+        target,
+        message: message.to_string(),
+        fields,
+    })
+}
+
+/// Server names are user defined, keep them safe for a file name
+fn session_prefix(server_name: &str) -> String {
+    let name: String = server_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-", name)
+}
+
+const SESSION_SUFFIX_LEN: usize = "20240101-120000.log".len();
+
+/// Matches the `<timestamp>.log` suffix, so that `corp` does not match the logs of `corp-eu`
+fn is_session_timestamp(suffix: &str) -> bool {
+    suffix.len() == SESSION_SUFFIX_LEN
+        && suffix.ends_with(".log")
+        && suffix
+            .bytes()
+            .take(15)
+            .all(|c| c.is_ascii_digit() || c == b'-')
+}
+
+fn is_session_log(file_name: &str) -> bool {
+    file_name.ends_with(".log") && !file_name.starts_with("openconnect-rs.log")
+}
+
+impl Logger {
+    pub fn get_log_path() -> PathBuf {
+        paths::log_dir()
     }
 
     pub fn init() -> Result<(), SetGlobalDefaultError> {
@@ -30,6 +189,77 @@ impl Logger {
             .build(Self::get_log_path())
             .expect("failed to create file appender");
 
+        Self::init_with_appender(file_appender)
+    }
+
+    /// Log a single connection attempt to its own `<server>-<timestamp>.log` file
+    pub fn init_for_session(server_name: &str) -> Result<(), SetGlobalDefaultError> {
+        let file_name = format!(
+            "{}{}.log",
+            session_prefix(server_name),
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let file_appender = tracing_appender::rolling::never(Self::get_log_path(), file_name);
+
+        let mut session_logs = Self::list_logs(None);
+        session_logs.retain(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_session_log)
+        });
+        for path in session_logs.iter().skip(MAX_SESSION_LOGS) {
+            let _ = std::fs::remove_file(path);
+        }
+
+        Self::init_with_appender(file_appender)
+    }
+
+    /// Log files in the log directory, newest first, optionally only the session logs of a server
+    pub fn list_logs(server_name: Option<&str>) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(Self::get_log_path()) else {
+            return vec![];
+        };
+
+        let prefix = server_name.map(session_prefix);
+        let mut logs: Vec<_> = entries
+            .flatten()
+            .filter(|entry| entry.metadata().is_ok_and(|m| m.is_file()))
+            .filter(|entry| {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                match &prefix {
+                    Some(prefix) => file_name
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(is_session_timestamp),
+                    None => true,
+                }
+            })
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+
+        logs.sort_by(|(a, _), (b, _)| b.cmp(a));
+        logs.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// The session id of a session log, the `<timestamp>` of `<server>-<timestamp>.log`
+    pub fn session_id(path: &Path) -> Option<&str> {
+        let file_name = path.file_name()?.to_str()?;
+        let suffix = file_name.get(file_name.len().checked_sub(SESSION_SUFFIX_LEN)?..)?;
+        is_session_timestamp(suffix).then(|| suffix.trim_end_matches(".log"))
+    }
+
+    /// The session log of a server, `latest` or a session id like `20240101-120000`
+    pub fn find_session(server_name: &str, session: &str) -> Option<PathBuf> {
+        let logs = Self::list_logs(Some(server_name));
+        match session {
+            "latest" => logs.into_iter().next(),
+            session => logs
+                .into_iter()
+                .find(|path| Self::session_id(path) == Some(session)),
+        }
+    }
+
+    fn init_with_appender(file_appender: RollingFileAppender) -> Result<(), SetGlobalDefaultError> {
         // for file based logging, waiting https://github.com/tokio-rs/tracing/pull/2497 to be merged
         let subscriber = tracing_subscriber::fmt()
             .compact()
@@ -43,7 +273,7 @@ impl Logger {
     }
 
     pub(crate) unsafe extern "C" fn raw_handle_process_log(
-        _privdata: *mut ::std::os::raw::c_void,
+        privdata: *mut ::std::os::raw::c_void,
         level: ::std::os::raw::c_int,
         buf: *const ::std::os::raw::c_char,
     ) {
@@ -56,8 +286,29 @@ impl Logger {
             PRG_TRACE => Level::TRACE,
             _ => unreachable!("unknown log level: {}", level),
         };
-        if buf.is_some() {
-            Logger::log(level, buf.unwrap_or(""));
+        if let Some(buf) = buf {
+            // privdata is the client instance registered in openconnect_vpninfo_new
+            let client = (!privdata.is_null()).then(|| VpnClient::ref_from_raw(privdata));
+
+            // the client may raise the verbosity beyond its log level while connecting
+            if !matches!(client, Some(client) if !client.logs_level(LogLevel::from(level))) {
+                Logger::log(level, buf);
+            }
+            if let Some(client) = client {
+                client.handle_progress(LogLevel::from(level), buf);
+            }
+        }
+    }
+
+    /// Parse a line of a log file, in the text format the logs are written with or the JSON format of tracing-subscriber
+    ///
+    /// Returns `None` for lines which do not start a record, such as the continuation of a multi-line message
+    pub fn parse_line(line: &str) -> Option<LogRecord> {
+        let line = line.trim_end();
+        if line.starts_with('{') {
+            parse_json_line(line)
+        } else {
+            parse_compact_line(line)
         }
     }
 
@@ -71,3 +322,60 @@ impl Logger {
         }
     }
 }
+
+#[test]
+fn test_session_prefix() {
+    assert_eq!(session_prefix("corp vpn/eu"), "corp_vpn_eu-");
+    assert!(is_session_log("corp-20240101-120000.log"));
+    assert!(!is_session_log("openconnect-rs.log.2024-01-01"));
+    assert!(is_session_timestamp("20240101-120000.log"));
+    assert!(!is_session_timestamp("eu-20240101-120000.log"));
+    assert_eq!(
+        Logger::session_id(Path::new("/logs/corp-20240101-120000.log")),
+        Some("20240101-120000")
+    );
+    assert_eq!(
+        Logger::session_id(Path::new("/logs/openconnect-rs.log.2024-01-01")),
+        None
+    );
+}
+
+#[test]
+fn test_parse_line() {
+    let record = Logger::parse_line(
+        "2024-01-01T12:00:00.123456Z  INFO openconnect_cli::server: Received start command, name: corp",
+    )
+    .unwrap();
+    assert_eq!(record.level, "INFO");
+    assert_eq!(record.target.as_deref(), Some("openconnect_cli::server"));
+    assert_eq!(record.message, "Received start command, name: corp");
+    assert!(record.fields.is_empty());
+
+    let record = Logger::parse_line(
+        "\x1b[2m2024-01-01T12:00:00.123456Z\x1b[0m \x1b[33m WARN\x1b[0m \x1b[2mopenconnect_core\x1b[0m\x1b[2m:\x1b[0m Reconnecting \x1b[3mattempt\x1b[0m\x1b[2m=\x1b[0m3",
+    )
+    .unwrap();
+    assert_eq!(record.level, "WARN");
+    assert_eq!(record.message, "Reconnecting");
+    assert_eq!(record.fields.get("attempt").map(String::as_str), Some("3"));
+
+    let record = Logger::parse_line(
+        r#"{"timestamp":"2024-01-01T12:00:00.123456Z","level":"ERROR","fields":{"message":"Failed","code":-1},"target":"openconnect_core"}"#,
+    )
+    .unwrap();
+    assert_eq!(record.message, "Failed");
+    assert_eq!(record.fields.get("code").map(String::as_str), Some("-1"));
+
+    let record = Logger::parse_line(
+        "\x1b[2m2024-01-01T12:00:00.123456Z\x1b[0m \x1b[32m INFO\x1b[0m \x1b[1mconnection\x1b[0m\x1b[2m:\x1b[0m \x1b[2mopenconnect_core\x1b[0m\x1b[2m:\x1b[0m Connected \x1b[2m\x1b[3mserver\x1b[0m\x1b[2m=\x1b[0mcorp\x1b[0m",
+    )
+    .unwrap();
+    assert_eq!(record.target.as_deref(), Some("openconnect_core"));
+    assert_eq!(record.message, "Connected");
+    assert_eq!(
+        record.fields.get("server").map(String::as_str),
+        Some("corp")
+    );
+
+    assert_eq!(Logger::parse_line("  continued message"), None);
+}
@@ -0,0 +1,218 @@
+//! Centralized path resolution
+//!
+//! Every component (config file, logs, daemon socket, PID file and vpnc-script) resolves its location through this module,
+//! so that a single override relocates all of them consistently.
+//!
+//! The root directory is resolved in the following order:
+//! 1. [set_config_dir], e.g. from the `--config-dir` CLI flag
+//! 2. The `OPENCONNECT_RS_HOME` environment variable
+//! 3. `~/.oidcvpn`
+//!
+//! When neither override is set, logs and the socket keep their system-wide default locations. The socket can be
+//! placed on its own, see [sock_file].
+
+use lazy_static::lazy_static;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::RwLock,
+};
+
+/// Environment variable that overrides the root directory
+pub const HOME_ENV: &str = "OPENCONNECT_RS_HOME";
+
+/// Environment variable that overrides the path of the daemon socket
+pub const SOCK_ENV: &str = "OPENCONNECT_SOCK";
+
+/// The per-user runtime directory, which holds the socket unless overridden
+pub const XDG_RUNTIME_DIR_ENV: &str = "XDG_RUNTIME_DIR";
+
+const DEFAULT_DIR_NAME: &str = ".oidcvpn";
+
+const SOCK_FILE_NAME: &str = "openconnect-rs.sock";
+
+lazy_static! {
+    static ref CONFIG_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Override the root directory for the current process, takes precedence over `OPENCONNECT_RS_HOME`
+pub fn set_config_dir(dir: PathBuf) {
+    if let Ok(mut config_dir) = CONFIG_DIR_OVERRIDE.write() {
+        *config_dir = Some(dir);
+    }
+}
+
+/// The explicitly overridden root directory, if any
+pub fn overridden_dir() -> Option<PathBuf> {
+    CONFIG_DIR_OVERRIDE
+        .read()
+        .ok()
+        .and_then(|dir| dir.clone())
+        .or_else(|| {
+            std::env::var_os(HOME_ENV)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        })
+}
+
+/// The root directory holding the config file and helper binaries
+pub fn config_dir() -> std::io::Result<PathBuf> {
+    if let Some(dir) = overridden_dir() {
+        return Ok(dir);
+    }
+
+    let home_dir = home::home_dir().ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Home directory not found",
+    ))?;
+
+    Ok(home_dir.join(DEFAULT_DIR_NAME))
+}
+
+pub fn config_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("config.json"))
+}
+
+/// Summary of the last session, kept after the daemon exits
+pub fn last_session_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("last-session.json"))
+}
+
+/// The tunnels set up by the daemon, so that the ones of a crashed daemon can be torn down
+pub fn tunnels_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("tunnels.json"))
+}
+
+/// The seed of the key encrypting the stored passwords, imported from another machine, see [crate::cipher_key]
+pub fn cipher_key_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("cipher-key"))
+}
+
+/// Lifetime traffic and connection counters of each server, kept across sessions
+pub fn server_stats_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("server-stats.json"))
+}
+
+/// The last status of each server the GUI connected to, shown before the live status arrives
+pub fn last_status_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("last-status.json"))
+}
+
+/// The preferences of the GUI, which are not part of the server config
+pub fn gui_preferences_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("gui-preferences.json"))
+}
+
+pub fn vpnc_script() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join("bin").join("vpnc-script"))
+}
+
+pub fn log_dir() -> PathBuf {
+    if let Some(dir) = overridden_dir() {
+        return dir.join("logs");
+    }
+
+    #[cfg(target_os = "linux")]
+    const LOG_PATH: &str = "/var/log/openconnect-rs";
+
+    #[cfg(target_os = "macos")]
+    const LOG_PATH: &str = "/Library/Logs/openconnect-rs";
+
+    #[cfg(target_os = "windows")]
+    const LOG_PATH: &str = "C:\\ProgramData\\openconnect-rs";
+
+    PathBuf::from(LOG_PATH)
+}
+
+/// The file in [log_dir] the progress messages of libopenconnect are appended to, see `--openconnect-log`
+///
+/// Only a file name is accepted, the daemon writes the file as root and must not append to any file a client names.
+pub fn openconnect_log_file(name: &Path) -> std::io::Result<PathBuf> {
+    let mut components = name.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(log_dir().join(name)),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a file name", name.display()),
+        )),
+    }
+}
+
+/// Directory for runtime files of the daemon, such as the socket and PID file
+pub fn runtime_dir() -> PathBuf {
+    if let Some(dir) = overridden_dir() {
+        return dir;
+    }
+
+    // not using std::env::temp_dir, it is per-user on macOS and differs before and after escalation
+    #[cfg(not(target_os = "windows"))]
+    return PathBuf::from("/tmp");
+
+    #[cfg(target_os = "windows")]
+    return std::env::temp_dir();
+}
+
+/// The socket of the daemon
+///
+/// Resolved in the following order: `OPENCONNECT_SOCK`, the overridden root directory, `$XDG_RUNTIME_DIR` and
+/// [runtime_dir]. The runtime directory of the user keeps the daemons of different users on a shared host apart.
+pub fn sock_file() -> PathBuf {
+    let var = |key| {
+        std::env::var_os(key)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(sock) = var(SOCK_ENV) {
+        return sock;
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    if let (None, Some(dir)) = (overridden_dir(), var(XDG_RUNTIME_DIR_ENV)) {
+        return dir.join(SOCK_FILE_NAME);
+    }
+
+    runtime_dir().join(SOCK_FILE_NAME)
+}
+
+pub fn pid_file() -> PathBuf {
+    runtime_dir().join("openconnect-rs.pid")
+}
+
+/// Check that a file the daemon reads or runs as root can only be changed by root or the user who escalated
+///
+/// Otherwise another user of the machine could have the daemon run commands of their choice as root.
+#[cfg(unix)]
+pub fn check_owner(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    let sudo_uid = std::env::var("SUDO_UID")
+        .ok()
+        .and_then(|uid| uid.parse::<u32>().ok());
+    let owner = metadata.uid();
+    if owner != 0 && owner != unsafe { libc::geteuid() } && Some(owner) != sudo_uid {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is owned by another user ({})", path.display(), owner),
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is writable by group or others", path.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_openconnect_log_file() {
+    assert_eq!(
+        openconnect_log_file(Path::new("oc.log")).unwrap(),
+        log_dir().join("oc.log")
+    );
+    assert!(openconnect_log_file(Path::new("/etc/passwd")).is_err());
+    assert!(openconnect_log_file(Path::new("../oc.log")).is_err());
+    assert!(openconnect_log_file(Path::new("logs/oc.log")).is_err());
+    assert!(openconnect_log_file(Path::new("")).is_err());
+}
@@ -0,0 +1,74 @@
+//! The pre-flight command of the [crate::storage::Defaults], a posture check such as disk encryption or a running
+//! antivirus which has to pass before any tunnel is established
+//!
+//! The command line is run through the shell with `OPENCONNECT_RS_SERVER_NAME` and `OPENCONNECT_RS_SERVER_URL` set,
+//! like a profile script. A non-zero exit aborts the connection, with the stderr of the command as reason.
+
+use crate::profile_script::{SERVER_NAME_ENV, SERVER_URL_ENV};
+use std::process::{Command, ExitStatus};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PreflightError {
+    #[error("Failed to run pre-flight command `{0}`: {1}")]
+    SpawnError(String, #[source] std::io::Error),
+
+    #[error("Pre-flight command `{0}` failed with {1}: {2}")]
+    Failed(String, ExitStatus, String),
+}
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    }
+
+    #[cfg(not(windows))]
+    {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Run the pre-flight command before connecting to a server, blocking until it exits
+pub fn run(command: &str, server_name: &str, server_url: &str) -> Result<(), PreflightError> {
+    let output = shell(command)
+        .env(SERVER_NAME_ENV, server_name)
+        .env(SERVER_URL_ENV, server_url)
+        .output()
+        .map_err(|e| PreflightError::SpawnError(command.to_string(), e))?;
+
+    if !output.status.success() {
+        return Err(PreflightError::Failed(
+            command.to_string(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preflight() {
+    assert!(run(
+        "test \"$OPENCONNECT_RS_SERVER_NAME\" = corp",
+        "corp",
+        "vpn.example.com"
+    )
+    .is_ok());
+    match run(
+        "echo 'disk not encrypted' >&2; exit 3",
+        "corp",
+        "vpn.example.com",
+    ) {
+        Err(PreflightError::Failed(_, status, stderr)) => {
+            assert_eq!(status.code(), Some(3));
+            assert_eq!(stderr, "disk not encrypted");
+        }
+        other => panic!("expected a failed pre-flight command, got {:?}", other),
+    }
+}
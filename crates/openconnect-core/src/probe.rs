@@ -0,0 +1,134 @@
+use crate::{config::ServerAddress, VpnClient};
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+const UDP_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of probing the DTLS (UDP) port of a gateway
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UdpProbeResult {
+    /// The gateway answered on the UDP port
+    Reachable,
+
+    /// An ICMP port unreachable was received, UDP is blocked or the gateway does not serve DTLS on this port
+    Closed,
+
+    /// Nothing came back in time. UDP may be filtered, or the gateway silently ignores unsolicited handshakes,
+    /// so this is inconclusive
+    NoResponse,
+
+    /// The probe could not be sent, e.g. the hostname could not be resolved
+    Error(String),
+}
+
+impl std::fmt::Display for UdpProbeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UdpProbeResult::Reachable => write!(f, "reachable"),
+            UdpProbeResult::Closed => write!(f, "closed (port unreachable)"),
+            UdpProbeResult::NoResponse => write!(f, "no response (possibly filtered)"),
+            UdpProbeResult::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// A minimal DTLS 1.2 ClientHello, so that gateways answering unsolicited handshakes reply with a HelloVerifyRequest
+fn dtls_client_hello() -> Vec<u8> {
+    let mut body = vec![0xfe, 0xfd]; // client_version: DTLS 1.2
+    body.extend((0..32).map(|_| rand::random::<u8>())); // random
+    body.push(0); // session_id length
+    body.push(0); // cookie length
+    body.extend([0x00, 0x02, 0xc0, 0x2f]); // cipher suites: TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256
+    body.extend([0x01, 0x00]); // compression methods: null
+
+    let body_len = (body.len() as u32).to_be_bytes();
+    let mut handshake = vec![0x01]; // msg_type: client_hello
+    handshake.extend(&body_len[1..]); // length
+    handshake.extend([0x00, 0x00]); // message_seq
+    handshake.extend([0x00, 0x00, 0x00]); // fragment_offset
+    handshake.extend(&body_len[1..]); // fragment_length
+    handshake.extend(body);
+
+    let mut record = vec![0x16, 0xfe, 0xfd]; // content type: handshake, version: DTLS 1.2
+    record.extend([0x00, 0x00]); // epoch
+    record.extend([0x00; 6]); // sequence number
+    record.extend((handshake.len() as u16).to_be_bytes());
+    record.extend(handshake);
+    record
+}
+
+fn probe_addr(addr: SocketAddr, timeout: Duration) -> std::io::Result<UdpProbeResult> {
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(addr)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(&dtls_client_hello())?;
+
+    let mut buf = [0u8; 1500];
+    match socket.recv(&mut buf) {
+        Ok(_) => Ok(UdpProbeResult::Reachable),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => Ok(UdpProbeResult::Closed),
+        Err(e)
+            if e.kind() == std::io::ErrorKind::WouldBlock
+                || e.kind() == std::io::ErrorKind::TimedOut =>
+        {
+            Ok(UdpProbeResult::NoResponse)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+impl VpnClient {
+    /// Check whether the DTLS (UDP) port of the gateway is reachable before connecting
+    ///
+    /// This does not need a client instance, so that it can be used to decide whether to disable UDP for the connection.
+    /// Every resolved address is tried, and the most positive result is returned.
+    pub fn probe_udp(server: &str, port: u16) -> UdpProbeResult {
+        let addrs = match (server, port).to_socket_addrs() {
+            Ok(addrs) => addrs.collect::<Vec<_>>(),
+            Err(e) => return UdpProbeResult::Error(e.to_string()),
+        };
+
+        let mut result = UdpProbeResult::Error(format!("No address found for {}", server));
+        for addr in addrs {
+            match probe_addr(addr, UDP_PROBE_TIMEOUT) {
+                Ok(UdpProbeResult::Reachable) => return UdpProbeResult::Reachable,
+                Ok(probe_result) => {
+                    if probe_result == UdpProbeResult::NoResponse
+                        || matches!(result, UdpProbeResult::Error(_))
+                    {
+                        result = probe_result;
+                    }
+                }
+                Err(e) => {
+                    if matches!(result, UdpProbeResult::Error(_)) {
+                        result = UdpProbeResult::Error(e.to_string());
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Split a server URL (with or without scheme) into its host and port, the port defaults to 443
+pub fn host_and_port(server: &str) -> Option<(String, u16)> {
+    let address: ServerAddress = server.parse().ok()?;
+    Some((address.host, address.port.unwrap_or(443)))
+}
+
+#[test]
+fn test_dtls_client_hello() {
+    let hello = dtls_client_hello();
+    assert_eq!(hello[0], 0x16);
+    let record_len = u16::from_be_bytes([hello[11], hello[12]]) as usize;
+    assert_eq!(hello.len(), 13 + record_len);
+}
@@ -0,0 +1,126 @@
+//! Profile scripts, executables which compute connection parameters of a stored server before every connect
+//!
+//! The script is run without arguments and with `OPENCONNECT_RS_SERVER_NAME` and `OPENCONNECT_RS_SERVER_URL` set.
+//! It prints a JSON object of [EntrypointOverride] on stdout, its fields replace the stored ones, absent fields and
+//! an empty output keep them. Unknown fields fail the connection, so that a typo is not silently ignored.
+
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+pub const SERVER_NAME_ENV: &str = "OPENCONNECT_RS_SERVER_NAME";
+pub const SERVER_URL_ENV: &str = "OPENCONNECT_RS_SERVER_URL";
+
+/// The connection parameters a profile script may override
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct EntrypointOverride {
+    /// The gateway to connect to, it has to accept the session cookie obtained from the stored server
+    pub server: Option<String>,
+
+    /// See [crate::config::EntrypointBuilder::reported_os]
+    pub reported_os: Option<String>,
+
+    /// See [crate::config::EntrypointBuilder::enable_udp]
+    pub enable_udp: Option<bool>,
+
+    /// See [crate::config::EntrypointBuilder::expected_subnet]
+    pub expected_subnet: Option<String>,
+
+    /// See [crate::config::EntrypointBuilder::accept_cert]
+    pub accept_cert: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileScriptError {
+    #[error("Failed to run profile script {0}: {1}")]
+    SpawnError(PathBuf, #[source] std::io::Error),
+
+    #[error("Profile script {0} failed with {1}: {2}")]
+    Failed(PathBuf, ExitStatus, String),
+
+    #[error("Invalid output of profile script {0}: {1}")]
+    InvalidOutput(PathBuf, #[source] serde_json::Error),
+
+    #[error("Refusing to run profile script {0}: {1}")]
+    Untrusted(PathBuf, #[source] std::io::Error),
+}
+
+impl EntrypointOverride {
+    /// Parse the stdout of a profile script, an empty output overrides nothing
+    pub fn parse(output: &str) -> Result<Self, serde_json::Error> {
+        if output.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(output)
+    }
+}
+
+/// Check that the script can only be replaced by root or the user who escalated, the daemon runs it as root
+fn check_trusted(script: &Path) -> std::io::Result<()> {
+    if !script.is_absolute() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the path is not absolute",
+        ));
+    }
+
+    #[cfg(unix)]
+    {
+        crate::paths::check_owner(script)?;
+        if let Some(dir) = script.parent() {
+            crate::paths::check_owner(dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the profile script of a server, blocking until it exits
+pub fn run(
+    script: &Path,
+    server_name: &str,
+    server_url: &str,
+) -> Result<EntrypointOverride, ProfileScriptError> {
+    check_trusted(script).map_err(|e| ProfileScriptError::Untrusted(script.to_path_buf(), e))?;
+
+    let output = Command::new(script)
+        .env(SERVER_NAME_ENV, server_name)
+        .env(SERVER_URL_ENV, server_url)
+        .output()
+        .map_err(|e| ProfileScriptError::SpawnError(script.to_path_buf(), e))?;
+
+    if !output.status.success() {
+        return Err(ProfileScriptError::Failed(
+            script.to_path_buf(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    EntrypointOverride::parse(&String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| ProfileScriptError::InvalidOutput(script.to_path_buf(), e))
+}
+
+#[test]
+fn test_entrypoint_override() {
+    assert_eq!(
+        EntrypointOverride::parse(
+            "{\"server\": \"https://night.example.com\", \"enableUdp\": false}"
+        )
+        .unwrap(),
+        EntrypointOverride {
+            server: Some("https://night.example.com".to_string()),
+            enable_udp: Some(false),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        EntrypointOverride::parse(" \n").unwrap(),
+        EntrypointOverride::default()
+    );
+    assert!(EntrypointOverride::parse("{\"sever\": \"typo.example.com\"}").is_err());
+    assert!(EntrypointOverride::parse("not json").is_err());
+}
@@ -1,4 +1,5 @@
 use openconnect_sys::{oc_vpn_proto, openconnect_get_supported_protocols};
+use std::{fmt::Display, str::FromStr};
 
 #[derive(Debug, Clone)]
 pub struct Protocol {
@@ -39,6 +40,81 @@ pub fn get_supported_protocols() -> Vec<Protocol> {
     protocols
 }
 
+/// A supported protocol by its name, e.g. `gp`
+pub fn find_protocol(name: &str) -> Option<Protocol> {
+    get_supported_protocols()
+        .into_iter()
+        .find(|protocol| protocol.name == name)
+}
+
+/// The protocols of libopenconnect, selected on the command line or in the config file by [VpnProtocol::name]
+///
+/// Which of them are available depends on the linked libopenconnect, see [VpnProtocol::protocol].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VpnProtocol {
+    AnyConnect,
+    Nc,
+    GlobalProtect,
+    Pulse,
+    Fortinet,
+    Array,
+}
+
+impl VpnProtocol {
+    pub const ALL: [VpnProtocol; 6] = [
+        VpnProtocol::AnyConnect,
+        VpnProtocol::Nc,
+        VpnProtocol::GlobalProtect,
+        VpnProtocol::Pulse,
+        VpnProtocol::Fortinet,
+        VpnProtocol::Array,
+    ];
+
+    /// The name passed to `openconnect_set_protocol`
+    pub fn name(&self) -> &'static str {
+        match self {
+            VpnProtocol::AnyConnect => "anyconnect",
+            VpnProtocol::Nc => "nc",
+            VpnProtocol::GlobalProtect => "gp",
+            VpnProtocol::Pulse => "pulse",
+            VpnProtocol::Fortinet => "fortinet",
+            VpnProtocol::Array => "array",
+        }
+    }
+
+    /// The protocol, if the linked libopenconnect supports it
+    pub fn protocol(&self) -> Option<Protocol> {
+        find_protocol(self.name())
+    }
+}
+
+impl Display for VpnProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Accepts the name of libopenconnect or the product name, case insensitive
+impl FromStr for VpnProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "anyconnect" | "cisco" => Ok(VpnProtocol::AnyConnect),
+            "nc" | "juniper" => Ok(VpnProtocol::Nc),
+            "gp" | "globalprotect" => Ok(VpnProtocol::GlobalProtect),
+            "pulse" => Ok(VpnProtocol::Pulse),
+            "fortinet" => Ok(VpnProtocol::Fortinet),
+            "array" => Ok(VpnProtocol::Array),
+            _ => Err(format!(
+                "Unknown protocol {}, expected one of {}",
+                s,
+                VpnProtocol::ALL.map(|protocol| protocol.name()).join(", ")
+            )),
+        }
+    }
+}
+
 // TODO: temp solution
 pub fn get_anyconnect_protocol() -> Protocol {
     get_supported_protocols()
@@ -47,3 +123,15 @@ pub fn get_anyconnect_protocol() -> Protocol {
         .expect("anyconnect protocol not found")
         .clone()
 }
+
+#[test]
+fn test_vpn_protocol() {
+    assert_eq!(
+        "GlobalProtect".parse::<VpnProtocol>(),
+        Ok(VpnProtocol::GlobalProtect)
+    );
+    for protocol in VpnProtocol::ALL {
+        assert_eq!(protocol.name().parse::<VpnProtocol>(), Ok(protocol));
+    }
+    assert!("ipsec".parse::<VpnProtocol>().is_err());
+}
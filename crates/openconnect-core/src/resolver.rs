@@ -0,0 +1,59 @@
+//! The DNS servers of the system resolver, to notice when the network below the tunnel changes
+//!
+//! With systemd-resolved `/etc/resolv.conf` only names the local stub, so the upstream servers are read from
+//! `/run/systemd/resolve/resolv.conf` when it exists.
+
+use crate::ip_info::IpInfo;
+
+#[cfg(not(target_os = "windows"))]
+const RESOLV_CONF_PATHS: [&str; 2] = ["/run/systemd/resolve/resolv.conf", "/etc/resolv.conf"];
+
+/// The `nameserver` entries of a resolv.conf
+fn resolver_nameservers(resolv_conf: &str) -> Vec<String> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(server)) => Some(server.to_lowercase()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The DNS servers of the system resolver, sorted, blocking while the resolver configuration is read
+///
+/// While connected, pass the [IpInfo] of the tunnel: the DNS servers pushed by the gateway are left out, so that
+/// only a change of the network below the tunnel is noticed.
+pub fn nameservers(connected: Option<&IpInfo>) -> Vec<String> {
+    #[cfg(not(target_os = "windows"))]
+    let mut servers = RESOLV_CONF_PATHS
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|resolv_conf| resolver_nameservers(&resolv_conf))
+        .unwrap_or_default();
+
+    // TODO: read the DNS servers of the network adapters on windows
+    #[cfg(target_os = "windows")]
+    let mut servers: Vec<String> = vec![];
+
+    let pushed: Vec<String> = connected
+        .into_iter()
+        .flat_map(|info| info.dns.iter().flatten())
+        .map(|server| server.to_lowercase())
+        .collect();
+    servers.retain(|server| !pushed.contains(server));
+    servers.sort();
+    servers.dedup();
+
+    servers
+}
+
+#[test]
+fn test_resolver_nameservers() {
+    let servers = resolver_nameservers(
+        "# generated\nnameserver 192.168.1.1\nnameserver FD00::1\nsearch lan\nnameserver\n",
+    );
+    assert_eq!(servers, vec!["192.168.1.1", "fd00::1"]);
+}
@@ -1,7 +1,7 @@
 use crate::{events::Events, VpnClient};
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[derive(Error, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum OpenconnectError {
     #[error("Failed to create new VPN entry point: {0}")]
     EntrypointConfigError(String),
@@ -21,6 +21,9 @@ pub enum OpenconnectError {
     #[error("Failed to set reported OS. Error code: {0}")]
     SetReportOSError(i32),
 
+    #[error("Failed to set user agent. Error code: {0}")]
+    SetUserAgentError(i32),
+
     #[error("Failed to setup command pipe. Error code: {0}")]
     CmdPipeError(i32),
 
@@ -51,10 +54,122 @@ pub enum OpenconnectError {
     #[error("Failed to get IP info. Error code: {0}")]
     GetIpInfoError(i32),
 
+    #[error("Failed to set CA file. Error code: {0}")]
+    SetCaFileError(i32),
+
+    #[error("Failed to re-apply the network configuration: {0}")]
+    ReapplyConfigError(String),
+
+    #[error("Invalid MTU bounds: {0}")]
+    InvalidMtuBounds(String),
+
+    #[error("Gateway set an MTU of {0}, outside of {1}")]
+    MtuOutOfRange(i32, String),
+
+    #[error("Invalid CA certificates: {0}")]
+    InvalidCaCert(String),
+
+    #[error("Invalid client certificate: {0}")]
+    InvalidClientCert(String),
+
+    #[error("The private key of the client certificate is encrypted, a key password is required")]
+    KeyPasswordRequired,
+
+    #[error("Failed to decrypt the private key of the client certificate, wrong key password")]
+    WrongKeyPassword,
+
+    #[error("Invalid search domain: {0}")]
+    InvalidSearchDomain(String),
+
+    #[error("Invalid vpnc-script environment variable: {0}")]
+    InvalidScriptEnv(String),
+
+    #[error("Gateway negotiated {0}, below the minimum of {1}")]
+    TlsVersionTooOld(String, String),
+
+    #[error("Gateway negotiated {0}, which is not in the allowed cipher list")]
+    CipherNotAllowed(String),
+
+    #[error("Gateway certificate {0} does not match the accepted fingerprint")]
+    CertFingerprintMismatch(String),
+
+    #[error("Gateway assigned {0}, outside of the expected subnet {1}")]
+    UnexpectedAddress(String, String),
+
+    #[error("Disconnected by server: {0}")]
+    DisconnectedByServer(String),
+
+    #[error("Connection was cancelled")]
+    Cancelled,
+
+    #[error("Not connected")]
+    NotConnected,
+
+    #[error("The session was not up again within {0}s after rekeying")]
+    RekeyTimeout(u64),
+
+    #[error("The gateway already has an active session for this user")]
+    SessionAlreadyActive,
+
+    #[error("Login failed on a transient error of the gateway: {0}")]
+    TransientLoginError(String),
+
+    #[error("Failed to open the libopenconnect log: {0}")]
+    OpenconnectLogError(String),
+
     #[error("Other general error: {0}")]
     OtherError(String),
 }
 
+impl OpenconnectError {
+    /// The gateway rejected the session cookie, the session can only continue after a new login
+    pub fn is_auth_expired(&self) -> bool {
+        matches!(
+            self,
+            OpenconnectError::MainLoopError(code) | OpenconnectError::MakeCstpError(code)
+                if *code == -libc::EPERM
+        )
+    }
+
+    /// The login failed because the gateway was unavailable, e.g. a 503 or a timeout, rather than rejecting it
+    pub fn is_transient(&self) -> bool {
+        matches!(self, OpenconnectError::TransientLoginError(_))
+    }
+}
+
+/// Whether a progress message of libopenconnect reports a failure worth retrying the login for
+pub(crate) fn is_transient_failure(message: &str) -> bool {
+    let server_error = message
+        .trim()
+        .strip_prefix("Unexpected ")
+        .and_then(|rest| rest.strip_suffix(" result from server"))
+        .and_then(|status| status.parse::<u16>().ok())
+        .is_some_and(|status| (500..600).contains(&status));
+
+    server_error
+        || message.starts_with("Failed to connect to host")
+        || message.starts_with("Failed to open HTTPS connection")
+        || message.contains("timed out")
+}
+
+/// The reason of a `Received server disconnect: <code> '<reason>'` progress message, sent when the gateway ends the session
+pub(crate) fn server_disconnect_reason(message: &str) -> Option<String> {
+    let details = message
+        .trim()
+        .strip_prefix("Received server disconnect:")?
+        .trim();
+    let reason = match details.split_once('\'') {
+        Some((_, reason)) => reason.trim_end_matches('\'').trim(),
+        None => details,
+    };
+
+    Some(if reason.is_empty() {
+        "no reason given".to_string()
+    } else {
+        reason.to_string()
+    })
+}
+
 pub type OpenconnectResult<T> = std::result::Result<T, OpenconnectError>;
 
 pub trait EmitError<T> {
@@ -69,3 +184,34 @@ impl<T> EmitError<T> for OpenconnectResult<T> {
         self
     }
 }
+
+#[test]
+fn test_is_auth_expired() {
+    assert!(OpenconnectError::MakeCstpError(-libc::EPERM).is_auth_expired());
+    assert!(OpenconnectError::MainLoopError(-libc::EPERM).is_auth_expired());
+    assert!(!OpenconnectError::MainLoopError(-libc::EIO).is_auth_expired());
+    assert!(!OpenconnectError::ObtainCookieError(-libc::EPERM).is_auth_expired());
+}
+
+#[test]
+fn test_is_transient_failure() {
+    assert!(is_transient_failure("Unexpected 503 result from server\n"));
+    assert!(!is_transient_failure("Unexpected 401 result from server\n"));
+    assert!(is_transient_failure(
+        "Failed to connect to host vpn.example.com\n"
+    ));
+    assert!(!is_transient_failure("Login failed.\n"));
+}
+
+#[test]
+fn test_server_disconnect_reason() {
+    assert_eq!(
+        server_disconnect_reason("Received server disconnect: 00 'Session timeout'\n").as_deref(),
+        Some("Session timeout")
+    );
+    assert_eq!(
+        server_disconnect_reason("Received server disconnect: b0 ''\n").as_deref(),
+        Some("no reason given")
+    );
+    assert!(server_disconnect_reason("Failed to connect to host vpn.example.com\n").is_none());
+}
@@ -0,0 +1,78 @@
+//! External sources for secrets which must not be stored in the config file
+//!
+//! A secret source is stored in place of the literal secret and resolved at connect time.
+//!
+//! There is no OS keyring source: the CLI resolves the secret after escalating to root, where the keyring of the
+//! user (the Secret Service of their session, the login keychain) is not reachable, and no keyring crate is a
+//! dependency yet. It needs the secret to be resolved before escalating first.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", tag = "type", content = "value")]
+pub enum SecretSource {
+    /// Read the secret from an environment variable
+    Env(String),
+
+    /// Read the secret from a file, surrounding whitespace (e.g. a trailing newline) is trimmed
+    File(PathBuf),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("Environment variable {0} is not set or not valid unicode")]
+    MissingEnv(String),
+
+    #[error("Failed to read secret file {0}: {1}")]
+    FileError(PathBuf, #[source] std::io::Error),
+}
+
+impl SecretSource {
+    pub fn resolve(&self) -> Result<String, SecretError> {
+        match self {
+            SecretSource::Env(name) => {
+                std::env::var(name).map_err(|_| SecretError::MissingEnv(name.clone()))
+            }
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map(|secret| secret.trim().to_string())
+                .map_err(|e| SecretError::FileError(path.clone(), e)),
+        }
+    }
+}
+
+/// Resolve a secret which is either given literally or by an external source, the source takes precedence
+pub fn resolve_secret(
+    literal: Option<&String>,
+    source: Option<&SecretSource>,
+) -> Result<Option<String>, SecretError> {
+    match source {
+        Some(source) => source.resolve().map(Some),
+        None => Ok(literal.cloned()),
+    }
+}
+
+#[test]
+fn test_resolve_secret() {
+    let path = std::env::temp_dir().join("openconnect-rs-test-secret");
+    std::fs::write(&path, "file-secret\n").unwrap();
+
+    let source = SecretSource::File(path.clone());
+    assert_eq!(source.resolve().unwrap(), "file-secret");
+    assert_eq!(
+        resolve_secret(Some(&"literal".to_string()), Some(&source)).unwrap(),
+        Some("file-secret".to_string())
+    );
+    assert_eq!(
+        resolve_secret(Some(&"literal".to_string()), None).unwrap(),
+        Some("literal".to_string())
+    );
+
+    let _ = std::fs::remove_file(path);
+    assert!(
+        SecretSource::Env("OPENCONNECT_RS_TEST_UNSET_SECRET".to_string())
+            .resolve()
+            .is_err()
+    );
+}
@@ -1,7 +1,7 @@
 use crate::VpnClient;
 use openconnect_sys::oc_stats;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct Stats {
     pub rx_bytes: u64,
     pub tx_bytes: u64,
@@ -10,7 +10,6 @@ pub struct Stats {
 }
 
 pub(crate) extern "C" fn stats_fn(privdata: *mut ::std::os::raw::c_void, stats: *const oc_stats) {
-    println!("stats_fn");
     let client = unsafe { VpnClient::ref_from_raw(privdata) };
     let dlts = client.get_dlts_cipher();
 
@@ -1,19 +1,31 @@
+use crate::{
+    config::LogLevel,
+    protocols::{find_protocol, Protocol},
+    secret::{resolve_secret, SecretError, SecretSource},
+};
 use chacha20poly1305::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     XChaCha20Poly1305, XNonce,
 };
 use rand::SeedableRng;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::{
     collections::{hash_map::Entry, HashMap},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The format of the config file, see [config_schema]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct StoredConfigsJson {
+    /// Name of the server used when none is given
     default: Option<String>,
     servers: Vec<StoredServer>,
+
+    /// Preferences applied to all connections, see [Defaults]
+    #[serde(default, skip_serializing_if = "Defaults::is_empty")]
+    defaults: Defaults,
 }
 
 impl StoredConfigsJson {
@@ -31,6 +43,7 @@ impl StoredConfigsJson {
         Self {
             default: self.default.clone(),
             servers,
+            defaults: self.defaults.clone(),
         }
     }
 }
@@ -61,6 +74,7 @@ impl TryFrom<(StoredConfigsJson, PathBuf)> for StoredConfigs {
         Ok(StoredConfigs {
             default: json.default,
             servers,
+            defaults: json.defaults,
             cipher: PassEncryptor::default(),
             config_file,
         })
@@ -72,11 +86,195 @@ impl From<StoredConfigs> for StoredConfigsJson {
         StoredConfigsJson {
             default: config.default,
             servers: config.servers.into_values().collect(),
+            defaults: config.defaults,
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Preferences applied to all connections, unless the server or a flag given on start sets them
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Defaults {
+    /// Path of the vpnc-script to run instead of the bundled one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vpnc_script: Option<String>,
+
+    /// Verbosity of libopenconnect: err, info, debug or trace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+
+    /// Name of the VPN protocol, e.g. anyconnect, gp or pulse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+
+    /// Whether the daemon reconnects when the connection drops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_reconnect: Option<bool>,
+
+    /// A shell command which has to exit successfully before any tunnel is established, see [crate::preflight]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight_cmd: Option<String>,
+}
+
+impl Defaults {
+    /// The keys of the defaults on the command line
+    pub const KEYS: [&'static str; 5] = [
+        "vpnc-script",
+        "log-level",
+        "protocol",
+        "auto-reconnect",
+        "preflight-cmd",
+    ];
+
+    pub fn is_empty(&self) -> bool {
+        self == &Defaults::default()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, StoredConfigError> {
+        match key {
+            "vpnc-script" => Ok(self.vpnc_script.clone()),
+            "log-level" => Ok(self.log_level.clone()),
+            "protocol" => Ok(self.protocol.clone()),
+            "auto-reconnect" => Ok(self.auto_reconnect.map(|enabled| enabled.to_string())),
+            "preflight-cmd" => Ok(self.preflight_cmd.clone()),
+            _ => Err(unknown_default(key)),
+        }
+    }
+
+    /// Validate and set the value of a key, `None` resets it to the built-in behavior
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), StoredConfigError> {
+        match key {
+            "vpnc-script" => {
+                if let Some(script) = value.filter(|script| !std::path::Path::new(script).is_file())
+                {
+                    return Err(StoredConfigError::BadInput(format!(
+                        "vpnc-script {} not found",
+                        script
+                    )));
+                }
+                self.vpnc_script = value.map(str::to_string);
+            }
+            "log-level" => {
+                self.log_level = value
+                    .map(|level| level.parse::<LogLevel>().map(|level| level.to_string()))
+                    .transpose()
+                    .map_err(StoredConfigError::BadInput)?;
+            }
+            "protocol" => {
+                if let Some(protocol) = value.filter(|protocol| find_protocol(protocol).is_none()) {
+                    return Err(StoredConfigError::BadInput(format!(
+                        "Unsupported protocol: {}",
+                        protocol
+                    )));
+                }
+                self.protocol = value.map(str::to_string);
+            }
+            "auto-reconnect" => {
+                self.auto_reconnect = value
+                    .map(|enabled| {
+                        enabled.parse::<bool>().map_err(|_| {
+                            StoredConfigError::BadInput(format!(
+                                "Expected true or false, got {}",
+                                enabled
+                            ))
+                        })
+                    })
+                    .transpose()?;
+            }
+            "preflight-cmd" => {
+                if value.is_some_and(|command| command.trim().is_empty()) {
+                    return Err(StoredConfigError::BadInput(
+                        "The pre-flight command is empty".to_string(),
+                    ));
+                }
+                self.preflight_cmd = value.map(str::to_string);
+            }
+            _ => return Err(unknown_default(key)),
+        }
+
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> Option<LogLevel> {
+        self.log_level
+            .as_deref()
+            .and_then(|level| level.parse().ok())
+    }
+
+    /// The default protocol, if libopenconnect supports it
+    pub fn protocol(&self) -> Option<Protocol> {
+        self.protocol.as_deref().and_then(find_protocol)
+    }
+}
+
+/// Named connection options of a stored server, e.g. a split tunnel next to the full tunnel, selected with `start --profile`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerProfile {
+    /// The vpnc-script setting up routes and DNS instead of [Defaults::vpnc_script], e.g. a split tunnel script
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vpnc_script: Option<String>,
+
+    /// Replace the search domains pushed by the gateway
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search_domains: Vec<String>,
+
+    /// Enable or disable UDP (DTLS), decides over the UDP rules of the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_udp: Option<bool>,
+}
+
+impl ServerProfile {
+    /// The options of a profile on the command line
+    pub const KEYS: [&'static str; 3] = ["vpnc-script", "search-domain", "udp"];
+
+    /// Validate and set an option, a search domain is added to the previous ones
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), StoredConfigError> {
+        match key {
+            "vpnc-script" => {
+                if !Path::new(value).is_file() {
+                    return Err(StoredConfigError::BadInput(format!(
+                        "vpnc-script {} not found",
+                        value
+                    )));
+                }
+                self.vpnc_script = Some(value.to_string());
+            }
+            "search-domain" => self.search_domains.push(value.to_string()),
+            "udp" => {
+                self.enable_udp = Some(match value.to_lowercase().as_str() {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => {
+                        return Err(StoredConfigError::BadInput(format!(
+                            "Expected on or off, got {}",
+                            value
+                        )))
+                    }
+                });
+            }
+            _ => {
+                return Err(StoredConfigError::BadInput(format!(
+                    "Unknown profile option {}, expected one of {}",
+                    key,
+                    Self::KEYS.join(", ")
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn unknown_default(key: &str) -> StoredConfigError {
+    StoredConfigError::BadInput(format!(
+        "Unknown key {}, expected one of {}",
+        key,
+        Defaults::KEYS.join(", ")
+    ))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct OidcServer {
     pub name: String,
@@ -86,25 +284,201 @@ pub struct OidcServer {
     pub client_secret: Option<String>,
     pub allow_insecure: Option<bool>,
     pub updated_at: Option<String>,
+
+    /// Resolve the client secret from this source at connect time instead of storing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret_source: Option<SecretSource>,
+
+    /// Name of another stored server which must be connected first, see [StoredServer::connect_via]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_via: Option<String>,
+
+    /// The platform reported to the gateway instead of the actual OS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_os: Option<String>,
+
+    /// Name of the VPN protocol instead of [Defaults::protocol], see [crate::protocols::VpnProtocol]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+
+    /// The proxy to connect through, a URL, `auto` or `none`, see [crate::proxy::resolve_proxy]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// A `host:port` behind the tunnel which is checked to be reachable after connecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_host: Option<String>,
+
+    /// A URL behind the tunnel which has to answer before the connection counts as up, see [crate::verify]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_url: Option<String>,
+
+    /// The status the verify URL has to answer with, any 2xx if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_expect_status: Option<u16>,
+
+    /// Desktop notifications of the GUI for its connects and disconnects, shown unless set to false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<bool>,
+
+    /// DNS suffixes or `host:port` checks of networks on which the daemon stays disconnected, see [crate::trusted_network]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_networks: Vec<String>,
+
+    /// Rules enabling or disabling UDP per SSID or interface, see [crate::udp_policy]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub udp_policy: Vec<String>,
+
+    /// The CIDR network the address assigned by the gateway must be in, see [crate::config::EntrypointBuilder::expected_subnet]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_subnet: Option<String>,
+
+    /// The SHA-256 fingerprint of the gateway certificate to accept, see [crate::config::EntrypointBuilder::accept_cert]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_cert: Option<String>,
+
+    /// The MTU of the tunnel device regardless of the gateway, see [crate::config::EntrypointBuilder::mtu]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>,
+
+    /// An executable overriding connection parameters before every connect, see [crate::profile_script]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_script: Option<PathBuf>,
+
+    /// End the session at the issuer with the ID token of the login when disconnecting
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oidc_logout: bool,
+
+    /// Named sets of connection options, see [StoredServer::profile]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, ServerProfile>,
+}
+
+impl OidcServer {
+    pub fn resolve_client_secret(&self) -> Result<Option<String>, SecretError> {
+        resolve_secret(
+            self.client_secret.as_ref(),
+            self.client_secret_source.as_ref(),
+        )
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PasswordServer {
     pub name: String,
     pub server: String,
     pub username: String,
+
+    /// Encrypted with a key derived from the machine id, use `passwordSource` for files shared between machines
     pub password: Option<String>,
     pub allow_insecure: Option<bool>,
     pub updated_at: Option<String>,
+
+    /// The last used auth group (realm)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_group: Option<String>,
+
+    /// Resolve the password from this source at connect time instead of storing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_source: Option<SecretSource>,
+
+    /// Name of another stored server which must be connected first, see [StoredServer::connect_via]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_via: Option<String>,
+
+    /// The platform reported to the gateway instead of the actual OS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_os: Option<String>,
+
+    /// Name of the VPN protocol instead of [Defaults::protocol], see [crate::protocols::VpnProtocol]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+
+    /// The proxy to connect through, a URL, `auto` or `none`, see [crate::proxy::resolve_proxy]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// A `host:port` behind the tunnel which is checked to be reachable after connecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_host: Option<String>,
+
+    /// A URL behind the tunnel which has to answer before the connection counts as up, see [crate::verify]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_url: Option<String>,
+
+    /// The status the verify URL has to answer with, any 2xx if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_expect_status: Option<u16>,
+
+    /// Desktop notifications of the GUI for its connects and disconnects, shown unless set to false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<bool>,
+
+    /// DNS suffixes or `host:port` checks of networks on which the daemon stays disconnected, see [crate::trusted_network]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_networks: Vec<String>,
+
+    /// Rules enabling or disabling UDP per SSID or interface, see [crate::udp_policy]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub udp_policy: Vec<String>,
+
+    /// The CIDR network the address assigned by the gateway must be in, see [crate::config::EntrypointBuilder::expected_subnet]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_subnet: Option<String>,
+
+    /// The SHA-256 fingerprint of the gateway certificate to accept, see [crate::config::EntrypointBuilder::accept_cert]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_cert: Option<String>,
+
+    /// The MTU of the tunnel device regardless of the gateway, see [crate::config::EntrypointBuilder::mtu]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>,
+
+    /// A certificate to authenticate with, see [crate::config::EntrypointBuilder::client_cert]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+
+    /// The private key of the client certificate, if not in the certificate file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+
+    /// The password of the client certificate key, encrypted like the password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_password: Option<String>,
+
+    /// An executable overriding connection parameters before every connect, see [crate::profile_script]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_script: Option<PathBuf>,
+
+    /// Authenticate with the gateway's web login (SAML) in a browser instead of the password form
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub web_auth: bool,
+
+    /// Non-secret answers of custom login form fields by field name, see [crate::config::EntrypointBuilder::form_default]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub form_defaults: HashMap<String, String>,
+
+    /// Named sets of connection options, see [StoredServer::profile]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, ServerProfile>,
 }
 
 impl PasswordServer {
+    /// Resolve the password of a decrypted server
+    pub fn resolve_password(&self) -> Result<Option<String>, SecretError> {
+        resolve_secret(self.password.as_ref(), self.password_source.as_ref())
+    }
+
     pub fn decrypted_by(&self, encryptor: &PassEncryptor) -> Self {
         let password = self
             .password
             .as_ref()
             .and_then(|p| encryptor.decrypt(p).ok());
+        let key_password = self
+            .key_password
+            .as_ref()
+            .and_then(|p| encryptor.decrypt(p).ok());
         Self {
             name: self.name.clone(),
             server: self.server.clone(),
@@ -112,6 +486,28 @@ impl PasswordServer {
             password,
             allow_insecure: self.allow_insecure,
             updated_at: self.updated_at.clone(),
+            auth_group: self.auth_group.clone(),
+            password_source: self.password_source.clone(),
+            connect_via: self.connect_via.clone(),
+            reported_os: self.reported_os.clone(),
+            protocol: self.protocol.clone(),
+            proxy: self.proxy.clone(),
+            probe_host: self.probe_host.clone(),
+            verify_url: self.verify_url.clone(),
+            verify_expect_status: self.verify_expect_status,
+            notify: self.notify,
+            trusted_networks: self.trusted_networks.clone(),
+            udp_policy: self.udp_policy.clone(),
+            expected_subnet: self.expected_subnet.clone(),
+            server_cert: self.server_cert.clone(),
+            mtu: self.mtu,
+            client_cert: self.client_cert.clone(),
+            client_key: self.client_key.clone(),
+            key_password,
+            profile_script: self.profile_script.clone(),
+            web_auth: self.web_auth,
+            form_defaults: self.form_defaults.clone(),
+            profiles: self.profiles.clone(),
         }
     }
 
@@ -120,6 +516,10 @@ impl PasswordServer {
             .password
             .as_ref()
             .and_then(|p| encryptor.encrypt(p).ok());
+        let key_password = self
+            .key_password
+            .as_ref()
+            .and_then(|p| encryptor.encrypt(p).ok());
         Self {
             name: self.name.clone(),
             server: self.server.clone(),
@@ -127,11 +527,33 @@ impl PasswordServer {
             password,
             allow_insecure: self.allow_insecure,
             updated_at: self.updated_at.clone(),
+            auth_group: self.auth_group.clone(),
+            password_source: self.password_source.clone(),
+            connect_via: self.connect_via.clone(),
+            reported_os: self.reported_os.clone(),
+            protocol: self.protocol.clone(),
+            proxy: self.proxy.clone(),
+            probe_host: self.probe_host.clone(),
+            verify_url: self.verify_url.clone(),
+            verify_expect_status: self.verify_expect_status,
+            notify: self.notify,
+            trusted_networks: self.trusted_networks.clone(),
+            udp_policy: self.udp_policy.clone(),
+            expected_subnet: self.expected_subnet.clone(),
+            server_cert: self.server_cert.clone(),
+            mtu: self.mtu,
+            client_cert: self.client_cert.clone(),
+            client_key: self.client_key.clone(),
+            key_password,
+            profile_script: self.profile_script.clone(),
+            web_auth: self.web_auth,
+            form_defaults: self.form_defaults.clone(),
+            profiles: self.profiles.clone(),
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase", tag = "authType")]
 pub enum StoredServer {
     #[serde(rename_all = "camelCase")]
@@ -141,6 +563,164 @@ pub enum StoredServer {
     Password(PasswordServer),
 }
 
+impl StoredServer {
+    /// The server whose tunnel must be up before connecting to this one, e.g. an outer gateway in front of an inner one
+    ///
+    /// The upstream server is connected first and disconnected last.
+    pub fn connect_via(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer { connect_via, .. }) => connect_via.as_deref(),
+            StoredServer::Password(PasswordServer { connect_via, .. }) => connect_via.as_deref(),
+        }
+    }
+
+    /// The proxy setting stored for the server, see [crate::proxy::resolve_proxy]
+    pub fn proxy(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer { proxy, .. }) => proxy.as_deref(),
+            StoredServer::Password(PasswordServer { proxy, .. }) => proxy.as_deref(),
+        }
+    }
+
+    /// The host checked to be reachable through the tunnel after connecting
+    pub fn probe_host(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer { probe_host, .. }) => probe_host.as_deref(),
+            StoredServer::Password(PasswordServer { probe_host, .. }) => probe_host.as_deref(),
+        }
+    }
+
+    /// Whether the GUI shows desktop notifications for the server, on unless turned off
+    pub fn notify(&self) -> bool {
+        match self {
+            StoredServer::Oidc(OidcServer { notify, .. })
+            | StoredServer::Password(PasswordServer { notify, .. }) => notify.unwrap_or(true),
+        }
+    }
+
+    /// The URL and expected status checked through the tunnel after connecting, see [crate::verify]
+    pub fn verify_url(&self) -> Option<(&str, Option<u16>)> {
+        match self {
+            StoredServer::Oidc(OidcServer {
+                verify_url,
+                verify_expect_status,
+                ..
+            })
+            | StoredServer::Password(PasswordServer {
+                verify_url,
+                verify_expect_status,
+                ..
+            }) => verify_url
+                .as_deref()
+                .map(|verify_url| (verify_url, *verify_expect_status)),
+        }
+    }
+
+    /// The networks on which the daemon stays disconnected, see [crate::trusted_network]
+    pub fn trusted_networks(&self) -> &[String] {
+        match self {
+            StoredServer::Oidc(OidcServer {
+                trusted_networks, ..
+            }) => trusted_networks,
+            StoredServer::Password(PasswordServer {
+                trusted_networks, ..
+            }) => trusted_networks,
+        }
+    }
+
+    /// The rules deciding whether to use UDP on the current network, see [crate::udp_policy]
+    pub fn udp_policy(&self) -> &[String] {
+        match self {
+            StoredServer::Oidc(OidcServer { udp_policy, .. }) => udp_policy,
+            StoredServer::Password(PasswordServer { udp_policy, .. }) => udp_policy,
+        }
+    }
+
+    /// The pinned gateway certificate, see [crate::config::EntrypointBuilder::accept_cert]
+    pub fn server_cert(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer { server_cert, .. })
+            | StoredServer::Password(PasswordServer { server_cert, .. }) => server_cert.as_deref(),
+        }
+    }
+
+    /// The MTU of the tunnel device, see [crate::config::EntrypointBuilder::mtu]
+    pub fn mtu(&self) -> Option<u32> {
+        match self {
+            StoredServer::Oidc(OidcServer { mtu, .. })
+            | StoredServer::Password(PasswordServer { mtu, .. }) => *mtu,
+        }
+    }
+
+    /// The network the assigned address must be in, see [crate::config::EntrypointBuilder::expected_subnet]
+    pub fn expected_subnet(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer {
+                expected_subnet, ..
+            }) => expected_subnet.as_deref(),
+            StoredServer::Password(PasswordServer {
+                expected_subnet, ..
+            }) => expected_subnet.as_deref(),
+        }
+    }
+
+    /// The executable computing connection parameters, see [crate::profile_script]
+    pub fn profile_script(&self) -> Option<&Path> {
+        match self {
+            StoredServer::Oidc(OidcServer { profile_script, .. }) => profile_script.as_deref(),
+            StoredServer::Password(PasswordServer { profile_script, .. }) => {
+                profile_script.as_deref()
+            }
+        }
+    }
+
+    /// The protocol of the server if the linked libopenconnect supports it, [Defaults::protocol] applies otherwise
+    pub fn protocol(&self) -> Option<Protocol> {
+        match self {
+            StoredServer::Oidc(OidcServer { protocol, .. })
+            | StoredServer::Password(PasswordServer { protocol, .. }) => {
+                protocol.as_deref().and_then(find_protocol)
+            }
+        }
+    }
+
+    /// The platform to report to the gateway, see [crate::config::EntrypointBuilder::reported_os]
+    pub fn reported_os(&self) -> Option<&str> {
+        match self {
+            StoredServer::Oidc(OidcServer { reported_os, .. }) => reported_os.as_deref(),
+            StoredServer::Password(PasswordServer { reported_os, .. }) => reported_os.as_deref(),
+        }
+    }
+
+    pub fn profiles(&self) -> &HashMap<String, ServerProfile> {
+        match self {
+            StoredServer::Oidc(OidcServer { profiles, .. }) => profiles,
+            StoredServer::Password(PasswordServer { profiles, .. }) => profiles,
+        }
+    }
+
+    /// The names of the profiles in alphabetical order
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles().keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// A named set of connection options, which replace the ones of the server for a connection
+    pub fn profile(&self, name: &str) -> Result<&ServerProfile, StoredConfigError> {
+        self.profiles().get(name).ok_or_else(|| {
+            StoredConfigError::BadInput(match self.profile_names().as_slice() {
+                [] => format!("Unknown profile {}, the server has no profiles", name),
+                names => format!(
+                    "Unknown profile {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                ),
+            })
+        })
+    }
+}
+
 impl TryFrom<&StoredServer> for OidcServer {
     type Error = StoredConfigError;
 
@@ -167,10 +747,16 @@ impl TryFrom<&StoredServer> for PasswordServer {
     }
 }
 
+/// JSON Schema of the config file, to validate generated configs against
+pub fn config_schema() -> schemars::schema::RootSchema {
+    schemars::schema_for!(StoredConfigsJson)
+}
+
 #[derive(Clone, Debug)]
 pub struct StoredConfigs {
     pub default: Option<String>,
     pub servers: HashMap<String, StoredServer>,
+    pub defaults: Defaults,
     pub cipher: PassEncryptor,
     pub config_file: PathBuf,
 }
@@ -195,6 +781,7 @@ impl StoredConfigs {
         Self {
             default: None,
             servers: HashMap::new(),
+            defaults: Defaults::default(),
             cipher: PassEncryptor::new(pass_key),
             config_file,
         }
@@ -207,17 +794,12 @@ impl StoredConfigs {
     }
 
     pub fn getorinit_config_file() -> Result<PathBuf, StoredConfigError> {
-        let home_dir = home::home_dir().ok_or(StoredConfigError::IoError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Home directory not found",
-        )))?;
-
-        let config_folder = home_dir.join(".oidcvpn");
+        let config_folder = crate::paths::config_dir()?;
         if !config_folder.exists() {
-            std::fs::create_dir(&config_folder)?;
+            std::fs::create_dir_all(&config_folder)?;
         }
 
-        let config_file = config_folder.join("config.json");
+        let config_file = crate::paths::config_file()?;
         if !config_file.exists() {
             std::fs::write(&config_file, br#"{"default":null,"servers":[]}"#)?;
         }
@@ -230,7 +812,10 @@ impl StoredConfigs {
             StoredConfigError::ParseError(format!("Failed to serialize config: {}", e))
         })?;
 
-        tokio::fs::write(&self.config_file, json).await?;
+        // replaced in one step, so that an interrupted write never leaves a truncated config behind
+        let tmp_file = self.config_file.with_extension("json.tmp");
+        tokio::fs::write(&tmp_file, json).await?;
+        tokio::fs::rename(&tmp_file, &self.config_file).await?;
 
         Ok(self)
     }
@@ -244,6 +829,7 @@ impl StoredConfigs {
 
         self.default = config.default;
         self.servers = config.servers;
+        self.defaults = config.defaults;
 
         Ok(self)
     }
@@ -342,6 +928,39 @@ impl StoredConfigs {
         Ok(self)
     }
 
+    /// Remove several servers with a single write, fails without removing any if one of them is the default server
+    pub async fn remove_servers(
+        &mut self,
+        names: &[String],
+    ) -> Result<&mut Self, StoredConfigError> {
+        if let Some(default) = self
+            .default
+            .as_ref()
+            .filter(|default| names.contains(default))
+        {
+            return Err(StoredConfigError::BadInput(format!(
+                "Cannot remove default server {}",
+                default
+            )));
+        }
+        for name in names {
+            self.servers.remove(name);
+        }
+        self.save_to_file().await?;
+        Ok(self)
+    }
+
+    /// Set one of the [Defaults] and save it, `None` resets it
+    pub async fn set_defaults_value(
+        &mut self,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<&mut Self, StoredConfigError> {
+        self.defaults.set(key, value)?;
+        self.save_to_file().await?;
+        Ok(self)
+    }
+
     pub async fn set_default_server(&mut self, name: &str) -> Result<&mut Self, StoredConfigError> {
         if !self.servers.contains_key(name) {
             return Err(StoredConfigError::ParseError(format!(
@@ -375,10 +994,8 @@ impl Default for PassEncryptor {
 
 impl PassEncryptor {
     pub fn new(unique_key: Option<String>) -> Self {
-        // by default, use machine uid as unique key to generate encryption key
-        let unique_key = unique_key
-            .or_else(|| machine_uid::get().ok())
-            .unwrap_or("openconnect-rs-2024".to_string());
+        // by default, use an imported seed or the machine uid as unique key to generate encryption key
+        let unique_key = unique_key.unwrap_or_else(crate::cipher_key::current_seed);
 
         let mut hasher: sha2::Sha256 = sha2::digest::Digest::new();
         hasher.update(unique_key.as_bytes());
@@ -444,6 +1061,23 @@ async fn test_save_config() {
         client_secret: Some("client_secret".to_string()),
         allow_insecure: Some(true),
         updated_at: None,
+        client_secret_source: None,
+        connect_via: None,
+        reported_os: None,
+        protocol: None,
+        proxy: None,
+        probe_host: None,
+        verify_url: None,
+        verify_expect_status: None,
+        notify: None,
+        trusted_networks: vec![],
+        udp_policy: vec![],
+        expected_subnet: None,
+        server_cert: None,
+        mtu: None,
+        profile_script: None,
+        oidc_logout: false,
+        profiles: HashMap::new(),
     });
 
     let config_file = StoredConfigs::getorinit_config_file().unwrap();
@@ -479,6 +1113,23 @@ async fn test_config_type() {
         client_secret: None,
         allow_insecure: Some(true),
         updated_at: None,
+        client_secret_source: None,
+        connect_via: None,
+        reported_os: None,
+        protocol: None,
+        proxy: None,
+        probe_host: None,
+        verify_url: None,
+        verify_expect_status: None,
+        notify: None,
+        trusted_networks: vec![],
+        udp_policy: vec![],
+        expected_subnet: None,
+        server_cert: None,
+        mtu: None,
+        profile_script: None,
+        oidc_logout: false,
+        profiles: HashMap::new(),
     });
 
     let json = serde_json::to_string(&server).unwrap();
@@ -494,6 +1145,28 @@ async fn test_config_type() {
         password: Some("password".to_string()),
         allow_insecure: Some(true),
         updated_at: None,
+        auth_group: None,
+        password_source: None,
+        connect_via: None,
+        reported_os: None,
+        protocol: None,
+        proxy: None,
+        probe_host: None,
+        verify_url: None,
+        verify_expect_status: None,
+        notify: None,
+        trusted_networks: vec![],
+        udp_policy: vec![],
+        expected_subnet: None,
+        server_cert: None,
+        mtu: None,
+        client_cert: None,
+        client_key: None,
+        key_password: None,
+        profile_script: None,
+        web_auth: false,
+        form_defaults: HashMap::new(),
+        profiles: HashMap::new(),
     });
 
     let json = serde_json::to_string(&server).unwrap();
@@ -502,3 +1175,78 @@ async fn test_config_type() {
         r#"{"authType":"password","server":"https://example.com","username":"username","password":"password","updatedAt":null}"#
     );
 }
+
+#[test]
+fn test_config_schema() {
+    let schema = serde_json::to_value(config_schema()).unwrap();
+    let definitions = &schema["definitions"];
+
+    assert!(definitions["StoredServer"].to_string().contains("authType"));
+    // the server types are inlined into the variants of StoredServer
+    let password_server = definitions["StoredServer"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|variant| variant["properties"]["authType"]["enum"][0] == "password")
+        .unwrap();
+    assert!(password_server["properties"]["passwordSource"].is_object());
+    assert!(definitions["SecretSource"].is_object());
+}
+
+#[test]
+fn test_defaults() {
+    let mut defaults = Defaults::default();
+    assert!(defaults.is_empty());
+
+    defaults.set("log-level", Some("DEBUG")).unwrap();
+    defaults.set("auto-reconnect", Some("false")).unwrap();
+    assert_eq!(defaults.get("log-level").unwrap().as_deref(), Some("debug"));
+    assert_eq!(defaults.log_level(), Some(LogLevel::Debug));
+    assert_eq!(defaults.auto_reconnect, Some(false));
+
+    assert!(defaults.set("log-level", Some("verbose")).is_err());
+    assert!(defaults.set("auto-reconnect", Some("maybe")).is_err());
+    assert!(defaults.set("preflight-cmd", Some(" ")).is_err());
+    assert!(defaults
+        .set("vpnc-script", Some("/nonexistent/vpnc-script"))
+        .is_err());
+    assert!(defaults.get("color").is_err());
+
+    let json = serde_json::to_string(&defaults).unwrap();
+    assert_eq!(json, r#"{"logLevel":"debug","autoReconnect":false}"#);
+
+    defaults.set("log-level", None).unwrap();
+    defaults.set("auto-reconnect", None).unwrap();
+    assert!(defaults.is_empty());
+}
+
+#[test]
+fn test_server_profile() {
+    let mut profile = ServerProfile::default();
+    profile.set("search-domain", "corp.example.com").unwrap();
+    profile.set("search-domain", "lab.example.com").unwrap();
+    profile.set("udp", "off").unwrap();
+    assert_eq!(
+        profile,
+        ServerProfile {
+            vpnc_script: None,
+            search_domains: vec![
+                "corp.example.com".to_string(),
+                "lab.example.com".to_string()
+            ],
+            enable_udp: Some(false),
+        }
+    );
+
+    assert!(profile.set("udp", "maybe").is_err());
+    assert!(profile
+        .set("vpnc-script", "/nonexistent/vpnc-script")
+        .is_err());
+    assert!(profile.set("routes", "10.0.0.0/8").is_err());
+
+    let json = serde_json::to_string(&profile).unwrap();
+    assert_eq!(
+        json,
+        r#"{"searchDomains":["corp.example.com","lab.example.com"],"enableUdp":false}"#
+    );
+}
@@ -0,0 +1,151 @@
+//! Trusted network detection, to connect only when the machine is outside of the corporate network
+//!
+//! A network is trusted if one of the configured checks matches:
+//! - a DNS suffix, matching the search domains of the system resolver (`/etc/resolv.conf`)
+//! - a `host:port`, which is trusted if it accepts a TCP connection, e.g. a server only reachable internally
+
+use crate::ip_info::IpInfo;
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustedNetwork {
+    DnsSuffix(String),
+    Reachable(String),
+}
+
+impl std::str::FromStr for TrustedNetwork {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some((host, port)) = value.rsplit_once(':') {
+            if host.is_empty() || port.parse::<u16>().map_or(true, |port| port == 0) {
+                return Err(format!("Invalid trusted network host: {}", value));
+            }
+            return Ok(TrustedNetwork::Reachable(value.to_string()));
+        }
+
+        let suffix = value.trim_matches('.').to_lowercase();
+        let is_domain = !suffix.is_empty()
+            && suffix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !is_domain {
+            return Err(format!("Invalid trusted network DNS suffix: {}", value));
+        }
+
+        Ok(TrustedNetwork::DnsSuffix(suffix))
+    }
+}
+
+impl std::fmt::Display for TrustedNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustedNetwork::DnsSuffix(suffix) => write!(f, "DNS suffix {}", suffix),
+            TrustedNetwork::Reachable(host) => write!(f, "reachable host {}", host),
+        }
+    }
+}
+
+/// The `search` and `domain` entries of a resolv.conf
+fn resolver_domains(resolv_conf: &str) -> Vec<String> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("search") | Some("domain") => Some(words),
+                _ => None,
+            }
+        })
+        .flatten()
+        .map(|domain| domain.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+fn system_resolver_domains() -> Vec<String> {
+    #[cfg(not(target_os = "windows"))]
+    return std::fs::read_to_string("/etc/resolv.conf")
+        .map(|resolv_conf| resolver_domains(&resolv_conf))
+        .unwrap_or_default();
+
+    // TODO: read the connection specific DNS suffixes on windows
+    #[cfg(target_os = "windows")]
+    return vec![];
+}
+
+fn matches_suffix(domain: &str, suffix: &str) -> bool {
+    domain == suffix || domain.ends_with(&format!(".{}", suffix))
+}
+
+fn is_reachable(host: &str) -> bool {
+    let Ok(addrs) = host.to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, REACHABILITY_TIMEOUT).is_ok())
+}
+
+/// The first check which matches the current network, blocking while hosts are probed
+///
+/// While connected, pass the [IpInfo] of the tunnel: the domains pushed by the gateway are ignored and hosts
+/// are not probed, because both would match through the tunnel itself.
+pub fn detect(networks: &[TrustedNetwork], connected: Option<&IpInfo>) -> Option<TrustedNetwork> {
+    let pushed_domains: Vec<String> = connected
+        .into_iter()
+        .flat_map(|info| {
+            info.domain
+                .iter()
+                .chain(&info.search_domains)
+                .chain(&info.split_dns_domains)
+        })
+        .map(|domain| domain.trim_end_matches('.').to_lowercase())
+        .collect();
+    let domains: Vec<String> = system_resolver_domains()
+        .into_iter()
+        .filter(|domain| !pushed_domains.contains(domain))
+        .collect();
+
+    networks
+        .iter()
+        .find(|network| match network {
+            TrustedNetwork::DnsSuffix(suffix) => {
+                domains.iter().any(|domain| matches_suffix(domain, suffix))
+            }
+            TrustedNetwork::Reachable(host) => connected.is_none() && is_reachable(host),
+        })
+        .cloned()
+}
+
+#[test]
+fn test_trusted_network() {
+    assert_eq!(
+        "Corp.Example.com.".parse(),
+        Ok(TrustedNetwork::DnsSuffix("corp.example.com".to_string()))
+    );
+    assert_eq!(
+        "intranet.corp:443".parse(),
+        Ok(TrustedNetwork::Reachable("intranet.corp:443".to_string()))
+    );
+    assert!("intranet.corp:0".parse::<TrustedNetwork>().is_err());
+    assert!("corp example".parse::<TrustedNetwork>().is_err());
+
+    let domains = resolver_domains(
+        "nameserver 10.0.0.1\nsearch office.corp.example.com lan\ndomain corp.example.com.\n",
+    );
+    assert_eq!(
+        domains,
+        vec!["office.corp.example.com", "lan", "corp.example.com"]
+    );
+    assert!(matches_suffix(
+        "office.corp.example.com",
+        "corp.example.com"
+    ));
+    assert!(!matches_suffix("notcorp.example.com", "corp.example.com"));
+}
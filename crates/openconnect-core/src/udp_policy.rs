@@ -0,0 +1,241 @@
+//! Per-network UDP (DTLS) policy, e.g. to connect over TLS only on a network which blocks UDP
+//!
+//! A rule matches either the SSID of the current Wi-Fi network, or the interface of the default route.
+//! Rules are written as `ssid:<SSID>=on|off` or `interface:<NAME>=on|off`, the first matching rule decides.
+
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkMatch {
+    Ssid(String),
+    Interface(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpRule {
+    pub network: NetworkMatch,
+    pub enable_udp: bool,
+}
+
+impl std::str::FromStr for UdpRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid UDP rule {}, expected ssid:<SSID>=on|off or interface:<NAME>=on|off",
+                value
+            )
+        };
+        let (network, enable_udp) = value.rsplit_once('=').ok_or_else(invalid)?;
+        let enable_udp = match enable_udp.trim().to_lowercase().as_str() {
+            "on" | "true" => true,
+            "off" | "false" => false,
+            _ => return Err(invalid()),
+        };
+
+        let network = match network.split_once(':').ok_or_else(invalid)? {
+            (_, "") => return Err(invalid()),
+            ("ssid", ssid) => NetworkMatch::Ssid(ssid.to_string()),
+            ("interface", name) if !name.contains(char::is_whitespace) => {
+                NetworkMatch::Interface(name.to_string())
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(UdpRule {
+            network,
+            enable_udp,
+        })
+    }
+}
+
+impl std::fmt::Display for UdpRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let enable_udp = if self.enable_udp { "on" } else { "off" };
+        match &self.network {
+            NetworkMatch::Ssid(ssid) => write!(f, "ssid:{}={}", ssid, enable_udp),
+            NetworkMatch::Interface(name) => write!(f, "interface:{}={}", name, enable_udp),
+        }
+    }
+}
+
+/// What is known about the network the machine is on, either may be undetectable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CurrentNetwork {
+    pub ssid: Option<String>,
+    pub interface: Option<String>,
+}
+
+impl CurrentNetwork {
+    /// Detect the default route interface and its SSID, blocking while the system tools run
+    pub fn detect() -> Self {
+        let interface = default_route_interface();
+        let ssid = current_ssid(interface.as_deref());
+        CurrentNetwork { ssid, interface }
+    }
+}
+
+/// Whether UDP should be used on the network, `None` if no rule matches and the default applies
+pub fn decide(rules: &[UdpRule], network: &CurrentNetwork) -> Option<bool> {
+    rules
+        .iter()
+        .find(|rule| match &rule.network {
+            NetworkMatch::Ssid(ssid) => network.ssid.as_ref() == Some(ssid),
+            NetworkMatch::Interface(name) => network.interface.as_ref() == Some(name),
+        })
+        .map(|rule| rule.enable_udp)
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The interface of the IPv4 default route in `/proc/net/route`
+#[cfg(any(target_os = "linux", test))]
+fn proc_route_default_interface(proc_route: &str) -> Option<String> {
+    proc_route.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        (fields.next()? == "00000000").then(|| interface.to_string())
+    })
+}
+
+/// The `interface:` line of `route -n get default`
+#[cfg(any(target_os = "macos", test))]
+fn route_get_interface(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("interface:")
+            .map(|interface| interface.trim().to_string())
+    })
+}
+
+fn default_route_interface() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    return std::fs::read_to_string("/proc/net/route")
+        .ok()
+        .and_then(|proc_route| proc_route_default_interface(&proc_route));
+
+    #[cfg(target_os = "macos")]
+    return command_output("route", &["-n", "get", "default"])
+        .and_then(|output| route_get_interface(&output));
+
+    // TODO: read the default route on windows
+    #[cfg(target_os = "windows")]
+    return None;
+}
+
+/// The SSID of `nmcli -t -f active,ssid dev wifi`, escaped colons in the SSID are kept
+#[cfg(any(target_os = "linux", test))]
+fn nmcli_active_ssid(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        .filter(|ssid| !ssid.is_empty())
+        .map(|ssid| ssid.replace("\\:", ":"))
+}
+
+/// The SSID of `networksetup -getairportnetwork <interface>` or `netsh wlan show interfaces`
+#[cfg(any(target_os = "macos", target_os = "windows", test))]
+fn labeled_ssid(output: &str, label: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == label)
+            .then(|| value.trim().to_string())
+            .filter(|ssid| !ssid.is_empty())
+    })
+}
+
+/// The SSID of the Wi-Fi network, on macOS the one of the interface
+fn current_ssid(interface: Option<&str>) -> Option<String> {
+    #[cfg(not(target_os = "macos"))]
+    let _ = interface;
+
+    #[cfg(target_os = "linux")]
+    return command_output("iwgetid", &["-r"])
+        .map(|output| output.trim().to_string())
+        .filter(|ssid| !ssid.is_empty())
+        .or_else(|| {
+            command_output("nmcli", &["-t", "-f", "active,ssid", "dev", "wifi"])
+                .and_then(|output| nmcli_active_ssid(&output))
+        });
+
+    #[cfg(target_os = "macos")]
+    return command_output("networksetup", &["-getairportnetwork", interface?])
+        .and_then(|output| labeled_ssid(&output, "Current Wi-Fi Network"));
+
+    #[cfg(target_os = "windows")]
+    return command_output("netsh", &["wlan", "show", "interfaces"])
+        .and_then(|output| labeled_ssid(&output, "SSID"));
+}
+
+#[test]
+fn test_udp_rule() {
+    assert_eq!(
+        "ssid:Office Wi-Fi=off".parse(),
+        Ok(UdpRule {
+            network: NetworkMatch::Ssid("Office Wi-Fi".to_string()),
+            enable_udp: false,
+        })
+    );
+    assert_eq!(
+        "interface:eth0=on"
+            .parse::<UdpRule>()
+            .map(|rule| rule.to_string()),
+        Ok("interface:eth0=on".to_string())
+    );
+    assert!("ssid:=off".parse::<UdpRule>().is_err());
+    assert!("interface:eth0=maybe".parse::<UdpRule>().is_err());
+    assert!("eth0=off".parse::<UdpRule>().is_err());
+
+    let rules: Vec<UdpRule> = ["ssid:Office=off", "interface:wlan0=on"]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+    let office = CurrentNetwork {
+        ssid: Some("Office".to_string()),
+        interface: Some("wlan0".to_string()),
+    };
+    let home = CurrentNetwork {
+        ssid: Some("Home".to_string()),
+        interface: Some("wlan0".to_string()),
+    };
+    assert_eq!(decide(&rules, &office), Some(false));
+    assert_eq!(decide(&rules, &home), Some(true));
+    assert_eq!(decide(&rules, &CurrentNetwork::default()), None);
+}
+
+#[test]
+fn test_detect_parsers() {
+    let proc_route = "Iface\tDestination\tGateway\tFlags\n\
+        docker0\t000011AC\t00000000\t0001\n\
+        wlp2s0\t00000000\t0100A8C0\t0003\n";
+    assert_eq!(
+        proc_route_default_interface(proc_route),
+        Some("wlp2s0".to_string())
+    );
+    assert_eq!(
+        route_get_interface("   route to: default\n  gateway: 192.168.0.1\n  interface: en0\n"),
+        Some("en0".to_string())
+    );
+    assert_eq!(
+        nmcli_active_ssid("no:Neighbour\nyes:Lab\\:5G\n"),
+        Some("Lab:5G".to_string())
+    );
+    assert_eq!(
+        labeled_ssid("Current Wi-Fi Network: Office\n", "Current Wi-Fi Network"),
+        Some("Office".to_string())
+    );
+    assert_eq!(
+        labeled_ssid(
+            "    Name : Wi-Fi\n    SSID : Home\n    BSSID : aa:bb\n",
+            "SSID"
+        ),
+        Some("Home".to_string())
+    );
+}
@@ -0,0 +1,81 @@
+//! Post-connect health check, an HTTP request to a URL behind the tunnel
+//!
+//! Stronger than a TCP probe for internal services which only speak HTTP. Redirects are not followed, so that the
+//! status of the URL itself is checked, e.g. the 302 of an intranet page redirecting to its login.
+
+use std::time::Duration;
+
+/// Time given to the URL to answer, including the TLS handshake
+pub const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("Failed to request {0}: {1}")]
+    RequestError(String, String),
+
+    #[error("{url} answered with status {status}, expected {expected}")]
+    UnexpectedStatus {
+        url: String,
+        status: u16,
+        expected: String,
+    },
+}
+
+/// Validate a verify URL, only http and https are requested
+pub fn parse_verify_url(value: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(value).map_err(|e| format!("Invalid URL {}: {}", value, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!("Expected an http(s) URL, got {}", value));
+    }
+
+    Ok(value.to_string())
+}
+
+/// Without an expected status any 2xx passes
+pub fn status_matches(status: u16, expect_status: Option<u16>) -> bool {
+    match expect_status {
+        Some(expect_status) => status == expect_status,
+        None => (200..300).contains(&status),
+    }
+}
+
+/// Request the URL through the tunnel, returns the status it answered with
+pub async fn verify_url(url: &str, expect_status: Option<u16>) -> Result<u16, VerifyError> {
+    let request_error =
+        |e: reqwest::Error| VerifyError::RequestError(url.to_string(), e.to_string());
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(VERIFY_TIMEOUT)
+        .build()
+        .map_err(request_error)?;
+
+    let status = client
+        .get(url)
+        .send()
+        .await
+        .map_err(request_error)?
+        .status()
+        .as_u16();
+    if !status_matches(status, expect_status) {
+        return Err(VerifyError::UnexpectedStatus {
+            url: url.to_string(),
+            status,
+            expected: expect_status.map_or("2xx".to_string(), |status| status.to_string()),
+        });
+    }
+
+    Ok(status)
+}
+
+#[test]
+fn test_verify_url() {
+    assert!(parse_verify_url("https://intranet.corp/health").is_ok());
+    assert!(parse_verify_url("http://10.0.0.1:8080/").is_ok());
+    assert!(parse_verify_url("ftp://intranet.corp/").is_err());
+    assert!(parse_verify_url("intranet.corp/health").is_err());
+
+    assert!(status_matches(204, None));
+    assert!(!status_matches(302, None));
+    assert!(status_matches(302, Some(302)));
+    assert!(!status_matches(200, Some(302)));
+}
@@ -1,6 +1,13 @@
-use crate::state::{AppState, StateError};
-use openconnect_core::storage::{StoredConfigError, StoredConfigsJson, StoredServer};
-use std::fmt::Display;
+use crate::{
+    last_status::{self, LastStatus},
+    preferences::Preferences,
+    state::{AppState, LogPayload, StateError, StatusPayload},
+};
+use openconnect_core::{
+    storage::{StoredConfigError, StoredConfigsJson, StoredServer},
+    AuthGroup,
+};
+use std::{collections::HashMap, fmt::Display};
 
 #[derive(serde::Serialize, thiserror::Error, Debug)]
 pub struct ErrorResponse {
@@ -19,10 +26,11 @@ impl From<StateError> for ErrorResponse {
         let code = match e {
             StateError::ConfigError(_) => "CONFIG_ERROR",
             StateError::OpenconnectError(_) => "OPENCONNECT_ERROR",
-            StateError::ChannelError(_) => "CHANNEL_ERROR",
             StateError::TauriError(_) => "TAURI_ERROR",
             StateError::OpenIdError(_) => "OPENID_ERROR",
             StateError::IoError(_) => "IO_ERROR",
+            StateError::SecretError(_) => "SECRET_ERROR",
+            StateError::CaptivePortal(_) => "CAPTIVE_PORTAL",
         };
         Self {
             code: code.to_string(),
@@ -50,8 +58,20 @@ impl From<StoredConfigError> for ErrorResponse {
 pub async fn connect_with_password(
     app_state: tauri::State<'_, AppState>,
     server_name: String,
+    auth_group: Option<String>,
 ) -> Result<(), ErrorResponse> {
-    Ok(app_state.connect_with_user_pass(&server_name).await?)
+    Ok(app_state
+        .connect_with_user_pass(&server_name, auth_group)
+        .await?)
+}
+
+#[tauri::command]
+pub async fn probe_auth_groups(
+    app_state: tauri::State<'_, AppState>,
+    server: String,
+    allow_insecure: bool,
+) -> Result<Vec<AuthGroup>, ErrorResponse> {
+    Ok(app_state.probe_auth_groups(&server, allow_insecure).await?)
 }
 
 #[tauri::command]
@@ -62,6 +82,15 @@ pub async fn connect_with_oidc(
     Ok(app_state.connect_with_oidc(&server_name).await?)
 }
 
+#[tauri::command]
+pub async fn connect_with_cookie(
+    app_state: tauri::State<'_, AppState>,
+    server_name: String,
+    cookie: String,
+) -> Result<(), ErrorResponse> {
+    Ok(app_state.connect_with_cookie(&server_name, &cookie).await?)
+}
+
 #[tauri::command]
 pub async fn disconnect(app_state: tauri::State<'_, AppState>) -> Result<(), ErrorResponse> {
     Ok(app_state.disconnect().await?)
@@ -70,10 +99,48 @@ pub async fn disconnect(app_state: tauri::State<'_, AppState>) -> Result<(), Err
 #[tauri::command]
 pub async fn trigger_state_retrieve(
     app_state: tauri::State<'_, AppState>,
-) -> Result<(), ErrorResponse> {
+) -> Result<StatusPayload, ErrorResponse> {
     Ok(app_state.trigger_state_retrieve().await?)
 }
 
+/// The cached status of each server, for the initial view before a connection is made
+#[tauri::command]
+pub async fn get_last_statuses() -> Result<HashMap<String, LastStatus>, ErrorResponse> {
+    Ok(last_status::load().map_err(StateError::from)?)
+}
+
+#[tauri::command]
+pub async fn get_preferences(
+    app_state: tauri::State<'_, AppState>,
+) -> Result<Preferences, ErrorResponse> {
+    Ok(app_state.preferences.read().await.clone())
+}
+
+/// Seconds between the `traffic-stats` events while connected, none disables them
+#[tauri::command]
+pub async fn set_traffic_stats_interval(
+    app_state: tauri::State<'_, AppState>,
+    secs: Option<u64>,
+) -> Result<(), ErrorResponse> {
+    Ok(app_state.set_traffic_stats_interval(secs).await?)
+}
+
+#[tauri::command]
+pub async fn get_connection_logs(
+    app_state: tauri::State<'_, AppState>,
+) -> Result<Vec<LogPayload>, ErrorResponse> {
+    let logs = app_state.connection_logs.read().await;
+    Ok(logs.iter().cloned().collect())
+}
+
+#[tauri::command]
+pub async fn clear_connection_logs(
+    app_state: tauri::State<'_, AppState>,
+) -> Result<(), ErrorResponse> {
+    app_state.connection_logs.write().await.clear();
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_stored_configs(
     app_state: tauri::State<'_, AppState>,
@@ -0,0 +1,94 @@
+//! The last status of each server, recorded on every transition so that a freshly started GUI can show when a
+//! server was last connected before it has a client of its own
+//!
+//! Like the server stats of the CLI, they are stored next to the config file.
+
+use crate::state::StatusPayload;
+use openconnect_core::paths;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastStatus {
+    pub status: String,
+    pub message: Option<String>,
+
+    /// Milliseconds since the unix epoch, like the timestamps of the connection log
+    pub timestamp: u64,
+
+    /// When the server was last connected, kept across the transitions after it
+    pub last_connected: Option<u64>,
+}
+
+/// The last status of all servers, empty if none was recorded yet
+pub fn load() -> std::io::Result<HashMap<String, LastStatus>> {
+    let json = match std::fs::read_to_string(paths::last_status_file()?) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+fn next_status(
+    previous: Option<&LastStatus>,
+    status: &StatusPayload,
+    timestamp: u64,
+) -> LastStatus {
+    let last_connected = if status.status == "CONNECTED" {
+        Some(timestamp)
+    } else {
+        previous.and_then(|previous| previous.last_connected)
+    };
+
+    LastStatus {
+        status: status.status.clone(),
+        message: status.message.clone(),
+        timestamp,
+        last_connected,
+    }
+}
+
+/// Record the status a server just transitioned to
+pub fn record(server_name: &str, status: &StatusPayload) -> std::io::Result<()> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    let mut all_statuses = load()?;
+    let last_status = next_status(all_statuses.get(server_name), status, timestamp);
+    all_statuses.insert(server_name.to_string(), last_status);
+
+    // replaced in one step, so that a crash never leaves a truncated file behind
+    let status_file = paths::last_status_file()?;
+    let tmp_file = status_file.with_extension("json.tmp");
+    std::fs::write(&tmp_file, serde_json::to_string_pretty(&all_statuses)?)?;
+    std::fs::rename(tmp_file, status_file)
+}
+
+#[test]
+fn test_next_status() {
+    let connected = next_status(
+        None,
+        &StatusPayload {
+            status: "CONNECTED".to_string(),
+            message: None,
+        },
+        1000,
+    );
+    assert_eq!(connected.last_connected, Some(1000));
+
+    let disconnected = next_status(
+        Some(&connected),
+        &StatusPayload {
+            status: "DISCONNECTED".to_string(),
+            message: None,
+        },
+        5000,
+    );
+    assert_eq!(disconnected.status, "DISCONNECTED");
+    assert_eq!(disconnected.timestamp, 5000);
+    assert_eq!(disconnected.last_connected, Some(1000));
+}
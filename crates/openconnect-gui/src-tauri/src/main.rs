@@ -2,6 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod command;
+mod last_status;
+mod preferences;
 mod state;
 mod system_tray;
 
@@ -9,7 +11,53 @@ use command::*;
 use openconnect_core::storage::StoredConfigs;
 use state::AppState;
 use system_tray::AppSystemTray;
-use tauri::Manager;
+use tauri::{
+    api::dialog::{MessageDialogBuilder, MessageDialogKind},
+    Manager,
+};
+
+/// The path of the vpnc-script bundled as a resource, made executable on unix
+fn resolve_vpnc_script(app: &tauri::App) -> Result<String, String> {
+    #[cfg(target_os = "windows")]
+    {
+        let resource_path = app
+            .path_resolver()
+            .resolve_resource("vpnc-script-win.js")
+            .ok_or("failed to resolve resource vpnc-script-win.js")?;
+
+        dunce::canonicalize(&resource_path)
+            .map(|path| path.to_string_lossy().to_string())
+            .map_err(|e| format!("{}: {}", resource_path.display(), e))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let resource_path = app
+            .path_resolver()
+            .resolve_resource("vpnc-script")
+            .ok_or("failed to resolve resource vpnc-script")?;
+        let with_path = |e: std::io::Error| format!("{}: {}", resource_path.display(), e);
+
+        let file = std::fs::OpenOptions::new()
+            .write(false)
+            .create(false)
+            .append(false)
+            .read(true)
+            .open(&resource_path)
+            .map_err(with_path)?;
+
+        let permissions = file.metadata().map_err(with_path)?.permissions();
+        let is_executable = permissions.mode() & 0o111 != 0;
+        if !is_executable {
+            let mut permissions = permissions;
+            permissions.set_mode(0o755);
+            file.set_permissions(permissions).map_err(with_path)?;
+        }
+
+        Ok(resource_path.to_string_lossy().to_string())
+    }
+}
 
 fn main() {
     let config_file = StoredConfigs::getorinit_config_file().expect("failed to get config file");
@@ -90,45 +138,22 @@ fn main() {
             // #[cfg(target_os = "macos")]
             // app.set_activation_policy(tauri::ActivationPolicy::Accessory);
 
-            let vpnc_script = {
-                #[cfg(target_os = "windows")]
-                {
-                    let resource_path = app
-                        .path_resolver()
-                        .resolve_resource("vpnc-script-win.js")
-                        .expect("failed to resolve resource");
-
-                    dunce::canonicalize(resource_path)
-                        .expect("failed to canonicalize path")
-                        .to_string_lossy()
-                        .to_string()
-                }
-
-                #[cfg(not(target_os = "windows"))]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    let resource_path = app
-                        .path_resolver()
-                        .resolve_resource("vpnc-script")
-                        .expect("failed to resolve resource");
-
-                    let file = std::fs::OpenOptions::new()
-                        .write(false)
-                        .create(false)
-                        .append(false)
-                        .read(true)
-                        .open(resource_path.clone())
-                        .expect("failed to open file");
-
-                    let permissions = file.metadata().unwrap().permissions();
-                    let is_executable = permissions.mode() & 0o111 != 0;
-                    if !is_executable {
-                        let mut permissions = permissions;
-                        permissions.set_mode(0o755);
-                        file.set_permissions(permissions).unwrap();
+            let vpnc_script = match resolve_vpnc_script(app) {
+                Ok(vpnc_script) => vpnc_script,
+                Err(e) => {
+                    // the app can't connect without it, tell the user instead of panicking
+                    eprintln!("VPN helper script missing: {}", e);
+                    if let Some(window) = app.get_window("main") {
+                        let _ = window.hide();
                     }
-
-                    resource_path.to_string_lossy().to_string()
+                    let app_handle = app.app_handle();
+                    MessageDialogBuilder::new(
+                        "VPN helper script missing",
+                        format!("VPN helper script missing — reinstall the app.\n\n{}", e),
+                    )
+                    .kind(MessageDialogKind::Error)
+                    .show(move |_| app_handle.exit(1));
+                    return Ok(());
                 }
             };
 
@@ -150,12 +175,19 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             disconnect,
             trigger_state_retrieve,
+            get_last_statuses,
+            get_connection_logs,
+            clear_connection_logs,
             get_stored_configs,
             upsert_stored_server,
             set_default_server,
             remove_server,
             connect_with_password,
             connect_with_oidc,
+            connect_with_cookie,
+            probe_auth_groups,
+            get_preferences,
+            set_traffic_stats_interval,
         ])
         .build(tauri::generate_context!())
         .unwrap();
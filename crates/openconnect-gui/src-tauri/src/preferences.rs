@@ -0,0 +1,70 @@
+//! The preferences of the GUI, stored next to the config file like the last statuses
+
+use openconnect_core::paths;
+use std::time::Duration;
+
+/// Bounds of the traffic stats interval, every second at most
+pub const TRAFFIC_STATS_INTERVAL_SECS: std::ops::RangeInclusive<u64> = 1..=60;
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preferences {
+    /// Seconds between the `traffic-stats` events while connected, none are emitted if unset
+    #[serde(default)]
+    pub traffic_stats_interval_secs: Option<u64>,
+}
+
+impl Preferences {
+    pub fn traffic_stats_interval(&self) -> Option<Duration> {
+        self.traffic_stats_interval_secs.map(Duration::from_secs)
+    }
+}
+
+/// Validate a traffic stats interval, before it is stored
+pub fn check_traffic_stats_interval(secs: Option<u64>) -> Result<(), String> {
+    match secs {
+        Some(secs) if !TRAFFIC_STATS_INTERVAL_SECS.contains(&secs) => Err(format!(
+            "Traffic stats interval must be between {} and {} seconds, got {}",
+            TRAFFIC_STATS_INTERVAL_SECS.start(),
+            TRAFFIC_STATS_INTERVAL_SECS.end(),
+            secs
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The stored preferences, the defaults if none were stored yet
+pub fn load() -> std::io::Result<Preferences> {
+    let json = match std::fs::read_to_string(paths::gui_preferences_file()?) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Preferences::default()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+pub fn save(preferences: &Preferences) -> std::io::Result<()> {
+    let preferences_file = paths::gui_preferences_file()?;
+    let tmp_file = preferences_file.with_extension("json.tmp");
+    std::fs::write(&tmp_file, serde_json::to_string_pretty(preferences)?)?;
+    std::fs::rename(tmp_file, preferences_file)
+}
+
+#[test]
+fn test_traffic_stats_interval() {
+    assert!(check_traffic_stats_interval(None).is_ok());
+    assert!(check_traffic_stats_interval(Some(1)).is_ok());
+    assert!(check_traffic_stats_interval(Some(60)).is_ok());
+    assert!(check_traffic_stats_interval(Some(0)).is_err());
+    assert!(check_traffic_stats_interval(Some(61)).is_err());
+
+    let preferences: Preferences = serde_json::from_str("{}").unwrap();
+    assert_eq!(preferences.traffic_stats_interval(), None);
+    let preferences: Preferences =
+        serde_json::from_str(r#"{"trafficStatsIntervalSecs":5}"#).unwrap();
+    assert_eq!(
+        preferences.traffic_stats_interval(),
+        Some(Duration::from_secs(5))
+    );
+}
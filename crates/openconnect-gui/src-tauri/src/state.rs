@@ -1,20 +1,31 @@
-use crate::system_tray::AppSystemTray;
+use crate::{
+    last_status,
+    preferences::{self, Preferences},
+    system_tray::AppSystemTray,
+};
+use futures::StreamExt;
 use openconnect_core::{
+    captive_portal::{self, CaptivePortal},
     config::{ConfigBuilder, EntrypointBuilder, LogLevel},
     events::EventHandlers,
-    storage::{StoredConfigError, StoredConfigs, StoredServer},
-    Connectable, Status, VpnClient,
+    protocols::find_protocol,
+    proxy::resolve_proxy,
+    stats::Stats,
+    storage::{OidcServer, PasswordServer, StoredConfigError, StoredConfigs, StoredServer},
+    AuthGroup, Connectable, Status, VpnClient,
 };
 use openconnect_oidc::{
     obtain_cookie_by_oidc_token,
+    oidc_logout::{self, OpenIDLogoutConfig},
     oidc_token::{OpenIDTokenAuth, OpenIDTokenAuthConfig, OpenIDTokenAuthError, OIDC_REDIRECT_URI},
 };
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::VecDeque, path::PathBuf, sync::Arc};
 use tauri::{
+    api::notification::Notification,
     async_runtime::{channel, RwLock, Sender},
-    Manager, State,
+    AppHandle, Manager, State,
 };
-use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, thiserror::Error)]
@@ -25,9 +36,6 @@ pub enum StateError {
     #[error("Openconnect error: {0}")]
     OpenconnectError(#[from] openconnect_core::result::OpenconnectError),
 
-    #[error("Channel error: {0}")]
-    ChannelError(#[from] SendError<VpnEvent>),
-
     #[error("Tauri error: {0}")]
     TauriError(#[from] tauri::Error),
 
@@ -36,11 +44,18 @@ pub enum StateError {
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("Secret error: {0}")]
+    SecretError(#[from] openconnect_core::secret::SecretError),
+
+    #[error("{0}")]
+    CaptivePortal(#[from] CaptivePortal),
 }
 
 #[derive(Debug, Clone)]
 pub enum VpnEvent {
     Status { status: StatusPayload },
+    Log { log: LogPayload },
 }
 
 #[derive(serde::Serialize, Debug, Clone)]
@@ -64,10 +79,136 @@ impl From<Status> for StatusPayload {
     }
 }
 
+/// Max number of connection log entries kept for the log panel
+const CONNECTION_LOG_CAPACITY: usize = 500;
+
+/// How long a disconnect waits for the OIDC issuer to end the session
+const OIDC_LOGOUT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often the traffic stats task looks for a connection while paused
+const TRAFFIC_STATS_IDLE_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct LogPayload {
+    pub level: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// Payload of the `traffic-stats` event, the counters of the connection for a live throughput graph
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_pkts: u64,
+    pub tx_pkts: u64,
+
+    /// Milliseconds since the unix epoch, like the timestamps of the connection log
+    pub timestamp: u64,
+}
+
+impl From<Stats> for TrafficStats {
+    fn from(stats: Stats) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_pkts: stats.rx_pkts,
+            tx_pkts: stats.tx_pkts,
+            timestamp,
+        }
+    }
+}
+
+impl LogPayload {
+    pub fn new(level: LogLevel, message: &str) -> Self {
+        let level = match level {
+            LogLevel::Err => "ERROR",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        };
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            level: level.to_string(),
+            message: message.to_string(),
+            timestamp,
+        }
+    }
+}
+
+/// Desktop notification for the transitions worth noticing while the window is hidden, titled with the server name
+fn notify_status(handle: &AppHandle, status: &StatusPayload, server_name: Option<&str>) {
+    let server_name = server_name.unwrap_or("VPN");
+    let (title, body) = match status.status.as_str() {
+        "CONNECTED" => (format!("{} connected", server_name), None),
+        "DISCONNECTED" => (format!("{} disconnected", server_name), None),
+        "ERROR" => (format!("{} failed", server_name), status.message.clone()),
+        _ => return,
+    };
+
+    let mut notification = Notification::new(&handle.config().tauri.bundle.identifier).title(title);
+    if let Some(body) = body {
+        notification = notification.body(body);
+    }
+    if let Err(e) = notification.show() {
+        eprintln!("Error while showing notification: {:?}", e);
+    }
+}
+
+/// Emit `traffic-stats` at the interval of the preferences while connected, paused while disconnected or disabled
+fn spawn_traffic_stats(handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let app_state: State<'_, AppState> = handle.state();
+            let interval = app_state.preferences.read().await.traffic_stats_interval();
+            // a connect in progress holds the lock, look again later
+            let client = app_state
+                .client
+                .try_read()
+                .ok()
+                .and_then(|client| client.clone())
+                .filter(|client| client.get_status() == Status::Connected);
+            let (Some(interval), Some(client)) = (interval, client) else {
+                tokio::time::sleep(TRAFFIC_STATS_IDLE_POLL).await;
+                continue;
+            };
+
+            // ends with the connection, or is resubscribed once the interval was changed
+            let mut stats_stream = Box::pin(client.stats_stream(interval));
+            while let Some(stats) = stats_stream.next().await {
+                if let Err(e) = handle.emit_all("traffic-stats", TrafficStats::from(stats)) {
+                    eprintln!("Error while emitting event: {:?}", e);
+                }
+                if app_state.preferences.read().await.traffic_stats_interval() != Some(interval) {
+                    break;
+                }
+            }
+        }
+    });
+}
+
 pub struct AppState {
     pub(crate) event_tx: Sender<VpnEvent>,
+
+    /// The transitions of the client, apart from [AppState::event_tx] so that a burst of log lines can't drop them
+    pub(crate) status_tx: UnboundedSender<VpnEvent>,
     pub(crate) client: RwLock<Option<Arc<VpnClient>>>,
     pub(crate) stored_configs: RwLock<StoredConfigs>,
+    pub(crate) connection_logs: RwLock<VecDeque<LogPayload>>,
+
+    /// The session at the OIDC issuer ended on disconnect, see [OidcServer::oidc_logout]
+    pub(crate) oidc_logout: RwLock<Option<OpenIDLogoutConfig>>,
+    pub(crate) preferences: RwLock<Preferences>,
     #[allow(dead_code)]
     pub(crate) vpnc_sciprt: String,
 }
@@ -79,23 +220,73 @@ impl AppState {
         config_file: PathBuf,
     ) -> Result<(), StateError> {
         let (event_tx, mut event_rx) = channel::<VpnEvent>(100);
-        let app_state = AppState::new(event_tx, vpnc_scipt, config_file).await?;
+        let (status_tx, mut status_rx) = unbounded_channel::<VpnEvent>();
+        let app_state = AppState::new(event_tx, status_tx, vpnc_scipt, config_file).await?;
         app.manage(app_state);
 
         let handle = app.app_handle();
+        spawn_traffic_stats(handle.clone());
 
         tauri::async_runtime::spawn(async move {
-            while let Some(event) = event_rx.recv().await {
+            // the client is gone by the time its disconnect is handled, keep naming the server it was connected to
+            let mut server_name: Option<String> = None;
+            loop {
+                let event = tokio::select! {
+                    Some(event) = status_rx.recv() => event,
+                    Some(event) = event_rx.recv() => event,
+                    else => break,
+                };
                 let handle = handle.clone();
                 let app_system_tray: State<'_, Arc<AppSystemTray>> = handle.state();
                 match event {
                     VpnEvent::Status { status } => {
+                        let app_state: State<'_, AppState> = handle.state();
+                        // a connect in progress holds the lock, don't wait for it
+                        if let Some(name) = app_state.client.try_read().ok().and_then(|client| {
+                            client.as_ref().and_then(|client| client.get_server_name())
+                        }) {
+                            server_name = Some(name);
+                        }
+                        let notify = match server_name.as_deref() {
+                            Some(name) => app_state
+                                .stored_configs
+                                .read()
+                                .await
+                                .servers
+                                .get(name)
+                                .map_or(true, StoredServer::notify),
+                            None => true,
+                        };
+                        if notify {
+                            notify_status(&handle, &status, server_name.as_deref());
+                        }
+                        if let Some(ref name) = server_name {
+                            // the initial status of a new client says nothing about the server
+                            if status.status != "INITIALIZED" {
+                                if let Err(e) = last_status::record(name, &status) {
+                                    eprintln!("Error while recording last status: {:?}", e);
+                                }
+                            }
+                        }
                         let result = handle.emit_all("vpnStatus", Some(status));
                         app_system_tray.recreate(&handle).await.unwrap();
                         if let Err(e) = result {
                             eprintln!("Error while emitting event: {:?}", e);
                         }
                     }
+                    VpnEvent::Log { log } => {
+                        let app_state: State<'_, AppState> = handle.state();
+                        {
+                            let mut logs = app_state.connection_logs.write().await;
+                            if logs.len() >= CONNECTION_LOG_CAPACITY {
+                                logs.pop_front();
+                            }
+                            logs.push_back(log.clone());
+                        }
+                        if let Err(e) = handle.emit_all("connection-log", Some(log)) {
+                            eprintln!("Error while emitting event: {:?}", e);
+                        }
+                    }
                 }
             }
         });
@@ -116,41 +307,103 @@ impl AppState {
         Ok((state.into(), name))
     }
 
-    pub async fn trigger_state_retrieve(&self) -> Result<(), StateError> {
+    /// The current status for the initial fetch of a window, later transitions are pushed as `vpnStatus` events
+    pub async fn trigger_state_retrieve(&self) -> Result<StatusPayload, StateError> {
         let (status, _server_name) = self.get_status_and_name().await?;
-        Ok(self.event_tx.send(VpnEvent::Status { status }).await?)
+        Ok(status)
     }
 
     pub async fn connect_with_server_name(&self, server_name: &str) -> Result<(), StateError> {
         let stored_server = self.stored_configs.read().await;
         let server = stored_server.servers.get(server_name);
         match server {
-            Some(StoredServer::Password(_)) => self.connect_with_user_pass(server_name).await,
+            Some(StoredServer::Password(_)) => self.connect_with_user_pass(server_name, None).await,
             Some(StoredServer::Oidc(_)) => self.connect_with_oidc(server_name).await,
             None => Err(StoredConfigError::BadInput("Server not found".to_string()).into()),
         }
     }
 
-    pub async fn connect_with_user_pass(&self, server_name: &str) -> Result<(), StateError> {
-        let stored_server = self.stored_configs.read().await;
-        let password_server = stored_server.get_server_as_password_server(server_name)?;
-        let password_server = &password_server.decrypted_by(&stored_server.cipher);
+    /// Connect to a password server, `auth_group` overrides and replaces the last used group of the server
+    pub async fn connect_with_user_pass(
+        &self,
+        server_name: &str,
+        auth_group: Option<String>,
+    ) -> Result<(), StateError> {
+        captive_portal::check().await?;
+
+        let mut stored_server = self.stored_configs.write().await;
+        let mut password_server = stored_server
+            .get_server_as_password_server(server_name)?
+            .decrypted_by(&stored_server.cipher);
+
+        if auth_group.is_some() && auth_group != password_server.auth_group {
+            password_server.auth_group = auth_group;
+            stored_server
+                .upsert_server(StoredServer::Password(password_server.clone()))
+                .await?;
+        }
+        drop(stored_server);
 
         let mut config = ConfigBuilder::default();
 
         #[cfg(not(target_os = "windows"))]
         let config = config.vpncscript(&self.vpnc_sciprt);
 
+        if let Some(proxy) = resolve_proxy(
+            None,
+            password_server.proxy.as_deref(),
+            &password_server.server,
+        ) {
+            config.http_proxy(&proxy);
+        }
+
         let config = config.loglevel(LogLevel::Info).build()?;
 
-        let entrypoint = EntrypointBuilder::new()
+        let mut entrypoint = EntrypointBuilder::new();
+        let entrypoint = entrypoint
             .name(&password_server.name)
             .server(&password_server.server)
             .username(&password_server.username)
-            .password(&password_server.password.clone().unwrap_or("".to_string()))
+            .password(&password_server.resolve_password()?.unwrap_or_default())
             .accept_insecure_cert(password_server.allow_insecure.unwrap_or(false))
             .enable_udp(true)
-            .build()?;
+            .web_auth(password_server.web_auth);
+
+        for (field, value) in &password_server.form_defaults {
+            entrypoint.form_default(field, value);
+        }
+
+        if let Some(ref auth_group) = password_server.auth_group {
+            entrypoint.auth_group(auth_group);
+        }
+
+        if let Some(ref reported_os) = password_server.reported_os {
+            entrypoint.reported_os(reported_os);
+        }
+
+        if let Some(mtu) = password_server.mtu {
+            entrypoint.mtu(mtu);
+        }
+
+        if let Some(ref server_cert) = password_server.server_cert {
+            entrypoint.accept_cert(server_cert);
+        }
+
+        if let Some(protocol) = password_server.protocol.as_deref().and_then(find_protocol) {
+            entrypoint.protocol(protocol);
+        }
+
+        if let Some(ref client_cert) = password_server.client_cert {
+            entrypoint.client_cert(&client_cert.to_string_lossy());
+            if let Some(ref client_key) = password_server.client_key {
+                entrypoint.client_key(&client_key.to_string_lossy());
+            }
+            if let Some(ref key_password) = password_server.key_password {
+                entrypoint.key_password(key_password);
+            }
+        }
+
+        let entrypoint = entrypoint.build()?;
 
         let event_handlers = self.create_event_handler();
 
@@ -167,15 +420,39 @@ impl AppState {
         Ok(())
     }
 
+    /// Fetch the auth groups offered by a server, without authenticating
+    pub async fn probe_auth_groups(
+        &self,
+        server: &str,
+        allow_insecure: bool,
+    ) -> Result<Vec<AuthGroup>, StateError> {
+        let config = ConfigBuilder::default().loglevel(LogLevel::Info).build()?;
+        let entrypoint = EntrypointBuilder::new()
+            .server(server)
+            .accept_insecure_cert(allow_insecure)
+            .build()?;
+
+        let groups = tauri::async_runtime::spawn_blocking(move || {
+            let client = VpnClient::new(config, EventHandlers::default())?;
+            client.probe_auth_groups(entrypoint)
+        })
+        .await??;
+
+        Ok(groups)
+    }
+
     pub async fn connect_with_oidc(&self, server_name: &str) -> Result<(), StateError> {
-        let stored_server = self.stored_configs.read().await;
-        let oidc_server = stored_server.get_server_as_oidc_server(server_name)?;
+        // before the browser is opened, the issuer is just as unreachable
+        captive_portal::check().await?;
+
+        let stored_configs = self.stored_configs.read().await;
+        let oidc_server = stored_configs.get_server_as_oidc_server(server_name)?;
 
         let openid_config = OpenIDTokenAuthConfig {
             issuer_url: oidc_server.issuer.clone(),
             redirect_uri: OIDC_REDIRECT_URI.to_string(),
             client_id: oidc_server.client_id.clone(),
-            client_secret: oidc_server.client_secret.clone(),
+            client_secret: oidc_server.resolve_client_secret()?,
             use_pkce_challenge: true,
         };
 
@@ -199,19 +476,99 @@ impl AppState {
                 "Failed to obtain cookie",
             )))?;
 
+        *self.oidc_logout.write().await = oidc_server.oidc_logout.then(|| OpenIDLogoutConfig {
+            issuer_url: oidc_server.issuer.clone(),
+            client_id: oidc_server.client_id.clone(),
+            id_token: token.clone(),
+        });
+
+        let stored_server = StoredServer::Oidc(oidc_server.clone());
+        drop(stored_configs);
+
+        self.connect_with_stored_cookie(&stored_server, &cookie)
+            .await
+    }
+
+    /// Connect to a stored server with a session cookie obtained by another tool, without any login
+    pub async fn connect_with_cookie(
+        &self,
+        server_name: &str,
+        cookie: &str,
+    ) -> Result<(), StateError> {
+        let cookie = cookie.trim();
+        if cookie.is_empty() {
+            return Err(StoredConfigError::BadInput("Cookie is empty".to_string()).into());
+        }
+        captive_portal::check().await?;
+
+        let stored_server = self
+            .stored_configs
+            .read()
+            .await
+            .servers
+            .get(server_name)
+            .cloned()
+            .ok_or(StoredConfigError::BadInput("Server not found".to_string()))?;
+
+        self.connect_with_stored_cookie(&stored_server, cookie)
+            .await
+    }
+
+    async fn connect_with_stored_cookie(
+        &self,
+        stored_server: &StoredServer,
+        cookie: &str,
+    ) -> Result<(), StateError> {
+        let (name, server, allow_insecure) = match stored_server {
+            StoredServer::Oidc(OidcServer {
+                name,
+                server,
+                allow_insecure,
+                ..
+            }) => (name, server, allow_insecure),
+            StoredServer::Password(PasswordServer {
+                name,
+                server,
+                allow_insecure,
+                ..
+            }) => (name, server, allow_insecure),
+        };
+
         let mut config = ConfigBuilder::default();
 
         #[cfg(not(target_os = "windows"))]
         let config = config.vpncscript(&self.vpnc_sciprt);
 
+        if let Some(proxy) = resolve_proxy(None, stored_server.proxy(), server) {
+            config.http_proxy(&proxy);
+        }
+
         let config = config.loglevel(LogLevel::Info).build()?;
 
-        let entrypoint = EntrypointBuilder::new()
-            .name(&oidc_server.name)
-            .server(&oidc_server.server)
-            .cookie(&cookie)
-            .accept_insecure_cert(oidc_server.allow_insecure.unwrap_or(false))
-            .build()?;
+        let mut entrypoint = EntrypointBuilder::new();
+        entrypoint
+            .name(name)
+            .server(server)
+            .cookie(cookie)
+            .accept_insecure_cert(allow_insecure.unwrap_or(false));
+
+        if let Some(reported_os) = stored_server.reported_os() {
+            entrypoint.reported_os(reported_os);
+        }
+
+        if let Some(mtu) = stored_server.mtu() {
+            entrypoint.mtu(mtu);
+        }
+
+        if let Some(server_cert) = stored_server.server_cert() {
+            entrypoint.accept_cert(server_cert);
+        }
+
+        if let Some(protocol) = stored_server.protocol() {
+            entrypoint.protocol(protocol);
+        }
+
+        let entrypoint = entrypoint.build()?;
 
         let event_handlers = self.create_event_handler();
 
@@ -230,6 +587,31 @@ impl AppState {
     }
 
     pub async fn disconnect(&self) -> Result<(), StateError> {
+        // before the tunnel goes down, the issuer may only be reachable through it
+        if let Some(logout) = self.oidc_logout.write().await.take() {
+            let log =
+                match tokio::time::timeout(OIDC_LOGOUT_TIMEOUT, oidc_logout::end_session(&logout))
+                    .await
+                {
+                    Ok(Ok(())) => LogPayload::new(
+                        LogLevel::Info,
+                        &format!("Logged out of OIDC issuer {}", logout.issuer_url),
+                    ),
+                    Ok(Err(e)) => LogPayload::new(
+                        LogLevel::Err,
+                        &format!(
+                            "Failed to log out of OIDC issuer {}: {}",
+                            logout.issuer_url, e
+                        ),
+                    ),
+                    Err(_) => LogPayload::new(
+                        LogLevel::Err,
+                        &format!("Timed out logging out of OIDC issuer {}", logout.issuer_url),
+                    ),
+                };
+            let _ = self.event_tx.send(VpnEvent::Log { log }).await;
+        }
+
         if let Some(client) = self.client.read().await.as_ref() {
             let client = client.clone();
             tauri::async_runtime::spawn_blocking(move || client.disconnect()).await?;
@@ -241,22 +623,46 @@ impl AppState {
         Ok(())
     }
 
+    /// Change the traffic stats interval, the running stream picks it up after its next event
+    pub async fn set_traffic_stats_interval(&self, secs: Option<u64>) -> Result<(), StateError> {
+        preferences::check_traffic_stats_interval(secs).map_err(StoredConfigError::BadInput)?;
+
+        let mut preferences = self.preferences.write().await;
+        let mut changed = preferences.clone();
+        changed.traffic_stats_interval_secs = secs;
+        preferences::save(&changed)?;
+        *preferences = changed;
+
+        Ok(())
+    }
+
     pub fn create_event_handler(&self) -> openconnect_core::events::EventHandlers {
-        let event_tx_for_state = self.event_tx.clone();
+        let status_tx = self.status_tx.clone();
         let event_tx_for_cert = self.event_tx.clone();
+        let event_tx_for_progress = self.event_tx.clone();
 
         EventHandlers::default()
             .with_handle_connection_state_change(move |state| {
-                let event_tx = event_tx_for_state.clone();
+                // queued in place rather than from a spawned task, so the transitions reach the window in order
+                if let Status::Error(ref err) = state {
+                    let _ = status_tx.send(VpnEvent::Log {
+                        log: LogPayload::new(LogLevel::Err, &err.to_string()),
+                    });
+                }
+                let _ = status_tx.send(VpnEvent::Status {
+                    status: state.into(),
+                });
+                // ignore the result, the receiver is only gone on exit
+            })
+            .with_handle_progress(move |level, message| {
+                let event_tx = event_tx_for_progress.clone();
+                let log = LogPayload::new(level, message);
                 tauri::async_runtime::spawn(async move {
-                    let _ = event_tx
-                        .send(VpnEvent::Status {
-                            status: state.into(),
-                        })
-                        .await;
+                    let _ = event_tx.send(VpnEvent::Log { log }).await;
                     // ignore the result
                 });
             })
+            .with_handle_external_browser(|url| open::that(url).is_ok())
             .with_handle_peer_cert_invalid(move |reason| {
                 let event_tx = event_tx_for_cert.clone();
                 let reason = reason.to_string();
@@ -277,15 +683,24 @@ impl AppState {
 
     pub async fn new(
         event_tx: Sender<VpnEvent>,
+        status_tx: UnboundedSender<VpnEvent>,
         vpnc_scipt: &str,
         config_file: PathBuf,
     ) -> Result<Self, StateError> {
         let mut stored_configs = StoredConfigs::new(None, config_file);
         stored_configs.read_from_file().await?;
+        let preferences = preferences::load().unwrap_or_else(|e| {
+            eprintln!("Error while loading preferences: {:?}", e);
+            Preferences::default()
+        });
         Ok(Self {
             event_tx,
+            status_tx,
             client: RwLock::new(None),
             stored_configs: RwLock::new(stored_configs),
+            connection_logs: RwLock::new(VecDeque::with_capacity(CONNECTION_LOG_CAPACITY)),
+            oidc_logout: RwLock::new(None),
+            preferences: RwLock::new(preferences),
             vpnc_sciprt: vpnc_scipt.to_string(),
         })
     }
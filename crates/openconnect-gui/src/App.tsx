@@ -14,12 +14,15 @@ import { useCallback, useEffect, useState } from "react";
 import { atom, useAtom } from "jotai";
 import { ServerSelector } from "./ServerSelector";
 import bg from "./assets/bg.jpeg";
-import { useStoredConfigs } from "./state";
+import { selectedAuthGroupAtom, useStoredConfigs } from "./state";
 import { toastError } from "./lib/toast";
 import { ToastContainer } from "react-toastify";
 import connected from "./assets/connected-animate.json";
 import Lottie from "lottie-react";
 import { AboutModal } from "./About";
+import { ConnectionLogModal } from "./ConnectionLog";
+import { CookieConnectModal } from "./CookieConnect";
+import { PreferencesModal } from "./Preferences";
 import { useKey } from "react-use";
 
 enum EStatus {
@@ -37,15 +40,29 @@ interface VpnStatus {
 
 const vpnStatusAtom = atom<VpnStatus>({ status: EStatus.Initialized });
 vpnStatusAtom.onMount = (set) => {
-  listen<VpnStatus>("vpnStatus", (event) => {
+  // transitions are pushed by the backend, the current status is only fetched once the listener is registered
+  const unlisten = listen<VpnStatus>("vpnStatus", (event) => {
     set(event.payload);
   });
+  unlisten.then(() =>
+    invoke<VpnStatus>("trigger_state_retrieve")
+      .then(set)
+      .catch(toastError)
+  );
+
+  return () => {
+    unlisten.then((f) => f());
+  };
 };
 
 function App() {
   const [vpnStatus] = useAtom(vpnStatusAtom);
   const { selectedServer } = useStoredConfigs();
+  const [selectedAuthGroup] = useAtom(selectedAuthGroupAtom);
   const [isAboutOpened, setIsAboutOpened] = useState(false);
+  const [isLogOpened, setIsLogOpened] = useState(false);
+  const [isCookieOpened, setIsCookieOpened] = useState(false);
+  const [isPreferencesOpened, setIsPreferencesOpened] = useState(false);
   const [mounted, setMounted] = useState(false);
 
   useKey(
@@ -58,7 +75,7 @@ function App() {
       }
     },
     undefined,
-    [vpnStatus, selectedServer]
+    [vpnStatus, selectedServer, selectedAuthGroup]
   );
 
   const handleConnect = useCallback(async () => {
@@ -73,6 +90,7 @@ function App() {
           case "password":
             await invoke("connect_with_password", {
               serverName: selectedServer.name,
+              authGroup: selectedAuthGroup,
             });
             break;
         }
@@ -80,7 +98,7 @@ function App() {
         toastError(e);
       }
     }
-  }, [selectedServer]);
+  }, [selectedServer, selectedAuthGroup]);
 
   const handleDisconnect = async () => {
     try {
@@ -97,7 +115,6 @@ function App() {
   }, [vpnStatus.status, vpnStatus.message]);
 
   useEffect(() => {
-    invoke("trigger_state_retrieve");
     setMounted(true);
   }, []);
 
@@ -207,12 +224,48 @@ function App() {
           >
             About this App
           </Link>
+          {" | "}
+          <Link
+            className="text-white underline text-xs"
+            onClick={() => setIsLogOpened(true)}
+          >
+            Connection Log
+          </Link>
+          {" | "}
+          <Link
+            className="text-white underline text-xs"
+            onClick={() => setIsCookieOpened(true)}
+          >
+            Connect with Cookie
+          </Link>
+          {" | "}
+          <Link
+            className="text-white underline text-xs"
+            onClick={() => setIsPreferencesOpened(true)}
+          >
+            Preferences
+          </Link>
         </div>
         <AboutModal
           isOpen={isAboutOpened}
           onOpen={() => setIsAboutOpened(true)}
           onOpenChange={setIsAboutOpened}
         />
+        <ConnectionLogModal
+          isOpen={isLogOpened}
+          onOpen={() => setIsLogOpened(true)}
+          onOpenChange={setIsLogOpened}
+        />
+        <CookieConnectModal
+          isOpen={isCookieOpened}
+          onOpen={() => setIsCookieOpened(true)}
+          onOpenChange={setIsCookieOpened}
+        />
+        <PreferencesModal
+          isOpen={isPreferencesOpened}
+          onOpen={() => setIsPreferencesOpened(true)}
+          onOpenChange={setIsPreferencesOpened}
+        />
       </main>
     </NextUIProvider>
   );
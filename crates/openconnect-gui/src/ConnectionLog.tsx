@@ -0,0 +1,102 @@
+import { Button } from "@nextui-org/button";
+import {
+  Modal,
+  ModalContent,
+  ModalHeader,
+  ModalBody,
+  ModalFooter,
+} from "@nextui-org/react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
+import { atom, useAtom } from "jotai";
+import { useEffect, useRef } from "react";
+
+export interface ConnectionLogEntry {
+  level: "ERROR" | "INFO" | "DEBUG" | "TRACE";
+  message: string;
+  timestamp: number;
+}
+
+// keep in sync with CONNECTION_LOG_CAPACITY in src-tauri/src/state.rs
+const MAX_ENTRIES = 500;
+
+export const connectionLogAtom = atom<ConnectionLogEntry[]>([]);
+connectionLogAtom.onMount = (set) => {
+  invoke<ConnectionLogEntry[]>("get_connection_logs").then(set);
+  const unlisten = listen<ConnectionLogEntry>("connection-log", (event) => {
+    set((logs) => [...logs, event.payload].slice(-MAX_ENTRIES));
+  });
+  return () => {
+    unlisten.then((fn) => fn());
+  };
+};
+
+const levelColor: Record<ConnectionLogEntry["level"], string> = {
+  ERROR: "text-danger",
+  INFO: "text-foreground",
+  DEBUG: "text-default-500",
+  TRACE: "text-default-400",
+};
+
+interface IModalProps {
+  isOpen: boolean;
+  onOpen: () => void;
+  onOpenChange: (open: boolean) => void;
+}
+
+export const ConnectionLogModal = (props: IModalProps) => {
+  const [logs, setLogs] = useAtom(connectionLogAtom);
+  const bottomRef = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    bottomRef.current?.scrollIntoView({ block: "end" });
+  }, [logs, props.isOpen]);
+
+  const handleClear = async () => {
+    await invoke("clear_connection_logs");
+    setLogs([]);
+  };
+
+  return (
+    <Modal
+      size="sm"
+      backdrop="blur"
+      shadow="lg"
+      isOpen={props.isOpen}
+      onOpenChange={props.onOpenChange}
+      className="min-w-[800px] min-h-[620px] dark bg-background text-foreground bg-opacity-90"
+    >
+      <ModalContent>
+        {(closeModal) => (
+          <>
+            <ModalHeader className="select-none">Connection Log</ModalHeader>
+            <ModalBody>
+              <div className="h-[440px] overflow-y-auto font-mono text-xs select-text">
+                {logs.length === 0 && (
+                  <p className="text-default-500">No connection logs yet</p>
+                )}
+                {logs.map((log, index) => (
+                  <div key={index} className={`${levelColor[log.level]}`}>
+                    <span className="text-default-400">
+                      {new Date(log.timestamp).toLocaleTimeString()}
+                    </span>{" "}
+                    [{log.level}] {log.message}
+                  </div>
+                ))}
+                <div ref={bottomRef} />
+              </div>
+            </ModalBody>
+            <ModalFooter>
+              <Button color="default" onClick={handleClear}>
+                Clear
+              </Button>
+              <Button color="primary" onClick={closeModal}>
+                Close
+              </Button>
+            </ModalFooter>
+          </>
+        )}
+      </ModalContent>
+    </Modal>
+  );
+};
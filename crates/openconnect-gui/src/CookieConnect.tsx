@@ -0,0 +1,86 @@
+import { Button } from "@nextui-org/button";
+import {
+  Modal,
+  ModalContent,
+  ModalHeader,
+  ModalBody,
+  ModalFooter,
+  Textarea,
+} from "@nextui-org/react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { useState } from "react";
+import { useStoredConfigs } from "./state";
+import { toastError } from "./lib/toast";
+
+interface IModalProps {
+  isOpen: boolean;
+  onOpen: () => void;
+  onOpenChange: (open: boolean) => void;
+}
+
+// a session obtained by another tool, e.g. for SSO flows the login can't handle
+export const CookieConnectModal = (props: IModalProps) => {
+  const { selectedServer } = useStoredConfigs();
+  const [cookie, setCookie] = useState("");
+
+  const handleConnect = async (closeModal: () => void) => {
+    if (!selectedServer) {
+      return;
+    }
+    try {
+      await invoke("connect_with_cookie", {
+        serverName: selectedServer.name,
+        cookie,
+      });
+      setCookie("");
+      closeModal();
+    } catch (e) {
+      toastError(e);
+    }
+  };
+
+  return (
+    <Modal
+      size="sm"
+      backdrop="blur"
+      shadow="lg"
+      isOpen={props.isOpen}
+      onOpenChange={props.onOpenChange}
+      className="min-w-[600px] dark bg-background text-foreground bg-opacity-90"
+    >
+      <ModalContent>
+        {(closeModal) => (
+          <>
+            <ModalHeader className="select-none">
+              Connect with Cookie
+            </ModalHeader>
+            <ModalBody>
+              <p className="text-sm text-default-500">
+                Connect to {selectedServer?.name ?? "the selected server"} with
+                a session cookie, skipping the login.
+              </p>
+              <Textarea
+                label="Session cookie"
+                value={cookie}
+                onValueChange={setCookie}
+                minRows={4}
+              />
+            </ModalBody>
+            <ModalFooter>
+              <Button color="default" onClick={closeModal}>
+                Cancel
+              </Button>
+              <Button
+                color="success"
+                isDisabled={!selectedServer || cookie.trim() === ""}
+                onClick={() => handleConnect(closeModal)}
+              >
+                Connect
+              </Button>
+            </ModalFooter>
+          </>
+        )}
+      </ModalContent>
+    </Modal>
+  );
+};
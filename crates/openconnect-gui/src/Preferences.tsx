@@ -0,0 +1,100 @@
+import { Button } from "@nextui-org/button";
+import {
+  Modal,
+  ModalContent,
+  ModalHeader,
+  ModalBody,
+  ModalFooter,
+  Select,
+  SelectItem,
+} from "@nextui-org/react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { useEffect, useState } from "react";
+import { Preferences } from "./state";
+import { toastError } from "./lib/toast";
+
+interface IModalProps {
+  isOpen: boolean;
+  onOpen: () => void;
+  onOpenChange: (open: boolean) => void;
+}
+
+// "off" stops the traffic-stats events, shorter intervals cost a stats request to the tunnel each time
+const trafficStatsIntervals = [
+  { key: "off", label: "Off" },
+  { key: "1", label: "Every second" },
+  { key: "2", label: "Every 2 seconds" },
+  { key: "5", label: "Every 5 seconds" },
+  { key: "10", label: "Every 10 seconds" },
+  { key: "30", label: "Every 30 seconds" },
+  { key: "60", label: "Every minute" },
+];
+
+export const PreferencesModal = (props: IModalProps) => {
+  const [interval, setInterval] = useState("off");
+
+  useEffect(() => {
+    if (props.isOpen) {
+      invoke<Preferences>("get_preferences")
+        .then((preferences) =>
+          setInterval(preferences.trafficStatsIntervalSecs?.toString() ?? "off")
+        )
+        .catch(toastError);
+    }
+  }, [props.isOpen]);
+
+  const handleIntervalChange = async (key: string) => {
+    try {
+      await invoke("set_traffic_stats_interval", {
+        secs: key === "off" ? null : Number(key),
+      });
+      setInterval(key);
+    } catch (e) {
+      toastError(e);
+    }
+  };
+
+  return (
+    <Modal
+      size="sm"
+      backdrop="blur"
+      shadow="lg"
+      isOpen={props.isOpen}
+      onOpenChange={props.onOpenChange}
+      className="min-w-[600px] dark bg-background text-foreground bg-opacity-90"
+    >
+      <ModalContent>
+        {(closeModal) => (
+          <>
+            <ModalHeader className="select-none">Preferences</ModalHeader>
+            <ModalBody>
+              <Select
+                label="Traffic statistics"
+                labelPlacement="inside"
+                selectionMode="single"
+                disallowEmptySelection
+                size="sm"
+                selectedKeys={[interval]}
+                onChange={(e) => handleIntervalChange(e.target.value)}
+              >
+                {trafficStatsIntervals.map((option) => (
+                  <SelectItem key={option.key} value={option.key}>
+                    {option.label}
+                  </SelectItem>
+                ))}
+              </Select>
+              <p className="text-sm text-default-500">
+                How often the traffic counters are updated while connected.
+              </p>
+            </ModalBody>
+            <ModalFooter>
+              <Button color="primary" onClick={closeModal}>
+                Close
+              </Button>
+            </ModalFooter>
+          </>
+        )}
+      </ModalContent>
+    </Modal>
+  );
+};
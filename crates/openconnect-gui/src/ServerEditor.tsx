@@ -53,6 +53,17 @@ export const ServerEditor = (props: FormParams) => {
           clientId: data.clientId,
           clientSecret: data.clientSecret,
           allowInsecure: data.allowInsecure,
+          clientSecretSource: (initialData as Partial<OidcServer>)
+            .clientSecretSource,
+          connectVia: initialData.connectVia,
+          reportedOs: initialData.reportedOs,
+          proxy: initialData.proxy,
+          oidcLogout: data.oidcLogout,
+          notify: data.notify,
+          serverCert: initialData.serverCert,
+          mtu: initialData.mtu,
+          protocol: initialData.protocol,
+          profiles: initialData.profiles,
         };
         break;
       case "password":
@@ -63,6 +74,22 @@ export const ServerEditor = (props: FormParams) => {
           username: data.username,
           password: data.password,
           allowInsecure: data.allowInsecure,
+          authGroup: (initialData as Partial<PasswordServer>).authGroup,
+          passwordSource: (initialData as Partial<PasswordServer>)
+            .passwordSource,
+          connectVia: initialData.connectVia,
+          reportedOs: initialData.reportedOs,
+          proxy: initialData.proxy,
+          webAuth: (initialData as Partial<PasswordServer>).webAuth,
+          formDefaults: (initialData as Partial<PasswordServer>).formDefaults,
+          notify: data.notify,
+          serverCert: initialData.serverCert,
+          mtu: initialData.mtu,
+          protocol: initialData.protocol,
+          clientCert: (initialData as Partial<PasswordServer>).clientCert,
+          clientKey: (initialData as Partial<PasswordServer>).clientKey,
+          keyPassword: (initialData as Partial<PasswordServer>).keyPassword,
+          profiles: initialData.profiles,
         };
         break;
     }
@@ -287,6 +314,39 @@ export const ServerEditor = (props: FormParams) => {
                 />
               )}
             />
+            <Controller
+              name="oidcLogout"
+              control={control}
+              render={({ field }) => (
+                <Switch
+                  classNames={{
+                    base: cn(
+                      "inline-flex flex-row-reverse w-full bg-content2 hover:bg-content3 items-center",
+                      "justify-between cursor-pointer rounded-lg gap-2 p-2 border-2 border-transparent",
+                    ),
+                    wrapper: "p-0 h-4 overflow-visible",
+                    thumb: cn(
+                      "w-6 h-6 border-2 shadow-lg",
+                      "group-data-[selected=true]:ml-6",
+                      "group-data-[pressed=true]:w-7",
+                      "group-data-[selected]:group-data-[pressed]:ml-4"
+                    ),
+                  }}
+                  size="sm"
+                  aria-label="Log Out of the Issuer on Disconnect"
+                  isSelected={field.value ?? false}
+                  onValueChange={field.onChange}
+                >
+                  <div className="flex flex-col gap-1">
+                    <p className="text-medium">Log Out of the Issuer on Disconnect</p>
+                    <p className="text-tiny text-default-400">
+                      End the single sign-on session too, so that the next
+                      connect asks for a login again.
+                    </p>
+                  </div>
+                </Switch>
+              )}
+            />
           </>
         )}
         <Controller
@@ -326,6 +386,39 @@ export const ServerEditor = (props: FormParams) => {
             </Switch>
           )}
         />
+        <Controller
+          name="notify"
+          control={control}
+          render={({ field }) => (
+            <Switch
+              classNames={{
+                base: cn(
+                  "inline-flex flex-row-reverse w-full bg-content2 hover:bg-content3 items-center",
+                  "justify-between cursor-pointer rounded-lg gap-2 p-2 border-2 border-transparent",
+                ),
+                wrapper: "p-0 h-4 overflow-visible",
+                thumb: cn(
+                  "w-6 h-6 border-2 shadow-lg",
+                  "group-data-[selected=true]:ml-6",
+                  "group-data-[pressed=true]:w-7",
+                  "group-data-[selected]:group-data-[pressed]:ml-4"
+                ),
+              }}
+              size="sm"
+              aria-label="Show Notifications"
+              isSelected={field.value ?? true}
+              onValueChange={field.onChange}
+            >
+              <div className="flex flex-col gap-1">
+                <p className="text-medium">Show Notifications</p>
+                <p className="text-tiny text-default-400">
+                  Notify when the server connects, disconnects or fails, turn
+                  off for noisy short-lived connections.
+                </p>
+              </div>
+            </Switch>
+          )}
+        />
       </div>
       <div className="flex gap-4 w-full self-end items-end pl-2 pr-2">
         {props.mode === "edit" && (
@@ -5,10 +5,20 @@ import {
   SelectItem,
   useDisclosure,
 } from "@nextui-org/react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { useAtom } from "jotai";
 import { FC, PropsWithChildren, useEffect } from "react";
 import { ServerEditorModal } from "./ServerEditorModal";
-import { useStoredConfigs } from "./state";
+import {
+  AuthGroup,
+  authGroupsAtom,
+  LastStatus,
+  lastStatusesAtom,
+  selectedAuthGroupAtom,
+  useStoredConfigs,
+} from "./state";
 import { useKey } from "react-use";
+import { formatAgo } from "./lib/time";
 
 export const ServerSelector = () => {
   const {
@@ -20,6 +30,11 @@ export const ServerSelector = () => {
   } = useStoredConfigs();
 
   const { isOpen, onOpen, onOpenChange } = useDisclosure();
+  const [authGroups, setAuthGroups] = useAtom(authGroupsAtom);
+  const [selectedAuthGroup, setSelectedAuthGroup] = useAtom(
+    selectedAuthGroupAtom
+  );
+  const [lastStatuses, setLastStatuses] = useAtom(lastStatusesAtom);
 
   useKey(
     "Tab",
@@ -54,6 +69,49 @@ export const ServerSelector = () => {
     getStoredConfigs();
   }, [getStoredConfigs]);
 
+  // shown until a connection is made, the selector is mounted again after every disconnect
+  useEffect(() => {
+    invoke<Record<string, LastStatus>>("get_last_statuses")
+      .then(setLastStatuses)
+      .catch(() => {
+        // an unreadable cache only hides the row
+      });
+  }, [setLastStatuses]);
+
+  const passwordServer =
+    selectedServer?.authType === "password" ? selectedServer : undefined;
+
+  useEffect(() => {
+    setAuthGroups([]);
+    setSelectedAuthGroup(passwordServer?.authGroup ?? null);
+    if (!passwordServer) {
+      return;
+    }
+
+    let cancelled = false;
+    invoke<AuthGroup[]>("probe_auth_groups", {
+      server: passwordServer.server,
+      allowInsecure: passwordServer.allowInsecure ?? false,
+    })
+      .then((groups) => {
+        if (!cancelled) {
+          setAuthGroups(groups);
+        }
+      })
+      .catch(() => {
+        // the server may not be reachable yet, the group list stays empty
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [
+    passwordServer?.name,
+    passwordServer?.server,
+    passwordServer?.allowInsecure,
+    setAuthGroups,
+    setSelectedAuthGroup,
+  ]);
+
   return (
     <section className="h-full w-full flex flex-col">
       <div className="w-full flex">
@@ -86,10 +144,40 @@ export const ServerSelector = () => {
       <div className="flex flex-col gap-6 p-1">
         <InfoRow label="Server Type">{selectedServer?.authType}</InfoRow>
         <InfoRow label="Server URL">{selectedServer?.server}</InfoRow>
+        {selectedName && lastStatuses[selectedName] && (
+          <InfoRow label="Last Status">
+            {describeLastStatus(lastStatuses[selectedName])}
+          </InfoRow>
+        )}
         {selectedServer?.authType === "password" && (
           <>
             <InfoRow label="Username">{selectedServer?.username}</InfoRow>
             <InfoRow label="Password">{"********"}</InfoRow>
+            {!!authGroups.length && (
+              <InfoRow label="Auth Group">
+                <Select
+                  size="sm"
+                  aria-label="Auth Group"
+                  className="w-[300px]"
+                  selectionMode="single"
+                  selectedKeys={selectedAuthGroup ? [selectedAuthGroup] : []}
+                  onSelectionChange={(keys) => {
+                    const name = Array.from(keys as Set<string>)[0];
+                    setSelectedAuthGroup(name ?? null);
+                  }}
+                >
+                  {authGroups.map((group) => (
+                    <SelectItem
+                      color="default"
+                      key={group.name}
+                      value={group.name}
+                    >
+                      {group.label}
+                    </SelectItem>
+                  ))}
+                </Select>
+              </InfoRow>
+            )}
           </>
         )}
         {selectedServer?.authType === "oidc" && (
@@ -109,6 +197,16 @@ export const ServerSelector = () => {
   );
 };
 
+const describeLastStatus = (lastStatus: LastStatus) => {
+  const status =
+    lastStatus.status.charAt(0) + lastStatus.status.slice(1).toLowerCase();
+  const described = `${status} ${formatAgo(lastStatus.timestamp)}`;
+  if (lastStatus.lastConnected && lastStatus.status !== "CONNECTED") {
+    return `${described}, last connected ${formatAgo(lastStatus.lastConnected)}`;
+  }
+  return described;
+};
+
 const InfoRow: FC<PropsWithChildren<{ label: string }>> = (props) => {
   return (
     <div className="flex gap-4">
@@ -0,0 +1,16 @@
+const UNITS: [string, number][] = [
+  ["d", 24 * 60 * 60 * 1000],
+  ["h", 60 * 60 * 1000],
+  ["m", 60 * 1000],
+];
+
+// a coarse "2h ago" for timestamps in milliseconds since the epoch
+export const formatAgo = (timestamp: number, now = Date.now()) => {
+  const elapsed = Math.max(now - timestamp, 0);
+  for (const [unit, ms] of UNITS) {
+    if (elapsed >= ms) {
+      return `${Math.floor(elapsed / ms)}${unit} ago`;
+    }
+  }
+  return "just now";
+};
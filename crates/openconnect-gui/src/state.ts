@@ -11,6 +11,20 @@ export interface OidcServer {
   clientSecret?: string;
   allowInsecure?: boolean,
   updatedAt?: string;
+  clientSecretSource?: SecretSource;
+  connectVia?: string;
+  reportedOs?: string;
+  proxy?: string;
+  oidcLogout?: boolean;
+  // desktop notifications on connect, disconnect and failure, shown unless false
+  notify?: boolean;
+  // the pinned SHA-256 fingerprint of the gateway certificate, only configurable from the CLI for now
+  serverCert?: string;
+  // the MTU of the tunnel device instead of the negotiated one, only configurable from the CLI for now
+  mtu?: number;
+  // name of the VPN protocol like anyconnect or gp, only configurable from the CLI for now
+  protocol?: string;
+  profiles?: Record<string, ServerProfile>;
 }
 
 export interface PasswordServer {
@@ -21,6 +35,61 @@ export interface PasswordServer {
   password: string;
   allowInsecure?: boolean,
   updatedAt?: string;
+  authGroup?: string;
+  passwordSource?: SecretSource;
+  connectVia?: string;
+  reportedOs?: string;
+  proxy?: string;
+  webAuth?: boolean;
+  // answers of custom login form fields by field name, only configurable from the CLI for now
+  formDefaults?: Record<string, string>;
+  notify?: boolean;
+  serverCert?: string;
+  mtu?: number;
+  protocol?: string;
+  // only configurable from the CLI for now
+  clientCert?: string;
+  clientKey?: string;
+  keyPassword?: string;
+  profiles?: Record<string, ServerProfile>;
+}
+
+// named connection options selected with `start --profile`, only configurable from the CLI for now
+export interface ServerProfile {
+  vpncScript?: string;
+  searchDomains?: string[];
+  enableUdp?: boolean;
+}
+
+// resolved at connect time, only configurable from the CLI for now
+export type SecretSource =
+  | { type: "env"; value: string }
+  | { type: "file"; value: string };
+
+export interface AuthGroup {
+  name: string;
+  label: string;
+}
+
+// cached by the backend on every status transition, timestamps in milliseconds since the epoch
+export interface LastStatus {
+  status: string;
+  message?: string;
+  timestamp: number;
+  lastConnected?: number;
+}
+
+// pushed as `traffic-stats` events while connected, at the interval of the preferences
+export interface TrafficStats {
+  rxBytes: number;
+  txBytes: number;
+  rxPkts: number;
+  txPkts: number;
+  timestamp: number;
+}
+
+export interface Preferences {
+  trafficStatsIntervalSecs?: number | null;
 }
 
 export interface StoredConfigs {
@@ -31,6 +100,9 @@ export interface StoredConfigs {
 export const storedConfigsAtom = atom<StoredConfigs["servers"]>([]);
 export const selectedNameAtom = atom<string | null>(null);
 export const defaultNameAtom = atom<string | null>(null);
+export const authGroupsAtom = atom<AuthGroup[]>([]);
+export const selectedAuthGroupAtom = atom<string | null>(null);
+export const lastStatusesAtom = atom<Record<string, LastStatus>>({});
 
 export const useStoredConfigs = () => {
   const [serverList, setServerList] = useAtom(storedConfigsAtom);
@@ -1,9 +1,47 @@
 use std::str::FromStr;
 
 pub mod oidc_device;
+pub mod oidc_logout;
 pub mod oidc_token;
 
+#[derive(Debug, thiserror::Error)]
+pub enum ObtainCookieError {
+    #[error("Failed to build the HTTP client: {0}")]
+    ClientError(String),
+
+    #[error("Invalid server URL: {0}")]
+    UrlParseError(#[from] url::ParseError),
+
+    /// The gateway was unavailable, e.g. a 5xx status or a timeout
+    #[error("Gateway unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Gateway rejected the token with status {0}")]
+    Rejected(reqwest::StatusCode),
+}
+
+impl ObtainCookieError {
+    /// Whether the request is worth retrying, a rejected token is not
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ObtainCookieError::Unavailable(_))
+    }
+}
+
 pub async fn obtain_cookie_by_oidc_token(server_url: &str, token: &str) -> Option<String> {
+    match request_cookie_by_oidc_token(server_url, token).await {
+        Ok(cookie) => Some(cookie),
+        Err(e) => {
+            eprintln!("Failed to obtain cookie from server: {}", e);
+            None
+        }
+    }
+}
+
+/// Exchange the OIDC token for the session cookie of the gateway
+pub async fn request_cookie_by_oidc_token(
+    server_url: &str,
+    token: &str,
+) -> Result<String, ObtainCookieError> {
     let client = reqwest::ClientBuilder::new()
         .danger_accept_invalid_certs(true)
         .default_headers(reqwest::header::HeaderMap::new())
@@ -16,9 +54,9 @@ pub async fn obtain_cookie_by_oidc_token(server_url: &str, token: &str) -> Optio
         .no_gzip()
         .no_proxy()
         .build()
-        .ok()?;
+        .map_err(|e| ObtainCookieError::ClientError(e.to_string()))?;
 
-    let mut url = reqwest::Url::from_str(server_url).ok()?;
+    let mut url = reqwest::Url::from_str(server_url)?;
     if url.path().is_empty() {
         url.set_path("auth")
     }
@@ -29,15 +67,22 @@ pub async fn obtain_cookie_by_oidc_token(server_url: &str, token: &str) -> Optio
         .header("Content-Type", "application/x-www-form-urlencoded")
         .bearer_auth(token);
 
-    let req = req_builder.build().ok()?;
+    let req = req_builder
+        .build()
+        .map_err(|e| ObtainCookieError::ClientError(e.to_string()))?;
 
-    let res = client.execute(req).await.ok()?;
+    let res = client.execute(req).await.map_err(|e| {
+        if e.is_timeout() || e.is_connect() {
+            ObtainCookieError::Unavailable(e.to_string())
+        } else {
+            ObtainCookieError::ClientError(e.to_string())
+        }
+    })?;
+    if res.status().is_server_error() {
+        return Err(ObtainCookieError::Unavailable(res.status().to_string()));
+    }
     if !res.status().is_success() {
-        eprintln!(
-            "Failed to obtain cookie from server. Error code: {:?}",
-            res.status()
-        );
-        return None;
+        return Err(ObtainCookieError::Rejected(res.status()));
     }
 
     let combined_cookie = res
@@ -46,5 +91,5 @@ pub async fn obtain_cookie_by_oidc_token(server_url: &str, token: &str) -> Optio
         .collect::<Vec<_>>()
         .join("; ");
 
-    Some(combined_cookie)
-}
\ No newline at end of file
+    Ok(combined_cookie)
+}
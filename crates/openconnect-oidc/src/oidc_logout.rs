@@ -0,0 +1,131 @@
+//! RP-initiated logout, ending the session at the identity provider with the ID token of the login
+//!
+//! The end session endpoint is requested without a browser, so providers which ask the user to confirm the logout
+//! keep the session.
+
+use openidconnect::{
+    core::{
+        CoreAuthDisplay, CoreClaimName, CoreClaimType, CoreClientAuthMethod, CoreGrantType,
+        CoreJsonWebKey, CoreJsonWebKeyType, CoreJsonWebKeyUse, CoreJweContentEncryptionAlgorithm,
+        CoreJweKeyManagementAlgorithm, CoreJwsSigningAlgorithm, CoreResponseMode, CoreResponseType,
+        CoreSubjectIdentifierType,
+    },
+    reqwest::async_http_client,
+    AdditionalProviderMetadata, IssuerUrl, ProviderMetadata,
+};
+use url::Url;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+struct EndSessionProviderMetadata {
+    end_session_endpoint: Option<String>,
+}
+
+impl AdditionalProviderMetadata for EndSessionProviderMetadata {}
+
+type EndSessionProviderMetadataDocument = ProviderMetadata<
+    EndSessionProviderMetadata,
+    CoreAuthDisplay,
+    CoreClientAuthMethod,
+    CoreClaimName,
+    CoreClaimType,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreResponseMode,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+/// What is needed to end the session of a login
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct OpenIDLogoutConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub id_token: String,
+}
+
+impl std::fmt::Debug for OpenIDLogoutConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the ID token is a credential, keep it out of logs
+        f.debug_struct("OpenIDLogoutConfig")
+            .field("issuer_url", &self.issuer_url)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OpenIDLogoutError {
+    #[error("Failed to discover the provider: {0}")]
+    DiscoveryError(String),
+
+    #[error("The provider does not support RP-initiated logout")]
+    NotSupported,
+
+    #[error("URL parse error: {0}")]
+    UrlParseError(#[from] url::ParseError),
+
+    #[error("Failed to request the end session endpoint: {0}")]
+    RequestError(String),
+
+    #[error("The provider rejected the logout with status {0}")]
+    Rejected(reqwest::StatusCode),
+}
+
+/// The end session URL with the ID token as hint of the session to end
+pub fn end_session_url(
+    end_session_endpoint: &str,
+    config: &OpenIDLogoutConfig,
+) -> Result<Url, url::ParseError> {
+    let mut url = Url::parse(end_session_endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("id_token_hint", &config.id_token)
+        .append_pair("client_id", &config.client_id);
+    Ok(url)
+}
+
+/// Discover the end session endpoint of the issuer and request it
+pub async fn end_session(config: &OpenIDLogoutConfig) -> Result<(), OpenIDLogoutError> {
+    let issuer_url = IssuerUrl::new(config.issuer_url.clone())?;
+    let provider_metadata =
+        EndSessionProviderMetadataDocument::discover_async(issuer_url, async_http_client)
+            .await
+            .map_err(|e| OpenIDLogoutError::DiscoveryError(e.to_string()))?;
+
+    let end_session_endpoint = provider_metadata
+        .additional_metadata()
+        .end_session_endpoint
+        .as_deref()
+        .ok_or(OpenIDLogoutError::NotSupported)?;
+    let url = end_session_url(end_session_endpoint, config)?;
+
+    let res = reqwest::get(url)
+        .await
+        .map_err(|e| OpenIDLogoutError::RequestError(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(OpenIDLogoutError::Rejected(res.status()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_end_session_url() {
+    let config = OpenIDLogoutConfig {
+        issuer_url: "https://idp.example.com".to_string(),
+        client_id: "vpn".to_string(),
+        id_token: "a.b.c".to_string(),
+    };
+    assert_eq!(
+        end_session_url("https://idp.example.com/logout?from=vpn", &config)
+            .unwrap()
+            .as_str(),
+        "https://idp.example.com/logout?from=vpn&id_token_hint=a.b.c&client_id=vpn"
+    );
+    assert!(end_session_url("not a url", &config).is_err());
+    assert!(!format!("{:?}", config).contains("a.b.c"));
+}
@@ -16,6 +16,14 @@ fn main() {
         return;
     }
 
+    // the stub feature replaces libopenconnect with src/stub.rs, only the helper is still needed
+    if env::var("CARGO_FEATURE_STUB").is_ok() {
+        println!("cargo:rerun-if-changed=c-src/helper.h");
+        println!("cargo:rerun-if-changed=c-src/helper.c");
+        compile_helper();
+        return;
+    }
+
     let use_prebuilt =
         env::var("OPENCONNECT_USE_PREBUILT").unwrap_or("false".to_string()) == "true";
 
@@ -128,12 +136,7 @@ fn main() {
     println!("cargo:rerun-if-changed=c-src/helper.h");
     println!("cargo:rerun-if-changed=c-src/helper.c");
 
-    // ===== compile helper.c start =====
-    let mut build = cc::Build::new();
-    let build = build.file("c-src/helper.c").include("c-src");
-    // .include(openconnect_src_dir.to_str().unwrap()); // maybe not needed
-    build.compile("helper");
-    // ===== compile helper.c end =====
+    compile_helper();
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
@@ -168,3 +171,10 @@ fn main() {
         )))
         .expect("Couldn't write bindings!");
 }
+
+fn compile_helper() {
+    let mut build = cc::Build::new();
+    let build = build.file("c-src/helper.c").include("c-src");
+    // .include(openconnect_src_dir.to_str().unwrap()); // maybe not needed
+    build.compile("helper");
+}
@@ -26,6 +26,9 @@ include!("bindings_x86_64_linux_musl.rs");
 #[cfg(target_env = "gnu")]
 include!("bindings_x86_64_windows_gnu.rs");
 
+#[cfg(feature = "stub")]
+mod stub;
+
 #[cfg(test)]
 mod tests {
     use super::*;
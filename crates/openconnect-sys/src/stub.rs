@@ -0,0 +1,340 @@
+//! Stand-ins for the libopenconnect symbols used by openconnect-core, enabled by the `stub` feature
+//!
+//! They let the higher level crates compile, link and run their unit tests without the native library.
+//! Every call fails (or returns null) and no connection can be made.
+
+use super::*;
+use std::os::raw::{c_char, c_int, c_uchar, c_void};
+
+/// Returned by every stubbed call which reports an error code
+const STUB_ERROR: c_int = -1;
+
+struct ProtocolTable([oc_vpn_proto; 2]);
+
+// SAFETY: the table only points to static strings and is never mutated
+unsafe impl Sync for ProtocolTable {}
+
+/// Core falls back to the anyconnect protocol, so it has to be listed
+static PROTOCOLS: ProtocolTable = ProtocolTable([
+    oc_vpn_proto {
+        name: c"anyconnect".as_ptr(),
+        pretty_name: c"Cisco AnyConnect or openconnect".as_ptr(),
+        description: c"Stub protocol, libopenconnect is not linked".as_ptr(),
+        flags: 0,
+    },
+    oc_vpn_proto {
+        name: std::ptr::null(),
+        pretty_name: std::ptr::null(),
+        description: std::ptr::null(),
+        flags: 0,
+    },
+]);
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_version() -> *const c_char {
+    c"stub".as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_supported_protocols(
+    protos: *mut *mut oc_vpn_proto,
+) -> c_int {
+    *protos = PROTOCOLS.0.as_ptr() as *mut oc_vpn_proto;
+    1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_free_supported_protocols(_protos: *mut oc_vpn_proto) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_init_ssl() -> c_int {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_vpninfo_new(
+    _useragent: *const c_char,
+    _validate_peer_cert: openconnect_validate_peer_cert_vfn,
+    _write_new_config: openconnect_write_new_config_vfn,
+    _process_auth_form: openconnect_process_auth_form_vfn,
+    _progress: openconnect_progress_vfn,
+    _privdata: *mut c_void,
+) -> *mut openconnect_info {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_vpninfo_free(_vpninfo: *mut openconnect_info) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_option_value(
+    _opt: *mut oc_form_opt,
+    _value: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_loglevel(_vpninfo: *mut openconnect_info, _level: c_int) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_reqmtu(_vpninfo: *mut openconnect_info, _reqmtu: c_int) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_pass_tos(_vpninfo: *mut openconnect_info, _enable: c_int) {
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_stats_handler(
+    _vpninfo: *mut openconnect_info,
+    _stats_handler: openconnect_stats_vfn,
+) {
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_external_browser_callback(
+    _vpninfo: *mut openconnect_info,
+    _browser_fn: openconnect_open_webview_vfn,
+) {
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_setup_tun_handler(
+    _vpninfo: *mut openconnect_info,
+    _setup_tun: openconnect_setup_tun_vfn,
+) {
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_override_getaddrinfo(
+    _vpninfo: *mut openconnect_info,
+    _gai_fn: openconnect_getaddrinfo_vfn,
+) {
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_setup_tun_device(
+    _vpninfo: *mut openconnect_info,
+    _vpnc_script: *const c_char,
+    _ifname: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_setup_tun_fd(
+    _vpninfo: *mut openconnect_info,
+    _tun_fd: c_int,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_setup_cmd_pipe(_vpninfo: *mut openconnect_info) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_setup_dtls(
+    _vpninfo: *mut openconnect_info,
+    _dtls_attempt_period: c_int,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_disable_dtls(_vpninfo: *mut openconnect_info) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_reported_os(
+    _vpninfo: *mut openconnect_info,
+    _os: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_useragent(
+    _vpninfo: *mut openconnect_info,
+    _useragent: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_protocol(
+    _vpninfo: *mut openconnect_info,
+    _protocol: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_http_proxy(
+    _vpninfo: *mut openconnect_info,
+    _proxy: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_client_cert(
+    _vpninfo: *mut openconnect_info,
+    _cert: *const c_char,
+    _sslkey: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_key_password(
+    _vpninfo: *mut openconnect_info,
+    _pass: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_cafile(
+    _vpninfo: *mut openconnect_info,
+    _cafile: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_mca_cert(
+    _vpninfo: *mut openconnect_info,
+    _cert: *const c_char,
+    _key: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_parse_url(
+    _vpninfo: *mut openconnect_info,
+    _url: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_obtain_cookie(_vpninfo: *mut openconnect_info) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_set_cookie(
+    _vpninfo: *mut openconnect_info,
+    _cookie: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_cookie(_vpninfo: *mut openconnect_info) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_clear_cookie(_vpninfo: *mut openconnect_info) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_reset_ssl(_vpninfo: *mut openconnect_info) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_make_cstp_connection(
+    _vpninfo: *mut openconnect_info,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_mainloop(
+    _vpninfo: *mut openconnect_info,
+    _reconnect_timeout: c_int,
+    _reconnect_interval: c_int,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_port(_vpninfo: *mut openconnect_info) -> c_int {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_connect_url(
+    _vpninfo: *mut openconnect_info,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_hostname(
+    _vpninfo: *mut openconnect_info,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_ifname(_vpninfo: *mut openconnect_info) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_cstp_cipher(
+    _vpninfo: *mut openconnect_info,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_dtls_cipher(
+    _vpninfo: *mut openconnect_info,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_ip_info(
+    _vpninfo: *mut openconnect_info,
+    _info: *mut *const oc_ip_info,
+    _cstp_options: *mut *const oc_vpn_option,
+    _dtls_options: *mut *const oc_vpn_option,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_peer_cert_hash(
+    _vpninfo: *mut openconnect_info,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_check_peer_cert_hash(
+    _vpninfo: *mut openconnect_info,
+    _old_hash: *const c_char,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_get_peer_cert_DER(
+    _vpninfo: *mut openconnect_info,
+    _buf: *mut *mut c_uchar,
+) -> c_int {
+    STUB_ERROR
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openconnect_free_cert_info(
+    _vpninfo: *mut openconnect_info,
+    _buf: *mut c_void,
+) {
+}